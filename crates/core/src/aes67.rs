@@ -0,0 +1,195 @@
+// AES67 output profile: 24-bit linear PCM (L24) at 48 kHz in 1 ms packets
+// to a multicast group, with RTP timestamps locked to PTP (IEEE 1588) and the
+// stream announced over SAP so AoIP gear and Dante devices in AES67 mode can
+// subscribe to it.
+//
+// RTP time = PTP time * 48000 (mediaclk:direct=0): the pipeline runs on the
+// PTP clock with base-time 0, so running time equals PTP time.
+use anyhow::{Context, Result};
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use gstreamer_net as gst_net;
+use std::net::{Ipv4Addr, UdpSocket};
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::pipeline::{apply_dscp, configure_multicast_sink, make_element};
+use crate::sdp::local_ip_towards;
+
+/// Default multicast group when `--aes67` is used without `--send-to`.
+pub const DEFAULT_GROUP: &str = "239.69.0.1";
+
+/// Dynamic payload type AES67 devices expect for L24.
+const PT: u32 = 96;
+const SAP_GROUP: Ipv4Addr = Ipv4Addr::new(239, 255, 255, 255);
+const SAP_PORT: u16 = 9875;
+const SAP_INTERVAL: Duration = Duration::from_secs(30);
+
+/// AES67 profile settings.
+#[derive(Debug, Clone)]
+pub struct Aes67Config {
+    /// PTP domain of the grandmaster to follow (0 for most AES67 networks).
+    pub ptp_domain: u32,
+    /// `s=` line of the announced SDP, shown by receivers as the stream name.
+    pub session_name: String,
+}
+
+/// Everything the L24 tail needs to know about the destination.
+pub(crate) struct Aes67Target<'a> {
+    pub bind: Option<std::net::IpAddr>,
+    pub dscp: Option<u8>,
+    pub group: &'a str,
+    pub port: u16,
+    pub ttl: u32,
+    pub iface: Option<&'a str>,
+}
+
+/// Link `upstream` (48 kHz stereo PCM) → L24 payloader → multicast udpsink,
+/// slave the pipeline to PTP and start the SAP announcer.
+pub(crate) fn attach_sender(
+    pipeline: &gst::Pipeline,
+    upstream: &gst::Element,
+    target: &Aes67Target,
+    cfg: &Aes67Config,
+) -> Result<()> {
+    let group: Ipv4Addr = target
+        .group
+        .parse()
+        .ok()
+        .filter(|a: &Ipv4Addr| a.is_multicast())
+        .with_context(|| format!("AES67 needs an IPv4 multicast destination, got '{}'", target.group))?;
+
+    // ---------- PTP clock ----------
+    // The PTP helper lives for the whole process; dropping the guard would stop it.
+    let guard = gst_net::PtpClock::init(None, &[]).context("PTP init failed (ptp-helper installed?)")?;
+    std::mem::forget(guard);
+    let clock = gst_net::PtpClock::new(Some("ptp-clock"), cfg.ptp_domain)
+        .context("PTP clock")?;
+    info!(target: "aes67", "waiting for PTP sync on domain {}", cfg.ptp_domain);
+    if clock.wait_for_sync(gst::ClockTime::from_seconds(10)).is_err() {
+        warn!(target: "aes67", "no PTP grandmaster yet; timestamps will jump once it syncs");
+    }
+    pipeline.use_clock(Some(&clock));
+    pipeline.set_base_time(gst::ClockTime::ZERO);
+    pipeline.set_start_time(gst::ClockTime::NONE);
+
+    // ---------- L24 / 1 ms packets ----------
+    let convert = make_element("audioconvert", "aconv_l24")?;
+    let caps = make_element("capsfilter", "acaps_l24")?;
+    caps.set_property(
+        "caps",
+        gst::Caps::builder("audio/x-raw")
+            .field("format", "S24BE")
+            .field("rate", 48_000i32)
+            .field("channels", 2i32)
+            .field("layout", "interleaved")
+            .build(),
+    );
+    let pay = make_element("rtpL24pay", "pay")?;
+    pay.set_property("pt", PT);
+    pay.set_property("timestamp-offset", 0u32);
+    pay.set_property("min-ptime", 1_000_000i64);
+    pay.set_property("max-ptime", 1_000_000i64);
+
+    let sink = make_element("udpsink", "udpsink")?;
+    sink.set_property("host", target.group);
+    sink.set_property("port", target.port as i32);
+    if let Some(bind) = target.bind {
+        sink.set_property("bind-address", bind.to_string());
+    }
+    configure_multicast_sink(&sink, target.ttl, target.iface);
+    // AES67 recommends EF (46) for media when the network does QoS
+    if let Some(dscp) = target.dscp {
+        apply_dscp(&sink, dscp);
+    }
+
+    pipeline.add_many(&[&convert, &caps, &pay, &sink])?;
+    gst::Element::link_many(&[upstream, &convert, &caps, &pay, &sink])
+        .context("aes67: link L24 chain")?;
+    info!(
+        target: "aes67",
+        "L24/48000/2, ptime=1ms → {}:{} (ttl={})",
+        target.group, target.port, target.ttl
+    );
+
+    spawn_sap_announcer(clock, group, target.port, target.ttl, cfg.clone());
+    Ok(())
+}
+
+/// Re-announce the stream every 30 s. The SDP is rebuilt each time so it
+/// picks up the grandmaster once PTP has synced.
+fn spawn_sap_announcer(clock: gst_net::PtpClock, group: Ipv4Addr, port: u16, ttl: u32, cfg: Aes67Config) {
+    std::thread::spawn(move || {
+        let sock = match UdpSocket::bind("0.0.0.0:0") {
+            Ok(s) => s,
+            Err(e) => {
+                warn!(target: "aes67", "SAP socket: {e}");
+                return;
+            }
+        };
+        let _ = sock.set_multicast_ttl_v4(ttl.max(1));
+        let origin = match local_ip_towards(group.into()) {
+            Some(std::net::IpAddr::V4(a)) => a,
+            _ => Ipv4Addr::UNSPECIFIED,
+        };
+        let session_id = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        loop {
+            let gm = clock.property::<u64>("grandmaster-clock-id");
+            let sdp = stream_sdp(&cfg, origin, session_id, group, port, ttl, gm);
+            let packet = sap_packet(origin, session_id as u16, &sdp);
+            if let Err(e) = sock.send_to(&packet, (SAP_GROUP, SAP_PORT)) {
+                warn!(target: "aes67", "SAP announce failed: {e}");
+            }
+            std::thread::sleep(SAP_INTERVAL);
+        }
+    });
+}
+
+/// The SDP receivers need to subscribe (RFC 4566 + AES67 / RFC 7273 clock attributes).
+fn stream_sdp(
+    cfg: &Aes67Config,
+    origin: Ipv4Addr,
+    session_id: u64,
+    group: Ipv4Addr,
+    port: u16,
+    ttl: u32,
+    grandmaster: u64,
+) -> String {
+    let gm = grandmaster
+        .to_be_bytes()
+        .iter()
+        .map(|b| format!("{b:02X}"))
+        .collect::<Vec<_>>()
+        .join("-");
+    let domain = cfg.ptp_domain;
+    format!(
+        "v=0\r\n\
+         o=- {session_id} {session_id} IN IP4 {origin}\r\n\
+         s={name}\r\n\
+         c=IN IP4 {group}/{ttl}\r\n\
+         t=0 0\r\n\
+         a=clock-domain:PTPv2 {domain}\r\n\
+         m=audio {port} RTP/AVP {PT}\r\n\
+         a=rtpmap:{PT} L24/48000/2\r\n\
+         a=recvonly\r\n\
+         a=ptime:1\r\n\
+         a=ts-refclk:ptp=IEEE1588-2008:{gm}:{domain}\r\n\
+         a=mediaclk:direct=0\r\n",
+        name = cfg.session_name,
+    )
+}
+
+/// SAP announcement (RFC 2974): v1, IPv4 origin, no auth, no encryption.
+fn sap_packet(origin: Ipv4Addr, msg_id_hash: u16, sdp: &str) -> Vec<u8> {
+    let mut p = Vec::with_capacity(24 + sdp.len());
+    p.push(0x20);
+    p.push(0);
+    p.extend_from_slice(&msg_id_hash.to_be_bytes());
+    p.extend_from_slice(&origin.octets());
+    p.extend_from_slice(b"application/sdp\0");
+    p.extend_from_slice(sdp.as_bytes());
+    p
+}
@@ -0,0 +1,66 @@
+// Applications playing audio, for capturing one of them rather than all that
+// plays (--capture-app). On Linux each stream an app plays is a PipeWire node
+// of class Stream/Output/Audio, which pipewiresrc can capture on its own
+// (`target-object`) while it still plays where it did; they're listed with
+// `pw-dump`. An app gets new nodes when it restarts, so the daemon looks it
+// up again by name (see the daemon's `apps.rs`). macOS taps apps with Core
+// Audio instead (the daemon's `tap.rs`).
+use anyhow::{Context, Result, bail};
+use serde_json::Value;
+use std::process::Command;
+
+#[derive(Debug, Clone)]
+pub struct AppStream {
+    /// The node's `object.serial`: what pipewiresrc's `target-object` takes,
+    /// and never reused, so a restarted app has a new one.
+    pub serial: u64,
+    /// `application.name`, e.g. "Firefox" (else the node's name).
+    pub app: String,
+    /// `application.process.binary`, e.g. "firefox"; may be empty.
+    pub binary: String,
+}
+
+/// Every stream an application is playing right now.
+pub fn streams() -> Result<Vec<AppStream>> {
+    let out = Command::new("pw-dump").output().context("run pw-dump (install pipewire-bin / pipewire-utils)")?;
+    if !out.status.success() {
+        bail!("pw-dump failed: {}", String::from_utf8_lossy(&out.stderr).trim());
+    }
+    let objects: Vec<Value> = serde_json::from_slice(&out.stdout).context("pw-dump printed no JSON")?;
+    Ok(objects.iter().filter_map(stream).collect())
+}
+
+fn stream(object: &Value) -> Option<AppStream> {
+    let props = &object["info"]["props"];
+    if object["type"] != "PipeWire:Interface:Node" || props["media.class"] != "Stream/Output/Audio" {
+        return None;
+    }
+    let text = |key: &str| props[key].as_str().map(str::to_string);
+    Some(AppStream {
+        serial: props["object.serial"].as_u64().or_else(|| object["id"].as_u64())?,
+        app: text("application.name").or_else(|| text("node.name"))?,
+        binary: text("application.process.binary").unwrap_or_default(),
+    })
+}
+
+/// The stream of the app `query` names, by (part of) its name or binary,
+/// ignoring case ("firefox", "spotify"): its newest, when it plays several.
+/// None when no such app is playing; an error listing them when several apps
+/// match (and none is named exactly that).
+pub fn find<'a>(query: &str, streams: &'a [AppStream]) -> Result<Option<&'a AppStream>> {
+    let q = query.to_lowercase();
+    let named = |s: &AppStream, exact: bool| {
+        [&s.app, &s.binary].into_iter().map(|n| n.to_lowercase()).any(|n| if exact { n == q } else { n.contains(&q) })
+    };
+    let mut matches: Vec<&AppStream> = streams.iter().filter(|s| named(s, false)).collect();
+    if matches.iter().any(|s| named(s, true)) {
+        matches.retain(|s| named(s, true));
+    }
+    let mut apps: Vec<&str> = matches.iter().map(|s| s.app.as_str()).collect();
+    apps.sort_unstable();
+    apps.dedup();
+    if apps.len() > 1 {
+        bail!("'{query}' matches {} apps: {}", apps.len(), apps.join(", "));
+    }
+    Ok(matches.into_iter().max_by_key(|s| s.serial))
+}
@@ -0,0 +1,88 @@
+// Threads a pipeline runs beside it, on sockets GStreamer doesn't own (QUIC,
+// WebRTC signaling, VBAN, Scream, HTTP). Each looks at its `StopFlag` at least
+// every `POLL`; stopping the pipeline raises the flag and waits for them, so
+// their ports are free again by the time a new pipeline wants them.
+use anyhow::Result;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// Longest a background thread blocks on a socket before looking at its flag.
+pub(crate) const POLL: Duration = Duration::from_millis(200);
+
+/// The threads of one pipeline. Stopped when dropped.
+#[derive(Default)]
+pub(crate) struct Tasks {
+    stop: StopFlag,
+    threads: Mutex<Vec<JoinHandle<()>>>,
+    spawned: AtomicBool,
+}
+
+/// Raised once the threads' pipeline has stopped.
+#[derive(Clone, Default)]
+pub(crate) struct StopFlag(Arc<AtomicBool>);
+
+impl StopFlag {
+    pub(crate) fn is_set(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    /// Sleep for `d`, waking early if raised. False once raised.
+    pub(crate) fn sleep(&self, d: Duration) -> bool {
+        let until = std::time::Instant::now() + d;
+        while !self.is_set() {
+            let left = until.saturating_duration_since(std::time::Instant::now());
+            if left.is_zero() {
+                return true;
+            }
+            std::thread::sleep(left.min(POLL));
+        }
+        false
+    }
+
+    /// Resolves once raised, for threads running an async runtime.
+    pub(crate) async fn wait(&self) {
+        while !self.is_set() {
+            tokio::time::sleep(POLL).await;
+        }
+    }
+}
+
+impl Tasks {
+    /// Run `f` on a thread of its own until it sees its flag raised.
+    pub(crate) fn spawn<F>(&self, name: &str, f: F) -> Result<()>
+    where
+        F: FnOnce(StopFlag) + Send + 'static,
+    {
+        let stop = self.stop.clone();
+        let thread = std::thread::Builder::new().name(name.to_string()).spawn(move || f(stop))?;
+        self.threads.lock().unwrap().push(thread);
+        self.spawned.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Whether threads were started and have since been stopped: such a
+    /// pipeline can't simply be set playing again.
+    pub(crate) fn spent(&self) -> bool {
+        self.stop.is_set() && self.spawned.load(Ordering::SeqCst)
+    }
+
+    /// Raise the flag and wait for every thread to finish.
+    pub(crate) fn stop(&self) {
+        self.stop.0.store(true, Ordering::SeqCst);
+        let threads = std::mem::take(&mut *self.threads.lock().unwrap());
+        for thread in threads {
+            // Dropped from one of its own threads: it ends right after
+            if thread.thread().id() != std::thread::current().id() {
+                let _ = thread.join();
+            }
+        }
+    }
+}
+
+impl Drop for Tasks {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
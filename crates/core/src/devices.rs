@@ -1,3 +1,176 @@
-// In v1 we rely on device names passed from CLI.
-// Later: enumerate GStreamer device providers and auto-pick by friendly name.
-// macOS: "BlackHole 2ch" for capture when mirroring system audio.
+// Audio devices as GStreamer's DeviceMonitor sees them, with the ID each one
+// takes on the command line: the pulsesrc/pulsesink `device` name on Linux
+// (system audio is captured from a `.monitor` source), the CoreAudio device
+// index on macOS. A device can also be picked by (part of) its name, see
+// `find`. Once `watch` has started a DeviceMonitor for good, devices are
+// listed from it, and what's plugged in or out is reported as it happens.
+use anyhow::{Context, Result, bail};
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use std::sync::OnceLock;
+use tokio::sync::mpsc;
+
+#[derive(Debug, Clone)]
+pub struct AudioDevice {
+    /// What `--capture-device` / `--output-device` expect.
+    pub id: String,
+    /// Human-readable name.
+    pub name: String,
+    /// Linux: a monitor of an output (i.e. system audio), not a microphone.
+    pub monitor: bool,
+    /// A playback sink, not a capture source.
+    pub sink: bool,
+}
+
+/// A device plugged in or out, as `watch` reports it.
+#[derive(Debug, Clone)]
+pub enum DeviceChange {
+    Added(AudioDevice),
+    Removed(AudioDevice),
+}
+
+/// The DeviceMonitor `watch` keeps running.
+static LIVE: OnceLock<gst::DeviceMonitor> = OnceLock::new();
+
+/// Capture sources. Call after `init_gst`.
+pub fn sources() -> Result<Vec<AudioDevice>> {
+    list("Audio/Source")
+}
+
+/// Playback sinks. Call after `init_gst`.
+pub fn sinks() -> Result<Vec<AudioDevice>> {
+    list("Audio/Sink")
+}
+
+/// Keep a DeviceMonitor running from now on: devices are listed from it
+/// rather than probed each time, and those plugged in or out arrive on the
+/// returned channel (some providers report the ones already there too). Call
+/// once, after `init_gst`.
+pub fn watch() -> Result<mpsc::UnboundedReceiver<DeviceChange>> {
+    let mon = gst::DeviceMonitor::new();
+    mon.add_filter(Some("Audio/Source"), Some(&gst::Caps::new_any()));
+    mon.add_filter(Some("Audio/Sink"), Some(&gst::Caps::new_any()));
+    let bus = mon.bus();
+    mon.start().context("device monitor failed to start")?;
+    LIVE.set(mon).map_err(|_| anyhow::anyhow!("devices are watched already"))?;
+    let (tx, rx) = mpsc::unbounded_channel();
+    std::thread::spawn(move || {
+        for msg in bus.iter_timed(gst::ClockTime::NONE) {
+            let change = match msg.view() {
+                gst::MessageView::DeviceAdded(m) => DeviceChange::Added(describe(&m.device())),
+                gst::MessageView::DeviceRemoved(m) => DeviceChange::Removed(describe(&m.device())),
+                _ => continue,
+            };
+            if tx.send(change).is_err() {
+                return;
+            }
+        }
+    });
+    Ok(rx)
+}
+
+/// Whether a pipeline error (as `Sender::error` / `Receiver::error` give it)
+/// is pulsesrc/pulsesink losing the sound server: PulseAudio or PipeWire
+/// restarting, or gone.
+pub fn is_disconnect(error: &str) -> bool {
+    error.contains("GstPulse") && error.contains("Disconnected")
+}
+
+/// The device `query` picks: the one with that ID, else the one whose ID or
+/// name contains it, ignoring case ("usb", "MacBook Pro Speakers"). None when
+/// nothing matches; an error listing them when several do (and none is
+/// named exactly that). A number is an index (macOS) and only ever an ID.
+pub fn find(query: &str, devices: &[AudioDevice]) -> Result<Option<String>> {
+    if devices.iter().any(|d| d.id == query) {
+        return Ok(Some(query.to_string()));
+    }
+    if query.parse::<i32>().is_ok() {
+        return Ok(None);
+    }
+    let q = query.to_lowercase();
+    let matches: Vec<&AudioDevice> = devices
+        .iter()
+        .filter(|d| d.id.to_lowercase().contains(&q) || d.name.to_lowercase().contains(&q))
+        .collect();
+    let exact: Vec<&AudioDevice> = matches.iter().copied().filter(|d| d.name.to_lowercase() == q).collect();
+    match (matches.as_slice(), exact.as_slice()) {
+        ([], _) => Ok(None),
+        ([one], _) | (_, [one]) => Ok(Some(one.id.clone())),
+        _ => bail!("'{query}' matches {} devices:\n{}", matches.len(), listing(&matches)),
+    }
+}
+
+/// macOS: the CoreAudio index osxaudiosrc/osxaudiosink take for `query`, an
+/// index or part of a device's name (see `find`) among `list()`. A device
+/// gets another index when it's replugged, so names are looked up each time.
+pub fn index(query: &str, list: fn() -> Result<Vec<AudioDevice>>) -> Result<i32> {
+    if let Ok(index) = query.parse() {
+        return Ok(index);
+    }
+    let id = find(query, &list()?)?.with_context(|| format!("no audio device matches '{query}'"))?;
+    id.parse().with_context(|| format!("device '{id}' has no index"))
+}
+
+/// One `  ID  (name)` line per device, to choose from.
+pub fn listing(devices: &[&AudioDevice]) -> String {
+    let lines: Vec<String> = devices.iter().map(|d| format!("  {}  ({})", d.id, d.name)).collect();
+    lines.join("\n")
+}
+
+/// The ID device `id` goes by now. PipeWire can rename devices when it
+/// restarts or a card is replugged (a `.2` suffix, another profile), so a
+/// device no longer listed is matched on its card: the name without the
+/// profile and counter (`alsa_output.usb-Foo-00` for
+/// `alsa_output.usb-Foo-00.analog-stereo.2`). None when nothing matches.
+pub fn follow(id: &str, devices: &[AudioDevice]) -> Option<String> {
+    if devices.iter().any(|d| d.id == id) {
+        return Some(id.to_string());
+    }
+    let monitor = id.ends_with(".monitor");
+    devices
+        .iter()
+        .find(|d| d.id.ends_with(".monitor") == monitor && card(&d.id) == card(id))
+        .map(|d| d.id.clone())
+}
+
+fn card(id: &str) -> &str {
+    let id = id.strip_suffix(".monitor").unwrap_or(id);
+    let id = match id.rsplit_once('.') {
+        Some((head, n)) if !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()) => head,
+        _ => id,
+    };
+    id.rsplit_once('.').map_or(id, |(head, _)| head)
+}
+
+fn list(class: &str) -> Result<Vec<AudioDevice>> {
+    if let Some(mon) = LIVE.get() {
+        return Ok(mon.devices().iter().filter(|d| d.has_classes(class)).map(describe).collect());
+    }
+    let mon = gst::DeviceMonitor::new();
+    mon.add_filter(Some(class), Some(&gst::Caps::new_any()));
+    mon.start().context("device monitor failed to start")?;
+    let devices = mon.devices().iter().map(describe).collect();
+    mon.stop();
+    Ok(devices)
+}
+
+fn describe(d: &gst::Device) -> AudioDevice {
+    let name = d.display_name().to_string();
+    let props = d.properties();
+    let prop = |key: &str| props.as_ref().and_then(|p| p.get::<String>(key).ok());
+
+    // osxaudiosrc/osxaudiosink take the CoreAudio device ID
+    let index = d.has_property("device-id", None).then(|| d.property::<i32>("device-id").to_string());
+    // pulsesrc/pulsesink take the device (node) name, e.g. "alsa_output...analog-stereo.monitor"
+    let id = index
+        .or_else(|| prop("device"))
+        .or_else(|| prop("node.name"))
+        .or_else(|| prop("device.name"))
+        .unwrap_or_else(|| name.clone());
+
+    let monitor = [Some(name.clone()), Some(id.clone()), prop("device.description"), prop("device.class")]
+        .into_iter()
+        .flatten()
+        .any(|s| s.to_lowercase().contains("monitor"));
+    AudioDevice { id, name, monitor, sink: d.has_classes("Audio/Sink") }
+}
@@ -1,4 +1,128 @@
-// Stub: In v2 read rtpjitterbuffer stats or queue level and adjust an
-// upstream resampler ratio ±0.1% to keep target fill. With GStreamer,
-// you can insert "audioresample" with dynamic "rate" (via caps renegotiation)
-// or implement tiny rate pulls by periodic drop/dupe of 1 sample per N seconds.
+// Clock drift compensation on the receiver. Without a shared clock the
+// sender's crystal and our sound card run at slightly different rates (tens of
+// ppm). The jitterbuffer (slave mode) keeps each stream's timestamps on our
+// clock, so a stream then carries a little more or less audio than the time
+// its timestamps cover; left alone, the surplus piles up as latency or the
+// mixer and sink throw it away in audible jumps. Here each stream's real rate
+// is measured over a long window (audio duration against timestamp span) and
+// restated in the caps its resampler sees, so it converts e.g. 48010 Hz to
+// 48000 and playout stays where the jitterbuffer put it.
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use std::sync::{Arc, Mutex};
+use tracing::info;
+
+/// Largest correction applied; crystals are well within this of each other.
+const MAX_PPM: f64 = 1000.0;
+/// Audio measured before the first correction.
+const WARMUP: gst::ClockTime = gst::ClockTime::from_seconds(30);
+/// Audio between re-measurements.
+const UPDATE: gst::ClockTime = gst::ClockTime::from_seconds(10);
+/// A timestamp this far from where the previous buffer ended is a gap (loss
+/// without concealment) or a jump, not drift.
+const GAP: gst::ClockTime = gst::ClockTime::from_mseconds(10);
+
+#[derive(Default)]
+struct Track {
+    /// Caps as the decoder states them.
+    nominal: Option<gst::Caps>,
+    /// Window: where it started, the audio seen since and where it ends.
+    start: Option<gst::ClockTime>,
+    audio: gst::ClockTime,
+    end: gst::ClockTime,
+    /// `audio` when the rate was last measured.
+    measured: gst::ClockTime,
+    /// Rate restated to the resampler, if corrected.
+    rate: Option<i32>,
+    /// Our own caps event is on its way through the probe.
+    resending: bool,
+}
+
+impl Track {
+    fn restart(&mut self, pts: gst::ClockTime) {
+        self.start = Some(pts);
+        self.audio = gst::ClockTime::ZERO;
+        self.measured = gst::ClockTime::ZERO;
+    }
+
+    /// `nominal` at `rate` instead of its own.
+    fn restated(&self) -> Option<gst::Caps> {
+        let mut caps = self.nominal.clone()?;
+        if let Some(rate) = self.rate {
+            caps.make_mut().set("rate", rate);
+        }
+        Some(caps)
+    }
+}
+
+/// Measure the audio leaving `pad` (a decoder's src pad, upstream of an
+/// audioresample) and correct for the sender's clock running fast or slow.
+/// `id` labels the log.
+pub(crate) fn attach(pad: &gst::Pad, id: &str) {
+    let id = id.to_string();
+    let track = Arc::new(Mutex::new(Track::default()));
+    pad.add_probe(gst::PadProbeType::BUFFER | gst::PadProbeType::EVENT_DOWNSTREAM, move |pad, info| {
+        match &mut info.data {
+            Some(gst::PadProbeData::Event(event)) => {
+                let mut t = track.lock().unwrap();
+                let caps = match event.view() {
+                    gst::EventView::Caps(caps) if !t.resending => Some(caps.caps_owned()),
+                    gst::EventView::FlushStop(_) | gst::EventView::Segment(_) => {
+                        t.start = None;
+                        None
+                    }
+                    _ => None,
+                };
+                // The decoder (re)states its caps: keep our correction on them
+                if let Some(caps) = caps {
+                    t.nominal = Some(caps);
+                    if let Some(restated) = t.restated().filter(|_| t.rate.is_some()) {
+                        *event = gst::event::Caps::new(&restated);
+                    }
+                }
+            }
+            Some(gst::PadProbeData::Buffer(buffer)) => {
+                let (Some(pts), Some(duration)) = (buffer.pts(), buffer.duration()) else {
+                    return gst::PadProbeReturn::Ok;
+                };
+                let mut t = track.lock().unwrap();
+                match t.start {
+                    None => t.restart(pts),
+                    // Lost audio isn't drift: count the gap as if it had arrived
+                    Some(_) if pts > t.end + GAP => {
+                        let gap = pts - t.end;
+                        t.audio += gap;
+                    }
+                    Some(_) if pts + GAP < t.end => t.restart(pts),
+                    Some(_) => {}
+                }
+                t.audio += duration;
+                t.end = pts + duration;
+                if t.audio < WARMUP || t.audio < t.measured + UPDATE {
+                    return gst::PadProbeReturn::Ok;
+                }
+                t.measured = t.audio;
+                let span = t.end.saturating_sub(t.start.unwrap_or(pts));
+                if span.is_zero() {
+                    return gst::PadProbeReturn::Ok;
+                }
+                // More audio than time: the sender's clock is fast, so play it faster
+                let ppm = ((t.audio.nseconds() as f64 / span.nseconds() as f64 - 1.0) * 1e6).clamp(-MAX_PPM, MAX_PPM);
+                let base = t.nominal.as_ref().and_then(|c| c.structure(0)?.get::<i32>("rate").ok()).unwrap_or(0);
+                let rate = (base as f64 * (1.0 + ppm / 1e6)).round() as i32;
+                if base == 0 || t.rate.unwrap_or(base) == rate {
+                    return gst::PadProbeReturn::Ok;
+                }
+                t.rate = Some(rate);
+                let Some(restated) = t.restated() else { return gst::PadProbeReturn::Ok };
+                t.resending = true;
+                drop(t);
+                info!(target: "drift", "{id}: sender clock {ppm:+.0} ppm, resampling from {rate} Hz");
+                pad.push_event(gst::event::Caps::new(&restated));
+                track.lock().unwrap().resending = false;
+            }
+            _ => {}
+        }
+        gst::PadProbeReturn::Ok
+    });
+}
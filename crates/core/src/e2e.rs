@@ -0,0 +1,164 @@
+// End-to-end latency measurement (--measure-latency on both ends). The sender
+// stamps every RTP packet with the time its audio was captured, in a one-byte
+// RTP header extension; the receiver reads it when the packet arrives and
+// again before decoding, where the packet's timestamp plus the pipeline
+// latency says when it will come out of the speaker. Times are on the shared
+// clock when there is one (--clock), else wall-clock time, so without a
+// shared clock the result is only as good as the two machines' NTP sync.
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use gstreamer_rtp as gst_rtp;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::info;
+
+/// One-byte header extension ID for the capture time (8 bytes, ns, big endian).
+const CAPTURE_TIME_EXT: u8 = 7;
+/// Weight of each new sample in the running averages.
+const SMOOTHING: f64 = 0.05;
+/// How often the pipeline latency is re-read, and the figures logged.
+const LATENCY_INTERVAL: Duration = Duration::from_secs(2);
+const REPORT_EVERY: u32 = 5;
+
+/// Capture-to-network and capture-to-speaker latency of one sender's stream.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct E2eLatency {
+    pub ssrc: u32,
+    /// Capture until the packet reached us (running average).
+    pub network_ms: f64,
+    /// Capture until it's played (running average).
+    pub total_ms: f64,
+    /// Lowest and highest capture-to-speaker figure since the last report.
+    pub min_ms: f64,
+    pub max_ms: f64,
+}
+
+#[derive(Default)]
+struct State {
+    streams: HashMap<u32, E2eLatency>,
+    /// Pipeline latency: how long after its running time a buffer is played.
+    latency: gst::ClockTime,
+}
+
+/// Figures per SSRC, shared between the receiver's probes and its owner.
+#[derive(Clone, Default)]
+pub(crate) struct Table(Arc<Mutex<State>>);
+
+impl Table {
+    pub(crate) fn snapshot(&self) -> Vec<E2eLatency> {
+        let mut out: Vec<_> = self.0.lock().unwrap().streams.values().copied().collect();
+        out.sort_by_key(|e| e.ssrc);
+        out
+    }
+}
+
+/// `at` (a time on `pipeline`'s clock) as ns on the common timeline: the
+/// shared clock itself, or the wall clock.
+fn reference(pipeline: &gst::Pipeline, at: gst::ClockTime, shared: bool) -> Option<i64> {
+    if shared {
+        return Some(at.nseconds() as i64);
+    }
+    let now = pipeline.clock()?.time()?;
+    let wall = SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_nanos() as i64;
+    Some(wall - (now.nseconds() as i64 - at.nseconds() as i64))
+}
+
+/// Stamp the RTP packets leaving `pad` (a payloader's src) with their
+/// capture time.
+pub(crate) fn stamp(pad: &gst::Pad, pipeline: &gst::Pipeline, shared: bool) {
+    let pipeline = pipeline.downgrade();
+    pad.add_probe(gst::PadProbeType::BUFFER, move |_, info| {
+        let Some(pipeline) = pipeline.upgrade() else { return gst::PadProbeReturn::Ok };
+        let Some(gst::PadProbeData::Buffer(buffer)) = &mut info.data else { return gst::PadProbeReturn::Ok };
+        let captured = buffer.pts().zip(pipeline.base_time()).and_then(|(pts, base)| reference(&pipeline, base + pts, shared));
+        if let Some(captured) = captured {
+            if let Ok(mut rtp) = gst_rtp::RTPBuffer::from_buffer_writable(buffer.make_mut()) {
+                let _ = rtp.add_extension_onebyte_header(CAPTURE_TIME_EXT, &captured.to_be_bytes());
+            }
+        }
+        gst::PadProbeReturn::Ok
+    });
+}
+
+/// SSRC and capture time of a stamped RTP packet.
+fn captured(buffer: &gst::BufferRef) -> Option<(u32, i64)> {
+    let rtp = gst_rtp::RTPBuffer::from_buffer_readable(buffer).ok()?;
+    let ext = rtp.extension_onebyte_header(CAPTURE_TIME_EXT, 0)?;
+    Some((rtp.ssrc(), i64::from_be_bytes(ext.try_into().ok()?)))
+}
+
+/// Time the RTP packets passing `pad` (before the jitterbuffer) arrive.
+pub(crate) fn attach_arrival(pad: &gst::Pad, pipeline: &gst::Pipeline, shared: bool, table: &Table) {
+    let (pipeline, table) = (pipeline.downgrade(), table.clone());
+    pad.add_probe(gst::PadProbeType::BUFFER, move |_, info| {
+        let Some(pipeline) = pipeline.upgrade() else { return gst::PadProbeReturn::Ok };
+        let Some(gst::PadProbeData::Buffer(buffer)) = &info.data else { return gst::PadProbeReturn::Ok };
+        let now = pipeline.clock().and_then(|c| c.time()).and_then(|t| reference(&pipeline, t, shared));
+        if let (Some((ssrc, captured)), Some(now)) = (captured(buffer), now) {
+            let ms = (now - captured) as f64 / 1e6;
+            let mut state = table.0.lock().unwrap();
+            let e = state.streams.entry(ssrc).or_insert(E2eLatency { ssrc, network_ms: ms, ..Default::default() });
+            e.network_ms += (ms - e.network_ms) * SMOOTHING;
+        }
+        gst::PadProbeReturn::Ok
+    });
+}
+
+/// Work out when the RTP packets passing `pad` (a stream's depayloader
+/// input, after the jitterbuffer) will be played.
+pub(crate) fn attach_playout(pad: &gst::Pad, pipeline: &gst::Pipeline, shared: bool, table: &Table) {
+    let (pipeline, table) = (pipeline.downgrade(), table.clone());
+    pad.add_probe(gst::PadProbeType::BUFFER, move |_, info| {
+        let Some(pipeline) = pipeline.upgrade() else { return gst::PadProbeReturn::Ok };
+        let Some(gst::PadProbeData::Buffer(buffer)) = &info.data else { return gst::PadProbeReturn::Ok };
+        let Some((ssrc, captured)) = captured(buffer) else { return gst::PadProbeReturn::Ok };
+        let mut state = table.0.lock().unwrap();
+        let latency = state.latency;
+        let played = buffer
+            .pts()
+            .zip(pipeline.base_time())
+            .and_then(|(pts, base)| reference(&pipeline, base + pts + latency, shared));
+        if let Some(played) = played {
+            let ms = (played - captured) as f64 / 1e6;
+            let e = state.streams.entry(ssrc).or_insert(E2eLatency { ssrc, total_ms: ms, min_ms: ms, max_ms: ms, ..Default::default() });
+            e.total_ms += (ms - e.total_ms) * SMOOTHING;
+            e.min_ms = if e.min_ms == 0.0 { ms } else { e.min_ms.min(ms) };
+            e.max_ms = e.max_ms.max(ms);
+        }
+        gst::PadProbeReturn::Ok
+    });
+}
+
+/// Keep the pipeline latency the playout probes add up to date, log the
+/// figures and forget streams that stopped.
+pub(crate) fn spawn_reporter(pipeline: &gst::Pipeline, table: &Table) {
+    let (pipeline, table) = (pipeline.downgrade(), table.clone());
+    std::thread::spawn(move || {
+        let mut seen: HashMap<u32, f64> = HashMap::new();
+        for round in 1u32.. {
+            std::thread::sleep(LATENCY_INTERVAL);
+            let Some(pipeline) = pipeline.upgrade() else { break };
+            let mut query = gst::query::Latency::new();
+            let latency = pipeline.query(&mut query).then(|| query.result()).filter(|(live, _, _)| *live).map(|(_, min, _)| min);
+            let mut state = table.0.lock().unwrap();
+            if let Some(latency) = latency {
+                state.latency = latency;
+            }
+            if round % REPORT_EVERY != 0 {
+                continue;
+            }
+            // A stream whose figures didn't move since the last report has ended
+            state.streams.retain(|ssrc, e| seen.get(ssrc) != Some(&e.total_ms));
+            seen = state.streams.iter().map(|(ssrc, e)| (*ssrc, e.total_ms)).collect();
+            for e in state.streams.values_mut() {
+                info!(
+                    target: "e2e",
+                    "ssrc {:08x}: capture→speaker {:.1} ms ({:.1}-{:.1}), capture→network {:.1} ms",
+                    e.ssrc, e.total_ms, e.min_ms, e.max_ms, e.network_ms
+                );
+                (e.min_ms, e.max_ms) = (e.total_ms, e.total_ms);
+            }
+        }
+    });
+}
@@ -0,0 +1,84 @@
+// Source gate: the receiver only plays RTP/RTCP from addresses the daemon
+// has admitted (peers that proved a paired identity on the handshake port),
+// and from those only the session SSRC each announced in its signed proof.
+// Everything else is dropped right after the socket, before rtpbin sees it.
+//
+// This filters, it doesn't authenticate: the SSRC travels in the clear in
+// every RTP header, so a host that can see the stream can copy it and spoof
+// the address too. Only `--srtp` authenticates the media packets themselves.
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use gstreamer_net as gst_net;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, RwLock};
+use tracing::{info, warn};
+
+use crate::session::Sessions;
+
+/// Admitted source addresses. Cheap to clone; admissions are counted so two
+/// sessions from one host can come and go independently.
+#[derive(Clone, Default, Debug)]
+pub struct SourceGate(Arc<RwLock<HashMap<IpAddr, usize>>>);
+
+impl SourceGate {
+    pub fn admit(&self, ip: IpAddr) {
+        let ip = ip.to_canonical();
+        *self.0.write().unwrap().entry(ip).or_default() += 1;
+        info!(target: "gate", "admitted {ip}");
+    }
+
+    pub fn release(&self, ip: IpAddr) {
+        let ip = ip.to_canonical();
+        let mut table = self.0.write().unwrap();
+        if let Some(n) = table.get_mut(&ip) {
+            *n -= 1;
+            if *n == 0 {
+                table.remove(&ip);
+                info!(target: "gate", "released {ip}");
+            }
+        }
+    }
+
+    pub fn allows(&self, ip: IpAddr) -> bool {
+        self.0.read().unwrap().contains_key(&ip.to_canonical())
+    }
+}
+
+/// Drop buffers on `src`'s src pad whose sender isn't admitted by `gate`, or
+/// (with `sessions`) whose SSRC isn't a session it announced. `src` must be a
+/// udpsrc (it tags buffers with the sender's address); `rtcp` picks where the
+/// SSRC sits in the header.
+pub(crate) fn attach(src: &gst::Element, gate: &SourceGate, sessions: Option<&Sessions>, rtcp: bool) {
+    let Some(pad) = src.static_pad("src") else { return };
+    let gate = gate.clone();
+    let sessions = sessions.cloned();
+    let at = if rtcp { 4 } else { 8 };
+    let mut last_refused = None;
+    pad.add_probe(gst::PadProbeType::BUFFER, move |_pad, info| {
+        let Some(buf) = info.buffer() else { return gst::PadProbeReturn::Ok };
+        let Some(meta) = buf.meta::<gst_net::NetAddressMeta>() else { return gst::PadProbeReturn::Drop };
+        let Ok(addr) = meta.addr().downcast::<gst_net::gio::InetSocketAddress>() else {
+            return gst::PadProbeReturn::Drop;
+        };
+        let ip = IpAddr::from(addr.address());
+        let admitted = gate.allows(ip);
+        let announced = sessions.as_ref().is_none_or(|s| {
+            let mut ssrc = [0u8; 4];
+            buf.copy_to_slice(at, &mut ssrc).is_ok() && s.is_live(ip, u32::from_be_bytes(ssrc))
+        });
+        if admitted && announced {
+            return gst::PadProbeReturn::Ok;
+        }
+        // One line per new offender, not one per packet
+        if last_refused != Some(ip) {
+            if admitted {
+                warn!(target: "receiver", "dropping packets from {ip} under an SSRC it didn't announce");
+            } else {
+                warn!(target: "receiver", "dropping packets from unpaired {ip}");
+            }
+            last_refused = Some(ip);
+        }
+        gst::PadProbeReturn::Drop
+    });
+}
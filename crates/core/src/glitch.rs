@@ -0,0 +1,162 @@
+// Glitch detection on the receiver: each stream's decoded PCM, as it leaves
+// opusdec, is checked for what people hear as "it sounds glitchy":
+//   click     consecutive samples jumping by far more than the signal around
+//             them moves (a discontinuity in the waveform)
+//   dropout   a run of digital silence with signal on both sides of it (a
+//             hole, e.g. loss without concealment)
+// Both are counted per sender (SSRC) and logged, at most a line a second per
+// stream, so tuning the jitterbuffer, FEC or PLC has a number to move.
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// A step this big (of full scale) can be a click…
+const CLICK_MIN: f32 = 0.25;
+/// …when it's this many times the signal's usual step.
+const CLICK_RATIO: f32 = 12.0;
+/// Weight of each sample in the usual step and level (about 20 ms at 48 kHz).
+const SMOOTHING: f32 = 1.0 / 1000.0;
+/// After a click, samples that can't count as another.
+const CLICK_HOLD: u32 = 480;
+/// Zero frames making a dropout (5 ms at 48 kHz)…
+const DROPOUT_FRAMES: u32 = 240;
+/// …when the audio before it was louder than this (about -50 dBFS).
+const SIGNAL_FLOOR: f32 = 0.003;
+const LOG_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Glitches heard in one sender's stream.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Glitches {
+    pub ssrc: u32,
+    pub clicks: u64,
+    pub dropouts: u64,
+}
+
+struct Detector {
+    /// Per channel: previous sample and usual step.
+    prev: Vec<f32>,
+    step: Vec<f32>,
+    level: f32,
+    hold: u32,
+    zeros: u32,
+    /// Level before the current zero run.
+    level_before: f32,
+}
+
+impl Detector {
+    fn new(channels: usize) -> Self {
+        Self { prev: vec![0.0; channels], step: vec![0.0; channels], level: 0.0, hold: 0, zeros: 0, level_before: 0.0 }
+    }
+
+    /// Clicks and dropouts in interleaved S16 `samples`.
+    fn feed(&mut self, samples: &[u8]) -> (u64, u64) {
+        let channels = self.prev.len();
+        let (mut clicks, mut dropouts) = (0, 0);
+        for frame in samples.chunks_exact(channels * 2) {
+            let mut silent = true;
+            let mut clicked = false;
+            for (c, s) in frame.chunks_exact(2).enumerate() {
+                let x = f32::from(i16::from_le_bytes([s[0], s[1]])) / 32768.0;
+                silent &= x == 0.0;
+                let d = (x - self.prev[c]).abs();
+                clicked |= d > CLICK_MIN && d > self.step[c] * CLICK_RATIO;
+                self.step[c] += (d - self.step[c]) * SMOOTHING;
+                self.prev[c] = x;
+                if self.zeros == 0 {
+                    self.level += (x.abs() - self.level) * SMOOTHING / channels as f32;
+                }
+            }
+            if self.hold > 0 {
+                self.hold -= 1;
+            } else if clicked {
+                clicks += 1;
+                self.hold = CLICK_HOLD;
+            }
+            if silent {
+                if self.zeros == 0 {
+                    self.level_before = self.level;
+                }
+                self.zeros += 1;
+            } else {
+                if self.zeros >= DROPOUT_FRAMES && self.level_before > SIGNAL_FLOOR {
+                    dropouts += 1;
+                }
+                self.zeros = 0;
+            }
+        }
+        (clicks, dropouts)
+    }
+}
+
+struct Stream {
+    glitches: Glitches,
+    /// Glitches not yet logged, and when the last line went out.
+    unlogged: (u64, u64),
+    logged: Option<Instant>,
+}
+
+/// Counts per SSRC, shared between the decoders' probes and the receiver.
+#[derive(Clone, Default)]
+pub(crate) struct Table(Arc<Mutex<HashMap<u32, Stream>>>);
+
+impl Table {
+    pub(crate) fn snapshot(&self) -> Vec<Glitches> {
+        let mut out: Vec<_> = self.0.lock().unwrap().values().map(|s| s.glitches).collect();
+        out.sort_by_key(|g| g.ssrc);
+        out
+    }
+
+    fn add(&self, ssrc: u32, id: &str, clicks: u64, dropouts: u64) {
+        let mut streams = self.0.lock().unwrap();
+        let s = streams.entry(ssrc).or_insert_with(|| Stream {
+            glitches: Glitches { ssrc, ..Default::default() },
+            unlogged: (0, 0),
+            logged: None,
+        });
+        s.glitches.clicks += clicks;
+        s.glitches.dropouts += dropouts;
+        s.unlogged = (s.unlogged.0 + clicks, s.unlogged.1 + dropouts);
+        if s.unlogged == (0, 0) || s.logged.is_some_and(|t| t.elapsed() < LOG_INTERVAL) {
+            return;
+        }
+        let (clicks, dropouts) = std::mem::take(&mut s.unlogged);
+        s.logged = Some(Instant::now());
+        warn!(
+            target: "receiver",
+            "{id}: glitch: {clicks} click(s), {dropouts} dropout(s) ({} and {} in all)",
+            s.glitches.clicks,
+            s.glitches.dropouts
+        );
+    }
+}
+
+/// Check the PCM leaving `pad` (a decoder's src pad) for stream `ssrc`,
+/// labelled `id` in the log.
+pub(crate) fn attach(pad: &gst::Pad, table: &Table, ssrc: u32, id: &str) {
+    let (table, id) = (table.clone(), id.to_string());
+    let detector: Mutex<Option<Detector>> = Mutex::new(None);
+    pad.add_probe(gst::PadProbeType::BUFFER, move |pad, info| {
+        let Some(gst::PadProbeData::Buffer(buffer)) = &info.data else { return gst::PadProbeReturn::Ok };
+        let Some(caps) = pad.current_caps() else { return gst::PadProbeReturn::Ok };
+        let Some(s) = caps.structure(0) else { return gst::PadProbeReturn::Ok };
+        // opusdec gives interleaved S16
+        let channels = s.get::<i32>("channels").unwrap_or(0).max(0) as usize;
+        if channels == 0 || s.get::<&str>("format").ok() != Some("S16LE") {
+            return gst::PadProbeReturn::Ok;
+        }
+        let Ok(map) = buffer.map_readable() else { return gst::PadProbeReturn::Ok };
+        let mut detector = detector.lock().unwrap();
+        let d = match detector.as_mut() {
+            Some(d) if d.prev.len() == channels => d,
+            _ => detector.insert(Detector::new(channels)),
+        };
+        let (clicks, dropouts) = d.feed(&map);
+        if clicks + dropouts > 0 {
+            table.add(ssrc, &id, clicks, dropouts);
+        }
+        gst::PadProbeReturn::Ok
+    });
+}
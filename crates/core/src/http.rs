@@ -0,0 +1,113 @@
+// HTTP streaming output: the receiver's mix re-encoded as Ogg/Opus and served
+// over plain HTTP, so any phone or browser on the LAN can listen.
+//
+//   GET /            tiny page with an <audio> player
+//   GET /stream.ogg  the live stream
+//
+// Connections are accepted here and then handed to multifdsink, which sends
+// the Ogg stream headers to every new client before live data. The accept
+// loop is one of the pipeline's `Tasks`, so the port is freed when it stops.
+use anyhow::{Context, Result};
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::net::{IpAddr, TcpListener, TcpStream};
+use std::os::fd::{FromRawFd, IntoRawFd};
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::background::{POLL, Tasks};
+use crate::pipeline::make_element;
+
+const PAGE: &str = "<!doctype html><meta name=viewport content=\"width=device-width\">\
+<title>audio-bridge</title><body style=\"font-family:sans-serif\"><h3>audio-bridge</h3>\
+<audio controls autoplay src=\"/stream.ogg\"></audio></body>";
+
+/// Branch `tee` into an Ogg/Opus encoder and serve it on `port`.
+pub(crate) fn attach_output(
+    tasks: &Tasks,
+    pipeline: &gst::Pipeline,
+    tee: &gst::Element,
+    bind: IpAddr,
+    port: u16,
+) -> Result<()> {
+    let queue = make_element("queue", "q_http")?;
+    // A slow listener must never stall local playback
+    queue.set_property_from_str("leaky", "downstream");
+    queue.set_property("max-size-time", 200_000_000u64);
+    let convert = make_element("audioconvert", "http_conv")?;
+    let enc = make_element("opusenc", "http_opusenc")?;
+    enc.set_property("bitrate", 128_000i32);
+    let mux = make_element("oggmux", "http_mux")?;
+    let sink = make_element("multifdsink", "http_sink")?;
+    sink.set_property("sync", false);
+    sink.set_property("async", false);
+    // Slow clients are dropped instead of buffered forever
+    sink.set_property_from_str("recover-policy", "latest");
+
+    pipeline.add_many(&[&queue, &convert, &enc, &mux, &sink])?;
+    gst::Element::link_many(&[tee, &queue, &convert, &enc, &mux, &sink])
+        .context("http: link tee → multifdsink")?;
+
+    // multifdsink leaves closing to us
+    sink.connect("client-fd-removed", false, |args| {
+        if let Ok(fd) = args[1].get::<i32>() {
+            drop(unsafe { TcpStream::from_raw_fd(fd) });
+        }
+        None
+    });
+
+    let listener = TcpListener::bind((bind, port)).with_context(|| format!("http: bind :{port}"))?;
+    // Polled, so the thread notices the pipeline stopping
+    listener.set_nonblocking(true)?;
+    let sink_weak = sink.downgrade();
+    tasks.spawn("http", move |stop| {
+        while !stop.is_set() {
+            let stream = match listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    if e.kind() != ErrorKind::WouldBlock {
+                        warn!(target: "http", "accept failed: {e}");
+                    }
+                    std::thread::sleep(POLL);
+                    continue;
+                }
+            };
+            let Some(sink) = sink_weak.upgrade() else { break };
+            if let Err(e) = serve(stream, &sink) {
+                warn!(target: "http", "{e:#}");
+            }
+        }
+    })?;
+    info!(target: "http", "serving Ogg/Opus on http://<this host>:{port}/ (stream at /stream.ogg)");
+    Ok(())
+}
+
+fn serve(mut stream: TcpStream, sink: &gst::Element) -> Result<()> {
+    let peer = stream.peer_addr()?;
+    // Accepted sockets may inherit the listener's non-blocking mode, and a
+    // client that never sends its request mustn't hold up the others (or a stop)
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(Duration::from_secs(2)))?;
+    let mut request = String::new();
+    BufReader::new(&stream).read_line(&mut request)?;
+    let path = request.split_whitespace().nth(1).unwrap_or("/");
+    match path {
+        "/stream.ogg" => {
+            stream.write_all(
+                b"HTTP/1.0 200 OK\r\nContent-Type: audio/ogg\r\nCache-Control: no-cache\r\n\r\n",
+            )?;
+            info!(target: "http", "listener {peer} connected");
+            sink.emit_by_name::<()>("add", &[&stream.into_raw_fd()]);
+        }
+        "/" | "/index.html" => {
+            let resp = format!(
+                "HTTP/1.0 200 OK\r\nContent-Type: text/html\r\nContent-Length: {}\r\n\r\n{PAGE}",
+                PAGE.len()
+            );
+            stream.write_all(resp.as_bytes())?;
+        }
+        _ => stream.write_all(b"HTTP/1.0 404 Not Found\r\nContent-Length: 0\r\n\r\n")?,
+    }
+    Ok(())
+}
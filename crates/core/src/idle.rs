@@ -0,0 +1,102 @@
+// Idle release (--idle-release): when no RTP has arrived for a while the
+// receiver takes its audio sink down to READY, which closes the device (and
+// lets the DAC sleep), and brings it back on the first packet. The pad
+// feeding the sink is held by an IDLE probe meanwhile, so the sink changes
+// state with nothing inside it and upstream just waits. The sink can't be
+// the pipeline clock while it comes and goes; the receiver runs on the
+// system clock instead.
+//
+// Each change is logged and posted on the bus as an application message:
+//   idle, idle=(boolean)true|false
+use anyhow::{Context, Result};
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+/// How often the time since the last packet is checked.
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// How long to wait for the sink's input to go quiet before releasing it.
+const BLOCK_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Watch the RTP passing `arrivals` and release `sink` after `after` without
+/// any. The returned flag says whether the device is released now.
+pub(crate) fn attach(pipeline: &gst::Pipeline, arrivals: &gst::Pad, sink: &gst::Element, after: Duration) -> Result<Arc<AtomicBool>> {
+    let idle = Arc::new(AtomicBool::new(false));
+    let input = sink
+        .static_pad("sink")
+        .and_then(|p| p.peer())
+        .context("idle: the sink isn't linked")?;
+    let last = Arc::new(Mutex::new(Instant::now()));
+    let (wake, woken) = mpsc::channel::<()>();
+
+    let (stamp, flag) = (last.clone(), idle.clone());
+    arrivals.add_probe(gst::PadProbeType::BUFFER | gst::PadProbeType::BUFFER_LIST, move |_pad, _info| {
+        *stamp.lock().unwrap() = Instant::now();
+        if flag.load(Ordering::SeqCst) {
+            let _ = wake.send(());
+        }
+        gst::PadProbeReturn::Ok
+    });
+
+    let (pipeline, sink, flag) = (pipeline.downgrade(), sink.downgrade(), idle.clone());
+    std::thread::spawn(move || {
+        let mut held: Option<gst::PadProbeId> = None;
+        loop {
+            let woke = match woken.recv_timeout(CHECK_INTERVAL) {
+                Ok(()) => true,
+                Err(RecvTimeoutError::Timeout) => false,
+                Err(RecvTimeoutError::Disconnected) => break,
+            };
+            let (Some(pipeline), Some(sink)) = (pipeline.upgrade(), sink.upgrade()) else { break };
+            if pipeline.current_state() != gst::State::Playing {
+                continue;
+            }
+            match held.take() {
+                Some(probe) if woke => {
+                    let _ = sink.set_locked_state(false);
+                    if let Err(e) = sink.sync_state_with_parent() {
+                        warn!(target: "receiver", "reopening the audio device: {e}");
+                    }
+                    input.remove_probe(probe);
+                    flag.store(false, Ordering::SeqCst);
+                    announce(&pipeline, false, "packets again: reopened the audio device");
+                }
+                Some(probe) => held = Some(probe),
+                None if last.lock().unwrap().elapsed() >= after => {
+                    // Hold the sink's input once nothing is being pushed into it
+                    let (quiet_tx, quiet) = mpsc::channel::<()>();
+                    let quiet_tx = Mutex::new(quiet_tx);
+                    let Some(probe) = input.add_probe(gst::PadProbeType::IDLE, move |_pad, _info| {
+                        let _ = quiet_tx.lock().unwrap().send(());
+                        gst::PadProbeReturn::Ok
+                    }) else {
+                        continue;
+                    };
+                    if quiet.recv_timeout(BLOCK_TIMEOUT).is_err() {
+                        input.remove_probe(probe);
+                        continue;
+                    }
+                    let _ = sink.set_locked_state(true);
+                    let _ = sink.set_state(gst::State::Ready);
+                    held = Some(probe);
+                    flag.store(true, Ordering::SeqCst);
+                    // Drain wake-ups from packets that raced the release
+                    while woken.try_recv().is_ok() {}
+                    announce(&pipeline, true, &format!("no packets for {} s: released the audio device", after.as_secs()));
+                }
+                None => {}
+            }
+        }
+    });
+    Ok(idle)
+}
+
+fn announce(pipeline: &gst::Pipeline, idle: bool, what: &str) {
+    info!(target: "receiver", "{what}");
+    let s = gst::Structure::builder("idle").field("idle", idle).build();
+    let _ = pipeline.post_message(gst::message::Application::builder(s).src(pipeline).build());
+}
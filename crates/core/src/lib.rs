@@ -1,3 +1,28 @@
 pub mod pipeline;
 pub mod devices;
+pub mod apps;
 pub mod drift;
+pub mod aes67;
+pub mod scream;
+pub mod sdp;
+pub mod gate;
+pub mod srtp;
+pub mod session;
+pub mod relay;
+pub mod netclock;
+pub mod e2e;
+pub mod record;
+pub mod liveness;
+pub mod glitch;
+pub mod rtpstats;
+pub mod xrun;
+pub mod plugins;
+pub mod pcap;
+mod background;
+mod silence;
+mod idle;
+mod http;
+mod quic;
+mod raop;
+mod vban;
+mod webrtc;
@@ -0,0 +1,144 @@
+// Stream liveness on the receiver: whether each sender is playing, silent
+// (no audio, as with --suppress-silence, but its RTCP or keepalives still
+// arrive) or gone (nothing at all for the stream timeout, or an RTCP BYE).
+// RTP and RTCP are timed per SSRC as they come in; a thread turns that into
+// a state and announces changes, logged and posted on the bus as:
+//   stream, ssrc=(uint)…, state=(string)active|silent|gone
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::info;
+
+/// RTP in this window means the sender is playing.
+const ACTIVE_WINDOW: Duration = Duration::from_secs(1);
+const CHECK_INTERVAL: Duration = Duration::from_millis(500);
+/// Opus's payload type; RTX and FEC come under other types or SSRCs.
+const OPUS_PT: u8 = 97;
+const RTCP_BYE: u8 = 203;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamState {
+    Active,
+    Silent,
+    Gone,
+}
+
+impl StreamState {
+    pub fn name(self) -> &'static str {
+        match self {
+            StreamState::Active => "active",
+            StreamState::Silent => "silent",
+            StreamState::Gone => "gone",
+        }
+    }
+}
+
+struct Stream {
+    last_rtp: Option<Instant>,
+    last_rtcp: Option<Instant>,
+    bye: bool,
+    state: StreamState,
+}
+
+impl Stream {
+    fn new() -> Self {
+        Self { last_rtp: None, last_rtcp: None, bye: false, state: StreamState::Active }
+    }
+
+    fn state(&self, timeout: Duration) -> StreamState {
+        let within = |t: Option<Instant>, d: Duration| t.is_some_and(|t| t.elapsed() < d);
+        if within(self.last_rtp, ACTIVE_WINDOW) {
+            StreamState::Active
+        } else if !self.bye && (within(self.last_rtp, timeout) || within(self.last_rtcp, timeout)) {
+            StreamState::Silent
+        } else {
+            StreamState::Gone
+        }
+    }
+}
+
+/// States per SSRC, shared between the receiver's probes and its owner.
+#[derive(Clone, Default)]
+pub(crate) struct Table(Arc<Mutex<HashMap<u32, Stream>>>);
+
+impl Table {
+    pub(crate) fn snapshot(&self) -> Vec<(u32, StreamState)> {
+        let mut out: Vec<_> = self.0.lock().unwrap().iter().map(|(ssrc, s)| (*ssrc, s.state)).collect();
+        out.sort_by_key(|(ssrc, _)| *ssrc);
+        out
+    }
+}
+
+fn ssrc_at(data: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(at..at + 4)?.try_into().ok()?))
+}
+
+/// Time the RTP passing `rtp` and the RTCP passing `rtcp`, and announce
+/// state changes on `pipeline`. `bye` reads BYEs, which SRTCP encrypts.
+pub(crate) fn attach(pipeline: &gst::Pipeline, rtp: &gst::Pad, rtcp: &gst::Pad, timeout: Duration, bye: bool) -> Table {
+    let table = Table::default();
+
+    let streams = table.clone();
+    rtp.add_probe(gst::PadProbeType::BUFFER, move |_pad, info| {
+        let Some(gst::PadProbeData::Buffer(buffer)) = &info.data else { return gst::PadProbeReturn::Ok };
+        let Ok(map) = buffer.map_readable() else { return gst::PadProbeReturn::Ok };
+        if map.len() >= 12 && map[1] & 0x7f == OPUS_PT {
+            if let Some(ssrc) = ssrc_at(&map, 8) {
+                let mut streams = streams.0.lock().unwrap();
+                let s = streams.entry(ssrc).or_insert_with(Stream::new);
+                s.last_rtp = Some(Instant::now());
+                s.bye = false;
+            }
+        }
+        gst::PadProbeReturn::Ok
+    });
+
+    let streams = table.clone();
+    rtcp.add_probe(gst::PadProbeType::BUFFER, move |_pad, info| {
+        let Some(gst::PadProbeData::Buffer(buffer)) = &info.data else { return gst::PadProbeReturn::Ok };
+        let Ok(map) = buffer.map_readable() else { return gst::PadProbeReturn::Ok };
+        // A compound packet opens with the sender's SR or RR
+        let Some(ssrc) = ssrc_at(&map, 4) else { return gst::PadProbeReturn::Ok };
+        let mut streams = streams.0.lock().unwrap();
+        // Only streams we've had RTP from; RTCP alone doesn't make a sender
+        let Some(s) = streams.get_mut(&ssrc) else { return gst::PadProbeReturn::Ok };
+        s.last_rtcp = Some(Instant::now());
+        let mut at = 0;
+        while bye && at + 4 <= map.len() {
+            if map[at] >> 6 != 2 {
+                break;
+            }
+            if map[at + 1] == RTCP_BYE {
+                s.bye = true;
+            }
+            at += (usize::from(u16::from_be_bytes([map[at + 2], map[at + 3]])) + 1) * 4;
+        }
+        gst::PadProbeReturn::Ok
+    });
+
+    let (pipeline, streams) = (pipeline.downgrade(), table.clone());
+    std::thread::spawn(move || {
+        loop {
+            std::thread::sleep(CHECK_INTERVAL);
+            let Some(pipeline) = pipeline.upgrade() else { break };
+            let mut changes = Vec::new();
+            streams.0.lock().unwrap().retain(|ssrc, s| {
+                let state = s.state(timeout);
+                if state != s.state {
+                    s.state = state;
+                    changes.push((*ssrc, state));
+                }
+                // Forget a sender long gone; a new stream is a new entry
+                state != StreamState::Gone || s.last_rtp.is_some_and(|t| t.elapsed() < timeout * 10)
+            });
+            for (ssrc, state) in changes {
+                info!(target: "receiver", "stream {ssrc:08x} {}", state.name());
+                let s = gst::Structure::builder("stream").field("ssrc", ssrc).field("state", state.name()).build();
+                let _ = pipeline.post_message(gst::message::Application::builder(s).src(&pipeline).build());
+            }
+        }
+    });
+    table
+}
@@ -0,0 +1,61 @@
+// Shared network clock: one machine serves its system clock over UDP
+// (GstNetTimeProvider) and receivers slave their pipelines to it
+// (GstNetClientClock), or every machine follows a PTP grandmaster. With RTCP
+// sender reports stamped from that clock, each receiver maps the stream onto
+// the same timeline: speakers play the same sample at the same moment, and a
+// receiver never drifts from its sender, instead of each following its own
+// crystal and leaving the jitterbuffer to absorb the difference.
+use anyhow::Context;
+use anyhow::Result;
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use gstreamer_net as gst_net;
+use std::net::IpAddr;
+use tracing::{info, warn};
+
+use crate::pipeline::resolve_host;
+
+pub use gst::Clock;
+
+/// Where the clock is served, from the daemon's listen port (RTCP is +1, the
+/// handshake +2).
+pub const PORT_OFFSET: u16 = 3;
+
+/// Serve the system clock on `bind:port` for as long as the provider lives.
+/// Senders stamping reports for followers must run on this clock too.
+pub fn serve(bind: IpAddr, port: u16) -> gst_net::NetTimeProvider {
+    let provider = gst_net::NetTimeProvider::new(&gst::SystemClock::obtain(), Some(&bind.to_string()), port as i32);
+    info!(target: "clock", "serving the system clock on :{port}");
+    provider
+}
+
+/// The system clock, which `serve` hands out.
+pub fn system() -> gst::Clock {
+    gst::SystemClock::obtain()
+}
+
+/// A clock following the one served at `host:port`. Waits a few seconds for
+/// it to sync; playback starts anyway (and converges) if it hasn't.
+pub fn follow(host: &str, port: u16) -> Result<gst::Clock> {
+    let addr = resolve_host(host)?;
+    let clock = gst_net::NetClientClock::new(Some("net-clock"), &addr.to_string(), port as i32, gst::ClockTime::ZERO);
+    info!(target: "clock", "following {host}:{port}");
+    if clock.wait_for_sync(gst::ClockTime::from_seconds(5)).is_err() {
+        warn!(target: "clock", "not in sync with {host}:{port} yet; starting anyway");
+    }
+    Ok(clock.upcast())
+}
+
+/// A clock following the PTP grandmaster on `domain`. Waits a few seconds for
+/// it to sync, like `follow`.
+pub fn ptp(domain: u32) -> Result<gst::Clock> {
+    // The PTP helper lives for the whole process; dropping the guard would stop it.
+    let guard = gst_net::PtpClock::init(None, &[]).context("PTP init failed (ptp-helper installed?)")?;
+    std::mem::forget(guard);
+    let clock = gst_net::PtpClock::new(Some("ptp-clock"), domain).context("PTP clock")?;
+    info!(target: "clock", "following PTP on domain {domain}");
+    if clock.wait_for_sync(gst::ClockTime::from_seconds(10)).is_err() {
+        warn!(target: "clock", "no PTP grandmaster on domain {domain} yet; starting anyway");
+    }
+    Ok(clock.upcast())
+}
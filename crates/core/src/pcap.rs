@@ -0,0 +1,215 @@
+// Packet capture (--pcap FILE): the RTP and RTCP the sender and receiver
+// exchange, written to a pcap file for Wireshark, so a dropout can be looked
+// into after the fact. Packets are taken at the pipeline's network elements,
+// as sent and as received (SRTP still encrypted), and wrapped in synthetic
+// IP/UDP headers: the peer's address where the socket reports it, the
+// configured ports, and unspecified addresses (port 0) for what isn't known,
+// such as our own sending port or the peer over TCP, SRT and QUIC. Wireshark
+// shows them as RTP with "Decode As… RTP" on the port, or with the rtp_udp
+// heuristic enabled. Every packet is written as it passes, so the file is
+// usable while the daemon runs and after it dies.
+use anyhow::{Context, Result};
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use gstreamer_net as gst_net;
+use std::fs::File;
+use std::io::Write;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+const MAGIC: u32 = 0xa1b2_c3d4;
+/// Raw IP: each record starts at the IP header.
+const LINKTYPE_RAW: u32 = 101;
+const SNAPLEN: u32 = 65_535;
+const TTL: u8 = 64;
+const UDP: u8 = 17;
+
+/// A capture file, shared by the pipelines writing to it (and kept across
+/// their rebuilds).
+#[derive(Debug, Clone)]
+pub struct Pcap {
+    file: Arc<Mutex<File>>,
+    /// A write failed; nothing more is written.
+    broken: Arc<AtomicBool>,
+}
+
+impl Pcap {
+    /// Create (or truncate) `path` and write the file header.
+    pub fn create(path: &Path) -> Result<Self> {
+        let mut file = File::create(path).with_context(|| format!("create {}", path.display()))?;
+        let mut header = Vec::with_capacity(24);
+        header.extend(MAGIC.to_le_bytes());
+        // Version 2.4, UTC, no accuracy given
+        header.extend(2u16.to_le_bytes());
+        header.extend(4u16.to_le_bytes());
+        header.extend([0; 8]);
+        header.extend(SNAPLEN.to_le_bytes());
+        header.extend(LINKTYPE_RAW.to_le_bytes());
+        file.write_all(&header).with_context(|| format!("write {}", path.display()))?;
+        Ok(Self { file: Arc::new(Mutex::new(file)), broken: Arc::default() })
+    }
+
+    fn write(&self, from: SocketAddr, to: SocketAddr, payload: &[u8]) {
+        if self.broken.load(Ordering::Relaxed) {
+            return;
+        }
+        let Some(packet) = udp_packet(from, to, payload) else { return };
+        let t = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let len = (packet.len() as u32).to_le_bytes();
+        let mut record = Vec::with_capacity(16 + packet.len());
+        record.extend((t.as_secs() as u32).to_le_bytes());
+        record.extend(t.subsec_micros().to_le_bytes());
+        record.extend(len);
+        record.extend(len);
+        record.extend(packet);
+        // One write per packet, so a record is never left half written
+        if let Err(e) = self.file.lock().unwrap().write_all(&record) {
+            if !self.broken.swap(true, Ordering::Relaxed) {
+                warn!(target: "pcap", "capture stopped: {e}");
+            }
+        }
+    }
+}
+
+/// Which way the packets through a pad go.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Way {
+    Out,
+    In,
+}
+
+/// Write the packets through `element`'s `pad`: out from `local` to each of
+/// `peers()`, or in from where the socket says they came from (else the
+/// first peer) to `local`.
+pub(crate) fn attach(
+    element: &gst::Element,
+    pad: &str,
+    pcap: &Pcap,
+    way: Way,
+    local: SocketAddr,
+    peers: impl Fn() -> Vec<SocketAddr> + Send + Sync + 'static,
+) -> Result<()> {
+    let pad = element.static_pad(pad).with_context(|| format!("{} has no {pad} pad", element.name()))?;
+    let pcap = pcap.clone();
+    let unknown = SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 0);
+    pad.add_probe(gst::PadProbeType::BUFFER | gst::PadProbeType::BUFFER_LIST, move |_pad, info| {
+        let write = |buf: &gst::BufferRef| {
+            let Ok(map) = buf.map_readable() else { return };
+            let peers = peers();
+            match way {
+                Way::Out if peers.is_empty() => pcap.write(local, unknown, &map),
+                Way::Out => peers.iter().for_each(|&peer| pcap.write(local, peer, &map)),
+                Way::In => {
+                    let from = source(buf).or(peers.first().copied()).unwrap_or(unknown);
+                    pcap.write(from, local, &map)
+                }
+            }
+        };
+        match &info.data {
+            Some(gst::PadProbeData::Buffer(buf)) => write(buf),
+            Some(gst::PadProbeData::BufferList(list)) => list.iter().for_each(write),
+            _ => {}
+        }
+        gst::PadProbeReturn::Ok
+    });
+    Ok(())
+}
+
+/// Where a udpsrc packet came from.
+fn source(buf: &gst::BufferRef) -> Option<SocketAddr> {
+    let meta = buf.meta::<gst_net::NetAddressMeta>()?;
+    let addr = meta.addr().downcast::<gst_net::gio::InetSocketAddress>().ok()?;
+    Some(SocketAddr::new(IpAddr::from(addr.address()), addr.port()))
+}
+
+/// `a` and `b` in one family: an unspecified address takes the other's,
+/// otherwise IPv4 is mapped into IPv6.
+fn same_family(a: IpAddr, b: IpAddr) -> (IpAddr, IpAddr) {
+    let unspecified = |like: IpAddr| match like {
+        IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+    };
+    let v6 = |ip: IpAddr| match ip {
+        IpAddr::V4(v4) => IpAddr::V6(v4.to_ipv6_mapped()),
+        v6 => v6,
+    };
+    let (a, b) = (a.to_canonical(), b.to_canonical());
+    match (a, b) {
+        (IpAddr::V4(_), IpAddr::V4(_)) | (IpAddr::V6(_), IpAddr::V6(_)) => (a, b),
+        _ if a.is_unspecified() => (unspecified(b), b),
+        _ if b.is_unspecified() => (a, unspecified(a)),
+        _ => (v6(a), v6(b)),
+    }
+}
+
+/// `payload` in an IPv4 or IPv6 UDP packet from `from` to `to`, checksums
+/// filled in; None if it can't fit.
+fn udp_packet(from: SocketAddr, to: SocketAddr, payload: &[u8]) -> Option<Vec<u8>> {
+    let udp_len = u16::try_from(8 + payload.len()).ok()?;
+    let mut udp = Vec::with_capacity(usize::from(udp_len));
+    udp.extend(from.port().to_be_bytes());
+    udp.extend(to.port().to_be_bytes());
+    udp.extend(udp_len.to_be_bytes());
+    udp.extend([0, 0]);
+    udp.extend(payload);
+
+    let (ip, pseudo) = match same_family(from.ip(), to.ip()) {
+        (IpAddr::V4(src), IpAddr::V4(dst)) => {
+            let total = u16::try_from(20 + udp.len()).ok()?;
+            let mut ip = vec![0x45, 0];
+            ip.extend(total.to_be_bytes());
+            // No ID, don't fragment
+            ip.extend([0, 0, 0x40, 0, TTL, UDP, 0, 0]);
+            ip.extend(src.octets());
+            ip.extend(dst.octets());
+            let sum = checksum(&[&ip[..]]);
+            ip[10..12].copy_from_slice(&sum.to_be_bytes());
+            let mut pseudo = Vec::with_capacity(12);
+            pseudo.extend(src.octets());
+            pseudo.extend(dst.octets());
+            pseudo.extend([0, UDP]);
+            pseudo.extend(udp_len.to_be_bytes());
+            (ip, pseudo)
+        }
+        (src, dst) => {
+            let (IpAddr::V6(src), IpAddr::V6(dst)) = (src, dst) else { return None };
+            let mut ip = vec![0x60, 0, 0, 0];
+            ip.extend(udp_len.to_be_bytes());
+            ip.extend([UDP, TTL]);
+            ip.extend(src.octets());
+            ip.extend(dst.octets());
+            let mut pseudo = Vec::with_capacity(40);
+            pseudo.extend(src.octets());
+            pseudo.extend(dst.octets());
+            pseudo.extend(u32::from(udp_len).to_be_bytes());
+            pseudo.extend([0, 0, 0, UDP]);
+            (ip, pseudo)
+        }
+    };
+    // Zero means "no checksum", so a computed zero is sent as all ones
+    let sum = match checksum(&[&pseudo[..], &udp[..]]) {
+        0 => 0xffff,
+        sum => sum,
+    };
+    udp[6..8].copy_from_slice(&sum.to_be_bytes());
+    let mut packet = ip;
+    packet.extend(udp);
+    Some(packet)
+}
+
+/// The Internet checksum over `parts`, each but the last of even length.
+fn checksum(parts: &[&[u8]]) -> u16 {
+    let mut sum: u32 = parts
+        .iter()
+        .flat_map(|p| p.chunks(2))
+        .map(|c| u32::from(u16::from_be_bytes([c[0], c.get(1).copied().unwrap_or(0)])))
+        .sum();
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
@@ -1,7 +1,37 @@
 use anyhow::{Context, Result};
 use gstreamer as gst;
 use gstreamer::prelude::*;
+use gstreamer_app as gst_app;
+use gstreamer_net as gst_net;
 use std::env;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use tracing::{debug, info, warn};
+
+use crate::aes67::{self, Aes67Config};
+use crate::gate::{self, SourceGate};
+use crate::pcap::{self, Pcap};
+use crate::record::{self, RecordConfig};
+use crate::relay::{self, RelayConfig};
+use crate::session::{self, Sessions};
+use crate::srtp::{self, SrtpKeys, SrtpSession};
+use crate::{
+    background, drift, e2e, glitch, http, idle, liveness, plugins, quic, raop, rtpstats, scream, silence, vban, webrtc,
+    xrun,
+};
+
+/// Log under the "sender" or "receiver" target, picked at run time: tracing
+/// fixes a call site's target, and the helpers below serve both sides.
+macro_rules! log_as {
+    ($level:ident, $side:expr, $($arg:tt)+) => {
+        match $side {
+            "sender" => tracing::$level!(target: "sender", $($arg)+),
+            _ => tracing::$level!(target: "receiver", $($arg)+),
+        }
+    };
+}
 
 /* ------------------------------------------------------------------------- */
 /* Types                                                                      */
@@ -9,49 +39,507 @@ use std::env;
 
 pub struct Sender {
     pipeline: gst::Pipeline,
+    meter: Meter,
+    /// Bumped per push-to-talk change so an older fade stops midway.
+    ptt_gen: Arc<AtomicU64>,
+    /// Silence suppression is holding transmission back.
+    silent: Arc<AtomicBool>,
+    sent: Arc<Mutex<TxTotals>>,
+    xruns: xrun::Table,
+    /// Threads serving sockets beside the pipeline (QUIC, WebRTC signaling).
+    tasks: background::Tasks,
 }
 pub struct Receiver {
     pipeline: gst::Pipeline,
+    meter: Meter,
+    e2e: e2e::Table,
+    recorder: Option<record::Recorder>,
+    /// The audio device is released for lack of packets (see `idle.rs`).
+    idle: Arc<AtomicBool>,
+    streams: liveness::Table,
+    rtp: rtpstats::Table,
+    xruns: xrun::Table,
+    glitches: glitch::Table,
+    /// The RAOP client playing to an AirPlay speaker (`Output::AirPlay`).
+    raop: Option<raop::Client>,
+    /// Threads serving sockets beside the pipeline (QUIC, VBAN, HTTP, ...).
+    tasks: background::Tasks,
+}
+
+/// How often `level` elements report.
+pub const LEVEL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(100);
+/// Readings kept for `levels()`: the last 10 s.
+const LEVEL_HISTORY: usize = 100;
+
+/// Latest `level` reading, in dBFS per channel.
+#[derive(Debug, Clone, Default)]
+pub struct Level {
+    pub rms_db: Vec<f64>,
+    pub peak_db: Vec<f64>,
+}
+
+/// What a sender has put on the wire since it was built.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TxTotals {
+    pub packets: u64,
+    pub bytes: u64,
+}
+
+/// What the bus thread keeps for the pipeline's owner.
+#[derive(Default)]
+struct Watched {
+    /// Latest `level` reading.
+    level: Option<Level>,
+    /// The last `LEVEL_HISTORY` readings.
+    history: std::collections::VecDeque<Level>,
+    /// First ERROR posted; the pipeline is broken from then on.
+    error: Option<String>,
+    /// EOS came out of every sink (see `drain`).
+    eos: bool,
+    /// `drain` sent EOS; a source ending now is expected.
+    draining: bool,
+}
+type Meter = Arc<Mutex<Watched>>;
+
+/// Everything `build_sender` needs. `Default` matches the v1 behaviour.
+#[derive(Debug, Clone)]
+pub struct SenderConfig {
+    /// macOS: osxaudiosrc device index; Linux: pulse device (default: auto monitor).
+    pub capture_device: Option<String>,
+    /// What to send; anything but `Capture` ignores `capture_device`.
+    pub source: Source,
+    /// Destinations; one encode is fanned out to all of them on `port`.
+    pub hosts: Vec<String>,
+    pub port: u16,
+    /// Initial Opus bitrate in bit/s.
+    pub bitrate: i32,
+    /// Opus frame duration in ms (2.5, 5, 10, 20, 40 or 60); None = 2.5, or 10
+    /// with in-band FEC.
+    pub frame_ms: Option<f32>,
+    /// When set, RTCP loss/RTT feedback moves the bitrate within these bounds.
+    pub adaptive_bitrate: Option<BitrateRange>,
+    /// Forward error correction scheme, if any.
+    pub fec: Option<FecMode>,
+    /// Expected packet loss (0-100) the encoder should budget redundancy for.
+    pub expected_loss_pct: i32,
+    /// Serve RTX retransmissions (RFC 4588); value is the receiver's retransmission budget in ms.
+    pub rtx_max_ms: Option<u32>,
+    /// TTL for multicast destinations (1 = stay on the local subnet).
+    pub multicast_ttl: u32,
+    /// Interface to send multicast on (e.g. "eth0"); None = OS routing.
+    pub multicast_iface: Option<String>,
+    pub transport: Transport,
+    /// STUN server for WebRTC ICE, e.g. "stun://stun.l.google.com:19302".
+    pub stun_server: Option<String>,
+    /// Send AES67 (L24, 1 ms, PTP, SAP) to `hosts[0]` instead of Opus.
+    pub aes67: Option<Aes67Config>,
+    /// Local address to send from, pinning traffic to one NIC; None = OS routing.
+    pub bind_address: Option<IpAddr>,
+    /// DSCP code point (0..=63, e.g. 46 = EF) for outgoing media packets.
+    pub dscp: Option<u8>,
+    /// Stream under this SSRC (RTX under SSRC + 1): the session ID receivers
+    /// filter on. None = whatever the payloader picks.
+    pub ssrc: Option<u32>,
+    /// Encrypt RTP/RTCP with this SRTP session (rtpbin transports only; needs `ssrc`).
+    pub srtp: Option<SrtpSession>,
+    /// Capture buffer-time / latency-time in µs; None = 200000/10000 on macOS,
+    /// the pulsesrc defaults on Linux.
+    pub src_buffer_us: Option<u32>,
+    pub src_latency_us: Option<u32>,
+    /// Linux: prefer the monitor source whose name contains this.
+    pub monitor_hint: Option<String>,
+    /// Capture only the app this names, in place of a device. Linux: through
+    /// PipeWire (see `apps::find`); macOS: from the tap the daemon makes and
+    /// sets as `capture_device`. Silence until then, or until the app plays.
+    pub capture_app: Option<String>,
+    /// Cancel what this process's receiver plays (its `ECHO_PROBE`) out of the
+    /// capture, with noise suppression and gain control (webrtcdsp). Without a
+    /// `capture_device` the microphone is captured rather than a monitor.
+    pub echo_cancel: bool,
+    /// Push-to-talk: start muted, sending only while `Sender::set_talking(true)`.
+    pub ptt: bool,
+    /// Stop sending while the audio stays below this level (dBFS); see `silence.rs`.
+    pub suppress_silence: Option<f64>,
+    /// While silence suppression holds back, still send a frame this often, so
+    /// receivers can tell a silent sender from a gone one (see `liveness.rs`).
+    pub keepalive: Option<std::time::Duration>,
+    /// Also play what's about to be encoded on the local output at this volume,
+    /// to hear what is actually captured.
+    pub local_monitor: Option<f64>,
+    /// Send only this channel, on both sides of the stereo stream (one speaker of a pair).
+    pub channel: Option<Channel>,
+    /// Run on this clock and stamp RTCP sender reports from it, so receivers
+    /// following the same clock (`netclock`) play in step.
+    pub clock: Option<gst::Clock>,
+    /// Stamp each packet with its capture time for receivers measuring
+    /// end-to-end latency (see `e2e.rs`).
+    pub measure_latency: bool,
+    /// Write the RTP/RTCP sent and received here (see `pcap.rs`; RTP transports).
+    pub pcap: Option<Pcap>,
+}
+
+impl Default for SenderConfig {
+    fn default() -> Self {
+        Self {
+            capture_device: None,
+            source: Source::Capture,
+            hosts: vec!["127.0.0.1".into()],
+            port: 5002,
+            bitrate: 256_000,
+            frame_ms: None,
+            adaptive_bitrate: None,
+            fec: None,
+            expected_loss_pct: 0,
+            rtx_max_ms: None,
+            multicast_ttl: 1,
+            multicast_iface: None,
+            transport: Transport::Udp,
+            stun_server: None,
+            aes67: None,
+            bind_address: None,
+            dscp: None,
+            ssrc: None,
+            srtp: None,
+            src_buffer_us: None,
+            src_latency_us: None,
+            monitor_hint: None,
+            capture_app: None,
+            echo_cancel: false,
+            ptt: false,
+            suppress_silence: None,
+            keepalive: None,
+            local_monitor: None,
+            channel: None,
+            clock: None,
+            measure_latency: false,
+            pcap: None,
+        }
+    }
+}
+
+/// Everything `build_receiver` needs. `Default` matches the v1 behaviour.
+#[derive(Debug, Clone)]
+pub struct ReceiverConfig {
+    pub listen_port: u16,
+    /// Forward error correction scheme the sender uses, if any.
+    pub fec: Option<FecMode>,
+    /// Request RTX retransmissions, waiting at most this many extra ms for them.
+    pub rtx_max_ms: Option<u32>,
+    /// Join this multicast group (RTP on listen_port, RTCP on listen_port+1).
+    pub multicast_group: Option<String>,
+    /// Interface to join the group on; None = OS default.
+    pub multicast_iface: Option<String>,
+    pub transport: Transport,
+    /// STUN server for WebRTC ICE, e.g. "stun://stun.l.google.com:19302".
+    pub stun_server: Option<String>,
+    /// Where the decoded mix is played.
+    pub output: Output,
+    /// Also play Scream (Windows virtual sound card) multicast PCM from this group.
+    pub scream_group: Option<std::net::Ipv4Addr>,
+    /// Also serve the mix as Ogg/Opus over HTTP on this port (for browsers/phones).
+    pub http_port: Option<u16>,
+    /// Also write the mix (before the playback volume) to WAV/FLAC files.
+    pub record: Option<RecordConfig>,
+    /// Local address to listen on, so only one NIC is used; None = all (dual-stack).
+    pub bind_address: Option<IpAddr>,
+    /// Only play UDP senders this gate admits; None = accept anyone.
+    pub source_gate: Option<SourceGate>,
+    /// Expect SRTP, decrypting with these per-sender keys.
+    pub srtp_keys: Option<SrtpKeys>,
+    /// Drop UDP packets that aren't from a sender's live session.
+    pub sessions: Option<Sessions>,
+    /// Playback device (see `devices::sinks`); None = the system default.
+    pub output_device: Option<String>,
+    /// Linux: play through autoaudiosink instead of pulsesink (no `output_device`).
+    pub auto_sink: bool,
+    /// Jitterbuffer latency in ms (RTX adds its budget on top).
+    pub jitter_ms: u32,
+    /// Let the jitterbuffer latency follow the network within these bounds,
+    /// starting from `jitter_ms` (RTP transports).
+    pub adaptive_jitter: Option<JitterRange>,
+    /// Drop packets that arrive after their playout time.
+    pub drop_on_late: bool,
+    /// Opus packet loss concealment (always on with FEC).
+    pub plc: bool,
+    /// Sink buffer-time / latency-time in µs.
+    pub sink_buffer_us: u32,
+    pub sink_latency_us: u32,
+    /// Most audio the queue in front of the sink holds, in ms.
+    pub queue_ms: u32,
+    /// Play against the pipeline clock; false lets the sink play as data arrives.
+    pub sink_sync: bool,
+    /// Feed what's played to a sender's echo canceller (see `SenderConfig::echo_cancel`).
+    pub echo_probe: bool,
+    /// Also forward the incoming RTP, as is, to these receivers (RTP transports only).
+    pub relay: Option<RelayConfig>,
+    /// Run on this (network) clock and play senders on its timeline, in step with
+    /// other receivers following it (RTP transports).
+    pub clock: Option<gst::Clock>,
+    /// Resample each stream to make up for its sender's clock running at another
+    /// rate than ours (see `drift.rs`); moot on a shared `clock`.
+    pub drift_correction: bool,
+    /// Measure capture-to-speaker latency from senders' capture stamps (see
+    /// `e2e.rs`; RTP transports).
+    pub measure_latency: bool,
+    /// Close the audio device after this long without packets and reopen it on
+    /// the next one (see `idle.rs`; local playback, RTP transports).
+    pub idle_release: Option<std::time::Duration>,
+    /// A sender with neither RTP nor RTCP for this long is gone (see
+    /// `liveness.rs`; RTP transports).
+    pub stream_timeout: std::time::Duration,
+    /// Write the RTP/RTCP received and sent here (see `pcap.rs`; RTP transports).
+    pub pcap: Option<Pcap>,
+}
+
+impl Default for ReceiverConfig {
+    fn default() -> Self {
+        Self {
+            listen_port: 5004,
+            fec: None,
+            rtx_max_ms: None,
+            multicast_group: None,
+            multicast_iface: None,
+            transport: Transport::Udp,
+            stun_server: None,
+            output: Output::Local,
+            scream_group: None,
+            http_port: None,
+            record: None,
+            bind_address: None,
+            source_gate: None,
+            srtp_keys: None,
+            sessions: None,
+            output_device: None,
+            auto_sink: false,
+            jitter_ms: 30,
+            adaptive_jitter: None,
+            drop_on_late: true,
+            plc: false,
+            sink_buffer_us: 70_000,
+            sink_latency_us: 15_000,
+            queue_ms: 20,
+            sink_sync: true,
+            echo_probe: false,
+            relay: None,
+            clock: None,
+            drift_correction: true,
+            measure_latency: false,
+            idle_release: None,
+            stream_timeout: std::time::Duration::from_secs(10),
+            pcap: None,
+        }
+    }
+}
+
+/// Receiver playback target.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Output {
+    /// The local audio device (osxaudiosink; pulsesink, else alsasink or autoaudiosink).
+    Local,
+    /// Relay to an AirPlay speaker through an external RAOP client: `cmd`,
+    /// run as `cmd <host> -`.
+    AirPlay { host: String, cmd: String },
+    /// Feed a Snapcast server: a `pipe://` stream FIFO path, or `tcp://host:port`
+    /// for a `tcp` stream source in server mode. Raw PCM, 48000:16:2.
+    Snapcast { target: String },
+}
+
+/// One side of a stereo stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Channel {
+    Left,
+    Right,
+}
+
+/// What a sender sends.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    /// The capture device (`SenderConfig::capture_device`, or the default).
+    Capture,
+    /// A sine at this frequency (Hz), to test the network and receiver on their own.
+    Tone(f64),
+    /// A sound file (WAV, FLAC, MP3… whatever decodebin reads), played once in real time.
+    File(PathBuf),
+}
+
+/// How long push-to-talk fades in and out, in `PTT_FADE_STEPS` volume steps.
+const PTT_FADE: std::time::Duration = std::time::Duration::from_millis(20);
+const PTT_FADE_STEPS: u32 = 10;
+
+/// Name of the receiver's webrtcechoprobe, which a sender's webrtcdsp in the
+/// same process reads the far-end audio from.
+pub const ECHO_PROBE: &str = "echo_probe";
+
+/// How RTP/RTCP travel between the two pipelines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transport {
+    /// Plain RTP/UDP: RTP on port, RTCP on port+1.
+    Udp,
+    /// RTP and RTCP as QUIC datagrams on one UDP port (encrypted, congestion-controlled).
+    Quic,
+    /// RTP over SRT (ARQ within `latency_ms`); RTCP stays on UDP port+1.
+    Srt { latency_ms: u32 },
+    /// RFC 4571 framed RTP over TCP (port), SRs over TCP (port+1). For networks that block UDP.
+    Tcp,
+    /// webrtcbin with ICE/STUN for NAT traversal; SDP/ICE signaling over TCP on the same port.
+    WebRtc,
+    /// VBAN: uncompressed 16-bit PCM over UDP, for VoiceMeeter interop. No RTP, no Opus.
+    Vban,
+}
+
+impl Transport {
+    /// Name used on the command line and in mDNS TXT records.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Transport::Udp => "udp",
+            Transport::Quic => "quic",
+            Transport::Srt { .. } => "srt",
+            Transport::Tcp => "tcp",
+            Transport::WebRtc => "webrtc",
+            Transport::Vban => "vban",
+        }
+    }
+
+    /// The elements this transport needs beyond the codec's.
+    pub fn elements(&self) -> &'static [&'static str] {
+        match self {
+            Transport::Udp => &["rtpbin", "udpsrc", "multiudpsink"],
+            Transport::Quic | Transport::Vban => &["appsrc", "appsink"],
+            Transport::Srt { .. } => &["rtpbin", "srtsrc", "srtsink"],
+            Transport::Tcp => &["rtpbin", "tcpserversrc", "tcpclientsink", "rtpstreampay", "rtpstreamdepay"],
+            Transport::WebRtc => &["webrtcbin"],
+        }
+    }
+
+    /// Whether the installed GStreamer has the elements this transport needs.
+    /// Call after `init_gst`.
+    pub fn available(&self) -> bool {
+        self.elements().iter().all(|e| gst::ElementFactory::find(e).is_some())
+    }
+}
+
+/// How the stream is protected against packet loss without retransmission.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FecMode {
+    /// Opus in-band FEC (LBRR). Forces 10 ms frames: FEC needs SILK/hybrid mode.
+    InBand,
+    /// RFC 5109 ULP FEC packets (PT 100) adding `percentage`% redundancy.
+    Ulp { percentage: u32 },
+}
+
+/// Payload type of ULP FEC packets.
+pub(crate) const ULPFEC_PT: u32 = 100;
+
+/// Floor/ceiling (bit/s) for the adaptive Opus bitrate controller.
+#[derive(Debug, Clone, Copy)]
+pub struct BitrateRange {
+    pub min: i32,
+    pub max: i32,
+}
+
+/// Floor/ceiling (ms) for the adaptive jitterbuffer controller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JitterRange {
+    pub min: u32,
+    pub max: u32,
 }
 
 /* ------------------------------------------------------------------------- */
 /* Utilities & logging                                                        */
 /* ------------------------------------------------------------------------- */
 
-fn make_element(factory: &str, name: &str) -> Result<gst::Element> {
+pub(crate) fn make_element(factory: &str, name: &str) -> Result<gst::Element> {
+    if !plugins::available(factory) {
+        let hint = plugins::install_hint(factory);
+        anyhow::bail!("can't make '{name}': GStreamer element '{factory}' isn't installed: {hint}");
+    }
     let e = gst::ElementFactory::make(factory)
         .name(name)
         .build()
         .with_context(|| format!("failed to make element '{factory}' as '{name}'"))?;
-    eprintln!("[build] created {factory} as {name}");
+    debug!(target: "build", "created {factory} as {name}");
     Ok(e)
 }
 
-fn attach_bus_logging(p: &gst::Pipeline, tag: &str) {
+/// Make the first installed of `alternatives` (best first) as `name`, with a
+/// warning when that isn't the best.
+pub(crate) fn make_first_element(alternatives: &[&'static str], name: &str) -> Result<gst::Element> {
+    let factory = plugins::first_available(alternatives).with_context(|| format!("can't make '{name}'"))?;
+    if factory != alternatives[0] {
+        warn!(
+            target: "build",
+            "{} isn't installed; using {factory} as {name} ({})",
+            alternatives[0],
+            plugins::install_hint(alternatives[0])
+        );
+    }
+    make_element(factory, name)
+}
+
+/// Point a Linux capture or playback element at `device`, a PulseAudio /
+/// PipeWire node name as `devices` lists them. False if it can't take one
+/// (ALSA's devices are named otherwise, autoaudiosink picks its own).
+fn set_linux_device(e: &gst::Element, device: &str) -> bool {
+    let factory = e.factory().map(|f| f.name().to_string()).unwrap_or_default();
+    match factory.as_str() {
+        "pulsesrc" | "pulsesink" if e.has_property("device", None) => e.set_property("device", device),
+        "pipewiresrc" | "pipewiresink" if e.has_property("target-object", None) => {
+            // A monitor is its sink's node, captured from the sink side
+            match device.strip_suffix(".monitor") {
+                Some(sink) => {
+                    e.set_property("target-object", sink);
+                    let props = gst::Structure::builder("props").field("stream.capture.sink", "true").build();
+                    e.set_property("stream-properties", props);
+                }
+                None => e.set_property("target-object", device),
+            }
+        }
+        _ => return false,
+    }
+    true
+}
+
+/// Linux: the device a pulsesrc/pulsesink is on, as opposed to the one it
+/// was given.
+#[cfg(target_os = "linux")]
+fn current_device(e: &gst::Element) -> Option<String> {
+    let property = ["current-device", "device"].into_iter().find(|p| e.has_property(p, None))?;
+    e.property::<Option<String>>(property)
+}
+
+/// Log bus messages on a thread of their own; `level` readings and the first
+/// error also land in the returned meter.
+fn attach_bus_logging(p: &gst::Pipeline, side: &'static str) -> Meter {
+    let meter = Meter::default();
     if let Some(bus) = p.bus() {
-        let tag = tag.to_string();
+        let meter = meter.clone();
         std::thread::spawn(move || {
             for msg in bus.iter_timed(gst::ClockTime::NONE) {
                 use gst::MessageView;
                 match msg.view() {
-                    MessageView::Error(e) => eprintln!(
-                        "[{tag}] ERROR from {}: {} (debug: {:?})",
-                        e.src()
-                            .map(|s| s.path_string())
-                            .unwrap_or_else(|| "<unknown>".into()),
-                        e.error(),
-                        e.debug()
-                    ),
-                    MessageView::Warning(w) => eprintln!(
-                        "[{tag}] WARN  from {}: {} (debug: {:?})",
+                    MessageView::Error(e) => {
+                        let from = e.src().map(|s| s.path_string()).unwrap_or_else(|| "<unknown>".into());
+                        log_as!(error, side, "{from}: {} (debug: {:?})", e.error(), e.debug());
+                        let error = format!("{from}: {}", e.error());
+                        if crate::devices::is_disconnect(&error) {
+                            log_as!(warn, side, "lost the sound server (PulseAudio/PipeWire restarting?)");
+                        }
+                        meter.lock().unwrap().error.get_or_insert(error);
+                    }
+                    MessageView::Warning(w) => log_as!(
+                        warn,
+                        side,
+                        "{}: {} (debug: {:?})",
                         w.src()
                             .map(|s| s.path_string())
                             .unwrap_or_else(|| "<unknown>".into()),
                         w.error(),
                         w.debug()
                     ),
-                    MessageView::Info(i) => eprintln!(
-                        "[{tag}] INFO  from {}: {} (debug: {:?})",
+                    MessageView::Info(i) => log_as!(
+                        info,
+                        side,
+                        "{}: {} (debug: {:?})",
                         i.src()
                             .map(|s| s.path_string())
                             .unwrap_or_else(|| "<unknown>".into()),
@@ -60,14 +548,32 @@ fn attach_bus_logging(p: &gst::Pipeline, tag: &str) {
                     ),
                     MessageView::Element(el) => {
                         if let Some(s) = el.structure() {
-                            eprintln!("[{tag}] ELEMENT {}", s.to_string());
+                            // Levels are kept for `level()`/`levels()` rather than logged
+                            if s.name() == "level" {
+                                let db = |field: &str| -> Vec<f64> {
+                                    s.get::<gst::glib::ValueArray>(field)
+                                        .map(|a| a.iter().filter_map(|v| v.get::<f64>().ok()).collect())
+                                        .unwrap_or_default()
+                                };
+                                let level = Level { rms_db: db("rms"), peak_db: db("peak") };
+                                let mut watched = meter.lock().unwrap();
+                                if watched.history.len() == LEVEL_HISTORY {
+                                    watched.history.pop_front();
+                                }
+                                watched.history.push_back(level.clone());
+                                watched.level = Some(level);
+                            } else {
+                                log_as!(debug, side, "element message {}", s.to_string());
+                            }
                         }
                     }
                     MessageView::StateChanged(s) => {
                         if let Some(src) = msg.src() {
                             if src.type_().is_a(gst::Pipeline::static_type()) {
-                                eprintln!(
-                                    "[{tag}] state changed: {:?} -> {:?} (pending {:?})",
+                                log_as!(
+                                    info,
+                                    side,
+                                    "state changed: {:?} -> {:?} (pending {:?})",
                                     s.old(),
                                     s.current(),
                                     s.pending()
@@ -75,22 +581,48 @@ fn attach_bus_logging(p: &gst::Pipeline, tag: &str) {
                             }
                         }
                     }
-                    MessageView::Latency(_) => eprintln!("[{tag}] latency message"),
+                    MessageView::Latency(_) => log_as!(debug, side, "latency message"),
+                    MessageView::Eos(_) => {
+                        log_as!(info, side, "end of stream");
+                        meter.lock().unwrap().eos = true;
+                    }
                     _ => {}
                 }
             }
         });
     }
+    meter
+}
+
+/// Send EOS from the sources of a playing pipeline and wait, at most
+/// `timeout`, for it to come out of the sinks: what's queued gets encoded
+/// and sent, or played, rather than cut off.
+fn drain(p: &gst::Pipeline, meter: &Meter, side: &'static str, timeout: std::time::Duration) {
+    if p.current_state() != gst::State::Playing || meter.lock().unwrap().error.is_some() {
+        return;
+    }
+    log_as!(info, side, "draining…");
+    meter.lock().unwrap().draining = true;
+    p.send_event(gst::event::Eos::new());
+    let deadline = std::time::Instant::now() + timeout;
+    while !meter.lock().unwrap().eos {
+        if std::time::Instant::now() >= deadline {
+            log_as!(warn, side, "not drained after {} ms; stopping anyway", timeout.as_millis());
+            return;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
 }
 
 fn attach_caps_probe(elem: &gst::Element, pad_name: &str, tag: &str) {
     if let Some(pad) = elem.static_pad(pad_name) {
         let t = tag.to_string();
         let p = pad_name.to_string();
+        let side = if tag.starts_with("snd/") { "sender" } else { "receiver" };
         pad.add_probe(gst::PadProbeType::EVENT_DOWNSTREAM, move |_pad, info| {
             if let Some(ev) = info.event() {
                 if let gst::EventView::Caps(c) = ev.view() {
-                    eprintln!("[caps:{t}] {p} -> {}", c.caps().to_string());
+                    log_as!(debug, side, "caps {t} {p} -> {}", c.caps().to_string());
                 }
             }
             gst::PadProbeReturn::Ok
@@ -99,17 +631,23 @@ fn attach_caps_probe(elem: &gst::Element, pad_name: &str, tag: &str) {
 }
 
 /// Attach a simple TX stats probe that counts packets/bytes and logs ~1s.
-fn attach_tx_stats(elem: &gst::Element, pad_name: &str, tag: &str) {
-    use std::sync::{Arc, Mutex};
+/// The returned totals keep counting.
+fn attach_tx_stats(elem: &gst::Element, pad_name: &str) -> Arc<Mutex<TxTotals>> {
     use std::time::{Duration, Instant};
 
+    let totals = Arc::new(Mutex::new(TxTotals::default()));
     if let Some(pad) = elem.static_pad(pad_name) {
         let state = Arc::new(Mutex::new((0u64, 0u64, Instant::now())));
-        let t = tag.to_string();
         let st = state.clone();
+        let sent = totals.clone();
         pad.add_probe(gst::PadProbeType::BUFFER, move |_pad, info| {
             if let Some(buf) = info.buffer() {
                 let sz = buf.size() as u64;
+                {
+                    let mut sent = sent.lock().unwrap();
+                    sent.packets += 1;
+                    sent.bytes += sz;
+                }
                 let mut s = st.lock().unwrap();
                 s.0 += 1;
                 s.1 += sz;
@@ -119,8 +657,9 @@ fn attach_tx_stats(elem: &gst::Element, pad_name: &str, tag: &str) {
                     let bytes = s.1;
                     let bps = (bytes as f64) * 8.0 / dt.as_secs_f64();
                     let kbps = bps / 1000.0;
-                    eprintln!(
-                        "[{t}] TX ~{:.0} pkts/s, ~{:.1} kbit/s ({} bytes in {:.2}s)",
+                    info!(
+                        target: "sender",
+                        "TX ~{:.0} pkts/s, ~{:.1} kbit/s ({} bytes in {:.2}s)",
                         pkts as f64 / dt.as_secs_f64(),
                         kbps,
                         bytes,
@@ -132,12 +671,457 @@ fn attach_tx_stats(elem: &gst::Element, pad_name: &str, tag: &str) {
             gst::PadProbeReturn::Ok
         });
     }
+    totals
+}
+
+/// Fill of the queue named `name` in `pipeline`, in ms.
+fn queued_ms(pipeline: &gst::Pipeline, name: &str) -> Option<f64> {
+    let queue = pipeline.by_name(name)?;
+    Some(queue.property::<u64>("current-level-time") as f64 / 1e6)
+}
+
+/// Payload type RTX retransmissions of our Opus stream (PT 97) are sent with.
+pub(crate) const RTX_PT: u32 = 98;
+
+fn rtx_pt_map() -> gst::Structure {
+    gst::Structure::builder("application/x-rtp-pt-map")
+        .field("97", RTX_PT)
+        .build()
+}
+
+/// Wrap an RTX element in the `sink_%u`/`src_%u` bin that rtpbin's
+/// `request-aux-sender`/`request-aux-receiver` handlers must return.
+fn make_rtx_aux_bin(rtx: &gst::Element, session: u32) -> Result<gst::Element> {
+    let bin = gst::Bin::new();
+    bin.add(rtx)?;
+    for (pad, ghost) in [("sink", format!("sink_{session}")), ("src", format!("src_{session}"))] {
+        let target = rtx
+            .static_pad(pad)
+            .with_context(|| format!("{} has no {pad} pad", rtx.name()))?;
+        let ghost = gst::GhostPad::builder_with_target(&target)?.name(ghost.as_str()).build();
+        bin.add_pad(&ghost)?;
+    }
+    Ok(bin.upcast())
+}
+
+/// One RTCP view of a remote party, read from the rtpbin session's sources.
+/// On the sender these come from receiver reports (loss/jitter/RTT of our
+/// stream as seen by the peer); on the receiver from our own reception stats.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RtcpReport {
+    pub ssrc: u32,
+    pub packets_received: u64,
+    pub packets_lost: i64,
+    pub fraction_lost_pct: f64,
+    pub jitter_ms: f64,
+    pub rtt_ms: Option<f64>,
+}
+
+/// Collect RTCP stats for every remote source known to session 0 of `rtpbin`.
+fn read_rtcp_reports(rtpbin: &gst::Element) -> Vec<RtcpReport> {
+    let session = rtpbin.emit_by_name::<gst::glib::Object>("get-internal-session", &[&0u32]);
+    let sources = session.property::<gst::glib::ValueArray>("sources");
+
+    let mut out = Vec::new();
+    for v in sources.iter() {
+        let Ok(src) = v.get::<gst::glib::Object>() else { continue };
+        let stats = src.property::<gst::Structure>("stats");
+        if stats.get::<bool>("internal").unwrap_or(true) {
+            continue;
+        }
+        let clock_rate = stats.get::<i32>("clock-rate").ok().filter(|r| *r > 0).unwrap_or(48_000) as f64;
+        let ssrc = stats.get::<u32>("ssrc").unwrap_or(0);
+
+        if stats.get::<bool>("have-rb").unwrap_or(false) {
+            // Receiver report about *our* stream (sender side)
+            let rtt = stats.get::<u32>("rb-round-trip").unwrap_or(0);
+            out.push(RtcpReport {
+                ssrc,
+                packets_received: 0,
+                packets_lost: stats.get::<i32>("rb-packetslost").unwrap_or(0) as i64,
+                fraction_lost_pct: stats.get::<u32>("rb-fractionlost").unwrap_or(0) as f64 * 100.0 / 256.0,
+                jitter_ms: stats.get::<u32>("rb-jitter").unwrap_or(0) as f64 * 1000.0 / clock_rate,
+                rtt_ms: (rtt > 0).then(|| rtt as f64 * 1000.0 / 65536.0),
+            });
+        } else if stats.get::<bool>("is-sender").unwrap_or(false) {
+            // Remote sender as seen by us (receiver side)
+            let received = stats.get::<u64>("packets-received").unwrap_or(0);
+            let lost = stats.get::<i32>("packets-lost").unwrap_or(0) as i64;
+            let expected = received as i64 + lost.max(0);
+            out.push(RtcpReport {
+                ssrc,
+                packets_received: received,
+                packets_lost: lost,
+                fraction_lost_pct: if expected > 0 { lost.max(0) as f64 * 100.0 / expected as f64 } else { 0.0 },
+                jitter_ms: stats.get::<u32>("jitter").unwrap_or(0) as f64 * 1000.0 / clock_rate,
+                rtt_ms: None,
+            });
+        }
+    }
+    out
+}
+
+/// Periodically log RTCP-derived loss/jitter/RTT for session 0 of `rtpbin`.
+fn attach_rtcp_stats(rtpbin: &gst::Element, side: &'static str) {
+    use std::time::Duration;
+
+    let weak = rtpbin.downgrade();
+    std::thread::spawn(move || {
+        loop {
+            std::thread::sleep(Duration::from_secs(5));
+            let Some(rtpbin) = weak.upgrade() else { break };
+            if rtpbin.current_state() != gst::State::Playing {
+                continue;
+            }
+            for r in read_rtcp_reports(&rtpbin) {
+                match r.rtt_ms {
+                    Some(rtt) => log_as!(
+                        info,
+                        side,
+                        "RTCP ssrc={:08x} loss={:.1}% (cum {}) jitter={:.2} ms rtt={:.1} ms",
+                        r.ssrc, r.fraction_lost_pct, r.packets_lost, r.jitter_ms, rtt
+                    ),
+                    None => log_as!(
+                        info,
+                        side,
+                        "RTCP ssrc={:08x} rx={} lost={} ({:.1}%) jitter={:.2} ms",
+                        r.ssrc, r.packets_received, r.packets_lost, r.fraction_lost_pct, r.jitter_ms
+                    ),
+                }
+            }
+        }
+    });
+}
+
+/// RTCP-driven Opus bitrate control: back off multiplicatively on loss or an
+/// RTT spike over the best RTT seen, creep back up while the link is clean.
+/// The worst-reporting receiver wins.
+fn attach_bitrate_controller(rtpbin: &gst::Element, opusenc: &gst::Element, range: BitrateRange) {
+    use std::time::Duration;
+
+    let rtpbin = rtpbin.downgrade();
+    let enc = opusenc.downgrade();
+    std::thread::spawn(move || {
+        let mut base_rtt: Option<f64> = None;
+        loop {
+            // RRs arrive roughly every 5s; adapting faster just chases stale data
+            std::thread::sleep(Duration::from_secs(5));
+            let (Some(rtpbin), Some(enc)) = (rtpbin.upgrade(), enc.upgrade()) else { break };
+            if rtpbin.current_state() != gst::State::Playing {
+                continue;
+            }
+            let reports = read_rtcp_reports(&rtpbin);
+            let Some(worst) = reports
+                .iter()
+                .filter(|r| r.rtt_ms.is_some())
+                .max_by(|a, b| a.fraction_lost_pct.total_cmp(&b.fraction_lost_pct))
+            else {
+                continue;
+            };
+            let loss = worst.fraction_lost_pct;
+            let rtt = worst.rtt_ms.unwrap_or(0.0);
+            let base = base_rtt.map_or(rtt, |b| b.min(rtt));
+            base_rtt = Some(base);
+
+            let cur = enc.property::<i32>("bitrate");
+            let next = if loss > 10.0 {
+                cur as f64 * (1.0 - loss / 200.0)
+            } else if rtt > base * 2.0 + 50.0 {
+                cur as f64 * 0.85
+            } else if loss < 2.0 {
+                cur as f64 * 1.08
+            } else {
+                cur as f64
+            };
+            let next = (next as i32).clamp(range.min, range.max);
+            if next != cur {
+                enc.set_property("bitrate", next);
+                info!(
+                    target: "sender",
+                    "bitrate {cur} → {next} bit/s (loss={loss:.1}%, rtt={rtt:.1} ms, base rtt={base:.1} ms)"
+                );
+            }
+        }
+    });
+}
+
+/// Grow the jitterbuffer as soon as packets arrive too late to play, and give
+/// latency back slowly once the network has been calm, within `range`.
+/// `extra_ms` (the RTX budget) stays on top.
+fn attach_jitter_controller(rtpbin: &gst::Element, range: JitterRange, start_ms: u32, extra_ms: u32) {
+    use std::time::Duration;
+
+    let jitterbuffers: Arc<Mutex<Vec<gst::glib::WeakRef<gst::Element>>>> = Arc::default();
+    let found = jitterbuffers.clone();
+    rtpbin.connect("new-jitterbuffer", false, move |args| {
+        if let Ok(jb) = args[1].get::<gst::Element>() {
+            found.lock().unwrap().push(jb.downgrade());
+        }
+        None
+    });
+    let rtpbin = rtpbin.downgrade();
+    std::thread::spawn(move || {
+        let mut current = start_ms.clamp(range.min, range.max);
+        let (mut late_seen, mut calm) = (0u64, 0u32);
+        loop {
+            std::thread::sleep(Duration::from_secs(2));
+            let Some(rtpbin) = rtpbin.upgrade() else { break };
+            if rtpbin.current_state() != gst::State::Playing {
+                continue;
+            }
+            // Packets that missed their playout time, over every stream
+            let late: u64 = {
+                let mut jbs = jitterbuffers.lock().unwrap();
+                jbs.retain(|jb| jb.upgrade().is_some());
+                jbs.iter()
+                    .filter_map(|jb| jb.upgrade())
+                    .map(|jb| jb.property::<gst::Structure>("stats").get::<u64>("num-late").unwrap_or(0))
+                    .sum()
+            };
+            let new_late = late.saturating_sub(late_seen);
+            late_seen = late;
+            let jitter = read_rtcp_reports(&rtpbin).iter().map(|r| r.jitter_ms).fold(0.0, f64::max);
+            // Room for a few times the interarrival jitter
+            let floor = ((jitter * 4.0).ceil() as u32).clamp(range.min, range.max);
+            let (next, why) = if new_late > 0 {
+                calm = 0;
+                ((current + current / 4).max(current + 5).max(floor), format!("{new_late} late packets"))
+            } else if current < floor {
+                (floor, format!("jitter {jitter:.1} ms"))
+            } else {
+                calm += 1;
+                // 30 s without a late packet: give back a tenth
+                if calm >= 15 && current > floor {
+                    calm = 0;
+                    ((current - current / 10).max(floor), "no late packets for 30 s".to_string())
+                } else {
+                    (current, String::new())
+                }
+            };
+            let next = next.clamp(range.min, range.max);
+            if next != current {
+                rtpbin.set_property("latency", next + extra_ms);
+                info!(target: "receiver", "jitterbuffer {current} → {next} ms ({why})");
+                current = next;
+            }
+        }
+    });
+}
+
+/// Sender RTCP: in/out share one socket so the receiver can answer our SRs
+/// with RRs to the address they came from (symmetric RTCP).
+fn make_sender_rtcp(hosts: &[String], port: u16, bind: Option<IpAddr>) -> Result<(gst::Element, gst::Element)> {
+    // One socket must serve every destination, so go dual-stack if any is IPv6
+    let v6 = match bind {
+        Some(ip) => ip.is_ipv6(),
+        None => hosts.iter().any(|h| resolve_host(h).map(|ip| ip.is_ipv6()).unwrap_or(false)),
+    };
+    let rtcp_src = make_element("udpsrc", "rtcp_src")?;
+    let address = bind.map(|ip| ip.to_string());
+    rtcp_src.set_property("address", address.as_deref().unwrap_or(if v6 { "::" } else { "0.0.0.0" }));
+    rtcp_src.set_property("port", 0i32);
+    rtcp_src.set_property("caps", &gst::Caps::new_empty_simple("application/x-rtcp"));
+    rtcp_src
+        .set_state(gst::State::Ready)
+        .context("sender: rtcp udpsrc to READY")?;
+
+    let rtcp_sink = make_element("multiudpsink", "rtcp_sink")?;
+    let socket_prop = if v6 { "socket-v6" } else { "socket" };
+    rtcp_sink.set_property_from_value(socket_prop, &rtcp_src.property_value("used-socket"));
+    rtcp_sink.set_property("close-socket", false);
+    rtcp_sink.set_property("sync", false);
+    rtcp_sink.set_property("async", false);
+    for h in hosts {
+        // IPv4 peers are reached through the dual-stack socket as ::ffff:a.b.c.d
+        let target = match (v6, resolve_host(h)) {
+            (true, Ok(IpAddr::V4(a))) => a.to_ipv6_mapped().to_string(),
+            _ => h.clone(),
+        };
+        rtcp_sink.emit_by_name::<()>("add", &[&target, &(port as i32 + 1)]);
+    }
+    info!(
+        target: "sender",
+        "rtcp → {}",
+        hosts.iter().map(|h| host_port(h, port + 1)).collect::<Vec<_>>().join(",")
+    );
+    Ok((rtcp_src, rtcp_sink))
+}
+
+/// Receiver RTCP: SRs arrive on listen_port+1; RRs go back to wherever they
+/// came from (see `attach_rtcp_return_path`).
+fn make_receiver_rtcp(bind: IpAddr, listen_port: u16) -> Result<(gst::Element, gst::Element)> {
+    let rtcp_src = make_element("udpsrc", "rtcp_src")?;
+    rtcp_src.set_property("address", bind.to_string());
+    rtcp_src.set_property("port", listen_port as i32 + 1);
+    rtcp_src.set_property("caps", &gst::Caps::new_empty_simple("application/x-rtcp"));
+    info!(target: "receiver", "rtcp udpsrc listening on :{}", listen_port as i32 + 1);
+
+    let rtcp_sink = make_element("multiudpsink", "rtcp_sink")?;
+    rtcp_sink.set_property("sync", false);
+    rtcp_sink.set_property("async", false);
+    Ok((rtcp_src, rtcp_sink))
+}
+
+/// Wrap `elements` (linked in order) in a bin exposing the first sink pad
+/// and/or the last src pad as ghost pads.
+pub(crate) fn make_chain_bin(name: &str, elements: &[&gst::Element]) -> Result<gst::Element> {
+    let bin = gst::Bin::with_name(name);
+    bin.add_many(elements)?;
+    gst::Element::link_many(elements)?;
+    if let Some(pad) = elements.first().and_then(|e| e.static_pad("sink")) {
+        bin.add_pad(&gst::GhostPad::builder_with_target(&pad)?.name("sink").build())?;
+    }
+    if let Some(pad) = elements.last().and_then(|e| e.static_pad("src")) {
+        bin.add_pad(&gst::GhostPad::builder_with_target(&pad)?.name("src").build())?;
+    }
+    Ok(bin.upcast())
+}
+
+/// RTP/RTCP over a TCP connection to `host:port`, framed per RFC 4571.
+fn make_tcp_out(name: &str, host: &str, port: u16) -> Result<gst::Element> {
+    let pay = make_element("rtpstreampay", &format!("{name}_pay"))?;
+    let sink = make_element("tcpclientsink", &format!("{name}_sink"))?;
+    sink.set_property("host", host);
+    sink.set_property("port", port as i32);
+    sink.set_property("sync", false);
+    make_chain_bin(name, &[&pay, &sink])
+}
+
+/// Accept an RFC 4571 framed TCP stream on `port` and restore the packet caps.
+fn make_tcp_in(name: &str, bind: IpAddr, port: u16, caps: &gst::Caps) -> Result<gst::Element> {
+    let src = make_element("tcpserversrc", &format!("{name}_src"))?;
+    src.set_property("host", bind.to_string());
+    src.set_property("port", port as i32);
+    let depay = make_element("rtpstreamdepay", &format!("{name}_depay"))?;
+    let filter = make_element("capsfilter", &format!("{name}_caps"))?;
+    filter.set_property("caps", caps);
+    make_chain_bin(name, &[&src, &depay, &filter])
+}
+
+/// tcpserversrc takes one client and ends its stream when that client hangs
+/// up, after which nothing plays. Unless we're draining, that's a failure: the
+/// receiver is rebuilt (see the daemon's `supervise.rs`) and listens again.
+fn fail_on_eos(pipeline: &gst::Pipeline, name: &str, meter: &Meter) {
+    let Some(pad) = pipeline.by_name(name).and_then(|src| src.static_pad("src")) else { return };
+    let meter = meter.clone();
+    let name = name.to_string();
+    pad.add_probe(gst::PadProbeType::EVENT_DOWNSTREAM, move |_pad, info| {
+        if info.event().is_some_and(|e| e.type_() == gst::EventType::Eos) {
+            let mut watched = meter.lock().unwrap();
+            if !watched.draining && watched.error.is_none() {
+                warn!(target: "receiver", "{name}: the sender disconnected");
+                watched.error = Some(format!("{name}: the sender disconnected"));
+            }
+        }
+        gst::PadProbeReturn::Ok
+    });
+}
+
+/// Learn where RTCP comes from and send our own RTCP back there (symmetric
+/// RTCP, RFC 4961). `sink` must be a `multiudpsink`.
+fn attach_rtcp_return_path(rtcp_src: &gst::Element, sink: &gst::Element) {
+    use std::collections::HashSet;
+
+    let Some(pad) = rtcp_src.static_pad("src") else { return };
+    let known = Arc::new(Mutex::new(HashSet::<String>::new()));
+    let sink = sink.downgrade();
+    pad.add_probe(gst::PadProbeType::BUFFER, move |_pad, info| {
+        let Some(buf) = info.buffer() else { return gst::PadProbeReturn::Ok };
+        let Some(meta) = buf.meta::<gst_net::NetAddressMeta>() else { return gst::PadProbeReturn::Ok };
+        let Ok(addr) = meta.addr().downcast::<gst_net::gio::InetSocketAddress>() else {
+            return gst::PadProbeReturn::Ok;
+        };
+        let mut host = addr.address().to_str().to_string();
+        // A dual-stack socket reports IPv4 peers as ::ffff:a.b.c.d; answer them over IPv4
+        if let Some(v4) = host.parse::<Ipv6Addr>().ok().and_then(|a| a.to_ipv4_mapped()) {
+            host = v4.to_string();
+        }
+        let port = addr.port() as i32;
+        let key = host_port(&host, port as u16);
+        if known.lock().unwrap().insert(key.clone()) {
+            if let Some(sink) = sink.upgrade() {
+                sink.emit_by_name::<()>("add", &[&host, &port]);
+                info!(target: "receiver", "RTCP peer {key} (sending reports back)");
+            }
+        }
+        gst::PadProbeReturn::Ok
+    });
+}
+
+/// Wildcard bind address: `::` (dual-stack, so IPv4 still arrives) when the
+/// host has IPv6, otherwise `0.0.0.0`.
+pub fn any_addr() -> IpAddr {
+    static ANY: OnceLock<IpAddr> = OnceLock::new();
+    *ANY.get_or_init(|| {
+        if std::net::UdpSocket::bind((Ipv6Addr::UNSPECIFIED, 0)).is_ok() {
+            Ipv6Addr::UNSPECIFIED.into()
+        } else {
+            info!(target: "net", "no IPv6 on this host; listening on IPv4 only");
+            Ipv4Addr::UNSPECIFIED.into()
+        }
+    })
+}
+
+/// IP of a destination given as an IPv4/IPv6 literal or a hostname (A or AAAA).
+pub(crate) fn resolve_host(host: &str) -> Result<IpAddr> {
+    if let Ok(ip) = host.parse() {
+        return Ok(ip);
+    }
+    (host, 0)
+        .to_socket_addrs()
+        .with_context(|| format!("resolve {host}"))?
+        .next()
+        .map(|a| a.ip())
+        .with_context(|| format!("{host} has no addresses"))
+}
+
+/// `host:port`, with IPv6 literals bracketed (`[fd00::2]:5002`).
+pub(crate) fn host_port(host: &str, port: u16) -> String {
+    if host.parse::<Ipv6Addr>().is_ok() {
+        format!("[{host}]:{port}")
+    } else {
+        format!("{host}:{port}")
+    }
+}
+
+fn is_multicast(host: &str) -> bool {
+    host.parse::<std::net::IpAddr>()
+        .map(|a| a.is_multicast())
+        .unwrap_or(false)
+}
+
+/// Set up a udpsink/multiudpsink that targets a multicast group.
+pub(crate) fn configure_multicast_sink(sink: &gst::Element, ttl: u32, iface: Option<&str>) {
+    sink.set_property("auto-multicast", true);
+    sink.set_property("ttl-mc", ttl as i32);
+    if let Some(iface) = iface {
+        sink.set_property("multicast-iface", iface);
+    }
+}
+
+/// Mark packets leaving `sink` with `dscp`; false if the element can't.
+pub(crate) fn apply_dscp(sink: &gst::Element, dscp: u8) -> bool {
+    if !sink.has_property("qos-dscp", None) {
+        return false;
+    }
+    sink.set_property("qos-dscp", dscp as i32);
+    true
+}
+
+/// Make a udpsrc join `group` instead of listening on the wildcard address.
+fn configure_multicast_src(src: &gst::Element, group: &str, iface: Option<&str>) {
+    src.set_property("address", group);
+    src.set_property("auto-multicast", true);
+    if let Some(iface) = iface {
+        src.set_property("multicast-iface", iface);
+    }
 }
 
 pub fn init_gst() -> Result<()> {
     gst::init().context("gst init failed")?;
-    eprintln!(
-        "[init] GStreamer {} (GST_PLUGIN_PATH={:?})",
+    info!(
+        target: "init",
+        "GStreamer {} (GST_PLUGIN_PATH={:?})",
         gst::version_string(),
         env::var("GST_PLUGIN_PATH").ok()
     );
@@ -148,143 +1132,228 @@ pub fn init_gst() -> Result<()> {
 /* Linux helper: pick a PulseAudio/pipewire *monitor* source                  */
 /* ------------------------------------------------------------------------- */
 
+/// The monitor the hint names, else the first; from the devices `watch`
+/// keeps up to date when it runs (the daemon), so a monitor plugged in since
+/// the start is there to pick.
 #[cfg(target_os = "linux")]
 fn pick_pulse_monitor(prefer_contains: Option<&str>) -> Option<String> {
-    use gst::{caps::Caps, DeviceMonitor};
-    let mon = DeviceMonitor::new();
-    mon.add_filter(Some("Audio/Source"), Some(&Caps::new_any()));
-    mon.start().ok()?;
-
     let hint = prefer_contains.map(|s| s.to_lowercase());
     let mut first_monitor: Option<String> = None;
-    let mut preferred: Option<String> = None;
-
-    for d in mon.devices() {
-        let name = d.display_name().to_string();     // FIX: GString -> String
-        let class = d.device_class().to_string();    // FIX: GString -> String
-        let props = d.properties();
-
-        // Consider anything whose display name or known props contain "monitor"
-        let mut is_monitor = name.to_lowercase().contains("monitor");
-        let mut dev_id: Option<String> = None;
-
-        if let Some(p) = props {
-            // Pulsesrc expects the "device" string (e.g., "alsa_output...analog-stereo.monitor")
-            if let Ok(v) = p.get::<String>("device") {
-                dev_id = Some(v);
-            } else if let Ok(v) = p.get::<String>("node.name") {
-                dev_id = Some(v);
-            }
-
-            // Extra checks for monitor-ness
-            if !is_monitor {
-                if let Ok(desc) = p.get::<String>("device.description") {
-                    if desc.to_lowercase().contains("monitor") {
-                        is_monitor = true;
-                    }
-                }
-                if let Ok(nodename) = p.get::<String>("node.name") {
-                    if nodename.to_lowercase().contains("monitor") {
-                        is_monitor = true;
-                    }
-                }
-            }
-        }
-
-        if is_monitor {
-            let id = dev_id.clone().unwrap_or_else(|| name.clone());
-            eprintln!(
-                "[linux] found monitor candidate: id='{}' name='{}' class='{}'",
-                id, name, class
-            );
 
-            if first_monitor.is_none() {
-                first_monitor = Some(id.clone());
-            }
-            if let Some(h) = &hint {
-                if name.to_lowercase().contains(h) || id.to_lowercase().contains(h) {
-                    preferred = Some(id);
-                    break;
-                }
+    for d in crate::devices::sources().ok()?.into_iter().filter(|d| d.monitor) {
+        info!(target: "linux", "found monitor candidate: id='{}' name='{}'", d.id, d.name);
+        if let Some(h) = &hint {
+            if d.name.to_lowercase().contains(h) || d.id.to_lowercase().contains(h) {
+                return Some(d.id);
             }
         }
+        if first_monitor.is_none() {
+            first_monitor = Some(d.id);
+        }
     }
+    first_monitor
+}
+
+/// Linux: pipewiresrc on the stream of the app `query` names (see
+/// `apps::find`). While the app plays nothing, a silent stand-in, so the
+/// sender is up to take the app's stream once there is one.
+#[cfg(target_os = "linux")]
+fn make_app_source(query: &str) -> Result<gst::Element> {
+    let streams = crate::apps::streams()?;
+    let Some(stream) = crate::apps::find(query, &streams)? else {
+        warn!(target: "linux", "no app '{query}' is playing; sending silence until one is");
+        return make_silent_source();
+    };
+    let s = make_element("pipewiresrc", "src")?;
+    anyhow::ensure!(
+        s.has_property("target-object", None),
+        "this pipewiresrc can't target a stream (--capture-app needs PipeWire 0.3.64 or later)"
+    );
+    s.set_property("target-object", stream.serial.to_string());
+    // When the app goes, stay unlinked rather than move on to the default
+    // source (the microphone)
+    let props = gst::Structure::builder("props")
+        .field("node.dont-reconnect", "true")
+        .field("node.dont-fallback", "true")
+        .build();
+    s.set_property("stream-properties", props);
+    info!(target: "linux", "capturing '{}' ({}), stream {}", stream.app, stream.binary, stream.serial);
+    Ok(s)
+}
 
-    // FIX: stop() returns (), not Result
-    mon.stop();
-    preferred.or(first_monitor)
+/// Live silence, standing in for a `capture_app` that isn't there yet.
+fn make_silent_source() -> Result<gst::Element> {
+    let s = make_element("audiotestsrc", "src")?;
+    s.set_property("is-live", true);
+    s.set_property_from_str("wave", "silence");
+    Ok(s)
 }
 
 /* ------------------------------------------------------------------------- */
 /* Sender                                                                     */
 /* ------------------------------------------------------------------------- */
 
+/// A pass-through that also plays what goes through it on the local output
+/// (`SenderConfig::local_monitor`): tee → { out, queue → volume → sink }.
+fn make_local_monitor(volume: f64) -> Result<gst::Element> {
+    let tee = make_element("tee", "monitor_tee")?;
+    let q_out = make_element("queue", "monitor_q_out")?;
+    let q_play = make_element("queue", "monitor_q_play")?;
+    // Playing locally must never hold up sending
+    q_play.set_property_from_str("leaky", "downstream");
+    let gain = make_element("volume", "monitor_volume")?;
+    gain.set_property("volume", volume);
+    let convert = make_element("audioconvert", "monitor_conv")?;
+    let sink = make_element("autoaudiosink", "monitor_sink")?;
+    if sink.has_property("sync", None) {
+        sink.set_property("sync", false);
+    }
+    let bin = gst::Bin::with_name("local_monitor");
+    bin.add_many(&[&tee, &q_out, &q_play, &gain, &convert, &sink])?;
+    gst::Element::link_many(&[&tee, &q_out])?;
+    gst::Element::link_many(&[&tee, &q_play, &gain, &convert, &sink])?;
+    let sink_pad = tee.static_pad("sink").context("tee has no sink pad")?;
+    bin.add_pad(&gst::GhostPad::builder_with_target(&sink_pad)?.name("sink").build())?;
+    let src_pad = q_out.static_pad("src").context("queue has no src pad")?;
+    bin.add_pad(&gst::GhostPad::builder_with_target(&src_pad)?.name("src").build())?;
+    info!(target: "sender", "local monitor: playing what's sent at volume {volume:.2}");
+    Ok(bin.upcast())
+}
+
+/// The element for a `Source` other than the capture device.
+fn make_builtin_source(source: &Source) -> Result<Option<gst::Element>> {
+    match source {
+        Source::Capture => Ok(None),
+        Source::Tone(freq) => {
+            let s = make_element("audiotestsrc", "src")?;
+            s.set_property("is-live", true);
+            s.set_property_from_str("wave", "sine");
+            s.set_property("freq", *freq);
+            s.set_property("volume", 0.5f64);
+            info!(target: "sender", "source: {freq} Hz test tone (audiotestsrc)");
+            Ok(Some(s))
+        }
+        Source::File(path) => {
+            anyhow::ensure!(path.is_file(), "no such file: {}", path.display());
+            let file = make_element("filesrc", "file_src")?;
+            file.set_property("location", path.to_string_lossy().as_ref());
+            let decode = make_element("decodebin", "file_decode")?;
+            // Decoding outruns real time and our sinks don't sync: pace it on the clock
+            let pace = make_element("identity", "file_pace")?;
+            pace.set_property("sync", true);
+            let bin = gst::Bin::with_name("src");
+            bin.add_many(&[&file, &decode, &pace])?;
+            file.link(&decode)?;
+            let pace_sink = pace.static_pad("sink").context("identity has no sink pad")?;
+            decode.connect_pad_added(move |_, pad| {
+                let audio = pad.current_caps().and_then(|c| c.structure(0).map(|s| s.name().starts_with("audio/")));
+                if audio == Some(true) && !pace_sink.is_linked() {
+                    if let Err(e) = pad.link(&pace_sink) {
+                        warn!(target: "sender", "file: can't link decoded audio: {e:?}");
+                    }
+                }
+            });
+            let src = pace.static_pad("src").context("identity has no src pad")?;
+            bin.add_pad(&gst::GhostPad::builder_with_target(&src)?.name("src").build())?;
+            info!(target: "sender", "source: file {} (decodebin)", path.display());
+            Ok(Some(bin.upcast()))
+        }
+    }
+}
+
 /// Build an Opus-over-RTP sender.
-/// macOS: normally omit `device_name` and set System Input = BlackHole 2ch.
+/// macOS: normally omit `capture_device` and set System Input = BlackHole 2ch.
 /// Linux: by default we pick a `.monitor` device (system audio), not the mic.
-pub fn build_sender(device_name: Option<&str>, host: &str, port: u16) -> Result<Sender> {
+pub fn build_sender(cfg: &SenderConfig) -> Result<Sender> {
     let pipeline = gst::Pipeline::new();
+    let tasks = background::Tasks::default();
+    if let Some(clock) = &cfg.clock {
+        pipeline.use_clock(Some(clock));
+    }
+    let device_name = cfg.capture_device.as_deref();
+    let port = cfg.port;
+    anyhow::ensure!(!cfg.hosts.is_empty(), "sender needs at least one destination");
+    let clients = |p: u16| {
+        cfg.hosts
+            .iter()
+            .map(|h| host_port(h, p))
+            .collect::<Vec<_>>()
+            .join(",")
+    };
 
     // ---------- Source selection ----------
     #[cfg(target_os = "macos")]
-    let src = {
+    let src = if let Some(s) = make_builtin_source(&cfg.source)? {
+        s
+    } else if let Some(app) = cfg.capture_app.as_deref().filter(|_| device_name.is_none()) {
+        // The daemon points `capture_device` at the app's tap once it has one
+        warn!(target: "sender", "not tapping '{app}' yet; sending silence");
+        make_silent_source()?
+    } else {
         let s = make_element("osxaudiosrc", "src")?;
         // Good macOS defaults (your proven values)
-        let src_buf_us: i64 = env::var("AB_SRC_BUFFER_US").ok().and_then(|v| v.parse().ok()).unwrap_or(200_000);
-        let src_lat_us: i64 = env::var("AB_SRC_LATENCY_US").ok().and_then(|v| v.parse().ok()).unwrap_or(10_000);
+        let src_buf_us = i64::from(cfg.src_buffer_us.unwrap_or(200_000));
+        let src_lat_us = i64::from(cfg.src_latency_us.unwrap_or(10_000));
         if s.has_property("buffer-time", None) {
             s.set_property("buffer-time", src_buf_us);
-            eprintln!("[sender] src.buffer-time={} us", src_buf_us);
+            info!(target: "sender", "src.buffer-time={} us", src_buf_us);
         }
         if s.has_property("latency-time", None) {
             s.set_property("latency-time", src_lat_us);
-            eprintln!("[sender] src.latency-time={} us", src_lat_us);
+            info!(target: "sender", "src.latency-time={} us", src_lat_us);
         }
         if let Some(name) = device_name {
             if s.has_property("device", None) {
-                if let Ok(idx) = name.parse::<i32>() {
-                    s.set_property("device", idx);
-                    eprintln!("[sender] set device index={idx}");
-                } else {
-                    eprintln!("[sender][warn] macOS '--capture-device' must be an integer index; got '{name}'");
-                }
+                let idx = crate::devices::index(name, crate::devices::sources).context("--capture-device")?;
+                s.set_property("device", idx);
+                info!(target: "sender", "set device index={idx} (from --capture-device '{name}')");
             }
         }
         s
     };
 
     #[cfg(target_os = "linux")]
-    let src = {
-        let s = make_element("pulsesrc", "src")?;
+    let src = if let Some(s) = make_builtin_source(&cfg.source)? {
+        s
+    } else if let Some(app) = cfg.capture_app.as_deref() {
+        make_app_source(app)?
+    } else {
+        let s = make_first_element(plugins::LINUX_SOURCES, "src")?;
+        let factory = s.factory().map(|f| f.name().to_string()).unwrap_or_default();
         if let Some(dev) = device_name {
-            if s.has_property("device", None) {
-                s.set_property("device", dev);
-                eprintln!("[linux] pulsesrc.device='{}' (from --capture-device)", dev);
+            if set_linux_device(&s, dev) {
+                info!(target: "linux", "{factory} device='{}' (from --capture-device)", dev);
+            } else {
+                warn!(target: "linux", "{factory} can't take --capture-device '{dev}'; capturing its default");
             }
+        } else if cfg.echo_cancel {
+            // A monitor would carry the peer's own audio straight back to it
+            info!(target: "linux", "echo cancelling: capturing the default source (microphone)");
         } else {
-            let hint = env::var("MONITOR_HINT").ok();
-            match pick_pulse_monitor(hint.as_deref()) {
+            match pick_pulse_monitor(cfg.monitor_hint.as_deref()) {
                 Some(dev) => {
-                    if s.has_property("device", None) {
-                        s.set_property("device", dev.as_str());
-                        eprintln!("[linux] using monitor device='{}'", dev);
+                    if set_linux_device(&s, &dev) {
+                        info!(target: "linux", "using monitor device='{}'", dev);
                     }
                 }
                 None => {
-                    eprintln!("[linux][warn] no monitor source found; falling back to default pulsesrc (may be the mic)");
+                    warn!(
+                        target: "linux",
+                        "no monitor source found; falling back to default {factory} (may be the mic)"
+                    );
                 }
             }
         }
-        if let Ok(v) = env::var("AB_SRC_BUFFER_US").and_then(|v| v.parse::<u64>().map_err(|_| env::VarError::NotPresent)) {
+        if let Some(v) = cfg.src_buffer_us {
             if s.has_property("buffer-time", None) {
-                s.set_property("buffer-time", v);
-                eprintln!("[sender] src.buffer-time={} us", v);
+                s.set_property("buffer-time", i64::from(v));
+                info!(target: "sender", "src.buffer-time={} us", v);
             }
         }
-        if let Ok(v) = env::var("AB_SRC_LATENCY_US").and_then(|v| v.parse::<u64>().map_err(|_| env::VarError::NotPresent)) {
+        if let Some(v) = cfg.src_latency_us {
             if s.has_property("latency-time", None) {
-                s.set_property("latency-time", v);
-                eprintln!("[sender] src.latency-time={} us", v);
+                s.set_property("latency-time", i64::from(v));
+                info!(target: "sender", "src.latency-time={} us", v);
             }
         }
         s
@@ -295,130 +1364,618 @@ pub fn build_sender(device_name: Option<&str>, host: &str, port: u16) -> Result<
     q_src.set_property("max-size-buffers", 0u32);
     q_src.set_property("max-size-bytes", 0u32);
     q_src.set_property("max-size-time", 20_000_000u64);
+    let xruns = xrun::Table::new("sender");
+    xrun::watch_capture(&xruns, &pipeline, &src);
+    xrun::watch_queue(&xruns, &pipeline, &q_src);
 
     let convert = make_element("audioconvert", "aconv")?;
     let resample = make_element("audioresample", "ares")?;
 
+    // AES67 carries 24-bit samples; S32 keeps them intact and `level` can still read it
+    let format = if cfg.aes67.is_some() { "S32LE" } else { "S16LE" };
     let caps = gst::Caps::builder("audio/x-raw")
         .field("rate", 48_000i32)
         .field("channels", 2i32)
-        .field("format", "S16LE")
+        .field("format", format)
         .field("layout", "interleaved")
         .build();
     let capsfilter = make_element("capsfilter", "acaps")?;
     capsfilter.set_property("caps", &caps);
-    eprintln!("[sender] enforce caps: {}", caps.to_string());
+    info!(target: "sender", "enforce caps: {}", caps.to_string());
+    // Echo cancellation and push-to-talk work on the normalized capture
+    let mut stages = vec![capsfilter];
+    if let Some(channel) = cfg.channel {
+        // The one channel on both outputs, so the receiver plays it on all its speakers
+        let pick = make_element("audioconvert", "channel_pick")?;
+        let row = match channel {
+            Channel::Left => "<(float)1.0, (float)0.0>",
+            Channel::Right => "<(float)0.0, (float)1.0>",
+        };
+        pick.set_property_from_str("mix-matrix", &format!("<{row}, {row}>"));
+        info!(target: "sender", "sending the {channel:?} channel only");
+        stages.push(pick);
+    }
+    if cfg.echo_cancel {
+        match make_element("webrtcdsp", "aec") {
+            Ok(dsp) => {
+                dsp.set_property("probe", ECHO_PROBE);
+                info!(target: "sender", "echo cancellation on (webrtcdsp)");
+                stages.push(dsp);
+            }
+            Err(e) => warn!(target: "sender", "no echo cancellation: {e:#}"),
+        }
+    }
+    if cfg.ptt {
+        // Closed until `set_talking(true)`; the gain fades so the valve never cuts mid-wave
+        let gain = make_element("volume", "ptt_gain")?;
+        gain.set_property("volume", 0.0f64);
+        let valve = make_element("valve", "ptt")?;
+        valve.set_property("drop", true);
+        info!(target: "sender", "push-to-talk: muted until talking");
+        stages.extend([gain, valve]);
+    }
+    if let Some(volume) = cfg.local_monitor {
+        stages.push(make_local_monitor(volume)?);
+    }
+    let capsfilter = if stages.len() == 1 {
+        stages.remove(0)
+    } else {
+        make_chain_bin("acaps_chain", &stages.iter().collect::<Vec<_>>())?
+    };
 
     // Live meter of captured audio (before encode)
     let level_tx = make_element("level", "level_tx")?;
     if level_tx.has_property("interval", None) {
-        level_tx.set_property("interval", 100_000_000u64);
+        level_tx.set_property("interval", LEVEL_INTERVAL.as_nanos() as u64);
     }
     if level_tx.has_property("post-messages", None) {
         level_tx.set_property("post-messages", true);
     }
 
+    // ---------- AES67: L24 straight to multicast, no Opus / RTP session ----------
+    if let Some(aes) = &cfg.aes67 {
+        pipeline.add_many(&[&src, &q_src, &convert, &resample, &capsfilter, &level_tx])?;
+        gst::Element::link_many(&[&src, &q_src, &convert, &resample, &capsfilter, &level_tx])?;
+        attach_caps_probe(&src, "src", "snd/src");
+        let target = aes67::Aes67Target {
+            bind: cfg.bind_address,
+            dscp: cfg.dscp,
+            group: &cfg.hosts[0],
+            port,
+            ttl: cfg.multicast_ttl,
+            iface: cfg.multicast_iface.as_deref(),
+        };
+        aes67::attach_sender(&pipeline, &level_tx, &target, aes)?;
+        let pay = pipeline.by_name("pay").context("aes67: no payloader")?;
+        let sent = attach_tx_stats(&pay, "src");
+        let meter = attach_bus_logging(&pipeline, "sender");
+        info!(target: "sender", "pipeline built (aes67)");
+        return Ok(Sender {
+            pipeline,
+            meter,
+            ptt_gen: Arc::default(),
+            silent: Arc::default(),
+            sent,
+            xruns,
+            tasks,
+        });
+    }
+
+    // ---------- VBAN: raw PCM frames, no Opus / RTP session ----------
+    if cfg.transport == Transport::Vban {
+        pipeline.add_many(&[&src, &q_src, &convert, &resample, &capsfilter, &level_tx])?;
+        gst::Element::link_many(&[&src, &q_src, &convert, &resample, &capsfilter, &level_tx])?;
+        attach_caps_probe(&src, "src", "snd/src");
+        vban::attach_sender(&pipeline, &level_tx, &cfg.hosts, port, cfg.bind_address, cfg.dscp)?;
+        let meter = attach_bus_logging(&pipeline, "sender");
+        info!(target: "sender", "pipeline built (vban)");
+        return Ok(Sender {
+            pipeline,
+            meter,
+            ptt_gen: Arc::default(),
+            silent: Arc::default(),
+            sent: Arc::default(),
+            xruns,
+            tasks,
+        });
+    }
+
     // ---------- Opus enc + RTP + UDP ----------
     let opusenc = make_element("opusenc", "opusenc")?;
-    opusenc.set_property("bitrate", 256_000i32);
-    opusenc.set_property("inband-fec", false);
+    let bitrate = match cfg.adaptive_bitrate {
+        Some(r) => cfg.bitrate.clamp(r.min, r.max),
+        None => cfg.bitrate,
+    };
+    opusenc.set_property("bitrate", bitrate);
+    let inband_fec = cfg.fec == Some(FecMode::InBand);
+    opusenc.set_property("inband-fec", inband_fec);
+    if opusenc.has_property("packet-loss-percentage", None) {
+        opusenc.set_property("packet-loss-percentage", cfg.expected_loss_pct);
+    }
+    // In-band FEC only exists in SILK/hybrid mode, which needs >= 10 ms frames;
+    // at 2.5 ms Opus is CELT-only and the flag would silently do nothing.
+    let frame_ms = cfg.frame_ms.unwrap_or(2.5);
+    let frame_size = if inband_fec { frame_ms.max(10.0) } else { frame_ms }.to_string();
     if opusenc.has_property("frame-size", None) {
-        opusenc.set_property_from_str("frame-size", "2.5");
+        opusenc.set_property_from_str("frame-size", &frame_size);
+    }
+    if inband_fec {
+        info!(
+            target: "sender",
+            "opusenc.inband-fec=true, packet-loss-percentage={} (frame-size at least 10ms)",
+            cfg.expected_loss_pct
+        );
     }
     if opusenc.has_property("complexity", None) {
         opusenc.set_property("complexity", 5i32);
-        eprintln!("[sender] opusenc.complexity=5");
+        info!(target: "sender", "opusenc.complexity=5");
     }
-    eprintln!("[sender] opusenc: bitrate=256000, frame-size=2.5ms");
+    info!(target: "sender", "opusenc: bitrate={bitrate}, frame-size={frame_size}ms");
 
     let pay = make_element("rtpopuspay", "pay")?;
     pay.set_property("pt", 97u32);
+    if let Some(ssrc) = cfg.ssrc {
+        // The session ID: receivers drop other SSRCs from us and find our SRTP key by it
+        pay.set_property("ssrc", ssrc);
+    }
 
-    let sink = make_element("udpsink", "udpsink")?;
-    sink.set_property("host", host);
-    sink.set_property("port", port as i32);
-    sink.set_property("sync", false);
-    sink.set_property("async", false);
-    eprintln!("[sender] udpsink → {host}:{port}");
-
-    // ---------- Build & link ----------
+    // ---------- Build & link capture → encode → pay ----------
     pipeline.add_many(&[
-        &src, &q_src, &convert, &resample, &capsfilter, &level_tx, &opusenc, &pay, &sink,
+        &src, &q_src, &convert, &resample, &capsfilter, &level_tx, &opusenc, &pay,
     ])?;
     gst::Element::link_many(&[
-        &src, &q_src, &convert, &resample, &capsfilter, &level_tx, &opusenc, &pay, &sink,
+        &src, &q_src, &convert, &resample, &capsfilter, &level_tx, &opusenc, &pay,
     ])?;
 
     attach_caps_probe(&src, "src", "snd/src");
     attach_caps_probe(&opusenc, "src", "snd/opus");
     attach_caps_probe(&pay, "src", "snd/rtp");
-    attach_tx_stats(&pay, "src", "sender");
-
-    attach_bus_logging(&pipeline, "sender");
-    eprintln!("[sender] pipeline built");
-    Ok(Sender { pipeline })
-}
+    let sent = attach_tx_stats(&pay, "src");
+    let silent = match cfg.suppress_silence {
+        Some(threshold_db) => {
+            info!(target: "sender", "silence suppression below {threshold_db} dBFS");
+            silence::attach(&opusenc, threshold_db, cfg.keepalive)
+        }
+        None => Arc::default(),
+    };
+    if cfg.measure_latency {
+        e2e::stamp(&pay.static_pad("src").context("pay has no src pad")?, &pipeline, cfg.clock.is_some());
+    }
 
-/* ------------------------------------------------------------------------- */
-/* Receiver                                                                   */
-/* ------------------------------------------------------------------------- */
+    // webrtcbin brings its own RTP session, ICE and DTLS-SRTP
+    if cfg.transport == Transport::WebRtc {
+        anyhow::ensure!(cfg.hosts.len() == 1, "WebRTC transport supports a single --send-to peer");
+        webrtc::attach_sender(&tasks, &pipeline, &pay, &cfg.hosts[0], port, cfg.stun_server.as_deref())?;
+        let meter = attach_bus_logging(&pipeline, "sender");
+        info!(target: "sender", "pipeline built (webrtc)");
+        return Ok(Sender { pipeline, meter, ptt_gen: Arc::default(), silent, sent, xruns, tasks });
+    }
 
-pub fn build_receiver(listen_port: u16) -> Result<Receiver> {
-    let pipeline = gst::Pipeline::new();
+    // ---------- RTP session + transport ----------
+    let rtpbin = make_element("rtpbin", "rtpbin")?;
+    if cfg.clock.is_some() {
+        // Reports carry the shared clock's time, which followers map the stream onto
+        rtpbin.set_property_from_str("ntp-time-source", "clock-time");
+    }
+    if let Some(session) = &cfg.srtp {
+        anyhow::ensure!(cfg.ssrc.is_some(), "SRTP needs a pinned SSRC");
+        srtp::attach_encoder(&rtpbin, &session.key);
+    }
+    if let Some(rtx_ms) = cfg.rtx_max_ms {
+        // Keep enough history to answer a NACK that needed the full budget plus an RTT
+        let history_ms = (rtx_ms * 2).max(500);
+        rtpbin.set_property_from_str("rtp-profile", "avpf");
+        let ssrc = cfg.ssrc;
+        rtpbin.connect("request-aux-sender", false, move |args| {
+            let session = args[1].get::<u32>().unwrap_or(0);
+            let bin = make_element("rtprtxsend", &format!("rtxsend_{session}")).and_then(|rtx| {
+                rtx.set_property("payload-type-map", rtx_pt_map());
+                rtx.set_property("max-size-time", history_ms);
+                if let Some(ssrc) = ssrc {
+                    // Pin the RTX SSRC too so the receiver counts it as the same session
+                    let map = gst::Structure::builder("application/x-rtp-ssrc-map")
+                        .field(ssrc.to_string(), ssrc.wrapping_add(1))
+                        .build();
+                    rtx.set_property("ssrc-map", map);
+                }
+                make_rtx_aux_bin(&rtx, session)
+            });
+            match bin {
+                Ok(bin) => Some(bin.to_value()),
+                Err(e) => {
+                    warn!(target: "sender", "RTX disabled: {e:#}");
+                    None
+                }
+            }
+        });
+        info!(target: "sender", "RTX on: pt {RTX_PT}, history {history_ms} ms");
+    }
+    if let Some(FecMode::Ulp { percentage }) = cfg.fec {
+        rtpbin.connect("request-fec-encoder", false, move |args| {
+            let session = args[1].get::<u32>().unwrap_or(0);
+            match make_element("rtpulpfecenc", &format!("fecenc_{session}")) {
+                Ok(enc) => {
+                    enc.set_property("pt", ULPFEC_PT);
+                    enc.set_property("percentage", percentage);
+                    Some(enc.to_value())
+                }
+                Err(e) => {
+                    warn!(target: "sender", "ULP FEC disabled: {e:#}");
+                    None
+                }
+            }
+        });
+        info!(target: "sender", "ULP FEC on: pt {ULPFEC_PT}, {percentage}% redundancy");
+    }
 
-    let src = make_element("udpsrc", "udpsrc")?;
-    src.set_property("port", listen_port as i32);
+    let (sink, rtcp_src, rtcp_sink, quic_ports) = match cfg.transport {
+        Transport::Udp => {
+            // multiudpsink so a single encode can feed several receivers
+            let sink = make_element("multiudpsink", "udpsink")?;
+            if let Some(bind) = cfg.bind_address {
+                sink.set_property("bind-address", bind.to_string());
+            }
+            for h in &cfg.hosts {
+                // Per-host `add` rather than the `clients` string, which can't carry IPv6 literals
+                sink.emit_by_name::<()>("add", &[h, &(port as i32)]);
+            }
+            sink.set_property("sync", false);
+            sink.set_property("async", false);
+            info!(target: "sender", "udpsink → {}", clients(port));
 
-    let rtp_caps = gst::Caps::builder("application/x-rtp")
-        .field("media", "audio")
-        .field("encoding-name", "OPUS")
-        .field("clock-rate", 48_000i32)
-        .field("payload", 97i32)
-        .build();
-    src.set_property("caps", &rtp_caps);
-    eprintln!(
-        "[recv] udpsrc listening on :{} with caps {}",
-        listen_port,
-        rtp_caps.to_string()
-    );
+            let (rtcp_src, rtcp_sink) = make_sender_rtcp(&cfg.hosts, port, cfg.bind_address)?;
 
-    let q_net = make_element("queue", "q_net")?;
-    q_net.set_property("max-size-buffers", 0u32);
-    q_net.set_property("max-size-bytes", 0u32);
-    q_net.set_property("max-size-time", 20_000_000u64);
+            if let Some(group) = cfg.hosts.iter().find(|h| is_multicast(h)) {
+                let iface = cfg.multicast_iface.as_deref();
+                configure_multicast_sink(&sink, cfg.multicast_ttl, iface);
+                configure_multicast_sink(&rtcp_sink, cfg.multicast_ttl, iface);
+                info!(
+                    target: "sender",
+                    "multicast group {group}: ttl={}, iface={}",
+                    cfg.multicast_ttl,
+                    iface.unwrap_or("<default>")
+                );
+            }
+            (sink, Some(rtcp_src), rtcp_sink, None)
+        }
+        Transport::Srt { latency_ms } => {
+            anyhow::ensure!(cfg.hosts.len() == 1, "SRT transport supports a single --send-to destination");
+            let host = &cfg.hosts[0];
+            let sink = make_element("srtsink", "srtsink")?;
+            let adapter = cfg.bind_address.map(|ip| format!("&adapter={ip}")).unwrap_or_default();
+            sink.set_property("uri", format!("srt://{}?mode=caller{adapter}", host_port(host, port)));
+            sink.set_property("latency", latency_ms as i32);
+            sink.set_property("wait-for-connection", false);
+            sink.set_property("sync", false);
+            sink.set_property("async", false);
+            info!(target: "sender", "SRT caller → {host}:{port} (latency {latency_ms} ms)");
 
-    let jitter = make_element("rtpjitterbuffer", "jbuf")?;
-    let jitter_ms: u32 = env::var("JITTER_MS")
-        .ok()
-        .and_then(|v| v.parse().ok())
-        .unwrap_or(30);
-    if jitter.has_property("latency", None) {
-        jitter.set_property("latency", jitter_ms);
-        eprintln!("[recv] jbuf.latency={} ms", jitter_ms);
+            let (rtcp_src, rtcp_sink) = make_sender_rtcp(&cfg.hosts, port, cfg.bind_address)?;
+            (sink, Some(rtcp_src), rtcp_sink, None)
+        }
+        Transport::Tcp => {
+            anyhow::ensure!(cfg.hosts.len() == 1, "TCP transport supports a single --send-to destination");
+            let host = &cfg.hosts[0];
+            let sink = make_tcp_out("tcp_rtp", host, port)?;
+            // No back-channel over TCP: SRs go out, RRs never come back
+            let rtcp_sink = make_tcp_out("tcp_rtcp", host, port + 1)?;
+            info!(target: "sender", "RTP/TCP (RFC 4571) → {host}:{port}, rtcp → {host}:{}", port + 1);
+            (sink, None, rtcp_sink, None)
+        }
+        Transport::Quic => {
+            let rtp_out = quic::make_out("rtp_out")?;
+            let rtcp_out = quic::make_out("rtcp_out")?;
+            let rtcp_in = quic::make_in("rtcp_in", &gst::Caps::new_empty_simple("application/x-rtcp"))?;
+            info!(target: "sender", "QUIC datagrams → {}", clients(port));
+            let ports = quic::QuicPorts {
+                rtp_out: Some(rtp_out.clone()),
+                rtcp_out: rtcp_out.clone(),
+                rtp_in: None,
+                rtcp_in: rtcp_in.clone(),
+            };
+            (rtp_out.upcast(), Some(rtcp_in.upcast()), rtcp_out.upcast(), Some(ports))
+        }
+        Transport::WebRtc | Transport::Vban => unreachable!("handled before the RTP session is built"),
+    };
+
+    if let Some(dscp) = cfg.dscp {
+        if apply_dscp(&sink, dscp) {
+            apply_dscp(&rtcp_sink, dscp);
+            info!(target: "sender", "DSCP {dscp} on RTP/RTCP");
+        } else {
+            warn!(target: "sender", "--dscp only applies to UDP-based RTP; ignored for {:?}", cfg.transport);
+        }
     }
-    if jitter.has_property("drop-on-late", None) {
-        let drop_on_late = env::var("DROP_ON_LATE").map(|v| v == "1").unwrap_or(true);
-        jitter.set_property("drop-on-late", drop_on_late);
-        eprintln!("[recv] jbuf.do-lost=true");
+
+    pipeline.add_many(&[&rtpbin, &sink, &rtcp_sink])?;
+    pay.link_pads(Some("src"), &rtpbin, Some("send_rtp_sink_0"))
+        .context("sender: link pay → rtpbin")?;
+    rtpbin
+        .link_pads(Some("send_rtp_src_0"), &sink, Some("sink"))
+        .context("sender: link rtpbin → udpsink")?;
+    rtpbin
+        .link_pads(Some("send_rtcp_src_0"), &rtcp_sink, Some("sink"))
+        .context("sender: link rtpbin → rtcp_sink")?;
+    if let Some(rtcp_src) = &rtcp_src {
+        pipeline.add(rtcp_src)?;
+        rtcp_src
+            .link_pads(Some("src"), &rtpbin, Some("recv_rtcp_sink_0"))
+            .context("sender: link rtcp_src → rtpbin")?;
     }
-    if jitter.has_property("do-lost", None) {
-        jitter.set_property("do-lost", true);
-        eprintln!("[recv] jbuf.do-lost=true");
+    if let Some(pcap) = &cfg.pcap {
+        let local = SocketAddr::new(cfg.bind_address.unwrap_or(Ipv4Addr::UNSPECIFIED.into()), 0);
+        let peers = |p: u16| {
+            let peers: Vec<_> = cfg
+                .hosts
+                .iter()
+                .map(|h| SocketAddr::new(resolve_host(h).unwrap_or(Ipv4Addr::UNSPECIFIED.into()), p))
+                .collect();
+            move || peers.clone()
+        };
+        pcap::attach(&sink, "sink", pcap, pcap::Way::Out, local, peers(port))?;
+        pcap::attach(&rtcp_sink, "sink", pcap, pcap::Way::Out, local, peers(port + 1))?;
+        if let Some(rtcp_src) = &rtcp_src {
+            pcap::attach(rtcp_src, "src", pcap, pcap::Way::In, local, peers(port + 1))?;
+        }
+        info!(target: "sender", "capturing RTP/RTCP (--pcap)");
+    }
+    if let Some(ports) = quic_ports {
+        quic::spawn_sender(&tasks, &cfg.hosts, port, cfg.bind_address, ports)?;
+    }
+
+    attach_rtcp_stats(&rtpbin, "sender");
+    if let Some(range) = cfg.adaptive_bitrate {
+        info!(target: "sender", "adaptive bitrate {}..{} bit/s", range.min, range.max);
+        attach_bitrate_controller(&rtpbin, &opusenc, range);
     }
 
-    let depay = make_element("rtpopusdepay", "depay")?;
-    let dec = make_element("opusdec", "opusdec")?;
+    let meter = attach_bus_logging(&pipeline, "sender");
+    info!(target: "sender", "pipeline built");
+    Ok(Sender { pipeline, meter, ptt_gen: Arc::default(), silent, sent, xruns, tasks })
+}
+
+/// PCM sink for a Snapcast stream source (snapserver's default 48000:16:2).
+fn make_snapcast_sink(target: &str) -> Result<gst::Element> {
+    let convert = make_element("audioconvert", "snap_conv")?;
+    let caps = make_element("capsfilter", "snap_caps")?;
+    caps.set_property(
+        "caps",
+        gst::Caps::builder("audio/x-raw")
+            .field("format", "S16LE")
+            .field("rate", 48_000i32)
+            .field("channels", 2i32)
+            .field("layout", "interleaved")
+            .build(),
+    );
+    let out = if let Some(addr) = target.strip_prefix("tcp://") {
+        let (host, port) = addr
+            .rsplit_once(':')
+            .and_then(|(h, p)| Some((h, p.parse::<i32>().ok()?)))
+            .with_context(|| format!("snapcast target '{target}' must be tcp://host:port"))?;
+        let s = make_element("tcpclientsink", "snap_tcp")?;
+        s.set_property("host", host);
+        s.set_property("port", port);
+        s
+    } else {
+        // snapserver creates the FIFO (`pipe:///tmp/snapfifo?name=...`); just open it
+        let path = target.strip_prefix("pipe://").unwrap_or(target);
+        let s = make_element("filesink", "snap_pipe")?;
+        s.set_property("location", path);
+        s
+    };
+    // snapserver timestamps on arrival; keep writing in real time
+    out.set_property("sync", true);
+    info!(target: "receiver", "snapcast output → {target} (48000:16:2)");
+    make_chain_bin("sink", &[&convert, &caps, &out])
+}
+
+/* ------------------------------------------------------------------------- */
+/* Receiver                                                                   */
+/* ------------------------------------------------------------------------- */
+
+/// Branch id for an rtpbin `recv_rtp_src_0_<ssrc>_<pt>` pad.
+fn stream_id(pad: &gst::Pad) -> String {
+    pad.name().trim_start_matches("recv_rtp_src_0_").to_string()
+}
+
+/// How incoming streams are decoded.
+#[derive(Clone)]
+struct StreamOpts {
+    fec: Option<FecMode>,
+    plc: bool,
+    /// Where senders' volumes are kept (RTP sessions we handshake for).
+    sessions: Option<Sessions>,
+    /// Correct each stream for its sender's clock rate (see `drift.rs`).
+    drift: bool,
+    /// Where capture-to-speaker latency is kept, when measured (see `e2e.rs`).
+    e2e: Option<e2e::Table>,
+    /// Senders' capture stamps are on our clock (a shared one).
+    shared_clock: bool,
+    /// Where clicks and dropouts in the decoded audio are counted (see `glitch.rs`).
+    glitches: glitch::Table,
+}
+
+/// Decode one incoming RTP stream (one SSRC) into a new mixer input.
+fn add_stream_branch(pipeline: &gst::Pipeline, mixer: &gst::Element, pad: &gst::Pad, opts: &StreamOpts) -> Result<()> {
+    let StreamOpts { fec, plc, ref sessions, drift, ref e2e, shared_clock, ref glitches } = *opts;
+    let id = stream_id(pad);
+    let depay = make_element("rtpopusdepay", &format!("depay_{id}"))?;
+    let dec = make_element("opusdec", &format!("opusdec_{id}"))?;
+    // In-band FEC recovery happens in the PLC path, and whatever ULP FEC can't
+    // rebuild should be concealed, so any FEC turns PLC on regardless of --plc
     if dec.has_property("plc", None) {
-        let plc = env::var("PLC").map(|v| v == "1").unwrap_or(false);
+        let plc = fec.is_some() || plc;
         dec.set_property("plc", plc);
-        eprintln!("[recv] opusdec.plc={plc}");
+        info!(target: "receiver", "{id}: opusdec.plc={plc}");
+    }
+    if fec == Some(FecMode::InBand) && dec.has_property("use-inband-fec", None) {
+        dec.set_property("use-inband-fec", true);
+        info!(target: "receiver", "{id}: opusdec.use-inband-fec=true");
+    }
+    let convert = make_element("audioconvert", &format!("aconv_{id}"))?;
+    let resample = make_element("audioresample", &format!("ares_{id}"))?;
+
+    let chain = [&depay, &dec, &convert, &resample];
+    pipeline.add_many(&chain)?;
+    gst::Element::link_many(&chain)?;
+    let mix_pad = mixer
+        .request_pad_simple("sink_%u")
+        .context("audiomixer: no sink pad")?;
+    resample
+        .static_pad("src")
+        .context("audioresample has no src pad")?
+        .link(&mix_pad)
+        .with_context(|| format!("link stream {id} → mixer"))?;
+    // A volume its sender asked for before the audio arrived
+    let ssrc = id.split('_').next().and_then(|s| s.parse::<u32>().ok());
+    if let Some(volume) = ssrc.and_then(|ssrc| sessions.as_ref()?.volume(ssrc)) {
+        mix_pad.set_property("volume", volume);
+    }
+    for e in chain {
+        e.sync_state_with_parent()?;
+    }
+    pad.link(&depay.static_pad("sink").context("depay has no sink pad")?)
+        .with_context(|| format!("link {} → depay", pad.name()))?;
+
+    attach_caps_probe(&depay, "src", "rcv/opus");
+    if let Some(table) = e2e {
+        e2e::attach_playout(&depay.static_pad("sink").context("depay has no sink pad")?, pipeline, shared_clock, table);
+    }
+    if drift {
+        drift::attach(&dec.static_pad("src").context("opusdec has no src pad")?, &id);
+    }
+    if let Some(ssrc) = ssrc {
+        glitch::attach(&dec.static_pad("src").context("opusdec has no src pad")?, glitches, ssrc, &id);
+    }
+    info!(target: "receiver", "stream {id} → mixer.{}", mix_pad.name());
+    Ok(())
+}
+
+/// Give every `prefix*` pad `source` exposes its own decode branch into
+/// `mixer`, and drop the branch again when the pad goes away.
+fn connect_stream_pads(
+    source: &gst::Element,
+    prefix: &'static str,
+    pipeline: &gst::Pipeline,
+    mixer: &gst::Element,
+    opts: StreamOpts,
+) {
+    let pipeline_weak = pipeline.downgrade();
+    let mixer_weak = mixer.downgrade();
+    source.connect_pad_added(move |_source, pad| {
+        let name = pad.name();
+        if !name.starts_with(prefix) {
+            return;
+        }
+        let (Some(pipeline), Some(mixer)) = (pipeline_weak.upgrade(), mixer_weak.upgrade()) else {
+            return;
+        };
+        if let Err(e) = add_stream_branch(&pipeline, &mixer, pad, &opts) {
+            warn!(target: "receiver", "failed to add stream {name}: {e:#}");
+        }
+    });
+    let pipeline_weak = pipeline.downgrade();
+    let mixer_weak = mixer.downgrade();
+    source.connect_pad_removed(move |_source, pad| {
+        if !pad.name().starts_with(prefix) {
+            return;
+        }
+        if let (Some(pipeline), Some(mixer)) = (pipeline_weak.upgrade(), mixer_weak.upgrade()) {
+            remove_stream_branch(&pipeline, &mixer, pad);
+        }
+    });
+}
+
+/// Raw PCM pushed from our own socket code (VBAN, Scream): appsrc → convert
+/// → resample into `mixer`. Safe to call while the pipeline is playing.
+pub(crate) fn add_pcm_branch(
+    pipeline: &gst::Pipeline,
+    mixer: &gst::Element,
+    name: &str,
+    caps: &gst::Caps,
+) -> Result<gst_app::AppSrc> {
+    let src = gst_app::AppSrc::builder()
+        .name(format!("{name}_src"))
+        .caps(caps)
+        .is_live(true)
+        .do_timestamp(true)
+        .format(gst::Format::Time)
+        .build();
+    let convert = make_element("audioconvert", &format!("{name}_conv"))?;
+    let resample = make_element("audioresample", &format!("{name}_res"))?;
+    pipeline.add_many(&[src.upcast_ref(), &convert, &resample])?;
+    gst::Element::link_many(&[src.upcast_ref(), &convert, &resample])?;
+    let mix_pad = mixer
+        .request_pad_simple("sink_%u")
+        .context("audiomixer: no sink pad")?;
+    resample
+        .static_pad("src")
+        .context("audioresample has no src pad")?
+        .link(&mix_pad)?;
+    for e in [src.upcast_ref(), &convert, &resample] {
+        e.sync_state_with_parent()?;
+    }
+    Ok(src)
+}
+
+/// Tear down the branch `add_stream_branch` built for `pad`.
+fn remove_stream_branch(pipeline: &gst::Pipeline, mixer: &gst::Element, pad: &gst::Pad) {
+    let id = stream_id(pad);
+    if let Some(peer) = pipeline
+        .by_name(&format!("ares_{id}"))
+        .and_then(|e| e.static_pad("src"))
+        .and_then(|p| p.peer())
+    {
+        mixer.release_request_pad(&peer);
+    }
+    for prefix in ["depay", "opusdec", "aconv", "ares"] {
+        if let Some(e) = pipeline.by_name(&format!("{prefix}_{id}")) {
+            let _ = e.set_state(gst::State::Null);
+            let _ = pipeline.remove(&e);
+        }
+    }
+    info!(target: "receiver", "stream {id} gone");
+}
+
+pub fn build_receiver(cfg: &ReceiverConfig) -> Result<Receiver> {
+    let pipeline = gst::Pipeline::new();
+    let tasks = background::Tasks::default();
+    let listen_port = cfg.listen_port;
+    let bind = cfg.bind_address.unwrap_or_else(any_addr);
+    if let Some(clock) = &cfg.clock {
+        pipeline.use_clock(Some(clock));
+    } else if cfg.idle_release.is_some() {
+        // The sink closes while idle, so it mustn't be the one providing the clock
+        pipeline.use_clock(Some(&gst::SystemClock::obtain()));
+    }
+    if cfg.idle_release.is_some()
+        && (cfg.output != Output::Local || matches!(cfg.transport, Transport::WebRtc | Transport::Vban))
+    {
+        warn!(target: "receiver", "--idle-release only applies to local playback over RTP; ignored");
+    }
+    anyhow::ensure!(
+        cfg.relay.is_none() || !matches!(cfg.transport, Transport::WebRtc | Transport::Vban),
+        "receiver: only RTP transports can be relayed (not {})",
+        cfg.transport.name()
+    );
+
+    // Every incoming SSRC gets its own depay/decode branch; audiomixer sums them
+    let mixer = make_element("audiomixer", "mixer")?;
+    if mixer.has_property("ignore-inactive-pads", None) {
+        // A sender that stalls must not hold the others back
+        mixer.set_property("ignore-inactive-pads", true);
+    }
+    if mixer.has_property("start-time-selection", None) {
+        mixer.set_property_from_str("start-time-selection", "first");
     }
     let convert = make_element("audioconvert", "aconv")?;
     let resample = make_element("audioresample", "ares")?;
+    // Playback volume, adjustable at runtime
+    let volume = make_element("volume", "volume")?;
 
     let level = make_element("level", "level")?;
     if level.has_property("interval", None) {
-        level.set_property("interval", 100_000_000u64);
+        level.set_property("interval", LEVEL_INTERVAL.as_nanos() as u64);
     }
     if level.has_property("post-messages", None) {
         level.set_property("post-messages", true);
@@ -427,54 +1984,417 @@ pub fn build_receiver(listen_port: u16) -> Result<Receiver> {
     let q_sink = make_element("queue", "q_sink")?;
     q_sink.set_property("max-size-buffers", 0u32);
     q_sink.set_property("max-size-bytes", 0u32);
-    q_sink.set_property("max-size-time", 20_000_000u64);
+    q_sink.set_property("max-size-time", u64::from(cfg.queue_ms) * 1_000_000);
 
-    let sink = if cfg!(target_os = "macos") {
-        make_element("osxaudiosink", "sink")?
-    } else if let Ok(dev) = env::var("PULSE_SINK") {
-        eprintln!("[recv] using pulsesink device='{dev}' (PULSE_SINK)");
-        let s = make_element("pulsesink", "sink")?;
-        if s.has_property("device", None) {
-            s.set_property("device", dev);
+    // What's played, as the sender's echo canceller hears it
+    let level = if cfg.echo_probe {
+        match make_element("webrtcechoprobe", ECHO_PROBE) {
+            Ok(probe) => make_chain_bin("level_probe", &[&level, &probe])?,
+            Err(e) => {
+                warn!(target: "receiver", "no echo probe: {e:#}");
+                level
+            }
+        }
+    } else {
+        level
+    };
+
+    let mut raop = None;
+    let sink = if let Output::AirPlay { host, cmd } = &cfg.output {
+        let (sink, client) = raop::make_sink(host, cmd)?;
+        raop = Some(client);
+        sink
+    } else if let Output::Snapcast { target } = &cfg.output {
+        make_snapcast_sink(target)?
+    } else if cfg!(target_os = "macos") {
+        let s = make_element("osxaudiosink", "sink")?;
+        if let Some(dev) = cfg.output_device.as_deref().filter(|_| s.has_property("device", None)) {
+            let idx = crate::devices::index(dev, crate::devices::sinks).context("--output-device")?;
+            s.set_property("device", idx);
+            info!(target: "receiver", "osxaudiosink.device={idx} (from --output-device '{dev}')");
         }
         s
-    } else if env::var("AUTO_SINK").as_deref() == Ok("1") {
-        eprintln!("[recv] using autoaudiosink (AUTO_SINK=1)");
+    } else if cfg.auto_sink && cfg.output_device.is_none() {
+        info!(target: "receiver", "using autoaudiosink (--auto-sink)");
         make_element("autoaudiosink", "sink")?
     } else {
-        eprintln!("[recv] using pulsesink (default)");
-        make_element("pulsesink", "sink")?
+        let s = make_first_element(plugins::LINUX_SINKS, "sink")?;
+        let factory = s.factory().map(|f| f.name().to_string()).unwrap_or_default();
+        match &cfg.output_device {
+            Some(dev) if set_linux_device(&s, dev) => {
+                info!(target: "receiver", "using {factory} device='{dev}' (from --output-device)")
+            }
+            Some(dev) => {
+                warn!(target: "receiver", "{factory} can't take --output-device '{dev}'; playing on its default")
+            }
+            None => info!(target: "receiver", "using {factory} (default)"),
+        }
+        s
     };
 
-    let sink_buf_us: i64 = env::var("SINK_BUFFER_US").ok().and_then(|v| v.parse().ok()).unwrap_or(70_000);
-    let sink_lat_us: i64 = env::var("SINK_LATENCY_US").ok().and_then(|v| v.parse().ok()).unwrap_or(15_000);
+    let sink_buf_us = i64::from(cfg.sink_buffer_us);
+    let sink_lat_us = i64::from(cfg.sink_latency_us);
     if sink.has_property("buffer-time", None) {
         sink.set_property("buffer-time", sink_buf_us);
-        eprintln!("[recv] sink.buffer-time={} us", sink_buf_us);
+        info!(target: "receiver", "sink.buffer-time={} us", sink_buf_us);
     }
     if sink.has_property("latency-time", None) {
         sink.set_property("latency-time", sink_lat_us);
-        eprintln!("[recv] sink.latency-time={} us", sink_lat_us);
+        info!(target: "receiver", "sink.latency-time={} us", sink_lat_us);
     }
     if sink.has_property("sync", None) {
-        let sync = env::var("SINK_SYNC").map(|v| v != "0").unwrap_or(true);
+        let sync = cfg.sink_sync;
         sink.set_property("sync", sync);
-        eprintln!("[recv] sink.sync={sync}");
+        info!(target: "receiver", "sink.sync={sync}");
     }
 
-    pipeline.add_many(&[
-        &src, &q_net, &jitter, &depay, &dec, &convert, &resample, &level, &q_sink, &sink,
-    ])?;
-    gst::Element::link_many(&[
-        &src, &q_net, &jitter, &depay, &dec, &convert, &resample, &level, &q_sink, &sink,
-    ])?;
-
-    attach_caps_probe(&depay, "src", "rcv/opus");
+    pipeline.add_many(&[&mixer, &convert, &resample, &volume, &level, &q_sink, &sink])?;
+    // resample → tee → { volume…, file } records the mix whatever the volume;
+    // level → tee → { local sink, HTTP encoder } serves what's played
+    let rec_tee = cfg.record.as_ref().map(|_| make_element("tee", "rec_tee")).transpose()?;
+    let out_tee = cfg.http_port.map(|_| make_element("tee", "out_tee")).transpose()?;
+    let mut chain = vec![&mixer, &convert, &resample];
+    chain.extend(&rec_tee);
+    chain.extend([&volume, &level]);
+    chain.extend(&out_tee);
+    chain.extend([&q_sink, &sink]);
+    for tee in rec_tee.iter().chain(&out_tee) {
+        pipeline.add(tee)?;
+    }
+    gst::Element::link_many(&chain)?;
+    if let (Some(tee), Some(http_port)) = (&out_tee, cfg.http_port) {
+        http::attach_output(&tasks, &pipeline, tee, bind, http_port)?;
+    }
+    let recorder = match (&rec_tee, &cfg.record) {
+        (Some(tee), Some(rec)) => Some(record::attach(&pipeline, tee, rec)?),
+        _ => None,
+    };
     attach_caps_probe(&sink, "sink", "rcv/sink");
+    let xruns = xrun::Table::new("receiver");
+    xrun::watch_queue(&xruns, &pipeline, &q_sink);
+    if cfg.sink_sync {
+        xrun::watch_sink(&xruns, &pipeline, &sink);
+    }
+
+    // Scream PCM joins the mix alongside whatever transport is in use
+    if let Some(group) = cfg.scream_group {
+        // Scream joins by interface address; a pinned IPv4 bind address is exactly that
+        let iface = match cfg.bind_address {
+            Some(IpAddr::V4(a)) => Some(a),
+            _ => cfg.multicast_iface.as_deref().and_then(|i| i.parse().ok()),
+        };
+        scream::attach_receiver(&tasks, &pipeline, &mixer, group, scream::DEFAULT_PORT, iface)?;
+    }
+
+    // VBAN senders feed PCM straight into the mixer
+    if cfg.transport == Transport::Vban {
+        vban::attach_receiver(&tasks, &pipeline, &mixer, bind, listen_port)?;
+        let meter = attach_bus_logging(&pipeline, "receiver");
+        info!(target: "receiver", "pipeline built (vban)");
+        return Ok(Receiver {
+            pipeline,
+            meter,
+            e2e: e2e::Table::default(),
+            recorder,
+            idle: Arc::default(),
+            streams: liveness::Table::default(),
+            rtp: rtpstats::Table::default(),
+            xruns,
+            glitches: glitch::Table::default(),
+            raop,
+            tasks,
+        });
+    }
+
+    // webrtcbin brings its own RTP session, ICE and DTLS-SRTP
+    if cfg.transport == Transport::WebRtc {
+        let webrtc = webrtc::attach_receiver(
+            &tasks,
+            &pipeline,
+            bind,
+            listen_port,
+            cfg.stun_server.as_deref(),
+            cfg.jitter_ms,
+        )?;
+        let opts = StreamOpts {
+            fec: cfg.fec,
+            plc: cfg.plc,
+            sessions: None,
+            drift: cfg.drift_correction,
+            e2e: None,
+            shared_clock: false,
+            glitches: glitch::Table::default(),
+        };
+        connect_stream_pads(&webrtc, "src_", &pipeline, &mixer, opts);
+        let meter = attach_bus_logging(&pipeline, "receiver");
+        info!(target: "receiver", "pipeline built (webrtc)");
+        return Ok(Receiver {
+            pipeline,
+            meter,
+            e2e: e2e::Table::default(),
+            recorder,
+            idle: Arc::default(),
+            streams: liveness::Table::default(),
+            rtp: rtpstats::Table::default(),
+            xruns,
+            glitches: glitch::Table::default(),
+            raop,
+            tasks,
+        });
+    }
+
+    let rtp_caps = gst::Caps::builder("application/x-rtp")
+        .field("media", "audio")
+        .field("encoding-name", "OPUS")
+        .field("clock-rate", 48_000i32)
+        .field("payload", 97i32)
+        .build();
+
+    let q_net = make_element("queue", "q_net")?;
+    q_net.set_property("max-size-buffers", 0u32);
+    q_net.set_property("max-size-bytes", 0u32);
+    q_net.set_property("max-size-time", 20_000_000u64);
+    xrun::watch_queue(&xruns, &pipeline, &q_net);
+
+    // rtpbin's internal jitterbuffer replaces the standalone rtpjitterbuffer
+    let rtpbin = make_element("rtpbin", "rtpbin")?;
+    if let Some(keys) = &cfg.srtp_keys {
+        srtp::attach_decoder(&rtpbin, keys);
+    }
+    let jitter_ms = cfg.jitter_ms;
+    rtpbin.set_property("latency", jitter_ms);
+    info!(target: "receiver", "rtpbin.latency={} ms", jitter_ms);
+    let drop_on_late = cfg.drop_on_late;
+    rtpbin.set_property("drop-on-late", drop_on_late);
+    info!(target: "receiver", "rtpbin.drop-on-late={drop_on_late}");
+    rtpbin.set_property("do-lost", true);
+    info!(target: "receiver", "rtpbin.do-lost=true");
+    if cfg.clock.is_some() {
+        // Place each sender on the shared clock's timeline from its reports
+        rtpbin.set_property_from_str("ntp-time-source", "clock-time");
+        rtpbin.set_property("ntp-sync", true);
+        rtpbin.set_property_from_str("buffer-mode", "synced");
+        info!(target: "receiver", "rtpbin synced to the shared clock");
+    }
+    if let Some(rtx_ms) = cfg.rtx_max_ms {
+        // Retransmissions need room in the jitterbuffer to land before playout
+        rtpbin.set_property("latency", jitter_ms + rtx_ms);
+        rtpbin.set_property("do-retransmission", true);
+        rtpbin.set_property_from_str("rtp-profile", "avpf");
+        rtpbin.connect("request-aux-receiver", false, move |args| {
+            let session = args[1].get::<u32>().unwrap_or(0);
+            let bin = make_element("rtprtxreceive", &format!("rtxrecv_{session}")).and_then(|rtx| {
+                rtx.set_property("payload-type-map", rtx_pt_map());
+                make_rtx_aux_bin(&rtx, session)
+            });
+            match bin {
+                Ok(bin) => Some(bin.to_value()),
+                Err(e) => {
+                    warn!(target: "receiver", "RTX disabled: {e:#}");
+                    None
+                }
+            }
+        });
+        rtpbin.connect("new-jitterbuffer", false, move |args| {
+            if let Ok(jb) = args[1].get::<gst::Element>() {
+                jb.set_property("rtx-retry-period", rtx_ms as i32);
+            }
+            None
+        });
+        info!(
+            target: "receiver",
+            "RTX on: pt {RTX_PT}, retry period {rtx_ms} ms, rtpbin.latency={} ms",
+            jitter_ms + rtx_ms
+        );
+    }
+    if let Some(FecMode::Ulp { .. }) = cfg.fec {
+        rtpbin.connect("request-fec-decoder", false, move |args| {
+            let Ok(rtpbin) = args[0].get::<gst::Element>() else { return None };
+            let session = args[1].get::<u32>().unwrap_or(0);
+            // The decoder rebuilds lost packets from what rtpbin's storage kept
+            if let Some(el) = rtpbin.emit_by_name::<Option<gst::Element>>("get-storage", &[&session]) {
+                el.set_property("size-time", 250_000_000u64);
+            }
+            let storage = rtpbin.emit_by_name::<gst::glib::Object>("get-internal-storage", &[&session]);
+            match make_element("rtpulpfecdec", &format!("fecdec_{session}")) {
+                Ok(dec) => {
+                    dec.set_property("pt", ULPFEC_PT);
+                    dec.set_property("storage", &storage);
+                    Some(dec.to_value())
+                }
+                Err(e) => {
+                    warn!(target: "receiver", "ULP FEC disabled: {e:#}");
+                    None
+                }
+            }
+        });
+        info!(target: "receiver", "ULP FEC on: pt {ULPFEC_PT}");
+    }
+
+    let (src, rtcp_src, rtcp_sink, quic_ports) = match cfg.transport {
+        Transport::Udp => {
+            let src = make_element("udpsrc", "udpsrc")?;
+            src.set_property("address", bind.to_string());
+            src.set_property("port", listen_port as i32);
+            src.set_property("caps", &rtp_caps);
+            info!(
+                target: "receiver",
+                "udpsrc listening on :{} with caps {}",
+                listen_port,
+                rtp_caps.to_string()
+            );
+
+            let (rtcp_src, rtcp_sink) = make_receiver_rtcp(bind, listen_port)?;
+            if let Some(g) = &cfg.source_gate {
+                gate::attach(&src, g, cfg.sessions.as_ref(), false);
+                gate::attach(&rtcp_src, g, cfg.sessions.as_ref(), true);
+            }
+            if let Some(s) = &cfg.sessions {
+                session::bind(s, &rtpbin);
+                session::attach(&src, s, false);
+                session::attach(&rtcp_src, s, true);
+            }
+
+            if let Some(group) = cfg.multicast_group.as_deref() {
+                let iface = cfg.multicast_iface.as_deref();
+                configure_multicast_src(&src, group, iface);
+                configure_multicast_src(&rtcp_src, group, iface);
+                info!(
+                    target: "receiver",
+                    "joined multicast group {group} (iface={})",
+                    iface.unwrap_or("<default>")
+                );
+            }
+            (src, rtcp_src, rtcp_sink, None)
+        }
+        Transport::Srt { latency_ms } => {
+            let src = make_element("srtsrc", "srtsrc")?;
+            src.set_property("uri", format!("srt://{}?mode=listener", host_port(&bind.to_string(), listen_port)));
+            src.set_property("latency", latency_ms as i32);
+            src.set_property("caps", &rtp_caps);
+            info!(target: "receiver", "SRT listener on :{listen_port} (latency {latency_ms} ms)");
+
+            let (rtcp_src, rtcp_sink) = make_receiver_rtcp(bind, listen_port)?;
+            (src, rtcp_src, rtcp_sink, None)
+        }
+        Transport::Tcp => {
+            let src = make_tcp_in("tcp_rtp", bind, listen_port, &rtp_caps)?;
+            let rtcp_src = make_tcp_in("tcp_rtcp", bind, listen_port + 1, &gst::Caps::new_empty_simple("application/x-rtcp"))?;
+            // RRs have no way back over a one-way TCP stream
+            let rtcp_sink = make_element("fakesink", "rtcp_sink")?;
+            rtcp_sink.set_property("sync", false);
+            rtcp_sink.set_property("async", false);
+            info!(target: "receiver", "RTP/TCP (RFC 4571) on :{listen_port}, rtcp on :{}", listen_port + 1);
+            (src, rtcp_src, rtcp_sink, None)
+        }
+        Transport::Quic => {
+            let rtp_in = quic::make_in("rtp_in", &rtp_caps)?;
+            let rtcp_in = quic::make_in("rtcp_in", &gst::Caps::new_empty_simple("application/x-rtcp"))?;
+            let rtcp_out = quic::make_out("rtcp_out")?;
+            info!(target: "receiver", "QUIC datagrams on :{listen_port}");
+            let ports = quic::QuicPorts {
+                rtp_out: None,
+                rtcp_out: rtcp_out.clone(),
+                rtp_in: Some(rtp_in.clone()),
+                rtcp_in: rtcp_in.clone(),
+            };
+            (rtp_in.upcast(), rtcp_in.upcast(), rtcp_out.upcast(), Some(ports))
+        }
+        Transport::WebRtc | Transport::Vban => unreachable!("handled before the RTP session is built"),
+    };
+
+    pipeline.add_many(&[&src, &q_net, &rtpbin, &rtcp_src, &rtcp_sink])?;
+    if let Some(pcap) = &cfg.pcap {
+        let (rtp, rtcp) = (SocketAddr::new(bind, listen_port), SocketAddr::new(bind, listen_port + 1));
+        pcap::attach(&src, "src", pcap, pcap::Way::In, rtp, Vec::new)?;
+        pcap::attach(&rtcp_src, "src", pcap, pcap::Way::In, rtcp, Vec::new)?;
+        // RRs go back where SRs came from (see `attach_rtcp_return_path`); none over TCP
+        if cfg.transport != Transport::Tcp {
+            let sink = rtcp_sink.downgrade();
+            let clients = move || {
+                // A QUIC path's appsink doesn't say
+                let Some(sink) = sink.upgrade().filter(|s| s.has_property("clients", None)) else { return Vec::new() };
+                let clients = sink.property::<String>("clients");
+                clients
+                    .split(',')
+                    .filter_map(|c| {
+                        let (host, port) = c.rsplit_once(':')?;
+                        Some(SocketAddr::new(host.parse().ok()?, port.parse().ok()?))
+                    })
+                    .collect()
+            };
+            pcap::attach(&rtcp_sink, "sink", pcap, pcap::Way::Out, rtcp, clients)?;
+        }
+        info!(target: "receiver", "capturing RTP/RTCP (--pcap)");
+    }
+    match &cfg.relay {
+        Some(r) => relay::attach(&pipeline, &src, &q_net, r, cfg.bind_address)?,
+        None => gst::Element::link_many(&[&src, &q_net])?,
+    }
+    q_net
+        .link_pads(Some("src"), &rtpbin, Some("recv_rtp_sink_0"))
+        .context("receiver: link q_net → rtpbin")?;
+    let e2e = e2e::Table::default();
+    if cfg.measure_latency {
+        let pad = q_net.static_pad("src").context("q_net has no src pad")?;
+        e2e::attach_arrival(&pad, &pipeline, cfg.clock.is_some(), &e2e);
+        e2e::spawn_reporter(&pipeline, &e2e);
+    }
+    let idle = match cfg.idle_release {
+        Some(after) if cfg.output == Output::Local => {
+            let pad = q_net.static_pad("src").context("q_net has no src pad")?;
+            info!(target: "receiver", "releasing the audio device after {} s without packets", after.as_secs());
+            idle::attach(&pipeline, &pad, &sink, after)?
+        }
+        _ => Arc::default(),
+    };
+    rtcp_src
+        .link_pads(Some("src"), &rtpbin, Some("recv_rtcp_sink_0"))
+        .context("receiver: link rtcp_src → rtpbin")?;
+    let streams = liveness::attach(
+        &pipeline,
+        &q_net.static_pad("src").context("q_net has no src pad")?,
+        &rtcp_src.static_pad("src").context("rtcp_src has no src pad")?,
+        cfg.stream_timeout,
+        cfg.srtp_keys.is_none(),
+    );
+    let rtp = rtpstats::attach(&pipeline, &q_net.static_pad("src").context("q_net has no src pad")?, &rtpbin);
+    rtpbin
+        .link_pads(Some("send_rtcp_src_0"), &rtcp_sink, Some("sink"))
+        .context("receiver: link rtpbin → rtcp_sink")?;
+
+    // RTP pads appear per stream (SSRC) and go away on BYE/timeout
+    let glitches = glitch::Table::default();
+    let opts = StreamOpts {
+        fec: cfg.fec,
+        plc: cfg.plc,
+        sessions: cfg.sessions.clone(),
+        // On a shared clock there's no drift to correct
+        drift: cfg.drift_correction && cfg.clock.is_none(),
+        e2e: cfg.measure_latency.then(|| e2e.clone()),
+        shared_clock: cfg.clock.is_some(),
+        glitches: glitches.clone(),
+    };
+    connect_stream_pads(&rtpbin, "recv_rtp_src_", &pipeline, &mixer, opts);
 
-    attach_bus_logging(&pipeline, "receiver");
-    eprintln!("[recv] pipeline built");
-    Ok(Receiver { pipeline })
+    match (quic_ports, cfg.transport) {
+        (Some(ports), _) => quic::spawn_receiver(&tasks, bind, listen_port, ports)?,
+        (None, Transport::Tcp) => {}
+        (None, _) => attach_rtcp_return_path(&rtcp_src, &rtcp_sink),
+    }
+    attach_rtcp_stats(&rtpbin, "receiver");
+    if let Some(range) = cfg.adaptive_jitter {
+        info!(target: "receiver", "adaptive jitterbuffer {}..{} ms", range.min, range.max);
+        attach_jitter_controller(&rtpbin, range, jitter_ms, cfg.rtx_max_ms.unwrap_or(0));
+    }
+
+    let meter = attach_bus_logging(&pipeline, "receiver");
+    if cfg.transport == Transport::Tcp {
+        fail_on_eos(&pipeline, "tcp_rtp_src", &meter);
+        fail_on_eos(&pipeline, "tcp_rtcp_src", &meter);
+    }
+    info!(target: "receiver", "pipeline built");
+    Ok(Receiver { pipeline, meter, e2e, recorder, idle, streams, rtp, xruns, glitches, raop, tasks })
 }
 
 /* ------------------------------------------------------------------------- */
@@ -483,32 +2403,385 @@ pub fn build_receiver(listen_port: u16) -> Result<Receiver> {
 
 impl Sender {
     pub fn start(&self) -> Result<()> {
-        eprintln!("[sender] starting…");
+        anyhow::ensure!(!self.tasks.spent(), "sender: stopped for good, build a new one");
+        info!(target: "sender", "starting…");
         self.pipeline
             .set_state(gst::State::Playing)
             .context("sender: set_state(Playing)")?;
-        eprintln!("[sender] started");
+        info!(target: "sender", "started");
         Ok(())
     }
+    /// Whether silence suppression is holding transmission back right now.
+    pub fn silent(&self) -> bool {
+        self.silent.load(Ordering::SeqCst)
+    }
+
     pub fn stop(&self) {
-        eprintln!("[sender] stopping…");
+        info!(target: "sender", "stopping…");
         let _ = self.pipeline.set_state(gst::State::Null);
-        eprintln!("[sender] stopped");
+        self.tasks.stop();
+        info!(target: "sender", "stopped");
+    }
+
+    /// Stop once what's been captured is encoded and sent, waiting at most
+    /// `timeout` for it.
+    pub fn shutdown(&self, timeout: std::time::Duration) {
+        drain(&self.pipeline, &self.meter, "sender", timeout);
+        self.stop();
+    }
+
+    /// Re-point a running UDP sender at `hosts` (RTP on `port`, RTCP on
+    /// `port`+1) without touching capture or the encoder. Other transports
+    /// hold per-connection state; rebuild the sender for those.
+    pub fn set_hosts(&self, hosts: &[String], port: u16) -> Result<()> {
+        let sink = self
+            .pipeline
+            .by_name("udpsink")
+            .filter(|s| s.factory().is_some_and(|f| f.name() == "multiudpsink"))
+            .context("sender: destinations can only be changed in place on the UDP transport")?;
+        sink.emit_by_name::<()>("clear", &[]);
+        for h in hosts {
+            sink.emit_by_name::<()>("add", &[h, &(port as i32)]);
+        }
+        if let Some(rtcp_sink) = self.pipeline.by_name("rtcp_sink") {
+            // Keep mapping IPv4 peers if the RTCP socket was opened dual-stack
+            let v6 = rtcp_sink
+                .property_value("socket-v6")
+                .get::<Option<gst::glib::Object>>()
+                .ok()
+                .flatten()
+                .is_some();
+            rtcp_sink.emit_by_name::<()>("clear", &[]);
+            for h in hosts {
+                let target = match (v6, resolve_host(h)) {
+                    (true, Ok(IpAddr::V4(a))) => a.to_ipv6_mapped().to_string(),
+                    _ => h.clone(),
+                };
+                rtcp_sink.emit_by_name::<()>("add", &[&target, &(port as i32 + 1)]);
+            }
+        }
+        info!(
+            target: "sender",
+            "destinations → {}",
+            hosts.iter().map(|h| host_port(h, port)).collect::<Vec<_>>().join(",")
+        );
+        Ok(())
+    }
+
+    /// Push-to-talk (`SenderConfig::ptt`): open the valve and fade in, or fade
+    /// out and close it, over `PTT_FADE`.
+    pub fn set_talking(&self, talking: bool) -> Result<()> {
+        let valve = self.pipeline.by_name("ptt").context("sender: push-to-talk is off (--ptt)")?;
+        let gain = self.pipeline.by_name("ptt_gain").context("sender: push-to-talk is off (--ptt)")?;
+        let current = self.ptt_gen.clone();
+        let generation = current.fetch_add(1, Ordering::SeqCst) + 1;
+        if talking {
+            valve.set_property("drop", false);
+        }
+        let from = gain.property::<f64>("volume");
+        let to = if talking { 1.0 } else { 0.0 };
+        std::thread::spawn(move || {
+            for step in 1..=PTT_FADE_STEPS {
+                if current.load(Ordering::SeqCst) != generation {
+                    return;
+                }
+                gain.set_property("volume", from + (to - from) * f64::from(step) / f64::from(PTT_FADE_STEPS));
+                std::thread::sleep(PTT_FADE / PTT_FADE_STEPS);
+            }
+            if !talking && current.load(Ordering::SeqCst) == generation {
+                valve.set_property("drop", true);
+            }
+        });
+        info!(target: "sender", "push-to-talk: {}", if talking { "talking" } else { "muted" });
+        Ok(())
+    }
+
+    /// Whether push-to-talk is open; None without --ptt.
+    pub fn talking(&self) -> Option<bool> {
+        Some(!self.pipeline.by_name("ptt")?.property::<bool>("drop"))
+    }
+
+    /// Latest capture level.
+    pub fn level(&self) -> Option<Level> {
+        self.meter.lock().unwrap().level.clone()
+    }
+
+    /// Recent capture levels, oldest first, `LEVEL_INTERVAL` apart.
+    pub fn levels(&self) -> Vec<Level> {
+        self.meter.lock().unwrap().history.iter().cloned().collect()
+    }
+
+    /// The first error the pipeline posted, if any: it has stopped working
+    /// and needs rebuilding.
+    pub fn error(&self) -> Option<String> {
+        self.meter.lock().unwrap().error.clone()
+    }
+
+    /// The pipeline is in PLAYING.
+    pub fn playing(&self) -> bool {
+        self.pipeline.current_state() == gst::State::Playing
+    }
+
+    /// The pipeline as a Graphviz graph (elements, pads, caps and states),
+    /// for bug reports.
+    pub fn dot(&self) -> String {
+        gst::debug_bin_to_dot_data(&self.pipeline, gst::DebugGraphDetails::all()).to_string()
+    }
+
+    /// What receivers report about our stream (loss, jitter, RTT), one per
+    /// receiver. Empty on transports without an rtpbin.
+    pub fn reports(&self) -> Vec<RtcpReport> {
+        self.pipeline.by_name("rtpbin").map(|b| read_rtcp_reports(&b)).unwrap_or_default()
+    }
+
+    /// Packets and bytes sent so far; zero for VBAN, which isn't counted.
+    pub fn sent(&self) -> TxTotals {
+        *self.sent.lock().unwrap()
+    }
+
+    /// How much captured audio is waiting to be encoded, in ms.
+    pub fn queued_ms(&self) -> Option<f64> {
+        queued_ms(&self.pipeline, "q_src")
+    }
+
+    /// Capture and queue overruns so far (see `xrun.rs`).
+    pub fn xrun_counts(&self) -> xrun::XrunCounts {
+        self.xruns.counts()
+    }
+
+    /// Recent overrun events, oldest first.
+    pub fn xruns(&self) -> Vec<xrun::Xrun> {
+        self.xruns.events()
+    }
+
+    /// Current Opus bitrate in bit/s; None for the PCM modes (AES67, VBAN).
+    pub fn bitrate(&self) -> Option<i32> {
+        Some(self.pipeline.by_name("opusenc")?.property::<i32>("bitrate"))
+    }
+
+    /// Change the Opus bitrate on the fly. With --adaptive-bitrate the
+    /// controller keeps moving it from here.
+    pub fn set_bitrate(&self, bitrate: i32) -> Result<()> {
+        let enc = self.pipeline.by_name("opusenc").context("sender: no Opus encoder (PCM mode)")?;
+        enc.set_property("bitrate", bitrate);
+        info!(target: "sender", "opusenc.bitrate={bitrate}");
+        Ok(())
+    }
+
+    /// Linux: the app stream (`object.serial`) captured for `capture_app`;
+    /// None while there's none to capture.
+    #[cfg(target_os = "linux")]
+    pub fn app_stream(&self) -> Option<u64> {
+        let src = self.pipeline.by_name("src")?;
+        if !src.has_property("target-object", None) {
+            return None;
+        }
+        src.property::<Option<String>>("target-object")?.parse().ok()
+    }
+
+    /// Linux: the device pulsesrc captures from now, which the sound server
+    /// can have moved it to (when the one asked for went away).
+    #[cfg(target_os = "linux")]
+    pub fn current_device(&self) -> Option<String> {
+        current_device(&self.pipeline.by_name("src")?)
+    }
+
+    /// macOS: the device index osxaudiosrc captures from, if it was given one.
+    #[cfg(target_os = "macos")]
+    pub fn device_index(&self) -> Option<i32> {
+        let src = self.pipeline.by_name("src")?;
+        src.has_property("device", None).then(|| src.property::<i32>("device"))
+    }
+}
+
+impl Drop for Receiver {
+    fn drop(&mut self) {
+        // fdsink must be done with the RAOP client's pipe before it's closed
+        if self.raop.is_some() {
+            let _ = self.pipeline.set_state(gst::State::Null);
+        }
     }
 }
 
 impl Receiver {
     pub fn start(&self) -> Result<()> {
-        eprintln!("[recv] starting…");
+        anyhow::ensure!(!self.tasks.spent(), "receiver: stopped for good, build a new one");
+        info!(target: "receiver", "starting…");
+        if let Some(recorder) = &self.recorder {
+            recorder.resume()?;
+        }
         self.pipeline
             .set_state(gst::State::Playing)
             .context("receiver: set_state(Playing)")?;
-        eprintln!("[recv] started");
+        info!(target: "receiver", "started");
         Ok(())
     }
     pub fn stop(&self) {
-        eprintln!("[recv] stopping…");
+        info!(target: "receiver", "stopping…");
+        // Let the recording's encoder finish its file first
+        if let Some(recorder) = &self.recorder {
+            recorder.finish();
+        }
+        // A sink released while idle is locked in READY; let it follow the pipeline
+        if let Some(sink) = self.pipeline.by_name("sink") {
+            let _ = sink.set_locked_state(false);
+        }
         let _ = self.pipeline.set_state(gst::State::Null);
-        eprintln!("[recv] stopped");
+        self.tasks.stop();
+        info!(target: "receiver", "stopped");
+    }
+
+    /// Stop once what's in the jitterbuffer and queues has played, waiting at
+    /// most `timeout` for it.
+    pub fn shutdown(&self, timeout: std::time::Duration) {
+        // The sink's input is held while idle; nothing would come out
+        if !self.idle() {
+            drain(&self.pipeline, &self.meter, "receiver", timeout);
+        }
+        self.stop();
+    }
+
+    /// Latest level of the played mix.
+    pub fn level(&self) -> Option<Level> {
+        self.meter.lock().unwrap().level.clone()
+    }
+
+    /// Recent levels of the played mix, oldest first, `LEVEL_INTERVAL` apart.
+    pub fn levels(&self) -> Vec<Level> {
+        self.meter.lock().unwrap().history.iter().cloned().collect()
+    }
+
+    /// The first error the pipeline posted, if any: it has stopped working
+    /// and needs rebuilding.
+    pub fn error(&self) -> Option<String> {
+        self.meter.lock().unwrap().error.clone()
+    }
+
+    /// The pipeline is in PLAYING.
+    pub fn playing(&self) -> bool {
+        self.pipeline.current_state() == gst::State::Playing
+    }
+
+    /// Linux: the device pulsesink plays on now (see `Sender::current_device`).
+    #[cfg(target_os = "linux")]
+    pub fn current_device(&self) -> Option<String> {
+        current_device(&self.pipeline.by_name("sink")?)
+    }
+
+    /// macOS: the device index osxaudiosink plays on, if it was given one.
+    #[cfg(target_os = "macos")]
+    pub fn device_index(&self) -> Option<i32> {
+        let sink = self.pipeline.by_name("sink")?;
+        sink.has_property("device", None).then(|| sink.property::<i32>("device"))
+    }
+
+    /// The pipeline as a Graphviz graph (elements, pads, caps and states),
+    /// for bug reports.
+    pub fn dot(&self) -> String {
+        gst::debug_bin_to_dot_data(&self.pipeline, gst::DebugGraphDetails::all()).to_string()
+    }
+
+    /// Reception stats per incoming sender (loss, jitter). Empty on
+    /// transports without an rtpbin.
+    pub fn reports(&self) -> Vec<RtcpReport> {
+        self.pipeline.by_name("rtpbin").map(|b| read_rtcp_reports(&b)).unwrap_or_default()
+    }
+
+    /// Playback volume (1.0 = unity).
+    pub fn volume(&self) -> f64 {
+        self.pipeline.by_name("volume").map_or(1.0, |v| v.property::<f64>("volume"))
+    }
+
+    /// Set playback volume, 0.0..=10.0 (1.0 = unity).
+    pub fn set_volume(&self, volume: f64) -> Result<()> {
+        anyhow::ensure!((0.0..=10.0).contains(&volume), "volume {volume} out of range (0.0-10.0)");
+        let v = self.pipeline.by_name("volume").context("receiver: no volume element")?;
+        v.set_property("volume", volume);
+        info!(target: "receiver", "volume={volume:.2}");
+        Ok(())
+    }
+
+    /// Whether playback is muted (the volume is kept for unmuting).
+    pub fn muted(&self) -> bool {
+        self.pipeline.by_name("volume").is_some_and(|v| v.property::<bool>("mute"))
+    }
+
+    pub fn set_muted(&self, muted: bool) -> Result<()> {
+        let v = self.pipeline.by_name("volume").context("receiver: no volume element")?;
+        v.set_property("mute", muted);
+        info!(target: "receiver", "muted={muted}");
+        Ok(())
+    }
+    /// End-to-end latency the running pipeline settled on (jitterbuffer, queues
+    /// and sink), from a latency query. None until it's playing.
+    pub fn latency(&self) -> Option<std::time::Duration> {
+        let mut query = gst::query::Latency::new();
+        if !self.pipeline.query(&mut query) {
+            return None;
+        }
+        let (live, min, _max) = query.result();
+        live.then(|| std::time::Duration::from_nanos(min.nseconds()))
+    }
+
+    /// Capture-to-speaker latency per sender, when measured (`measure_latency`).
+    pub fn e2e_latency(&self) -> Vec<e2e::E2eLatency> {
+        self.e2e.snapshot()
+    }
+
+    /// Whether each sender is playing, silent or gone, by SSRC (RTP
+    /// transports).
+    pub fn stream_states(&self) -> Vec<(u32, liveness::StreamState)> {
+        self.streams.snapshot()
+    }
+
+    /// Sequence gaps, reordering, duplicates, late drops and loss per sender
+    /// (RTP transports; see `rtpstats.rs`).
+    pub fn rtp_stats(&self) -> Vec<rtpstats::RtpStats> {
+        self.rtp.snapshot()
+    }
+
+    /// Clicks and dropouts heard in each sender's decoded audio (see
+    /// `glitch.rs`).
+    pub fn glitches(&self) -> Vec<glitch::Glitches> {
+        self.glitches.snapshot()
+    }
+
+    /// The audio device is released for lack of packets (`idle_release`).
+    pub fn idle(&self) -> bool {
+        self.idle.load(Ordering::SeqCst)
+    }
+
+    /// How much decoded audio is waiting for the sink, in ms; None when the
+    /// output has no such queue.
+    pub fn queued_ms(&self) -> Option<f64> {
+        queued_ms(&self.pipeline, "q_sink")
+    }
+
+    /// Sink underruns and queue overruns so far (see `xrun.rs`).
+    pub fn xrun_counts(&self) -> xrun::XrunCounts {
+        self.xruns.counts()
+    }
+
+    /// Recent underrun and overrun events, oldest first.
+    pub fn xruns(&self) -> Vec<xrun::Xrun> {
+        self.xruns.events()
+    }
+
+    /// Current jitterbuffer latency in ms, RTX budget included.
+    pub fn jitter_ms(&self) -> Option<u32> {
+        let bin = self.pipeline.by_name("rtpbin").or_else(|| self.pipeline.by_name("webrtc"))?;
+        Some(bin.property::<u32>("latency"))
+    }
+
+    /// Change the jitterbuffer latency (include any RTX budget) without a rebuild.
+    pub fn set_latency_ms(&self, ms: u32) -> Result<()> {
+        let bin = self
+            .pipeline
+            .by_name("rtpbin")
+            .or_else(|| self.pipeline.by_name("webrtc"))
+            .context("receiver: this transport has no jitterbuffer")?;
+        bin.set_property("latency", ms);
+        info!(target: "receiver", "latency={ms} ms");
+        Ok(())
     }
 }
@@ -0,0 +1,186 @@
+// Where the GStreamer elements we build come from: each element's plugin and
+// the package that ships it, so a missing element can be reported as what to
+// install instead of "failed to make element". Package names follow the
+// usual split (core, base, good, bad, plus the sound-server plugins some
+// distros package on their own) and the package manager found on this
+// machine, as scripts/linux_setup.sh picks it; on macOS Homebrew's formulas.
+// Where there are alternatives (the Linux audio elements) the first one
+// installed is used.
+use anyhow::{Result, anyhow};
+use gstreamer as gst;
+
+/// Linux capture elements, best first: PulseAudio (or PipeWire's pulse
+/// server), PipeWire itself, plain ALSA.
+pub const LINUX_SOURCES: &[&str] = &["pulsesrc", "pipewiresrc", "alsasrc"];
+/// Linux playback elements, best first.
+pub const LINUX_SINKS: &[&str] = &["pulsesink", "alsasink", "autoaudiosink"];
+
+/// The package an element's plugin comes in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Package {
+    Core,
+    Base,
+    Good,
+    Bad,
+    Alsa,
+    Pulse,
+    PipeWire,
+}
+
+/// Element, plugin, package.
+const ELEMENTS: &[(&str, &str, Package)] = &[
+    ("capsfilter", "coreelements", Package::Core),
+    ("fakesink", "coreelements", Package::Core),
+    ("fdsink", "coreelements", Package::Core),
+    ("filesink", "coreelements", Package::Core),
+    ("filesrc", "coreelements", Package::Core),
+    ("identity", "coreelements", Package::Core),
+    ("queue", "coreelements", Package::Core),
+    ("tee", "coreelements", Package::Core),
+    ("valve", "coreelements", Package::Core),
+    ("appsink", "app", Package::Base),
+    ("appsrc", "app", Package::Base),
+    ("audioconvert", "audioconvert", Package::Base),
+    ("audiomixer", "audiomixer", Package::Base),
+    ("audioresample", "audioresample", Package::Base),
+    ("audiotestsrc", "audiotestsrc", Package::Base),
+    ("decodebin", "playback", Package::Base),
+    ("multifdsink", "tcp", Package::Base),
+    ("oggmux", "ogg", Package::Base),
+    ("opusdec", "opus", Package::Base),
+    ("opusenc", "opus", Package::Base),
+    ("tcpclientsink", "tcp", Package::Base),
+    ("tcpserversrc", "tcp", Package::Base),
+    ("volume", "volume", Package::Base),
+    ("alsasink", "alsa", Package::Alsa),
+    ("alsasrc", "alsa", Package::Alsa),
+    ("autoaudiosink", "autodetect", Package::Good),
+    ("flacenc", "flac", Package::Good),
+    ("level", "level", Package::Good),
+    ("multiudpsink", "udp", Package::Good),
+    ("osxaudiosink", "osxaudio", Package::Good),
+    ("osxaudiosrc", "osxaudio", Package::Good),
+    ("rtpL24pay", "rtp", Package::Good),
+    ("rtpbin", "rtpmanager", Package::Good),
+    ("rtpopusdepay", "rtp", Package::Good),
+    ("rtpopuspay", "rtp", Package::Good),
+    ("rtprtxreceive", "rtpmanager", Package::Good),
+    ("rtprtxsend", "rtpmanager", Package::Good),
+    ("rtpstreamdepay", "rtpmanager", Package::Good),
+    ("rtpstreampay", "rtpmanager", Package::Good),
+    ("rtpulpfecdec", "rtp", Package::Good),
+    ("rtpulpfecenc", "rtp", Package::Good),
+    ("udpsink", "udp", Package::Good),
+    ("udpsrc", "udp", Package::Good),
+    ("wavenc", "wavenc", Package::Good),
+    ("pulsesink", "pulseaudio", Package::Pulse),
+    ("pulsesrc", "pulseaudio", Package::Pulse),
+    ("srtpdec", "srtp", Package::Bad),
+    ("srtpenc", "srtp", Package::Bad),
+    ("srtsink", "srt", Package::Bad),
+    ("srtsrc", "srt", Package::Bad),
+    ("webrtcbin", "webrtc", Package::Bad),
+    ("webrtcdsp", "webrtcdsp", Package::Bad),
+    ("webrtcechoprobe", "webrtcdsp", Package::Bad),
+    ("pipewiresink", "pipewire", Package::PipeWire),
+    ("pipewiresrc", "pipewire", Package::PipeWire),
+];
+
+/// The plugin and package `element` comes in, if it's one we build.
+pub fn origin(element: &str) -> Option<(&'static str, Package)> {
+    ELEMENTS.iter().find(|(e, ..)| *e == element).map(|&(_, plugin, package)| (plugin, package))
+}
+
+/// The installed GStreamer's version (major, minor, micro) and its description.
+/// Call after `init_gst`.
+pub fn version() -> ((u32, u32, u32), String) {
+    let (major, minor, micro, _nano) = gst::version();
+    ((major, minor, micro), gst::version_string().to_string())
+}
+
+/// Whether the installed GStreamer can make `element`. Call after `init_gst`.
+pub fn available(element: &str) -> bool {
+    gst::ElementFactory::find(element).is_some()
+}
+
+/// The first of `alternatives` that's installed; if none is, an error saying
+/// what to install for the first. Call after `init_gst`.
+pub fn first_available(alternatives: &[&'static str]) -> Result<&'static str> {
+    alternatives.iter().copied().find(|e| available(e)).ok_or_else(|| match alternatives {
+        [only] => anyhow!("GStreamer element '{only}' isn't installed: {}", install_hint(only)),
+        _ => anyhow!("none of {} is installed: {}", alternatives.join(", "), install_hint(alternatives[0])),
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Manager {
+    Apt,
+    Dnf,
+    Pacman,
+    Brew,
+    Unknown,
+}
+
+fn manager() -> Manager {
+    if cfg!(target_os = "macos") {
+        return Manager::Brew;
+    }
+    let on_path = |cmd: &str| {
+        std::env::var_os("PATH").is_some_and(|path| std::env::split_paths(&path).any(|dir| dir.join(cmd).is_file()))
+    };
+    [("apt", Manager::Apt), ("dnf", Manager::Dnf), ("pacman", Manager::Pacman)]
+        .into_iter()
+        .find(|(cmd, _)| on_path(cmd))
+        .map_or(Manager::Unknown, |(_, m)| m)
+}
+
+impl Package {
+    /// Its name for this machine's package manager.
+    pub fn name(self) -> &'static str {
+        use Package::*;
+        match (manager(), self) {
+            (Manager::Apt, Core) => "libgstreamer1.0-0",
+            (Manager::Apt, Base) => "gstreamer1.0-plugins-base",
+            (Manager::Apt, Good) => "gstreamer1.0-plugins-good",
+            (Manager::Apt, Bad) => "gstreamer1.0-plugins-bad",
+            (Manager::Apt, Alsa) => "gstreamer1.0-alsa",
+            (Manager::Apt, Pulse) => "gstreamer1.0-pulseaudio",
+            (Manager::Apt, PipeWire) => "gstreamer1.0-pipewire",
+            (Manager::Dnf, Core) => "gstreamer1",
+            (Manager::Dnf, Base | Alsa) => "gstreamer1-plugins-base",
+            (Manager::Dnf, Good | Pulse) => "gstreamer1-plugins-good",
+            (Manager::Dnf, Bad) => "gstreamer1-plugins-bad-free",
+            (Manager::Dnf, PipeWire) => "pipewire-gstreamer",
+            (Manager::Pacman, Core) => "gstreamer",
+            (Manager::Pacman, Base | Alsa) => "gst-plugins-base",
+            (Manager::Pacman, Good | Pulse) => "gst-plugins-good",
+            (Manager::Pacman, Bad) => "gst-plugins-bad",
+            (Manager::Pacman, PipeWire) => "gst-plugin-pipewire",
+            (Manager::Brew, Core) => "gstreamer",
+            (Manager::Brew, Base | Alsa) => "gst-plugins-base",
+            (Manager::Brew, Good | Pulse) => "gst-plugins-good",
+            (Manager::Brew, Bad) => "gst-plugins-bad",
+            (Manager::Unknown, Core) => "GStreamer",
+            (Manager::Unknown, Base | Alsa) => "GStreamer's base plugins",
+            (Manager::Unknown, Good | Pulse) => "GStreamer's good plugins",
+            (Manager::Unknown, Bad) => "GStreamer's bad plugins",
+            (Manager::Brew | Manager::Unknown, PipeWire) => "PipeWire's GStreamer plugin",
+        }
+    }
+}
+
+/// What to run (or do) to get `element`, e.g.
+/// `sudo apt install gstreamer1.0-plugins-bad (srtp plugin)`.
+pub fn install_hint(element: &str) -> String {
+    let Some((plugin, package)) = origin(element) else {
+        return format!("install the GStreamer plugin that provides '{element}' (gst-inspect-1.0 {element})");
+    };
+    let name = package.name();
+    match manager() {
+        Manager::Apt => format!("sudo apt install {name} ({plugin} plugin)"),
+        Manager::Dnf => format!("sudo dnf install {name} ({plugin} plugin)"),
+        Manager::Pacman => format!("sudo pacman -S {name} ({plugin} plugin)"),
+        Manager::Brew => format!("brew install {name} ({plugin} plugin)"),
+        Manager::Unknown => format!("install {name} ({plugin} plugin)"),
+    }
+}
@@ -0,0 +1,304 @@
+// QUIC datagram transport: RTP/RTCP packets leave the pipeline through
+// appsinks and re-enter through appsrcs on the far side, carried as QUIC
+// datagrams (RFC 9221). Each datagram is one packet prefixed with a kind byte.
+//
+// The receiver is the QUIC server (it owns the listen port); senders connect
+// and reconnect. Certificates are self-signed per run and NOT verified — this
+// gives encryption and congestion control, not authentication, so the daemon
+// refuses QUIC wherever senders or receivers must be authenticated.
+//
+// Each side runs its endpoint on a runtime thread owned by the pipeline's
+// `Tasks`: stopping the pipeline closes the endpoint and frees the port.
+use anyhow::{Context, Result};
+use bytes::Bytes;
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use gstreamer_app as gst_app;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+use crate::background::Tasks;
+use crate::pipeline::any_addr;
+
+const KIND_RTP: u8 = 0;
+const KIND_RTCP: u8 = 1;
+const ALPN: &[u8] = b"audiobridge/1";
+
+/// Pipeline endpoints a set of QUIC connections is spliced into.
+pub(crate) struct QuicPorts {
+    pub rtp_out: Option<gst_app::AppSink>,
+    pub rtcp_out: gst_app::AppSink,
+    pub rtp_in: Option<gst_app::AppSrc>,
+    pub rtcp_in: gst_app::AppSrc,
+}
+
+type Conns = Arc<Mutex<Vec<quinn::Connection>>>;
+
+/// appsink used as the pipeline end of a QUIC path.
+pub(crate) fn make_out(name: &str) -> Result<gst_app::AppSink> {
+    let sink = gst_app::AppSink::builder().name(name).sync(false).build();
+    sink.set_property("async", false);
+    debug!(target: "build", "created appsink as {name}");
+    Ok(sink)
+}
+
+/// appsrc used as the pipeline start of a QUIC path.
+pub(crate) fn make_in(name: &str, caps: &gst::Caps) -> Result<gst_app::AppSrc> {
+    let src = gst_app::AppSrc::builder()
+        .name(name)
+        .caps(caps)
+        .is_live(true)
+        .do_timestamp(true)
+        .format(gst::Format::Time)
+        .build();
+    debug!(target: "build", "created appsrc as {name}");
+    Ok(src)
+}
+
+/// Sender side: connect to every `host:port` and keep reconnecting.
+pub(crate) fn spawn_sender(
+    tasks: &Tasks,
+    hosts: &[String],
+    port: u16,
+    bind: Option<IpAddr>,
+    ports: QuicPorts,
+) -> Result<()> {
+    let conns: Conns = Arc::default();
+    wire_outputs(&ports, &conns);
+    let hosts = hosts.to_vec();
+    let ports = Arc::new(ports);
+
+    let rt = runtime()?;
+    let endpoint = {
+        let _guard = rt.enter();
+        let mut endpoint = quinn::Endpoint::client(SocketAddr::new(bind.unwrap_or_else(any_addr), 0))?;
+        endpoint.set_default_client_config(client_config()?);
+        endpoint
+    };
+    let run = endpoint.clone();
+    spawn_runtime(tasks, "quic-sender", rt, endpoint, async move {
+        let endpoint = run;
+        for host in hosts {
+            let endpoint = endpoint.clone();
+            let conns = conns.clone();
+            let ports = ports.clone();
+            tokio::spawn(async move {
+                let mut backoff = Duration::from_millis(500);
+                loop {
+                    match connect(&endpoint, &host, port).await {
+                        Ok(conn) => {
+                            info!(target: "quic", "connected to {host}:{port}");
+                            backoff = Duration::from_millis(500);
+                            conns.lock().unwrap().push(conn.clone());
+                            let reason = pump_inputs(conn.clone(), ports.clone()).await;
+                            conns.lock().unwrap().retain(|c| c.stable_id() != conn.stable_id());
+                            warn!(target: "quic", "connection to {host}:{port} closed: {reason}");
+                        }
+                        Err(e) => warn!(target: "quic", "connect {host}:{port} failed: {e:#}"),
+                    }
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(Duration::from_secs(10));
+                }
+            });
+        }
+        // The reconnect loops above run until the pipeline stops
+        std::future::pending::<()>().await;
+    })
+}
+
+/// Receiver side: serve QUIC on `listen_port` and accept any number of senders.
+pub(crate) fn spawn_receiver(tasks: &Tasks, bind: IpAddr, listen_port: u16, ports: QuicPorts) -> Result<()> {
+    let conns: Conns = Arc::default();
+    wire_outputs(&ports, &conns);
+    let ports = Arc::new(ports);
+    let server = server_config()?;
+
+    let rt = runtime()?;
+    let addr = SocketAddr::new(bind, listen_port);
+    let endpoint = {
+        let _guard = rt.enter();
+        quinn::Endpoint::server(server, addr).with_context(|| format!("quic: listen on {addr}"))?
+    };
+    info!(target: "quic", "listening on {addr}");
+    let run = endpoint.clone();
+    spawn_runtime(tasks, "quic-receiver", rt, endpoint, async move {
+        let endpoint = run;
+        while let Some(incoming) = endpoint.accept().await {
+            let conns = conns.clone();
+            let ports = ports.clone();
+            tokio::spawn(async move {
+                let conn = match incoming.await {
+                    Ok(c) => c,
+                    Err(e) => {
+                        warn!(target: "quic", "handshake failed: {e}");
+                        return;
+                    }
+                };
+                let peer = conn.remote_address();
+                info!(target: "quic", "sender {peer} connected");
+                conns.lock().unwrap().push(conn.clone());
+                let reason = pump_inputs(conn.clone(), ports).await;
+                conns.lock().unwrap().retain(|c| c.stable_id() != conn.stable_id());
+                info!(target: "quic", "sender {peer} gone: {reason}");
+            });
+        }
+    })
+}
+
+/// A current-thread runtime of its own, so callers need no tokio context.
+fn runtime() -> Result<tokio::runtime::Runtime> {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .context("quic: tokio runtime")
+}
+
+/// Drive `fut` on `rt` until the pipeline stops, then close `endpoint`. The
+/// runtime goes with the thread, taking every task and endpoint handle along.
+fn spawn_runtime<F>(
+    tasks: &Tasks,
+    name: &str,
+    rt: tokio::runtime::Runtime,
+    endpoint: quinn::Endpoint,
+    fut: F,
+) -> Result<()>
+where
+    F: std::future::Future<Output = ()> + Send + 'static,
+{
+    let label = name.to_string();
+    tasks.spawn(name, move |stop| {
+        rt.block_on(async move {
+            tokio::select! {
+                () = fut => {}
+                () = stop.wait() => {}
+            }
+            endpoint.close(quinn::VarInt::from_u32(0), b"stopped");
+            // Let connected peers hear about it, without waiting on ones that are gone
+            let _ = tokio::time::timeout(Duration::from_secs(1), endpoint.wait_idle()).await;
+        });
+        info!(target: "quic", "{label} stopped");
+    })
+}
+
+async fn connect(endpoint: &quinn::Endpoint, host: &str, port: u16) -> Result<quinn::Connection> {
+    let addr = (host, port)
+        .to_socket_addrs()?
+        .next()
+        .with_context(|| format!("{host} did not resolve"))?;
+    let conn = endpoint.connect(addr, "ab-node")?.await?;
+    Ok(conn)
+}
+
+/// Forward everything the pipeline emits on the appsinks to all live connections.
+fn wire_outputs(ports: &QuicPorts, conns: &Conns) {
+    let outs = [(ports.rtp_out.as_ref(), KIND_RTP), (Some(&ports.rtcp_out), KIND_RTCP)];
+    for (sink, kind) in outs {
+        let Some(sink) = sink else { continue };
+        let conns = conns.clone();
+        sink.set_callbacks(
+            gst_app::AppSinkCallbacks::builder()
+                .new_sample(move |sink| {
+                    let sample = sink.pull_sample().map_err(|_| gst::FlowError::Eos)?;
+                    let buf = sample.buffer().ok_or(gst::FlowError::Error)?;
+                    let map = buf.map_readable().map_err(|_| gst::FlowError::Error)?;
+                    let mut dgram = Vec::with_capacity(map.len() + 1);
+                    dgram.push(kind);
+                    dgram.extend_from_slice(&map);
+                    let dgram = Bytes::from(dgram);
+                    for c in conns.lock().unwrap().iter() {
+                        // Datagrams are unreliable by design; a full buffer is just loss
+                        let _ = c.send_datagram(dgram.clone());
+                    }
+                    Ok(gst::FlowSuccess::Ok)
+                })
+                .build(),
+        );
+    }
+}
+
+/// Push datagrams from one connection into the pipeline until it closes.
+async fn pump_inputs(conn: quinn::Connection, ports: Arc<QuicPorts>) -> quinn::ConnectionError {
+    loop {
+        let dgram = match conn.read_datagram().await {
+            Ok(d) => d,
+            Err(e) => return e,
+        };
+        let Some((&kind, payload)) = dgram.split_first() else { continue };
+        let target = match kind {
+            KIND_RTP => ports.rtp_in.as_ref(),
+            KIND_RTCP => Some(&ports.rtcp_in),
+            _ => None,
+        };
+        if let Some(src) = target {
+            let _ = src.push_buffer(gst::Buffer::from_slice(payload.to_vec()));
+        }
+    }
+}
+
+fn server_config() -> Result<quinn::ServerConfig> {
+    let cert = rcgen::generate_simple_self_signed(vec!["ab-node".into()])?;
+    let cert_der = cert.cert.der().clone();
+    let key_der = rustls::pki_types::PrivatePkcs8KeyDer::from(cert.key_pair.serialize_der());
+    let mut crypto = rustls::ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(vec![cert_der], key_der.into())?;
+    crypto.alpn_protocols = vec![ALPN.to_vec()];
+    Ok(quinn::ServerConfig::with_crypto(Arc::new(
+        quinn::crypto::rustls::QuicServerConfig::try_from(crypto)?,
+    )))
+}
+
+fn client_config() -> Result<quinn::ClientConfig> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let mut crypto = rustls::ClientConfig::builder()
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(AcceptAnyCert(provider)))
+        .with_no_client_auth();
+    crypto.alpn_protocols = vec![ALPN.to_vec()];
+    Ok(quinn::ClientConfig::new(Arc::new(
+        quinn::crypto::rustls::QuicClientConfig::try_from(crypto)?,
+    )))
+}
+
+/// Receivers use throwaway self-signed certs, so there is nothing to verify
+/// against: this is why QUIC isn't offered with authentication. Signatures are
+/// still checked so the handshake itself is sound.
+#[derive(Debug)]
+struct AcceptAnyCert(Arc<rustls::crypto::CryptoProvider>);
+
+impl rustls::client::danger::ServerCertVerifier for AcceptAnyCert {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &rustls::pki_types::CertificateDer<'_>,
+        _intermediates: &[rustls::pki_types::CertificateDer<'_>],
+        _server_name: &rustls::pki_types::ServerName<'_>,
+        _ocsp: &[u8],
+        _now: rustls::pki_types::UnixTime,
+    ) -> Result<rustls::client::danger::ServerCertVerified, rustls::Error> {
+        Ok(rustls::client::danger::ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &rustls::pki_types::CertificateDer<'_>,
+        dss: &rustls::DigitallySignedStruct,
+    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &rustls::pki_types::CertificateDer<'_>,
+        dss: &rustls::DigitallySignedStruct,
+    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
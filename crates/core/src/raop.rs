@@ -0,0 +1,72 @@
+// AirPlay (RAOP) output: GStreamer has no RAOP sink, so the decoded mix is
+// handed to an external RAOP client as raw PCM on its stdin. By default that
+// is `raop_play` from libraop (`raop_play <host> -`); --raop-cmd names a
+// different client that reads 44.1 kHz S16LE stereo from stdin. The client
+// lives as long as the receiver: when the receiver goes, its stdin is closed
+// and it's stopped, so the speaker is free for the next one.
+use anyhow::{Context, Result};
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use std::os::fd::AsRawFd;
+use std::process::{Child, ChildStdin, Command, Stdio};
+use tracing::{info, warn};
+
+use crate::pipeline::{make_chain_bin, make_element};
+
+/// The running RAOP client and the pipe into it. Drop it only once the
+/// pipeline writing to the pipe has stopped.
+pub(crate) struct Client {
+    child: Child,
+    stdin: Option<ChildStdin>,
+    host: String,
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        // EOF first, then make sure it's gone; a client that died on its own
+        // (the pipe broke, and the receiver with it) is just reaped
+        drop(self.stdin.take());
+        if let Ok(None) = self.child.try_wait() {
+            let _ = self.child.kill();
+        }
+        match self.child.wait() {
+            Ok(status) => info!(target: "raop", "client for {} stopped: {status}", self.host),
+            Err(e) => warn!(target: "raop", "client for {}: {e}", self.host),
+        }
+    }
+}
+
+/// Sink bin that resamples to AirPlay's 44.1 kHz and pipes PCM into the RAOP
+/// client `cmd`, started here; the client is the caller's to keep.
+pub(crate) fn make_sink(host: &str, cmd: &str) -> Result<(gst::Element, Client)> {
+    let mut child = Command::new(cmd)
+        .arg(host)
+        .arg("-")
+        .stdin(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("failed to start RAOP client '{cmd}' (install libraop or pass --raop-cmd)"))?;
+    let stdin = child.stdin.take();
+    let client = Client { child, stdin, host: host.to_string() };
+    let fd = client.stdin.as_ref().context("RAOP client has no stdin")?.as_raw_fd();
+
+    let convert = make_element("audioconvert", "raop_conv")?;
+    let resample = make_element("audioresample", "raop_res")?;
+    let caps = make_element("capsfilter", "raop_caps")?;
+    caps.set_property(
+        "caps",
+        gst::Caps::builder("audio/x-raw")
+            .field("format", "S16LE")
+            .field("rate", 44_100i32)
+            .field("channels", 2i32)
+            .field("layout", "interleaved")
+            .build(),
+    );
+    let fdsink = make_element("fdsink", "raop_fd")?;
+    // Still ours: `client` closes it. If the client exits, writes fail and
+    // the receiver errors out to be rebuilt
+    fdsink.set_property("fd", fd);
+    // The RAOP client buffers and paces itself; don't block on our clock
+    fdsink.set_property("sync", false);
+    info!(target: "raop", "streaming to AirPlay device {host} via '{cmd}'");
+    Ok((make_chain_bin("sink", &[&convert, &resample, &caps, &fdsink])?, client))
+}
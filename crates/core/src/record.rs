@@ -0,0 +1,230 @@
+// Recording (--record): the receiver's mix, before the playback volume, teed
+// into a WAV or FLAC file alongside playback. With a size or duration limit
+// the recording rotates: the next numbered file takes over between two
+// buffers, and the old one gets an EOS so its encoder writes the final
+// header before the file is closed.
+use anyhow::{Context, Result, bail};
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use crate::pipeline::make_element;
+
+/// How often the current file is checked against the limits.
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// How long a file may take to be finished once it's handed its EOS.
+const FINISH_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Where and how to record.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordConfig {
+    /// A `.wav` or `.flac` file; with a limit set, files are numbered after it
+    /// (`radio-0001.flac`, `radio-0002.flac`…) and existing ones are skipped.
+    pub path: PathBuf,
+    /// Start a new file once this one reaches this size, in bytes.
+    pub max_bytes: Option<u64>,
+    /// Start a new file after this long.
+    pub max_duration: Option<Duration>,
+}
+
+impl RecordConfig {
+    fn rotates(&self) -> bool {
+        self.max_bytes.is_some() || self.max_duration.is_some()
+    }
+
+    /// The file to write after number `n`, which is advanced.
+    fn next_path(&self, n: &mut u32) -> PathBuf {
+        *n += 1;
+        if !self.rotates() {
+            return self.path.clone();
+        }
+        let stem = self.path.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+        let ext = self.path.extension().unwrap_or_default().to_string_lossy().into_owned();
+        loop {
+            let path = self.path.with_file_name(format!("{stem}-{n:04}.{ext}"));
+            if !path.exists() {
+                return path;
+            }
+            *n += 1;
+        }
+    }
+}
+
+fn encoder(path: &Path) -> Result<&'static str> {
+    match path.extension().and_then(|e| e.to_str()).map(str::to_ascii_lowercase).as_deref() {
+        Some("wav") => Ok("wavenc"),
+        Some("flac") => Ok("flacenc"),
+        _ => bail!("record: {} must end in .wav or .flac", path.display()),
+    }
+}
+
+/// One file being written: `queue ! audioconvert ! encoder ! filesink`.
+struct Segment {
+    bin: gst::Bin,
+    path: PathBuf,
+    opened: Instant,
+    /// The EOS reached the file: everything, header included, is written.
+    done: Arc<AtomicBool>,
+}
+
+impl Segment {
+    fn open(pipeline: &gst::Pipeline, path: PathBuf, n: u32) -> Result<Self> {
+        let queue = make_element("queue", &format!("rec_q_{n}"))?;
+        // A slow disk must never stall playback
+        queue.set_property_from_str("leaky", "downstream");
+        queue.set_property("max-size-buffers", 0u32);
+        queue.set_property("max-size-bytes", 0u32);
+        queue.set_property("max-size-time", 2_000_000_000u64);
+        let convert = make_element("audioconvert", &format!("rec_conv_{n}"))?;
+        let enc = make_element(encoder(&path)?, &format!("rec_enc_{n}"))?;
+        let sink = make_element("filesink", &format!("rec_sink_{n}"))?;
+        sink.set_property("location", path.to_string_lossy().as_ref());
+        sink.set_property("sync", false);
+        sink.set_property("async", false);
+
+        let bin = gst::Bin::with_name(&format!("rec_{n}"));
+        bin.add_many(&[&queue, &convert, &enc, &sink])?;
+        gst::Element::link_many(&[&queue, &convert, &enc, &sink])?;
+        let pad = queue.static_pad("sink").context("queue has no sink pad")?;
+        bin.add_pad(&gst::GhostPad::builder_with_target(&pad)?.name("sink").build())?;
+
+        let done = Arc::new(AtomicBool::new(false));
+        let flag = done.clone();
+        let sink_pad = sink.static_pad("sink").context("filesink has no sink pad")?;
+        sink_pad.add_probe(gst::PadProbeType::EVENT_DOWNSTREAM, move |_pad, info| {
+            if let Some(ev) = info.event() {
+                if let gst::EventView::Eos(_) = ev.view() {
+                    flag.store(true, Ordering::SeqCst);
+                }
+            }
+            gst::PadProbeReturn::Ok
+        });
+
+        pipeline.add(&bin)?;
+        bin.sync_state_with_parent()?;
+        info!(target: "record", "writing {}", path.display());
+        Ok(Self { bin, path, opened: Instant::now(), done })
+    }
+
+    fn sink_pad(&self) -> Result<gst::Pad> {
+        self.bin.static_pad("sink").context("record bin has no sink pad")
+    }
+
+    /// Wait for the EOS to reach the file, then close it and take it out.
+    fn close(self, pipeline: &gst::Pipeline) {
+        let deadline = Instant::now() + FINISH_TIMEOUT;
+        while !self.done.load(Ordering::SeqCst) && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        if !self.done.load(Ordering::SeqCst) {
+            warn!(target: "record", "{} not finished in time; its header may be incomplete", self.path.display());
+        }
+        let _ = self.bin.set_state(gst::State::Null);
+        let _ = pipeline.remove(&self.bin);
+        info!(target: "record", "finished {}", self.path.display());
+    }
+}
+
+struct State {
+    cfg: RecordConfig,
+    /// The tee's pad the current file hangs off.
+    tee_pad: gst::Pad,
+    current: Option<Segment>,
+    n: u32,
+}
+
+/// The recording branch of a receiver.
+pub(crate) struct Recorder {
+    pipeline: gst::glib::WeakRef<gst::Pipeline>,
+    state: Arc<Mutex<State>>,
+}
+
+/// Record what passes `tee` as `cfg` says.
+pub(crate) fn attach(pipeline: &gst::Pipeline, tee: &gst::Element, cfg: &RecordConfig) -> Result<Recorder> {
+    encoder(&cfg.path)?;
+    let tee_pad = tee.request_pad_simple("src_%u").context("tee: no src pad")?;
+    let state = Arc::new(Mutex::new(State { cfg: cfg.clone(), tee_pad, current: None, n: 0 }));
+    let recorder = Recorder { pipeline: pipeline.downgrade(), state };
+    recorder.resume()?;
+    if cfg.rotates() {
+        spawn_rotation(pipeline, &recorder.state);
+    }
+    Ok(recorder)
+}
+
+impl Recorder {
+    /// Start a file if none is being written (after `finish`).
+    pub(crate) fn resume(&self) -> Result<()> {
+        let Some(pipeline) = self.pipeline.upgrade() else { return Ok(()) };
+        let mut guard = self.state.lock().unwrap();
+        let s = &mut *guard;
+        if s.current.is_some() {
+            return Ok(());
+        }
+        let path = s.cfg.next_path(&mut s.n);
+        let segment = Segment::open(&pipeline, path, s.n)?;
+        s.tee_pad.link(&segment.sink_pad()?).context("record: link tee → file")?;
+        s.current = Some(segment);
+        Ok(())
+    }
+
+    /// Finish the file being written, before the pipeline stops.
+    pub(crate) fn finish(&self) {
+        let Some(pipeline) = self.pipeline.upgrade() else { return };
+        let mut s = self.state.lock().unwrap();
+        let Some(segment) = s.current.take() else { return };
+        if let Ok(pad) = segment.sink_pad() {
+            pad.send_event(gst::event::Eos::new());
+            let _ = s.tee_pad.unlink(&pad);
+        }
+        segment.close(&pipeline);
+    }
+}
+
+/// Start the next file whenever the current one reaches a limit.
+fn spawn_rotation(pipeline: &gst::Pipeline, state: &Arc<Mutex<State>>) {
+    let (pipeline, state) = (pipeline.downgrade(), Arc::downgrade(state));
+    std::thread::spawn(move || {
+        loop {
+            std::thread::sleep(CHECK_INTERVAL);
+            let (Some(pipeline), Some(state)) = (pipeline.upgrade(), state.upgrade()) else { break };
+            let mut s = state.lock().unwrap();
+            let Some(current) = &s.current else { continue };
+            let size = std::fs::metadata(&current.path).map_or(0, |m| m.len());
+            // Nothing written yet (no audio): keep the file rather than leave empty ones behind
+            let full = size > 0
+                && (s.cfg.max_bytes.is_some_and(|max| size >= max)
+                    || s.cfg.max_duration.is_some_and(|max| current.opened.elapsed() >= max));
+            if !full {
+                continue;
+            }
+            if let Err(e) = rotate(&mut s, &pipeline) {
+                warn!(target: "record", "can't start the next file: {e:#}");
+            }
+        }
+    });
+}
+
+fn rotate(s: &mut State, pipeline: &gst::Pipeline) -> Result<()> {
+    let path = s.cfg.next_path(&mut s.n);
+    let next = Segment::open(pipeline, path, s.n)?;
+    let new_sink = next.sink_pad()?;
+    let Some(old) = s.current.replace(next) else { return Ok(()) };
+    let old_sink = old.sink_pad()?;
+    // Swap files between two buffers, so no audio falls in between
+    s.tee_pad.add_probe(gst::PadProbeType::BLOCK_DOWNSTREAM, move |pad, _info| {
+        let _ = pad.unlink(&old_sink);
+        if let Err(e) = pad.link(&new_sink) {
+            warn!(target: "record", "link next file: {e:?}");
+        }
+        old_sink.send_event(gst::event::Eos::new());
+        gst::PadProbeReturn::Remove
+    });
+    let pipeline = pipeline.clone();
+    std::thread::spawn(move || old.close(&pipeline));
+    Ok(())
+}
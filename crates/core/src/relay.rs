@@ -0,0 +1,61 @@
+// Relay: forward the RTP a receiver takes in, untouched (no decode or
+// re-encode), to more receivers over UDP. A wired node can then fan one
+// stream out to several Wi-Fi speakers while the sender encodes and sends it
+// once. Packets are copied after the source gate and session filter, so only
+// admitted senders are passed on; SRTP stays encrypted with the original
+// sender's key, which only its own handshakes hand out.
+use anyhow::{Context, Result};
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use std::net::IpAddr;
+use tracing::info;
+
+use crate::pipeline::{configure_multicast_sink, host_port, make_element};
+
+/// Where relayed RTP goes.
+#[derive(Debug, Clone)]
+pub struct RelayConfig {
+    pub hosts: Vec<String>,
+    pub port: u16,
+    /// TTL for multicast destinations.
+    pub multicast_ttl: u32,
+    pub multicast_iface: Option<String>,
+}
+
+/// Link `src` → tee → `next`, with the tee also feeding a multiudpsink to
+/// `cfg.hosts`. `src` and `next` must be in `pipeline` and not yet linked.
+pub(crate) fn attach(
+    pipeline: &gst::Pipeline,
+    src: &gst::Element,
+    next: &gst::Element,
+    cfg: &RelayConfig,
+    bind: Option<IpAddr>,
+) -> Result<()> {
+    let tee = make_element("tee", "relay_tee")?;
+    // A stalled destination must not hold up local playback
+    let queue = make_element("queue", "relay_q")?;
+    queue.set_property_from_str("leaky", "downstream");
+    queue.set_property("max-size-time", 50_000_000u64);
+    let sink = make_element("multiudpsink", "relay_sink")?;
+    if let Some(bind) = bind.filter(|b| !b.is_unspecified()) {
+        sink.set_property("bind-address", bind.to_string());
+    }
+    for h in &cfg.hosts {
+        sink.emit_by_name::<()>("add", &[h, &(cfg.port as i32)]);
+    }
+    if cfg.hosts.iter().any(|h| h.parse::<IpAddr>().is_ok_and(|a| a.is_multicast())) {
+        configure_multicast_sink(&sink, cfg.multicast_ttl, cfg.multicast_iface.as_deref());
+    }
+    sink.set_property("sync", false);
+    sink.set_property("async", false);
+
+    pipeline.add_many(&[&tee, &queue, &sink])?;
+    gst::Element::link_many(&[src, &tee, next]).context("relay: link source → tee → receiver")?;
+    gst::Element::link_many(&[&tee, &queue, &sink]).context("relay: link tee → relay_sink")?;
+    info!(
+        target: "relay",
+        "forwarding RTP → {}",
+        cfg.hosts.iter().map(|h| host_port(h, cfg.port)).collect::<Vec<_>>().join(",")
+    );
+    Ok(())
+}
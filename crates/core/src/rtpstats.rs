@@ -0,0 +1,253 @@
+// RTP reception stats on the receiver, per sender (SSRC), from the sequence
+// numbers as packets arrive: gaps, packets that come after a later one
+// (reordered) or twice (duplicates), and packets the jitterbuffer dropped for
+// missing their playout time (late). Loss is what RFC 3550 counts, packets
+// the sequence numbers say were sent less those that arrived, since the
+// stream started and over the last `WINDOW`. A stream that had trouble in a
+// window gets a log line at its end.
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::info;
+
+/// Recent loss is over this long.
+pub const WINDOW: Duration = Duration::from_secs(10);
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// Opus's payload type; RTX and FEC come under other types.
+const OPUS_PT: u8 = 97;
+/// A jump further than this is a new sequence, not loss (RFC 3550's MAX_DROPOUT).
+const MAX_DROPOUT: i32 = 3000;
+/// Sequence numbers remembered behind the newest, to tell duplicates from
+/// reordered packets.
+const SEEN: u64 = 4096;
+/// Forget a stream after this long without RTP.
+const FORGET_AFTER: Duration = Duration::from_secs(600);
+
+/// One sender's stream as it arrived.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RtpStats {
+    pub ssrc: u32,
+    /// Packets received, duplicates included.
+    pub received: u64,
+    /// Packets the sequence numbers say were sent.
+    pub expected: u64,
+    /// Expected packets that never arrived.
+    pub lost: u64,
+    /// Runs of missing sequence numbers.
+    pub gaps: u64,
+    pub reordered: u64,
+    pub duplicates: u64,
+    /// Dropped by the jitterbuffer for arriving too late to play.
+    pub late: u64,
+    pub loss_pct: f64,
+    /// Loss over the last `WINDOW`; None until the stream is a second old.
+    pub window_loss_pct: Option<f64>,
+}
+
+struct Stream {
+    /// Newest extended (wrap-counting) sequence number.
+    max: u64,
+    seen: [u64; (SEEN / 64) as usize],
+    /// Packets received, duplicates not.
+    unique: u64,
+    last: Instant,
+    stats: RtpStats,
+    /// (when, expected, unique) each check, reaching back `WINDOW`.
+    samples: VecDeque<(Instant, u64, u64)>,
+    /// Counters at the end of the previous window.
+    logged: RtpStats,
+}
+
+impl Stream {
+    fn new(ssrc: u32, seq: u16) -> Self {
+        let stats = RtpStats { ssrc, ..Default::default() };
+        let mut s = Self {
+            max: 0,
+            seen: [0; (SEEN / 64) as usize],
+            unique: 0,
+            last: Instant::now(),
+            stats,
+            samples: VecDeque::new(),
+            logged: stats,
+        };
+        s.restart(seq);
+        s
+    }
+
+    /// Count on from `seq` as the first of a sequence.
+    fn restart(&mut self, seq: u16) {
+        // Start a wrap up so packets from before the first don't go negative
+        self.max = (1 << 16) | u64::from(seq);
+        self.seen = [0; (SEEN / 64) as usize];
+        self.mark(self.max, true);
+        self.stats.expected += 1;
+        self.unique += 1;
+    }
+
+    fn mark(&mut self, ext: u64, on: bool) {
+        let (word, bit) = (((ext % SEEN) / 64) as usize, ext % 64);
+        if on {
+            self.seen[word] |= 1 << bit;
+        } else {
+            self.seen[word] &= !(1 << bit);
+        }
+    }
+
+    fn is_seen(&self, ext: u64) -> bool {
+        self.seen[((ext % SEEN) / 64) as usize] & (1 << (ext % 64)) != 0
+    }
+
+    fn arrive(&mut self, seq: u16) {
+        self.stats.received += 1;
+        self.last = Instant::now();
+        let delta = i32::from(seq.wrapping_sub(self.max as u16) as i16);
+        if delta.abs() > MAX_DROPOUT {
+            // The sender restarted or skipped ahead
+            self.restart(seq);
+            return;
+        }
+        let ext = self.max.wrapping_add_signed(i64::from(delta));
+        if delta > 0 {
+            for skipped in self.max + 1..ext {
+                self.mark(skipped, false);
+            }
+            if delta > 1 {
+                self.stats.gaps += 1;
+            }
+            self.stats.expected += delta as u64;
+            self.max = ext;
+        } else if self.is_seen(ext) {
+            self.stats.duplicates += 1;
+            return;
+        } else {
+            self.stats.reordered += 1;
+        }
+        self.mark(ext, true);
+        self.unique += 1;
+    }
+
+    /// Bring loss and the window up to date, with `late` from the jitterbuffer.
+    fn check(&mut self, late: Option<u64>) {
+        let s = &mut self.stats;
+        if let Some(late) = late {
+            s.late = late;
+        }
+        s.lost = s.expected.saturating_sub(self.unique);
+        s.loss_pct = pct(s.lost, s.expected);
+        let now = Instant::now();
+        self.samples.push_back((now, s.expected, self.unique));
+        while self.samples.len() > 2 && now.duration_since(self.samples[1].0) >= WINDOW {
+            self.samples.pop_front();
+        }
+        let (_, expected, unique) = self.samples[0];
+        let (expected, unique) = (s.expected - expected, self.unique - unique);
+        s.window_loss_pct = (self.samples.len() > 1).then(|| pct(expected.saturating_sub(unique), expected));
+    }
+
+    /// A log line for the window just ended, if anything went wrong in it.
+    fn log(&mut self) {
+        let (s, before) = (self.stats, self.logged);
+        self.logged = s;
+        let (gaps, reordered, duplicates, late) = (
+            s.gaps - before.gaps,
+            s.reordered - before.reordered,
+            s.duplicates - before.duplicates,
+            s.late.saturating_sub(before.late),
+        );
+        if gaps + reordered + duplicates + late == 0 && s.window_loss_pct.unwrap_or(0.0) == 0.0 {
+            return;
+        }
+        info!(
+            target: "receiver",
+            "stream {:08x}: {:.1}% lost over {} s ({gaps} gaps, {reordered} reordered, {duplicates} duplicates, \
+             {late} late), {:.2}% since it started",
+            s.ssrc,
+            s.window_loss_pct.unwrap_or(0.0),
+            WINDOW.as_secs(),
+            s.loss_pct
+        );
+    }
+}
+
+fn pct(part: u64, whole: u64) -> f64 {
+    if whole == 0 { 0.0 } else { part as f64 * 100.0 / whole as f64 }
+}
+
+/// Stats per SSRC, shared between the receiver's probe and its owner.
+#[derive(Clone, Default)]
+pub(crate) struct Table(Arc<Mutex<HashMap<u32, Stream>>>);
+
+impl Table {
+    pub(crate) fn snapshot(&self) -> Vec<RtpStats> {
+        let mut out: Vec<_> = self.0.lock().unwrap().values().map(|s| s.stats).collect();
+        out.sort_by_key(|s| s.ssrc);
+        out
+    }
+}
+
+/// Count the RTP passing `rtp` (before `rtpbin`, whose jitterbuffers report
+/// late packets), per SSRC.
+pub(crate) fn attach(pipeline: &gst::Pipeline, rtp: &gst::Pad, rtpbin: &gst::Element) -> Table {
+    let table = Table::default();
+
+    let streams = table.clone();
+    rtp.add_probe(gst::PadProbeType::BUFFER, move |_pad, info| {
+        let Some(gst::PadProbeData::Buffer(buffer)) = &info.data else { return gst::PadProbeReturn::Ok };
+        let Ok(map) = buffer.map_readable() else { return gst::PadProbeReturn::Ok };
+        // SRTP leaves the header in the clear
+        if map.len() < 12 || map[0] >> 6 != 2 || map[1] & 0x7f != OPUS_PT {
+            return gst::PadProbeReturn::Ok;
+        }
+        let seq = u16::from_be_bytes([map[2], map[3]]);
+        let ssrc = u32::from_be_bytes([map[8], map[9], map[10], map[11]]);
+        match streams.0.lock().unwrap().entry(ssrc) {
+            Entry::Occupied(mut s) => s.get_mut().arrive(seq),
+            Entry::Vacant(v) => {
+                v.insert(Stream::new(ssrc, seq));
+            }
+        }
+        gst::PadProbeReturn::Ok
+    });
+
+    // rtpbin makes a jitterbuffer per SSRC
+    let jitterbuffers: Arc<Mutex<HashMap<u32, gst::glib::WeakRef<gst::Element>>>> = Arc::default();
+    let found = jitterbuffers.clone();
+    rtpbin.connect("new-jitterbuffer", false, move |args| {
+        if let (Ok(jb), Ok(ssrc)) = (args[1].get::<gst::Element>(), args[3].get::<u32>()) {
+            found.lock().unwrap().insert(ssrc, jb.downgrade());
+        }
+        None
+    });
+
+    let (pipeline, streams) = (pipeline.downgrade(), table.clone());
+    std::thread::spawn(move || {
+        let mut window_start = Instant::now();
+        loop {
+            std::thread::sleep(CHECK_INTERVAL);
+            if pipeline.upgrade().is_none() {
+                break;
+            }
+            let late = |ssrc: &u32| {
+                let jb = jitterbuffers.lock().unwrap().get(ssrc)?.upgrade()?;
+                jb.property::<gst::Structure>("stats").get::<u64>("num-late").ok()
+            };
+            let log = window_start.elapsed() >= WINDOW;
+            if log {
+                window_start = Instant::now();
+            }
+            let mut streams = streams.0.lock().unwrap();
+            streams.retain(|_, s| s.last.elapsed() < FORGET_AFTER);
+            jitterbuffers.lock().unwrap().retain(|ssrc, jb| streams.contains_key(ssrc) && jb.upgrade().is_some());
+            for (ssrc, s) in streams.iter_mut() {
+                s.check(late(ssrc));
+                if log {
+                    s.log();
+                }
+            }
+        }
+    });
+    table
+}
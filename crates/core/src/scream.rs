@@ -0,0 +1,112 @@
+// Scream receiver: plays audio from Windows' Scream virtual sound card
+// (multicast PCM, 239.255.77.77:4010 by default) through the same mixer as
+// the Opus/RTP streams.
+//
+// Each datagram is a 5-byte header followed by 1152 bytes of LE PCM:
+//
+//   0      rate: bit 7 = base (0: 48000, 1: 44100), bits 0..6 = multiplier
+//   1      bits per sample (16, 24, 32)
+//   2      channels
+//   3..5   channel mask (LE, WAVEFORMATEXTENSIBLE)
+use anyhow::{Context, Result};
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use gstreamer_app as gst_app;
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use tracing::{info, warn};
+
+use crate::background::{POLL, Tasks};
+use crate::pipeline::add_pcm_branch;
+
+/// Scream's default multicast group and port.
+pub const DEFAULT_GROUP: Ipv4Addr = Ipv4Addr::new(239, 255, 77, 77);
+pub const DEFAULT_PORT: u16 = 4010;
+
+const HEADER_LEN: usize = 5;
+
+/// Join `group` (unicast Scream packets to the port are accepted too) and mix
+/// every source address into `mixer`, until the pipeline's `tasks` stop.
+pub(crate) fn attach_receiver(
+    tasks: &Tasks,
+    pipeline: &gst::Pipeline,
+    mixer: &gst::Element,
+    group: Ipv4Addr,
+    port: u16,
+    iface: Option<Ipv4Addr>,
+) -> Result<()> {
+    let sock = UdpSocket::bind(("0.0.0.0", port)).with_context(|| format!("scream: bind :{port}"))?;
+    // std joins by interface address, not name; None = default route
+    let iface_addr = iface.unwrap_or(Ipv4Addr::UNSPECIFIED);
+    sock.join_multicast_v4(&group, &iface_addr)
+        .with_context(|| format!("scream: join {group}"))?;
+    // Wakes up now and then to see whether the pipeline has stopped
+    sock.set_read_timeout(Some(POLL))?;
+
+    let pipeline = pipeline.downgrade();
+    let mixer = mixer.downgrade();
+    tasks.spawn("scream-receiver", move |stop| {
+        let mut srcs: HashMap<SocketAddr, (gst_app::AppSrc, gst::Caps)> = HashMap::new();
+        let mut buf = [0u8; 2048];
+        while !stop.is_set() {
+            let Ok((n, from)) = sock.recv_from(&mut buf) else { continue };
+            let Some(caps) = parse_header(&buf[..n]) else { continue };
+            let (Some(pipeline), Some(mixer)) = (pipeline.upgrade(), mixer.upgrade()) else { break };
+            if !srcs.contains_key(&from) {
+                match add_pcm_branch(&pipeline, &mixer, &format!("scream_{}", srcs.len()), &caps) {
+                    Ok(src) => {
+                        info!(target: "receiver", "Scream source {from}: {caps}");
+                        srcs.insert(from, (src, caps.clone()));
+                    }
+                    Err(e) => {
+                        warn!(target: "receiver", "Scream source {from}: {e:#}");
+                        continue;
+                    }
+                }
+            }
+            let (src, current) = srcs.get_mut(&from).expect("inserted above");
+            // Scream follows the Windows mixer format, which can change at any time
+            if *current != caps {
+                info!(target: "receiver", "Scream source {from} changed format: {caps}");
+                src.set_caps(Some(&caps));
+                *current = caps;
+            }
+            let _ = src.push_buffer(gst::Buffer::from_slice(buf[HEADER_LEN..n].to_vec()));
+        }
+    })?;
+    info!(target: "receiver", "Scream on {group}:{port}");
+    Ok(())
+}
+
+/// Raw-audio caps described by a Scream header, or None for malformed packets.
+fn parse_header(p: &[u8]) -> Option<gst::Caps> {
+    if p.len() <= HEADER_LEN {
+        return None;
+    }
+    let base = if p[0] & 0x80 != 0 { 44_100 } else { 48_000 };
+    let mult = (p[0] & 0x7F) as i32;
+    if mult == 0 {
+        return None;
+    }
+    let format = match p[1] {
+        16 => "S16LE",
+        24 => "S24LE",
+        32 => "S32LE",
+        _ => return None,
+    };
+    let channels = p[2] as i32;
+    if channels == 0 {
+        return None;
+    }
+    let mut caps = gst::Caps::builder("audio/x-raw")
+        .field("format", format)
+        .field("rate", base * mult)
+        .field("channels", channels)
+        .field("layout", "interleaved");
+    if channels > 2 {
+        // audioconvert needs the speaker layout to downmix 5.1/7.1
+        let mask = u16::from_le_bytes([p[3], p[4]]) as u64;
+        caps = caps.field("channel-mask", gst::Bitmask::new(mask));
+    }
+    Some(caps.build())
+}
@@ -0,0 +1,163 @@
+// SDP (RFC 4566) for the Opus/RTP stream, so VLC, ffmpeg and other RTP tools
+// can play what the sender emits, and a receiver can be set up from an SDP
+// file written elsewhere.
+use anyhow::{Context, Result, bail};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, UdpSocket};
+use tracing::warn;
+
+use crate::pipeline::{FecMode, RTX_PT, SenderConfig, ULPFEC_PT, resolve_host};
+
+const OPUS_PT: u32 = 97;
+
+/// What a receiver needs to know from an SDP description.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StreamDescription {
+    pub port: u16,
+    /// Multicast group from the `c=` line, if the stream is multicast.
+    pub multicast_group: Option<String>,
+    pub fec: Option<FecMode>,
+    /// `rtx-time` of an RTX payload, if retransmission is offered.
+    pub rtx_max_ms: Option<u32>,
+}
+
+/// Describe the stream `build_sender` would produce for `cfg` (first destination).
+pub fn sender_sdp(cfg: &SenderConfig) -> Result<String> {
+    let dest = cfg.hosts.first().context("sender needs at least one destination")?;
+    let dest_ip = resolve_host(dest)?;
+    let origin = local_ip_towards(dest_ip).unwrap_or(Ipv4Addr::UNSPECIFIED.into());
+    let origin_net = if origin.is_ipv6() { "IP6" } else { "IP4" };
+    let session_id = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let (net, addr) = match dest_ip {
+        IpAddr::V4(a) if a.is_multicast() => ("IP4", format!("{a}/{}", cfg.multicast_ttl)),
+        IpAddr::V4(a) => ("IP4", a.to_string()),
+        IpAddr::V6(a) => ("IP6", a.to_string()),
+    };
+
+    let mut pts = vec![OPUS_PT];
+    if matches!(cfg.fec, Some(FecMode::Ulp { .. })) {
+        pts.push(ULPFEC_PT);
+    }
+    if cfg.rtx_max_ms.is_some() {
+        pts.push(RTX_PT);
+    }
+    let pts = pts.iter().map(|p| p.to_string()).collect::<Vec<_>>().join(" ");
+    let inband = u8::from(cfg.fec == Some(FecMode::InBand));
+
+    let mut sdp = format!(
+        "v=0\r\n\
+         o=- {session_id} {session_id} IN {origin_net} {origin}\r\n\
+         s=audio-bridge\r\n\
+         c=IN {net} {addr}\r\n\
+         t=0 0\r\n\
+         m=audio {port} RTP/AVP {pts}\r\n\
+         a=rtpmap:{OPUS_PT} OPUS/48000/2\r\n\
+         a=fmtp:{OPUS_PT} stereo=1;sprop-stereo=1;useinbandfec={inband};maxaveragebitrate={bitrate}\r\n",
+        port = cfg.port,
+        bitrate = cfg.bitrate,
+    );
+    if matches!(cfg.fec, Some(FecMode::Ulp { .. })) {
+        sdp.push_str(&format!("a=rtpmap:{ULPFEC_PT} ulpfec/48000\r\n"));
+    }
+    if let Some(ms) = cfg.rtx_max_ms {
+        sdp.push_str(&format!(
+            "a=rtpmap:{RTX_PT} rtx/48000\r\na=fmtp:{RTX_PT} apt={OPUS_PT};rtx-time={ms}\r\n"
+        ));
+    }
+    sdp.push_str(&format!("a=rtcp:{}\r\na=sendonly\r\n", cfg.port + 1));
+    Ok(sdp)
+}
+
+/// Read the first Opus audio stream out of an SDP description.
+pub fn parse(text: &str) -> Result<StreamDescription> {
+    let mut port = None;
+    let mut group = None;
+    let mut opus_pt = None;
+    let mut inband = false;
+    let mut ulp = false;
+    let mut rtx_max_ms = None;
+    let mut in_audio = false;
+
+    for line in text.lines().map(str::trim) {
+        let Some((kind, value)) = line.split_once('=') else { continue };
+        match kind {
+            "m" => {
+                if port.is_some() {
+                    break; // only the first audio stream
+                }
+                let mut f = value.split_whitespace();
+                in_audio = f.next() == Some("audio");
+                if in_audio {
+                    let p = f.next().context("SDP m= line without port")?;
+                    // "port/count" is allowed; we only use the first
+                    let p = p.split('/').next().unwrap_or(p);
+                    port = Some(p.parse::<u16>().with_context(|| format!("bad SDP port '{p}'"))?);
+                }
+            }
+            "c" => {
+                // c=IN IP4 239.1.2.3/16 (session- or media-level)
+                if let Some(addr) = value.split_whitespace().nth(2) {
+                    let ip = addr.split('/').next().unwrap_or(addr);
+                    if ip.parse::<IpAddr>().map(|a| a.is_multicast()).unwrap_or(false) {
+                        group = Some(ip.to_string());
+                    }
+                }
+            }
+            "a" if in_audio => {
+                let Some((attr, rest)) = value.split_once(':') else { continue };
+                let (pt, params) = rest.split_once(' ').unwrap_or((rest, ""));
+                match attr {
+                    "rtpmap" => {
+                        let codec = params.split('/').next().unwrap_or("").to_ascii_lowercase();
+                        match codec.as_str() {
+                            "opus" => opus_pt = Some(pt.to_string()),
+                            "ulpfec" => ulp = true,
+                            _ => {}
+                        }
+                    }
+                    "fmtp" => {
+                        for kv in params.split(';').map(str::trim) {
+                            match kv.split_once('=') {
+                                Some(("useinbandfec", "1")) => inband = true,
+                                Some(("rtx-time", ms)) => rtx_max_ms = ms.parse().ok(),
+                                _ => {}
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let Some(port) = port else { bail!("SDP has no audio stream") };
+    let Some(opus_pt) = opus_pt else {
+        bail!("SDP audio stream is not Opus (only OPUS/48000/2 is supported)");
+    };
+    if opus_pt != OPUS_PT.to_string() {
+        warn!(target: "sdp", "Opus payload type {opus_pt} differs from {OPUS_PT}; streams may not decode");
+    }
+    let fec = if ulp {
+        // Redundancy level is the sender's business; the decoder doesn't need it
+        Some(FecMode::Ulp { percentage: 0 })
+    } else if inband {
+        Some(FecMode::InBand)
+    } else {
+        None
+    };
+    Ok(StreamDescription { port, multicast_group: group, fec, rtx_max_ms })
+}
+
+/// Source address the OS would use to reach `dest` (no packets are sent).
+pub(crate) fn local_ip_towards(dest: IpAddr) -> Option<IpAddr> {
+    let bind: IpAddr = match dest {
+        IpAddr::V4(_) => Ipv4Addr::UNSPECIFIED.into(),
+        IpAddr::V6(_) => Ipv6Addr::UNSPECIFIED.into(),
+    };
+    let sock = UdpSocket::bind((bind, 0)).ok()?;
+    sock.connect((dest, 9)).ok()?;
+    Some(sock.local_addr().ok()?.ip())
+}
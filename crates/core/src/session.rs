@@ -0,0 +1,207 @@
+// Sender sessions: every sender run streams under a fresh SSRC and announces
+// it on the handshake, so the receiver knows which SSRCs are live for each
+// source address. Packets from an address under an SSRC it has retired (the
+// tail of a previous run, duplicates replayed off the network) are dropped,
+// as is anything not live once the address has a session. When a session ends
+// or is superseded, rtpbin forgets that SSRC so its jitterbuffer starts clean.
+// A sender may also ask, over its handshake, to be played at another volume
+// (zones); that gain sits on the session's mixer input.
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use gstreamer_net as gst_net;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex, RwLock};
+use tracing::{info, warn};
+
+/// Retired SSRCs remembered per address.
+const RETIRED_KEEP: usize = 8;
+
+#[derive(Debug, Default)]
+struct Source {
+    /// (owner, SSRC) of each live session.
+    live: Vec<(String, u32)>,
+    retired: Vec<u32>,
+}
+
+impl Source {
+    fn retire(&mut self, ssrc: u32) {
+        self.retired.retain(|s| *s != ssrc);
+        self.retired.push(ssrc);
+        if self.retired.len() > RETIRED_KEEP {
+            self.retired.remove(0);
+        }
+    }
+
+    fn allows(&self, ssrc: u32) -> bool {
+        self.is_live(ssrc) || (self.live.is_empty() && !self.retired.iter().any(|s| base(ssrc).contains(s)))
+    }
+
+    fn is_live(&self, ssrc: u32) -> bool {
+        self.live.iter().any(|(_, s)| base(ssrc).contains(s))
+    }
+}
+
+/// The session SSRCs `ssrc` may belong to: RTX goes out under SSRC + 1.
+fn base(ssrc: u32) -> [u32; 2] {
+    [ssrc, ssrc.wrapping_sub(1)]
+}
+
+/// Live sessions by source address. Cheap to clone.
+#[derive(Clone, Default)]
+pub struct Sessions {
+    table: Arc<RwLock<HashMap<IpAddr, Source>>>,
+    rtpbin: Arc<Mutex<Option<gst::glib::WeakRef<gst::Element>>>>,
+    /// Volume per SSRC, as its sender asked.
+    volumes: Arc<RwLock<HashMap<u32, f64>>>,
+}
+
+impl std::fmt::Debug for Sessions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Sessions").field(&self.table.read().unwrap()).finish()
+    }
+}
+
+impl Sessions {
+    /// `owner` (e.g. the sender's identity key) started streaming from `ip`
+    /// under `ssrc`. An earlier session of the same owner there is retired.
+    pub fn begin(&self, ip: IpAddr, owner: &str, ssrc: u32) {
+        let ip = ip.to_canonical();
+        let mut stale = Vec::new();
+        {
+            let mut table = self.table.write().unwrap();
+            let source = table.entry(ip).or_default();
+            source.live.retain(|(o, s)| {
+                let keep = o != owner || *s == ssrc;
+                if !keep {
+                    stale.push(*s);
+                }
+                keep
+            });
+            for s in &stale {
+                source.retire(*s);
+            }
+            if !source.live.iter().any(|(_, s)| *s == ssrc) {
+                source.live.push((owner.to_string(), ssrc));
+            }
+        }
+        for s in stale {
+            info!(target: "session", "{ip}: ssrc {s:08x} superseded by {ssrc:08x}");
+            self.clear(s);
+        }
+        info!(target: "session", "{ip}: ssrc {ssrc:08x} live");
+    }
+
+    /// The session's handshake went away.
+    pub fn end(&self, ip: IpAddr, ssrc: u32) {
+        let ip = ip.to_canonical();
+        {
+            let mut table = self.table.write().unwrap();
+            let Some(source) = table.get_mut(&ip) else { return };
+            let before = source.live.len();
+            source.live.retain(|(_, s)| *s != ssrc);
+            if source.live.len() == before {
+                return;
+            }
+            source.retire(ssrc);
+        }
+        self.volumes.write().unwrap().remove(&ssrc);
+        info!(target: "session", "{ip}: ssrc {ssrc:08x} ended");
+        self.clear(ssrc);
+    }
+
+    /// Every live session as (address, owner, SSRC).
+    pub fn live(&self) -> Vec<(IpAddr, String, u32)> {
+        let table = self.table.read().unwrap();
+        let mut v: Vec<_> = table
+            .iter()
+            .flat_map(|(ip, source)| source.live.iter().map(|(o, s)| (*ip, o.clone(), *s)))
+            .collect();
+        v.sort();
+        v
+    }
+
+    /// Play `ssrc` at `volume` (1.0 = unity, on top of the receiver's own),
+    /// now if it's playing, else once it starts.
+    pub fn set_volume(&self, ssrc: u32, volume: f64) {
+        self.volumes.write().unwrap().insert(ssrc, volume);
+        if let Some(pad) = self.mixer_pad(ssrc) {
+            pad.set_property("volume", volume);
+        }
+        info!(target: "session", "ssrc {ssrc:08x}: volume {volume:.2}");
+    }
+
+    pub(crate) fn volume(&self, ssrc: u32) -> Option<f64> {
+        self.volumes.read().unwrap().get(&ssrc).copied()
+    }
+
+    /// The audiomixer input `ssrc`'s decode branch feeds.
+    fn mixer_pad(&self, ssrc: u32) -> Option<gst::Pad> {
+        let rtpbin = self.rtpbin.lock().unwrap().as_ref()?.upgrade()?;
+        let pipeline = rtpbin.parent()?.downcast::<gst::Bin>().ok()?;
+        let prefix = format!("ares_{ssrc}_");
+        let resample = pipeline.iterate_elements().into_iter().flatten().find(|e| e.name().starts_with(&prefix))?;
+        resample.static_pad("src")?.peer()
+    }
+
+    fn allows(&self, ip: IpAddr, ssrc: u32) -> bool {
+        self.table
+            .read()
+            .unwrap()
+            .get(&ip.to_canonical())
+            .is_none_or(|source| source.allows(ssrc))
+    }
+
+    /// Whether `ssrc` from `ip` is a session its sender announced (with the
+    /// source gate on, nothing else gets through).
+    pub(crate) fn is_live(&self, ip: IpAddr, ssrc: u32) -> bool {
+        self.table.read().unwrap().get(&ip.to_canonical()).is_some_and(|source| source.is_live(ssrc))
+    }
+
+    /// Drop rtpbin's state (pads, jitterbuffer) for `ssrc` and its RTX SSRC.
+    fn clear(&self, ssrc: u32) {
+        let Some(rtpbin) = self.rtpbin.lock().unwrap().as_ref().and_then(|w| w.upgrade()) else { return };
+        if gst::glib::subclass::signal::SignalId::lookup("clear-ssrc", rtpbin.type_()).is_none() {
+            return;
+        }
+        for s in [ssrc, ssrc.wrapping_add(1)] {
+            rtpbin.emit_by_name::<()>("clear-ssrc", &[&0u32, &s]);
+        }
+    }
+}
+
+/// Let `sessions` reset `rtpbin` when a session ends.
+pub(crate) fn bind(sessions: &Sessions, rtpbin: &gst::Element) {
+    *sessions.rtpbin.lock().unwrap() = Some(rtpbin.downgrade());
+}
+
+/// Drop packets on `src` (a udpsrc) from SSRCs `sessions` doesn't allow for
+/// their source address. `rtcp` picks where the SSRC sits in the header.
+pub(crate) fn attach(src: &gst::Element, sessions: &Sessions, rtcp: bool) {
+    let Some(pad) = src.static_pad("src") else { return };
+    let sessions = sessions.clone();
+    let at = if rtcp { 4 } else { 8 };
+    let mut last_dropped = None;
+    pad.add_probe(gst::PadProbeType::BUFFER, move |_pad, info| {
+        let Some(buf) = info.buffer() else { return gst::PadProbeReturn::Ok };
+        let Some(meta) = buf.meta::<gst_net::NetAddressMeta>() else { return gst::PadProbeReturn::Ok };
+        let Ok(addr) = meta.addr().downcast::<gst_net::gio::InetSocketAddress>() else {
+            return gst::PadProbeReturn::Ok;
+        };
+        let mut ssrc = [0u8; 4];
+        if buf.copy_to_slice(at, &mut ssrc).is_err() {
+            return gst::PadProbeReturn::Ok;
+        }
+        let ssrc = u32::from_be_bytes(ssrc);
+        let ip = IpAddr::from(addr.address());
+        if sessions.allows(ip, ssrc) {
+            return gst::PadProbeReturn::Ok;
+        }
+        // One line per stale stream, not one per packet
+        if last_dropped != Some(ssrc) {
+            warn!(target: "receiver", "dropping stale packets from {ip} (ssrc {ssrc:08x}, not a live session)");
+            last_dropped = Some(ssrc);
+        }
+        gst::PadProbeReturn::Drop
+    });
+}
@@ -0,0 +1,91 @@
+// Silence suppression (--suppress-silence). When nothing plays on the sender
+// the encoder still turns out a full-rate stream of silence. Here the raw
+// audio going into the encoder is watched: after `HOLD` with every sample
+// below the threshold the encoded frames are dropped before they're
+// payloaded, so nothing goes out (RTCP keeps the session alive), and the
+// first frame with signal goes out again. Sequence numbers stay contiguous,
+// so receivers see a timestamp jump, not loss to conceal or retransmit.
+// With a keepalive, one frame still goes out every so often meanwhile.
+//
+// Each change is logged and posted on the bus as an application message:
+//   silence, silent=(boolean)true|false
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::info;
+
+/// Continuous silence before transmission stops.
+const HOLD: gst::ClockTime = gst::ClockTime::from_seconds(2);
+
+/// Peak of S16 interleaved samples, full scale = 1.0.
+fn peak(buffer: &gst::BufferRef) -> Option<f64> {
+    let map = buffer.map_readable().ok()?;
+    let max = map
+        .chunks_exact(2)
+        .map(|s| i16::from_le_bytes([s[0], s[1]]).unsigned_abs())
+        .max()
+        .unwrap_or(0);
+    Some(f64::from(max) / 32768.0)
+}
+
+/// Gate the encoder `enc` (S16LE in): stop sending after `HOLD` below
+/// `threshold_db` dBFS, but for a frame every `keepalive`. The returned flag
+/// says whether it's holding back now.
+pub(crate) fn attach(enc: &gst::Element, threshold_db: f64, keepalive: Option<Duration>) -> Arc<AtomicBool> {
+    let silent = Arc::new(AtomicBool::new(false));
+    let (Some(sink), Some(src)) = (enc.static_pad("sink"), enc.static_pad("src")) else { return silent };
+    let threshold = 10f64.powf(threshold_db / 20.0);
+    // Running time of the first quiet buffer in the current stretch
+    let quiet_since: Arc<Mutex<Option<gst::ClockTime>>> = Arc::default();
+
+    let flag = silent.clone();
+    let element = enc.downgrade();
+    sink.add_probe(gst::PadProbeType::BUFFER, move |_pad, info| {
+        let Some(gst::PadProbeData::Buffer(buffer)) = &info.data else { return gst::PadProbeReturn::Ok };
+        let (Some(level), Some(pts)) = (peak(buffer), buffer.pts()) else { return gst::PadProbeReturn::Ok };
+        let mut since = quiet_since.lock().unwrap();
+        let now_silent = if level < threshold {
+            let start = *since.get_or_insert(pts);
+            pts.saturating_sub(start) >= HOLD
+        } else {
+            *since = None;
+            false
+        };
+        if flag.swap(now_silent, Ordering::SeqCst) != now_silent {
+            if now_silent {
+                info!(target: "sender", "silent for {} s: not sending until there's signal", HOLD.seconds());
+            } else {
+                info!(target: "sender", "signal: sending again");
+            }
+            if let Some(enc) = element.upgrade() {
+                let s = gst::Structure::builder("silence").field("silent", now_silent).build();
+                let _ = enc.post_message(gst::message::Application::builder(s).src(&enc).build());
+            }
+        }
+        gst::PadProbeReturn::Ok
+    });
+
+    let flag = silent.clone();
+    let resumed = Arc::new(AtomicBool::new(false));
+    let last_sent = Mutex::new(Instant::now());
+    src.add_probe(gst::PadProbeType::BUFFER, move |_pad, info| {
+        let mut last = last_sent.lock().unwrap();
+        let held = flag.load(Ordering::SeqCst);
+        if held && !keepalive.is_some_and(|k| last.elapsed() >= k) {
+            resumed.store(true, Ordering::SeqCst);
+            return gst::PadProbeReturn::Drop;
+        }
+        *last = Instant::now();
+        // Mark the first frame after a pause so the payloader flags the talkspurt
+        // (a keepalive too: it comes after a pause, and another one follows)
+        if resumed.swap(held, Ordering::SeqCst) {
+            if let Some(gst::PadProbeData::Buffer(buffer)) = &mut info.data {
+                buffer.make_mut().set_flags(gst::BufferFlags::DISCONT | gst::BufferFlags::MARKER);
+            }
+        }
+        gst::PadProbeReturn::Ok
+    });
+    silent
+}
@@ -0,0 +1,115 @@
+// SRTP (AES-128-ICM + HMAC-SHA1-80) for the rtpbin transports. The master
+// key is not configured by hand: the sender picks a fresh one per run and
+// hands it to each receiver over the authenticated handshake, so all this
+// module needs is the key (sender) or a table of keys by SSRC (receiver).
+// rtpbin's request-rtp/rtcp-encoder/decoder hooks slot srtpenc/srtpdec in.
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+use tracing::{info, warn};
+
+use crate::pipeline::make_element;
+
+/// 16-byte AES key + 14-byte salt.
+pub const MASTER_KEY_LEN: usize = 30;
+
+/// The sender's side of one SRTP session. Receivers find the key by the
+/// sender's SSRC, so it goes with a pinned `SenderConfig::ssrc`.
+#[derive(Debug, Clone)]
+pub struct SrtpSession {
+    pub key: Vec<u8>,
+}
+
+/// Receiver's keys by SSRC, filled in as senders complete the handshake.
+/// Cheap to clone.
+#[derive(Debug, Clone, Default)]
+pub struct SrtpKeys(Arc<RwLock<HashMap<u32, Vec<u8>>>>);
+
+impl SrtpKeys {
+    /// Install `key` for a sender's media SSRC and its RTX SSRC.
+    pub fn insert(&self, ssrc: u32, key: Vec<u8>) {
+        let mut table = self.0.write().unwrap();
+        table.insert(ssrc.wrapping_add(1), key.clone());
+        table.insert(ssrc, key);
+    }
+
+    pub fn remove(&self, ssrc: u32) {
+        let mut table = self.0.write().unwrap();
+        table.remove(&ssrc);
+        table.remove(&ssrc.wrapping_add(1));
+    }
+
+    fn get(&self, ssrc: u32) -> Option<Vec<u8>> {
+        self.0.read().unwrap().get(&ssrc).cloned()
+    }
+}
+
+/// Encrypt everything session 0 of `rtpbin` sends (RTP and SRs) with `key`.
+pub(crate) fn attach_encoder(rtpbin: &gst::Element, key: &[u8]) {
+    // RTP and RTCP must share one srtpenc per session
+    let enc = Arc::new(Mutex::new(None::<gst::Element>));
+    let key = gst::Buffer::from_slice(key.to_vec());
+    for signal in ["request-rtp-encoder", "request-rtcp-encoder"] {
+        let enc = enc.clone();
+        let key = key.clone();
+        rtpbin.connect(signal, false, move |args| {
+            let session = args[1].get::<u32>().unwrap_or(0);
+            let mut slot = enc.lock().unwrap();
+            if slot.is_none() {
+                match make_element("srtpenc", &format!("srtpenc_{session}")) {
+                    Ok(e) => {
+                        e.set_property("key", &key);
+                        *slot = Some(e);
+                    }
+                    Err(e) => warn!(target: "sender", "SRTP unavailable: {e:#}"),
+                }
+            }
+            Some(slot.clone().to_value())
+        });
+    }
+    info!(target: "sender", "SRTP on (AES-128-ICM / HMAC-SHA1-80)");
+}
+
+/// Decrypt what session 0 of `rtpbin` receives, with keys looked up by SSRC.
+/// Packets from an SSRC without a key are dropped (and the key is asked
+/// for again on the next one).
+pub(crate) fn attach_decoder(rtpbin: &gst::Element, keys: &SrtpKeys) {
+    let dec = Arc::new(Mutex::new(None::<gst::Element>));
+    for signal in ["request-rtp-decoder", "request-rtcp-decoder"] {
+        let dec = dec.clone();
+        let keys = keys.clone();
+        rtpbin.connect(signal, false, move |args| {
+            let session = args[1].get::<u32>().unwrap_or(0);
+            let mut slot = dec.lock().unwrap();
+            if slot.is_none() {
+                match make_element("srtpdec", &format!("srtpdec_{session}")) {
+                    Ok(d) => {
+                        let keys = keys.clone();
+                        d.connect("request-key", false, move |args| {
+                            let ssrc = args[1].get::<u32>().unwrap_or(0);
+                            Some(key_caps(keys.get(ssrc)).to_value())
+                        });
+                        *slot = Some(d);
+                    }
+                    Err(e) => warn!(target: "receiver", "SRTP unavailable: {e:#}"),
+                }
+            }
+            Some(slot.clone().to_value())
+        });
+    }
+    info!(target: "receiver", "SRTP on (keys from the handshake)");
+}
+
+fn key_caps(key: Option<Vec<u8>>) -> Option<gst::Caps> {
+    let key = key?;
+    Some(
+        gst::Caps::builder("application/x-srtp")
+            .field("srtp-key", gst::Buffer::from_slice(key))
+            .field("srtp-cipher", "aes-128-icm")
+            .field("srtp-auth", "hmac-sha1-80")
+            .field("srtcp-cipher", "aes-128-icm")
+            .field("srtcp-auth", "hmac-sha1-80")
+            .build(),
+    )
+}
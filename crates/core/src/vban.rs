@@ -0,0 +1,211 @@
+// VBAN wire format (VB-Audio's UDP PCM protocol) so Windows machines running
+// VoiceMeeter can talk to the bridge without GStreamer on their end.
+//
+// Each datagram is a 28-byte header followed by up to 256 interleaved PCM
+// samples per channel:
+//
+//   0..4   "VBAN"
+//   4      sample-rate index (low 5 bits) | sub-protocol (high 3 bits, 0 = audio)
+//   5      samples per frame - 1
+//   6      channels - 1
+//   7      data format (low 3 bits, 1 = INT16) | codec (high bits, 0 = PCM)
+//   8..24  stream name, NUL padded
+//   24..28 frame counter (LE)
+use anyhow::{Context, Result};
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use gstreamer_app as gst_app;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs, UdpSocket};
+use tracing::{info, warn};
+
+use crate::background::{POLL, Tasks};
+use crate::pipeline::{add_pcm_branch, any_addr};
+
+/// Stream name we send under; set VoiceMeeter's incoming stream to match.
+pub const STREAM_NAME: &str = "AudioBridge";
+
+const HEADER_LEN: usize = 28;
+const MAX_SAMPLES: usize = 256;
+const FORMAT_INT16: u8 = 0x01;
+const SUB_AUDIO: u8 = 0x00;
+const SAMPLE_RATES: [u32; 21] = [
+    6000, 12000, 24000, 48000, 96000, 192000, 384000, 8000, 16000, 32000, 64000, 128000, 256000,
+    512000, 11025, 22050, 44100, 88200, 176400, 352800, 705600,
+];
+
+/// Caps the sender feeds in and the receiver produces before conversion.
+fn pcm_caps(rate: u32, channels: u32) -> gst::Caps {
+    gst::Caps::builder("audio/x-raw")
+        .field("format", "S16LE")
+        .field("rate", rate as i32)
+        .field("channels", channels as i32)
+        .field("layout", "interleaved")
+        .build()
+}
+
+/// Send `upstream` (48 kHz stereo S16LE) as VBAN frames to every `host:port`.
+pub(crate) fn attach_sender(
+    pipeline: &gst::Pipeline,
+    upstream: &gst::Element,
+    hosts: &[String],
+    port: u16,
+    bind: Option<IpAddr>,
+    dscp: Option<u8>,
+) -> Result<()> {
+    let dests = hosts
+        .iter()
+        .map(|h| {
+            (h.as_str(), port)
+                .to_socket_addrs()
+                .ok()
+                .and_then(|mut a| a.next())
+                .with_context(|| format!("vban: {h} did not resolve"))
+        })
+        .collect::<Result<Vec<SocketAddr>>>()?;
+    let sock = UdpSocket::bind((bind.unwrap_or_else(any_addr), 0)).context("vban: bind send socket")?;
+    if let Some(dscp) = dscp {
+        // DSCP sits in the top six bits of the TOS / traffic-class byte
+        let sref = socket2::SockRef::from(&sock);
+        let res = match sock.local_addr()? {
+            SocketAddr::V4(_) => sref.set_tos((dscp as u32) << 2),
+            SocketAddr::V6(_) => sref.set_tclass_v6((dscp as u32) << 2),
+        };
+        if let Err(e) = res {
+            warn!(target: "sender", "VBAN DSCP {dscp}: {e}");
+        }
+    }
+
+    let sink = gst_app::AppSink::builder()
+        .name("vban_out")
+        .caps(&pcm_caps(48_000, 2))
+        .sync(false)
+        .build();
+    pipeline.add(&sink)?;
+    upstream.link(&sink).context("vban: link → appsink")?;
+
+    let channels = 2usize;
+    let frame_bytes = MAX_SAMPLES * channels * 2;
+    let mut pending: Vec<u8> = Vec::with_capacity(frame_bytes * 2);
+    let mut counter: u32 = 0;
+    sink.set_callbacks(
+        gst_app::AppSinkCallbacks::builder()
+            .new_sample(move |sink| {
+                let sample = sink.pull_sample().map_err(|_| gst::FlowError::Eos)?;
+                let buf = sample.buffer().ok_or(gst::FlowError::Error)?;
+                let map = buf.map_readable().map_err(|_| gst::FlowError::Error)?;
+                pending.extend_from_slice(&map);
+                while pending.len() >= frame_bytes {
+                    let packet = encode_frame(48_000, channels as u8, &pending[..frame_bytes], counter);
+                    for d in &dests {
+                        // Unreliable by design, like the RTP path
+                        let _ = sock.send_to(&packet, d);
+                    }
+                    counter = counter.wrapping_add(1);
+                    pending.drain(..frame_bytes);
+                }
+                Ok(gst::FlowSuccess::Ok)
+            })
+            .build(),
+    );
+    info!(
+        target: "sender",
+        "VBAN '{STREAM_NAME}' 48000 Hz/2ch INT16 → {}",
+        dests.iter().map(|d| d.to_string()).collect::<Vec<_>>().join(",")
+    );
+    Ok(())
+}
+
+/// Listen for VBAN audio on `listen_port`; each source address gets its own
+/// appsrc → convert → resample branch into `mixer`. The socket is closed
+/// when the pipeline's `tasks` stop.
+pub(crate) fn attach_receiver(
+    tasks: &Tasks,
+    pipeline: &gst::Pipeline,
+    mixer: &gst::Element,
+    bind: IpAddr,
+    listen_port: u16,
+) -> Result<()> {
+    let sock = UdpSocket::bind((bind, listen_port))
+        .with_context(|| format!("vban: bind :{listen_port}"))?;
+    // Wakes up now and then to see whether the pipeline has stopped
+    sock.set_read_timeout(Some(POLL))?;
+    let pipeline = pipeline.downgrade();
+    let mixer = mixer.downgrade();
+    tasks.spawn("vban-receiver", move |stop| {
+        let mut srcs: HashMap<SocketAddr, (gst_app::AppSrc, gst::Caps)> = HashMap::new();
+        let mut buf = [0u8; 2048];
+        while !stop.is_set() {
+            let Ok((n, from)) = sock.recv_from(&mut buf) else { continue };
+            let Some(frame) = decode_frame(&buf[..n]) else { continue };
+            let (Some(pipeline), Some(mixer)) = (pipeline.upgrade(), mixer.upgrade()) else { break };
+            let caps = pcm_caps(frame.rate, frame.channels);
+            if !srcs.contains_key(&from) {
+                match add_pcm_branch(&pipeline, &mixer, &format!("vban_{}", srcs.len()), &caps) {
+                    Ok(src) => {
+                        info!(
+                            target: "receiver",
+                            "VBAN stream '{}' from {from}: {} Hz/{}ch",
+                            frame.name, frame.rate, frame.channels
+                        );
+                        srcs.insert(from, (src, caps.clone()));
+                    }
+                    Err(e) => {
+                        warn!(target: "receiver", "VBAN stream from {from}: {e:#}");
+                        continue;
+                    }
+                }
+            }
+            let (src, current) = srcs.get_mut(&from).expect("inserted above");
+            if *current != caps {
+                src.set_caps(Some(&caps));
+                *current = caps;
+            }
+            let _ = src.push_buffer(gst::Buffer::from_slice(frame.pcm.to_vec()));
+        }
+    })?;
+    info!(target: "receiver", "VBAN on udp :{listen_port}");
+    Ok(())
+}
+
+fn encode_frame(rate: u32, channels: u8, pcm: &[u8], counter: u32) -> Vec<u8> {
+    let rate_idx = SAMPLE_RATES.iter().position(|&r| r == rate).unwrap_or(3) as u8;
+    let samples = pcm.len() / (channels as usize * 2);
+    let mut p = Vec::with_capacity(HEADER_LEN + pcm.len());
+    p.extend_from_slice(b"VBAN");
+    p.push(rate_idx | SUB_AUDIO);
+    p.push((samples - 1) as u8);
+    p.push(channels - 1);
+    p.push(FORMAT_INT16);
+    let mut name = [0u8; 16];
+    let n = STREAM_NAME.len().min(16);
+    name[..n].copy_from_slice(&STREAM_NAME.as_bytes()[..n]);
+    p.extend_from_slice(&name);
+    p.extend_from_slice(&counter.to_le_bytes());
+    p.extend_from_slice(pcm);
+    p
+}
+
+struct Frame<'a> {
+    name: String,
+    rate: u32,
+    channels: u32,
+    pcm: &'a [u8],
+}
+
+/// Parse an audio frame; anything else (text, service, other formats) is ignored.
+fn decode_frame(p: &[u8]) -> Option<Frame<'_>> {
+    if p.len() < HEADER_LEN || &p[..4] != b"VBAN" {
+        return None;
+    }
+    if p[4] & 0xE0 != SUB_AUDIO || p[7] != FORMAT_INT16 {
+        return None;
+    }
+    let rate = *SAMPLE_RATES.get((p[4] & 0x1F) as usize)?;
+    let channels = p[6] as u32 + 1;
+    let samples = p[5] as usize + 1;
+    let len = samples * channels as usize * 2;
+    let pcm = p.get(HEADER_LEN..HEADER_LEN + len)?;
+    let name = String::from_utf8_lossy(&p[8..24]).trim_end_matches('\0').to_string();
+    Some(Frame { name, rate, channels, pcm })
+}
@@ -0,0 +1,313 @@
+// WebRTC transport: webrtcbin does ICE/STUN, DTLS-SRTP and the RTP session,
+// so two nodes on different networks can reach each other. The daemons swap
+// SDP and ICE candidates over a tiny signaling channel: newline-delimited
+// JSON on a TCP connection from the sender to the receiver's listen port.
+//
+//   {"type":"offer","sdp":"…"}                     sender → receiver
+//   {"type":"answer","sdp":"…"}                    receiver → sender
+//   {"type":"ice","mline":0,"candidate":"…"}       both ways
+//
+// The signaling port must be reachable from the sender (e.g. forwarded);
+// media then flows over whatever path ICE finds. The signaling threads belong
+// to the pipeline's `Tasks` and end with it, closing the listen port.
+use anyhow::{Context, Result};
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use gstreamer_sdp as gst_sdp;
+use gstreamer_webrtc as gst_webrtc;
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::net::{IpAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::background::{POLL, StopFlag, Tasks};
+use crate::pipeline::{host_port, make_element};
+
+/// Outgoing signaling messages, buffered until a peer is connected.
+#[derive(Clone, Default)]
+struct Signal(Arc<Mutex<SignalState>>);
+
+#[derive(Default)]
+struct SignalState {
+    writer: Option<TcpStream>,
+    pending: Vec<String>,
+}
+
+impl Signal {
+    fn send(&self, msg: Value) {
+        let line = format!("{msg}\n");
+        let mut st = self.0.lock().unwrap();
+        let sent = match st.writer.as_mut() {
+            Some(w) => w.write_all(line.as_bytes()).is_ok(),
+            None => false,
+        };
+        if !sent {
+            st.pending.push(line);
+        }
+    }
+
+    fn attach(&self, mut stream: TcpStream) {
+        let mut st = self.0.lock().unwrap();
+        for line in st.pending.drain(..) {
+            let _ = stream.write_all(line.as_bytes());
+        }
+        st.writer = Some(stream);
+    }
+
+    fn detach(&self) {
+        self.0.lock().unwrap().writer = None;
+    }
+}
+
+fn make_webrtcbin(stun: Option<&str>) -> Result<gst::Element> {
+    let webrtc = make_element("webrtcbin", "webrtc")?;
+    webrtc.set_property_from_str("bundle-policy", "max-bundle");
+    if let Some(stun) = stun {
+        webrtc.set_property("stun-server", stun);
+        info!(target: "webrtc", "stun-server={stun}");
+    }
+    Ok(webrtc)
+}
+
+/// Forward our local ICE candidates to the peer.
+fn send_ice_candidates(webrtc: &gst::Element, signal: &Signal) {
+    let signal = signal.clone();
+    webrtc.connect("on-ice-candidate", false, move |args| {
+        let mline = args[1].get::<u32>().unwrap_or(0);
+        let candidate = args[2].get::<String>().unwrap_or_default();
+        signal.send(json!({ "type": "ice", "mline": mline, "candidate": candidate }));
+        None
+    });
+}
+
+/// Send-only WebRTC peer: links `pay` into webrtcbin and offers to `host:port`.
+pub(crate) fn attach_sender(
+    tasks: &Tasks,
+    pipeline: &gst::Pipeline,
+    pay: &gst::Element,
+    host: &str,
+    port: u16,
+    stun: Option<&str>,
+) -> Result<()> {
+    let webrtc = make_webrtcbin(stun)?;
+    // webrtcbin builds its offer from the caps on its sink pad
+    let caps = make_element("capsfilter", "webrtc_caps")?;
+    caps.set_property(
+        "caps",
+        gst::Caps::builder("application/x-rtp")
+            .field("media", "audio")
+            .field("encoding-name", "OPUS")
+            .field("clock-rate", 48_000i32)
+            .field("payload", 97i32)
+            .build(),
+    );
+    pipeline.add_many(&[&caps, &webrtc])?;
+    pay.link(&caps)?;
+    let sinkpad = webrtc
+        .request_pad_simple("sink_%u")
+        .context("webrtcbin: no sink pad")?;
+    caps.static_pad("src")
+        .context("capsfilter has no src pad")?
+        .link(&sinkpad)
+        .context("sender: link pay → webrtcbin")?;
+    let transceiver = sinkpad.property::<gst_webrtc::WebRTCRTPTransceiver>("transceiver");
+    transceiver.set_property("direction", gst_webrtc::WebRTCRTPTransceiverDirection::Sendonly);
+
+    let signal = Signal::default();
+    send_ice_candidates(&webrtc, &signal);
+    let offer_signal = signal.clone();
+    webrtc.connect("on-negotiation-needed", false, move |args| {
+        let Ok(webrtc) = args[0].get::<gst::Element>() else { return None };
+        let weak = webrtc.downgrade();
+        let signal = offer_signal.clone();
+        let promise = gst::Promise::with_change_func(move |reply| {
+            let Some(webrtc) = weak.upgrade() else { return };
+            let offer = match reply {
+                Ok(Some(r)) => r.get::<gst_webrtc::WebRTCSessionDescription>("offer").ok(),
+                _ => None,
+            };
+            let Some(offer) = offer else {
+                warn!(target: "webrtc", "create-offer failed: {reply:?}");
+                return;
+            };
+            webrtc.emit_by_name::<()>("set-local-description", &[&offer, &None::<gst::Promise>]);
+            let sdp = offer.sdp().as_text().unwrap_or_default();
+            signal.send(json!({ "type": "offer", "sdp": sdp }));
+            info!(target: "webrtc", "offer created");
+        });
+        webrtc.emit_by_name::<()>("create-offer", &[&None::<gst::Structure>, &promise]);
+        None
+    });
+
+    let weak = webrtc.downgrade();
+    let addr = host_port(host, port);
+    tasks.spawn("webrtc-signal", move |stop| {
+        let stream = loop {
+            match connect(&addr) {
+                Ok(s) => break s,
+                Err(e) => {
+                    warn!(target: "webrtc", "signaling connect {addr} failed: {e:#}; retrying");
+                    if !stop.sleep(Duration::from_secs(2)) {
+                        return;
+                    }
+                }
+            }
+        };
+        info!(target: "webrtc", "signaling connected to {addr}");
+        let Ok(writer) = stream.try_clone() else { return };
+        signal.attach(writer);
+        handle_signaling(stream, &weak, &signal, &stop);
+        signal.detach();
+        if !stop.is_set() {
+            warn!(target: "webrtc", "signaling to {addr} closed; restart the sender to renegotiate");
+        }
+    })?;
+
+    info!(target: "sender", "WebRTC → {host}:{port} (signaling)");
+    Ok(())
+}
+
+/// Receive-only WebRTC peer: answers offers arriving on `listen_port`.
+/// Returns webrtcbin so the caller can hook its `src_%u` pads.
+pub(crate) fn attach_receiver(
+    tasks: &Tasks,
+    pipeline: &gst::Pipeline,
+    bind: IpAddr,
+    listen_port: u16,
+    stun: Option<&str>,
+    jitter_ms: u32,
+) -> Result<gst::Element> {
+    let webrtc = make_webrtcbin(stun)?;
+    webrtc.set_property("latency", jitter_ms);
+    info!(target: "receiver", "webrtc.latency={jitter_ms} ms");
+    pipeline.add(&webrtc)?;
+
+    let signal = Signal::default();
+    send_ice_candidates(&webrtc, &signal);
+
+    let listener = TcpListener::bind((bind, listen_port))
+        .with_context(|| format!("webrtc: bind signaling port {listen_port}"))?;
+    // Polled, so the thread notices the pipeline stopping
+    listener.set_nonblocking(true)?;
+    let weak = webrtc.downgrade();
+    tasks.spawn("webrtc-signal", move |stop| {
+        while !stop.is_set() {
+            let stream = match listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    if e.kind() != ErrorKind::WouldBlock {
+                        warn!(target: "webrtc", "signaling accept failed: {e}");
+                    }
+                    std::thread::sleep(POLL);
+                    continue;
+                }
+            };
+            let peer = stream.peer_addr().map(|a| a.to_string()).unwrap_or_default();
+            info!(target: "webrtc", "signaling peer {peer} connected");
+            let Ok(writer) = stream.try_clone() else { continue };
+            signal.attach(writer);
+            handle_signaling(stream, &weak, &signal, &stop);
+            signal.detach();
+            info!(target: "webrtc", "signaling peer {peer} gone");
+        }
+    })?;
+
+    info!(target: "receiver", "WebRTC signaling on tcp :{listen_port}");
+    Ok(webrtc)
+}
+
+/// Resolve `addr` and connect, giving up on each address after a few seconds.
+fn connect(addr: &str) -> Result<TcpStream> {
+    let mut last = None;
+    for a in addr.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&a, Duration::from_secs(3)) {
+            Ok(s) => return Ok(s),
+            Err(e) => last = Some(e),
+        }
+    }
+    match last {
+        Some(e) => Err(e.into()),
+        None => anyhow::bail!("{addr} did not resolve"),
+    }
+}
+
+/// Apply offers/answers/candidates from the peer until the connection closes
+/// or the pipeline stops.
+fn handle_signaling(stream: TcpStream, webrtc: &gst::glib::WeakRef<gst::Element>, signal: &Signal, stop: &StopFlag) {
+    // Accepted sockets may inherit the listener's non-blocking mode
+    if stream.set_nonblocking(false).and_then(|_| stream.set_read_timeout(Some(POLL))).is_err() {
+        return;
+    }
+    let mut reader = BufReader::new(stream);
+    let mut buf = Vec::new();
+    while !stop.is_set() {
+        // A timeout keeps what was read so far in `buf`; the line is finished next time round
+        match reader.read_until(b'\n', &mut buf) {
+            Ok(0) => break,
+            Ok(_) => {}
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => continue,
+            Err(_) => break,
+        }
+        let line = String::from_utf8_lossy(&std::mem::take(&mut buf)).into_owned();
+        let Some(webrtc) = webrtc.upgrade() else { break };
+        let msg: Value = match serde_json::from_str(&line) {
+            Ok(v) => v,
+            Err(e) => {
+                warn!(target: "webrtc", "bad signaling message: {e}");
+                continue;
+            }
+        };
+        match msg["type"].as_str() {
+            Some("offer") => {
+                let Some(desc) = parse_sdp(&msg, gst_webrtc::WebRTCSDPType::Offer) else { continue };
+                webrtc.emit_by_name::<()>("set-remote-description", &[&desc, &None::<gst::Promise>]);
+                answer(&webrtc, signal);
+            }
+            Some("answer") => {
+                let Some(desc) = parse_sdp(&msg, gst_webrtc::WebRTCSDPType::Answer) else { continue };
+                webrtc.emit_by_name::<()>("set-remote-description", &[&desc, &None::<gst::Promise>]);
+                info!(target: "webrtc", "answer applied");
+            }
+            Some("ice") => {
+                let mline = msg["mline"].as_u64().unwrap_or(0) as u32;
+                let candidate = msg["candidate"].as_str().unwrap_or_default();
+                webrtc.emit_by_name::<()>("add-ice-candidate", &[&mline, &candidate]);
+            }
+            other => warn!(target: "webrtc", "unknown signaling message type {other:?}"),
+        }
+    }
+}
+
+fn parse_sdp(msg: &Value, kind: gst_webrtc::WebRTCSDPType) -> Option<gst_webrtc::WebRTCSessionDescription> {
+    let sdp = msg["sdp"].as_str()?;
+    match gst_sdp::SDPMessage::parse_buffer(sdp.as_bytes()) {
+        Ok(sdp) => Some(gst_webrtc::WebRTCSessionDescription::new(kind, sdp)),
+        Err(e) => {
+            warn!(target: "webrtc", "unparsable SDP: {e}");
+            None
+        }
+    }
+}
+
+fn answer(webrtc: &gst::Element, signal: &Signal) {
+    let weak = webrtc.downgrade();
+    let signal = signal.clone();
+    let promise = gst::Promise::with_change_func(move |reply| {
+        let Some(webrtc) = weak.upgrade() else { return };
+        let answer = match reply {
+            Ok(Some(r)) => r.get::<gst_webrtc::WebRTCSessionDescription>("answer").ok(),
+            _ => None,
+        };
+        let Some(answer) = answer else {
+            warn!(target: "webrtc", "create-answer failed: {reply:?}");
+            return;
+        };
+        webrtc.emit_by_name::<()>("set-local-description", &[&answer, &None::<gst::Promise>]);
+        let sdp = answer.sdp().as_text().unwrap_or_default();
+        signal.send(json!({ "type": "answer", "sdp": sdp }));
+        info!(target: "webrtc", "answer sent");
+    });
+    webrtc.emit_by_name::<()>("create-answer", &[&None::<gst::Structure>, &promise]);
+}
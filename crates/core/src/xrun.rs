@@ -0,0 +1,213 @@
+// Underruns and overruns, counted and kept as timestamped events so a glitch
+// can be lined up with what the network was doing at the time:
+//   underrun          the receiver's sink got a buffer after its time to play
+//                     (the device ran dry)
+//   overrun           a queue filled up (whatever is downstream of it can't
+//                     keep up): q_net and q_sink on the receiver, q_src on
+//                     the sender
+//   capture-overrun   the sender's audio source skipped samples (a
+//                     discontinuity after the first buffer)
+// Repeats of the same kind in the same element within a second fold into one
+// event. Each new event is logged and posted on the bus as:
+//   xrun, kind=(string)…, element=(string)…, detail=(string)…
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Events kept for `events()`.
+const HISTORY: usize = 100;
+/// Repeats this close together are one event.
+const COALESCE: Duration = Duration::from_secs(1);
+/// A buffer this much past its play time counts as an underrun.
+const LATE_TOLERANCE: gst::ClockTime = gst::ClockTime::from_mseconds(10);
+/// After this long without buffers (stream start, idle release) the next one
+/// isn't judged.
+const REARM_AFTER: Duration = Duration::from_secs(1);
+/// How often the receiver's latency is re-read for the underrun check.
+const LATENCY_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum XrunKind {
+    Underrun,
+    Overrun,
+    CaptureOverrun,
+}
+
+impl XrunKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            XrunKind::Underrun => "underrun",
+            XrunKind::Overrun => "overrun",
+            XrunKind::CaptureOverrun => "capture-overrun",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Xrun {
+    /// When it (first) happened.
+    pub at: SystemTime,
+    pub kind: XrunKind,
+    /// The element it happened in.
+    pub element: String,
+    /// Occurrences folded into this event.
+    pub count: u64,
+    pub detail: String,
+    last: Instant,
+}
+
+/// Occurrences so far, by kind.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct XrunCounts {
+    pub underruns: u64,
+    pub overruns: u64,
+    pub capture_overruns: u64,
+}
+
+#[derive(Default)]
+struct Log {
+    counts: XrunCounts,
+    events: VecDeque<Xrun>,
+}
+
+/// One pipeline's events, logged under `side`'s target.
+#[derive(Clone)]
+pub(crate) struct Table {
+    side: &'static str,
+    log: Arc<Mutex<Log>>,
+}
+
+impl Table {
+    pub(crate) fn new(side: &'static str) -> Self {
+        Self { side, log: Arc::default() }
+    }
+
+    pub(crate) fn counts(&self) -> XrunCounts {
+        self.log.lock().unwrap().counts
+    }
+
+    pub(crate) fn events(&self) -> Vec<Xrun> {
+        self.log.lock().unwrap().events.iter().cloned().collect()
+    }
+
+    fn record(&self, pipeline: &gst::glib::WeakRef<gst::Pipeline>, kind: XrunKind, element: &str, detail: String) {
+        let mut log = self.log.lock().unwrap();
+        let counts = &mut log.counts;
+        match kind {
+            XrunKind::Underrun => counts.underruns += 1,
+            XrunKind::Overrun => counts.overruns += 1,
+            XrunKind::CaptureOverrun => counts.capture_overruns += 1,
+        }
+        let repeat = log.events.iter_mut().rev().find(|e| e.kind == kind && e.element == element);
+        if let Some(e) = repeat.filter(|e| e.last.elapsed() < COALESCE) {
+            e.count += 1;
+            e.last = Instant::now();
+            return;
+        }
+        if log.events.len() == HISTORY {
+            log.events.pop_front();
+        }
+        log.events.push_back(Xrun {
+            at: SystemTime::now(),
+            kind,
+            element: element.to_string(),
+            count: 1,
+            detail: detail.clone(),
+            last: Instant::now(),
+        });
+        drop(log);
+        match self.side {
+            "sender" => tracing::warn!(target: "sender", "{} in {element}: {detail}", kind.name()),
+            _ => tracing::warn!(target: "receiver", "{} in {element}: {detail}", kind.name()),
+        }
+        if let Some(pipeline) = pipeline.upgrade() {
+            let s = gst::Structure::builder("xrun")
+                .field("kind", kind.name())
+                .field("element", element)
+                .field("detail", detail)
+                .build();
+            let _ = pipeline.post_message(gst::message::Application::builder(s).src(&pipeline).build());
+        }
+    }
+}
+
+/// Count `queue` filling up.
+pub(crate) fn watch_queue(table: &Table, pipeline: &gst::Pipeline, queue: &gst::Element) {
+    let (table, pipeline, name) = (table.clone(), pipeline.downgrade(), queue.name().to_string());
+    queue.connect("overrun", false, move |args| {
+        let level_ms = args[0]
+            .get::<gst::Element>()
+            .map_or(0, |q| q.property::<u64>("current-level-time") / 1_000_000);
+        table.record(&pipeline, XrunKind::Overrun, &name, format!("full at {level_ms} ms"));
+        None
+    });
+}
+
+/// Count the samples `src` skips: a discontinuity after its first buffer.
+pub(crate) fn watch_capture(table: &Table, pipeline: &gst::Pipeline, src: &gst::Element) {
+    let Some(pad) = src.static_pad("src") else { return };
+    let (table, pipeline, name) = (table.clone(), pipeline.downgrade(), src.name().to_string());
+    // When the previous buffer came, and where it ended
+    let previous: Mutex<Option<(Instant, Option<gst::ClockTime>)>> = Mutex::new(None);
+    pad.add_probe(gst::PadProbeType::BUFFER, move |_pad, info| {
+        let Some(gst::PadProbeData::Buffer(buffer)) = &info.data else { return gst::PadProbeReturn::Ok };
+        let end = buffer.pts().zip(buffer.duration()).map(|(pts, d)| pts + d);
+        let before = previous.lock().unwrap().replace((Instant::now(), end));
+        if let Some((at, before_end)) = before {
+            if at.elapsed() < REARM_AFTER && buffer.flags().contains(gst::BufferFlags::DISCONT) {
+                let skipped = buffer.pts().zip(before_end).and_then(|(pts, end)| pts.checked_sub(end));
+                let detail = match skipped {
+                    Some(t) => format!("{} ms of audio skipped", t.mseconds()),
+                    None => "discontinuity".to_string(),
+                };
+                table.record(&pipeline, XrunKind::CaptureOverrun, &name, detail);
+            }
+        }
+        gst::PadProbeReturn::Ok
+    });
+}
+
+/// Count buffers reaching `sink` after their time to play. Only meaningful
+/// when the sink syncs to the clock.
+pub(crate) fn watch_sink(table: &Table, pipeline: &gst::Pipeline, sink: &gst::Element) {
+    let Some(pad) = sink.static_pad("sink") else { return };
+    // The pipeline's latency in ns (0 until known); a query is too heavy per buffer
+    let latency = Arc::new(AtomicU64::new(0));
+    let (weak, current) = (pipeline.downgrade(), latency.clone());
+    std::thread::spawn(move || {
+        loop {
+            std::thread::sleep(LATENCY_INTERVAL);
+            let Some(pipeline) = weak.upgrade() else { break };
+            let mut query = gst::query::Latency::new();
+            if pipeline.current_state() == gst::State::Playing && pipeline.query(&mut query) {
+                let (live, min, _max) = query.result();
+                current.store(if live { min.nseconds() } else { 0 }, Ordering::Relaxed);
+            }
+        }
+    });
+
+    let (table, pipeline, name) = (table.clone(), pipeline.downgrade(), sink.name().to_string());
+    let previous: Mutex<Option<Instant>> = Mutex::new(None);
+    pad.add_probe(gst::PadProbeType::BUFFER, move |pad, info| {
+        let Some(gst::PadProbeData::Buffer(buffer)) = &info.data else { return gst::PadProbeReturn::Ok };
+        let before = previous.lock().unwrap().replace(Instant::now());
+        if !before.is_some_and(|t| t.elapsed() < REARM_AFTER) {
+            return gst::PadProbeReturn::Ok;
+        }
+        let latency = gst::ClockTime::from_nseconds(latency.load(Ordering::Relaxed));
+        let Some(element) = pad.parent_element() else { return gst::PadProbeReturn::Ok };
+        let Some(segment) = pad.sticky_event::<gst::event::Segment>(0) else { return gst::PadProbeReturn::Ok };
+        let late = (|| {
+            let running = segment.segment().downcast_ref::<gst::ClockTime>()?.to_running_time(buffer.pts()?)?;
+            let now = element.clock()?.time()?.checked_sub(element.base_time()?)?;
+            now.checked_sub(running + latency)
+        })();
+        if let Some(late) = late.filter(|l| latency > gst::ClockTime::ZERO && *l > LATE_TOLERANCE) {
+            table.record(&pipeline, XrunKind::Underrun, &name, format!("audio {} ms late", late.mseconds()));
+        }
+        gst::PadProbeReturn::Ok
+    });
+}
@@ -0,0 +1,104 @@
+// --capture-app: keeping the sender on the app it captures. Every couple of
+// seconds the app is looked up again, and the sender rebuilt when what it
+// captures isn't the app any more:
+//
+// Linux: the app's PipeWire stream goes away when it stops playing or
+// restarts, and a new one (another serial) comes when it plays again;
+// pipewiresrc doesn't follow, and is told not to fall back to the microphone
+// meanwhile (see `apps.rs` in ab-core). The sender moves onto the new stream.
+//
+// macOS: a tap (`tap.rs`) is on the app's processes as they were when it was
+// made, so when they change (the app restarted, a browser started a helper)
+// the app is tapped afresh, and the sender captures the new tap's device.
+// Until then, and while the app isn't running, the sender sends silence.
+use std::time::Duration;
+use tracing::{info, warn};
+
+use ab_core::pipeline::Source;
+
+use crate::control::Control;
+use crate::supervise;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Follow the app for as long as the daemon runs.
+pub fn spawn(control: std::sync::Arc<Control>) {
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(POLL_INTERVAL);
+        let mut complained = false;
+        #[cfg(target_os = "macos")]
+        let mut current = None;
+        loop {
+            tick.tick().await;
+            #[cfg(target_os = "linux")]
+            let result = moved(&control).await;
+            #[cfg(target_os = "macos")]
+            let result = tapped(&control, &mut current).await;
+            match result {
+                Ok(Some(why)) => supervise::restart(&control, false, true, &why).await,
+                Ok(None) => complained = false,
+                // Said once, not every poll
+                Err(e) if !complained => {
+                    warn!(target: "sender", "--capture-app: {e:#}");
+                    complained = true;
+                }
+                Err(_) => {}
+            }
+        }
+    });
+}
+
+/// Linux: why the sender should be rebuilt, if it should: the app has a
+/// stream, and the sender captures none of the app's that are still there.
+#[cfg(target_os = "linux")]
+async fn moved(control: &Control) -> anyhow::Result<Option<String>> {
+    use ab_core::apps;
+
+    let sending = control.sending.lock().await;
+    let Some(s) = sending.as_ref().filter(|s| s.cfg.source == Source::Capture) else { return Ok(None) };
+    let Some(query) = s.cfg.capture_app.as_deref() else { return Ok(None) };
+    let streams = apps::streams()?;
+    let Some(stream) = apps::find(query, &streams)? else { return Ok(None) };
+    let current = s.with_sender(|tx| tx.app_stream()).flatten();
+    if current.is_some_and(|c| streams.iter().any(|other| other.serial == c)) {
+        return Ok(None);
+    }
+    info!(target: "sender", "'{}' is playing (stream {})", stream.app, stream.serial);
+    Ok(Some(format!("--capture-app '{query}' has a new stream")))
+}
+
+/// macOS: the same, tapping the app's processes when the `current` tap
+/// isn't on them, and pointing the sender's capture device at the tap.
+#[cfg(target_os = "macos")]
+async fn tapped(control: &Control, current: &mut Option<crate::tap::Tap>) -> anyhow::Result<Option<String>> {
+    use crate::tap;
+
+    let mut sending = control.sending.lock().await;
+    let Some(s) = sending.as_mut().filter(|s| s.cfg.source == Source::Capture) else { return Ok(None) };
+    let Some(query) = s.cfg.capture_app.clone() else { return Ok(None) };
+    let processes = tap::processes(&query);
+    // Gone: the old tap, if any, is just silent
+    if processes.is_empty() {
+        return Ok(None);
+    }
+    let objects: Vec<_> = processes.iter().map(|p| p.object).collect();
+    let why = if current.as_ref().is_some_and(|t| t.processes == objects) {
+        let device = current.as_ref().map(|t| t.device.to_string());
+        if s.cfg.capture_device == device {
+            return Ok(None);
+        }
+        // A reload or a new route started from the plain config
+        format!("--capture-app '{query}': back on its tap")
+    } else {
+        // The sender loses the old tap's device, but is rebuilt right away
+        // (or, should the new tap fail, by the watchdog, to send silence)
+        *current = None;
+        s.cfg.capture_device = None;
+        *current = Some(tap::create(&objects)?);
+        let names: Vec<String> = processes.iter().map(|p| format!("{} ({})", p.name, p.pid)).collect();
+        info!(target: "sender", "tapping {}", names.join(", "));
+        format!("--capture-app '{query}' has new processes")
+    };
+    s.cfg.capture_device = current.as_ref().map(|t| t.device.to_string());
+    Ok(Some(why))
+}
@@ -1,15 +1,284 @@
-use clap::Parser;
+use clap::{Parser, Subcommand, ValueEnum};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use ab_core::pipeline::Source;
+
+use crate::routes::RouteSpec;
 
 #[derive(Parser, Debug)]
 #[command(name="ab-daemon", version, about="Rust LAN audio bridge")]
 pub struct Args {
-    /// Optional device name to capture (e.g., "BlackHole 2ch" on macOS)
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Options file (default: ~/.config/audiobridge/config.toml); flags override it
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+
+    /// What to log: a level (error, warn, info, debug, trace), or a default plus levels per
+    /// target as in `info,receiver=debug,mdns=warn`; the target is the tag a line starts with
+    /// (sender, receiver, mdns, control, handshake, config, net…)
+    #[arg(long, value_name = "FILTER", default_value = "info")]
+    pub log_level: String,
+
+    /// Log to stderr as `[target] message` lines or as JSON, one object per line
+    #[arg(long, value_enum, default_value_t = LogFormatArg::Text)]
+    pub log_format: LogFormatArg,
+
+    /// Run in the background: return once the daemon is up (printing its pid), with the pid
+    /// in --pidfile and the log in --log-file
+    #[arg(long)]
+    pub detach: bool,
+
+    /// Where --detach writes the daemon's pid [default: audiobridge.pid next to the control
+    /// socket]
+    #[arg(long, value_name = "PATH", requires = "detach")]
+    pub pidfile: Option<PathBuf>,
+
+    /// Send the log (and anything else on stdout/stderr) to this file, rotated past
+    /// --log-max-mb [default with --detach: ab-daemon.log in the config directory]
+    #[arg(long, value_name = "PATH")]
+    pub log_file: Option<PathBuf>,
+
+    /// Rotate the log file when it grows past this many MB, keeping the last 3 (.1 to .3)
+    #[arg(long, value_name = "MB", default_value_t = 10)]
+    pub log_max_mb: u64,
+
+    /// Write a stats record (TX packets and bitrate, RX packets, jitter, loss, buffer fill,
+    /// levels) every this many seconds, to stdout or --stats-file
+    #[arg(long, value_name = "SECS", value_parser = clap::value_parser!(u64).range(1..))]
+    pub stats_interval: Option<u64>,
+
+    /// Format of the stats records: JSON, one object per line
+    #[arg(long, value_enum, default_value_t = StatsFormatArg::Json, requires = "stats_interval")]
+    pub stats_format: StatsFormatArg,
+
+    /// Append stats records to this file instead of writing them to stdout
+    #[arg(long, value_name = "PATH", requires = "stats_interval")]
+    pub stats_file: Option<PathBuf>,
+
+    /// Serve Prometheus metrics (packets, bytes, bitrate, jitterbuffer, loss, levels, pipeline
+    /// state) on http://<host>:<port>/metrics
+    #[arg(long, value_name = "PORT")]
+    pub metrics_port: Option<u16>,
+
+    /// Export traces of the session lifecycle (sending, receiving, sessions, streams, recoveries)
+    /// and the --metrics-port metrics to an OpenTelemetry collector, as OTLP/HTTP JSON
+    #[arg(long, value_name = "URL")]
+    pub otlp_endpoint: Option<String>,
+
+    /// Seconds between exports to --otlp-endpoint
+    #[arg(long, value_name = "SECS", default_value_t = 10,
+          value_parser = clap::value_parser!(u64).range(1..=3600), requires = "otlp_endpoint")]
+    pub otlp_interval: u64,
+
+    /// POST each event (peer discovered or gone, sending started or stopped, stream started or
+    /// stopped, silence, error, reconnected, device plugged in or out) to this URL as JSON, e.g.
+    /// for home automation
+    #[arg(long, value_name = "URL")]
+    pub on_event_url: Option<String>,
+
+    /// Publish status, levels and events to this MQTT broker, HOST[:PORT] (default port 1883),
+    /// and take commands from it (volume, mute, start/stop sending), e.g. for Home Assistant
+    #[arg(long, value_name = "HOST[:PORT]")]
+    pub mqtt_broker: Option<String>,
+
+    /// Topic prefix on --mqtt-broker [default: audio-bridge/<hostname>]
+    #[arg(long, value_name = "TOPIC", requires = "mqtt_broker")]
+    pub mqtt_topic: Option<String>,
+
+    /// User name on --mqtt-broker
+    #[arg(long, value_name = "NAME", requires = "mqtt_broker")]
+    pub mqtt_username: Option<String>,
+
+    /// Password for --mqtt-username
+    #[arg(long, env = "AB_MQTT_PASSWORD", hide_env_values = true)]
+    pub mqtt_password: Option<String>,
+
+    /// Seconds between status updates on --mqtt-broker (and after each command)
+    #[arg(long, value_name = "SECS", default_value_t = 5,
+          value_parser = clap::value_parser!(u64).range(1..=3600), requires = "mqtt_broker")]
+    pub mqtt_interval: u64,
+
+    /// Announce this node to Home Assistant over --mqtt-broker (MQTT discovery), as a device with
+    /// volume, mute and sending controls, what's playing, and events
+    #[arg(long, requires = "mqtt_broker")]
+    pub ha_discovery: bool,
+
+    /// Home Assistant's discovery topic prefix
+    #[arg(long, value_name = "TOPIC", default_value = "homeassistant")]
+    pub ha_discovery_prefix: String,
+
+    /// Write a Graphviz snapshot of a pipeline here when it fails, and of both on
+    /// `ctl dump-dot`, to attach to bug reports
+    #[arg(long, value_name = "DIR")]
+    pub dump_dot: Option<PathBuf>,
+
+    /// Write the RTP/RTCP the sender and receiver send and receive to this pcap file,
+    /// to look into dropouts with Wireshark afterwards
+    #[arg(long, value_name = "FILE")]
+    pub pcap: Option<PathBuf>,
+
+    /// `[peers]` from the config file: name → address.
+    #[arg(skip)]
+    pub peer_names: BTreeMap<String, String>,
+
+    /// `[groups]` from the config file: zone name → receivers in it.
+    #[arg(skip)]
+    pub groups: BTreeMap<String, Vec<String>>,
+
+    /// Device to capture: an ID from `ab-daemon devices` (macOS: device index, or part of
+    /// the device's name, e.g. "BlackHole 2ch", looked up again when it's replugged;
+    /// Linux: pulse source name; default: the first `.monitor` source)
     #[arg(long)]
     pub capture_device: Option<String>,
 
-    /// Remote host to send to (IPv4 LAN)
+    /// What to send: `capture` (the capture device), `tone[:FREQ]`, a test sine (default
+    /// 440 Hz) that checks the network and receivers without any capture setup, or
+    /// `file:PATH`, a WAV/FLAC/MP3… file played once; the last two replace --capture-device
+    #[arg(long, default_value = "capture", value_parser = parse_source)]
+    pub source: Source,
+
+    /// Device to play on: an ID from `ab-daemon devices`, or part of its name ("usb",
+    /// "speakers"), which must pick one (default: the system output)
+    #[arg(long, env = "PULSE_SINK")]
+    pub output_device: Option<String>,
+
+    /// Linux: with no --capture-device, prefer the monitor source whose name contains this
+    #[arg(long, env = "MONITOR_HINT")]
+    pub monitor_hint: Option<String>,
+
+    /// Linux: make a null sink of this name while running, for apps to play into, and
+    /// capture its monitor (removed on exit; an existing one is used as it is)
+    #[arg(long, value_name = "NAME", conflicts_with = "capture_device")]
+    pub virtual_sink: Option<String>,
+
+    /// Make --virtual-sink the default output while running, so everything plays into it
+    #[arg(long, requires = "virtual_sink")]
+    pub virtual_sink_default: bool,
+
+    /// Capture only this app's audio, by part of its name or program ("firefox"), not all
+    /// that plays; it still plays here too. Linux: needs PipeWire; macOS: 14.4 or later,
+    /// no BlackHole needed. Silence until the app plays; it's followed when it restarts
+    #[arg(long, value_name = "APP", conflicts_with_all = ["capture_device", "virtual_sink"])]
+    pub capture_app: Option<String>,
+
+    /// Capture buffer size in µs (macOS default 200000)
+    #[arg(long, env = "AB_SRC_BUFFER_US", value_parser = clap::value_parser!(u32).range(1_000..=2_000_000))]
+    pub src_buffer_us: Option<u32>,
+
+    /// Capture period in µs (macOS default 10000)
+    #[arg(long, env = "AB_SRC_LATENCY_US", value_parser = clap::value_parser!(u32).range(1_000..=1_000_000))]
+    pub src_latency_us: Option<u32>,
+
+    /// Remote host(s) to send to: IPv4/IPv6 address, hostname, multicast group
+    /// (239.x.x.x / ff0x::), a discovered peer (`studio-mac` / `studio-mac.local`), or a
+    /// zone from the config's `[groups]`. Repeat or comma-separate to fan one encode out
+    /// to several receivers.
+    #[arg(long, value_delimiter = ',')]
+    pub send_to: Vec<String>,
+
+    /// Send to the one peer found on mDNS, at its advertised port (no addressing needed)
+    #[arg(long, conflicts_with = "send_to")]
+    pub auto_send: bool,
+
+    /// With --auto-send on a LAN with several peers: pick the one whose name contains this
+    #[arg(long, requires = "auto_send")]
+    pub auto_send_hint: Option<String>,
+
+    /// Two-way audio with one peer (an intercom): send to it at its advertised port
+    /// (else our --listen-port) and play what it sends, capturing the microphone with
+    /// echo cancellation. Give each machine the other's name
+    #[arg(long, value_name = "PEER", conflicts_with_all = ["send_to", "auto_send", "no_send", "no_receive"])]
+    pub duplex: Option<String>,
+
+    /// Cancel what we play out of what we capture, with noise suppression (webrtcdsp;
+    /// default: on with --duplex)
+    #[arg(long, action = clap::ArgAction::Set, value_parser = clap::builder::BoolishValueParser::new())]
+    pub echo_cancel: Option<bool>,
+
+    /// Push-to-talk: send nothing until told to talk (`ctl ptt on|off`, or --ptt-key)
     #[arg(long)]
-    pub send_to: Option<String>,
+    pub ptt: bool,
+
+    /// Stop sending while the capture stays below this level for 2 s (dBFS, default -60)
+    /// and resume on the first frame with signal, instead of streaming silence all night
+    #[arg(long, value_name = "DB", num_args = 0..=1, default_missing_value = "-60", allow_negative_numbers = true,
+          value_parser = parse_silence_db)]
+    pub suppress_silence: Option<f64>,
+
+    /// While --suppress-silence holds back, still send a frame every this many seconds, so
+    /// receivers can tell we're silent rather than gone sooner (see --stream-timeout)
+    #[arg(long, value_name = "SECS", requires = "suppress_silence",
+          value_parser = clap::value_parser!(u64).range(1..=60))]
+    pub keepalive: Option<u64>,
+
+    /// Also play what we send on this machine's output, quietly (volume 0.0-10.0, default
+    /// 0.2), to hear what is really being captured. Capturing the monitor of that same
+    /// output makes it echo
+    #[arg(long, value_name = "VOLUME", num_args = 0..=1, default_missing_value = "0.2",
+          value_parser = parse_volume)]
+    pub local_monitor: Option<f64>,
+
+    /// Linux: talk while this key is held, read from evdev (needs the `input` group):
+    /// a key code or a name like `f13`, `scrolllock`, `rightctrl`, `mouse-side`. Implies --ptt
+    #[arg(long, value_name = "KEY")]
+    pub ptt_key: Option<String>,
+
+    /// Input device(s) to watch for --ptt-key (default: every /dev/input/event* we can read)
+    #[arg(long, value_name = "PATH", requires = "ptt_key")]
+    pub ptt_device: Vec<PathBuf>,
+
+    /// Extra stream alongside the main sender and receiver, repeatable:
+    /// `mic=capture:DEVICE->office` (a capture device to receivers, `HOST[,HOST][:PORT]`)
+    /// or `phones=listen:6004->output:DEVICE` (a port to a playback device)
+    #[arg(long, value_name = "NAME=FROM->TO")]
+    pub route: Vec<RouteSpec>,
+
+    /// Speaker pair: send the left channel to the first receiver and the right to the
+    /// second, in step on our shared clock (run them with --clock-from <this machine>)
+    #[arg(long, value_name = "LEFT,RIGHT", value_delimiter = ',',
+          conflicts_with_all = ["send_to", "auto_send", "duplex", "no_send", "aes67"])]
+    pub speaker_pair: Vec<String>,
+
+    /// Clock to run on: `system` (each machine its own; --drift-correction makes up the
+    /// difference), `net` (serve ours on --listen-port + 3 for receivers to follow, or follow
+    /// --clock-from) or `ptp` (follow the PTP grandmaster on --ptp-domain, on every machine)
+    #[arg(long, value_enum, default_value_t = ClockArg::System)]
+    pub clock: ClockArg,
+
+    /// Play on the clock served by this machine (HOST or HOST:PORT, default port: our
+    /// --listen-port + 3), in step with other receivers following it. Implies --clock net
+    #[arg(long, value_name = "HOST[:PORT]")]
+    pub clock_from: Option<String>,
+
+    /// Measure capture-to-speaker latency: senders stamp packets with their capture time,
+    /// receivers log and report (`status`) how long after it they play. Needs it on both
+    /// ends, and the same --clock (or NTP-synced machines with --clock system)
+    #[arg(long)]
+    pub measure_latency: bool,
+
+    /// Relay: forward what we receive, untouched, to these receivers too (a wired hub
+    /// for Wi-Fi speakers). Repeat or comma-separate
+    #[arg(long, value_delimiter = ',', conflicts_with = "no_receive")]
+    pub relay_to: Vec<String>,
+
+    /// Port the --relay-to receivers listen on (default: our --listen-port)
+    #[arg(long, requires = "relay_to")]
+    pub relay_port: Option<u16>,
+
+    /// Never send: don't capture, and refuse `ctl start-send` (a speaker endpoint)
+    #[arg(long, conflicts_with_all = ["send_to", "auto_send", "no_receive"])]
+    pub no_send: bool,
+
+    /// Don't receive: no listening ports, no mDNS advertisement (a send-only kiosk)
+    #[arg(long)]
+    pub no_receive: bool,
+
+    /// Don't take keys from the terminal (m mute, +/- volume, s send, d status, q quit)
+    #[arg(long)]
+    pub no_keys: bool,
 
     /// Send port
     #[arg(long, default_value_t = 5002)]
@@ -19,7 +288,596 @@ pub struct Args {
     #[arg(long, default_value_t = 5004)]
     pub listen_port: u16,
 
-    /// Advertise & discover peers on mDNS
-    #[arg(long, default_value_t = true)]
+    /// Opus bitrate in bit/s (starting point when --adaptive-bitrate is on)
+    #[arg(long, default_value_t = 256_000, value_parser = clap::value_parser!(i32).range(4_000..=650_000))]
+    pub bitrate: i32,
+
+    /// Opus frame duration in ms: 2.5, 5, 10, 20, 40 or 60 (default 2.5; at least 10 with in-band FEC).
+    /// Longer frames cost latency but survive jitter and loss better
+    #[arg(long, value_parser = parse_frame_ms)]
+    pub frame_ms: Option<f32>,
+
+    /// Tuning preset for frame size, jitterbuffer, sink buffering and FEC; flags and
+    /// config keys given explicitly still win
+    #[arg(long, value_enum)]
+    pub profile: Option<ProfileArg>,
+
+    /// Adjust the bitrate at runtime from RTCP loss/RTT reports
+    #[arg(long)]
+    pub adaptive_bitrate: bool,
+
+    /// Lowest bitrate the adaptive controller may pick
+    #[arg(long, default_value_t = 48_000, value_parser = clap::value_parser!(i32).range(4_000..=650_000))]
+    pub min_bitrate: i32,
+
+    /// Highest bitrate the adaptive controller may pick
+    #[arg(long, default_value_t = 256_000, value_parser = clap::value_parser!(i32).range(4_000..=650_000))]
+    pub max_bitrate: i32,
+
+    /// Enable forward error correction (sender encodes it, receiver decodes it; use on both ends)
+    #[arg(long)]
+    pub fec: bool,
+
+    /// FEC scheme: Opus in-band FEC, or separate ULP FEC packets (implies --fec)
+    #[arg(long, value_enum)]
+    pub fec_mode: Option<FecModeArg>,
+
+    /// ULP FEC redundancy, as a percentage of media packets
+    #[arg(long, default_value_t = 25, value_parser = clap::value_parser!(u32).range(1..=100))]
+    pub fec_percentage: u32,
+
+    /// Packet loss percentage the encoder should plan FEC redundancy for
+    #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(i32).range(0..=100))]
+    pub expected_loss_pct: i32,
+
+    /// Enable RTP retransmission (NACK + RTX) on both directions; use on both ends
+    #[arg(long)]
+    pub rtx: bool,
+
+    /// Extra receiver latency budget for retransmissions, in ms
+    #[arg(long, default_value_t = 60)]
+    pub rtx_max_ms: u32,
+
+    /// Multicast group to receive from (e.g. 239.255.42.1)
+    #[arg(long)]
+    pub multicast_group: Option<String>,
+
+    /// TTL for multicast sends (1 keeps packets on the local subnet)
+    #[arg(long, default_value_t = 1)]
+    pub multicast_ttl: u32,
+
+    /// Network interface for multicast send/join (e.g. eth0)
+    #[arg(long)]
+    pub multicast_iface: Option<String>,
+
+    /// DSCP for outgoing media (0-63 or a name: EF, AF41, CS5, ...). EF (46) is the usual choice for audio
+    #[arg(long, value_parser = parse_dscp)]
+    pub dscp: Option<u8>,
+
+    /// Local IP to bind every socket to, pinning sends and receives to one NIC
+    #[arg(long, conflicts_with = "interface")]
+    pub bind_address: Option<std::net::IpAddr>,
+
+    /// Network interface to bind to (e.g. eth0, en0); uses its IPv4 address, else its IPv6 one.
+    /// Also the default for --multicast-iface
+    #[arg(long)]
+    pub interface: Option<String>,
+
+    /// Wire transport for RTP/RTCP (use the same on both ends)
+    #[arg(long, value_enum, default_value_t = TransportArg::Udp)]
+    pub transport: TransportArg,
+
+    /// SRT latency budget in ms (time SRT may spend recovering lost packets)
+    #[arg(long, default_value_t = 120)]
+    pub srt_latency_ms: u32,
+
+    /// STUN server for WebRTC ICE, e.g. stun://stun.l.google.com:19302
+    #[arg(long)]
+    pub stun_server: Option<String>,
+
+    /// Play received audio on this AirPlay speaker (host or IP) instead of the local device.
+    /// Needs an external RAOP client (see --raop-cmd)
+    #[arg(long, conflicts_with = "snapcast")]
+    pub airplay: Option<String>,
+
+    /// RAOP client for --airplay, run as `<cmd> <host> -` with 44.1 kHz S16LE stereo on its stdin
+    #[arg(long, env = "RAOP_CMD", default_value = "raop_play")]
+    pub raop_cmd: String,
+
+    /// Feed received audio to a Snapcast server instead of the local device:
+    /// a stream FIFO path (pipe:///tmp/snapfifo) or tcp://host:port
+    #[arg(long)]
+    pub snapcast: Option<String>,
+
+    /// Playback volume, 0.0-10.0 (1.0 = unity)
+    #[arg(long, default_value_t = 1.0, value_parser = parse_volume)]
+    pub volume: f64,
+
+    /// Receiver jitterbuffer latency in ms (--rtx adds --rtx-max-ms on top)
+    #[arg(long, env = "JITTER_MS", default_value_t = 30, value_parser = clap::value_parser!(u32).range(0..=2_000))]
+    pub jitter_ms: u32,
+
+    /// Grow the jitterbuffer when packets arrive too late and shrink it back when the
+    /// network is calm, starting from --jitter-ms, between --min-jitter-ms and --max-jitter-ms
+    #[arg(long)]
+    pub adaptive_jitter: bool,
+
+    /// Smallest jitterbuffer latency --adaptive-jitter may pick, in ms
+    #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u32).range(0..=2_000))]
+    pub min_jitter_ms: u32,
+
+    /// Largest jitterbuffer latency --adaptive-jitter may pick, in ms
+    #[arg(long, default_value_t = 200, value_parser = clap::value_parser!(u32).range(0..=2_000))]
+    pub max_jitter_ms: u32,
+
+    /// Drop packets that arrive after their playout time (`--drop-on-late false` to keep them)
+    #[arg(long, env = "DROP_ON_LATE", default_value_t = true, action = clap::ArgAction::Set,
+          value_parser = clap::builder::BoolishValueParser::new())]
+    pub drop_on_late: bool,
+
+    /// Measure each sender's clock against ours and resample to make up the difference,
+    /// so latency doesn't creep over hours of streaming (`--drift-correction false` to
+    /// leave it to the jitterbuffer)
+    #[arg(long, default_value_t = true, action = clap::ArgAction::Set,
+          value_parser = clap::builder::BoolishValueParser::new())]
+    pub drift_correction: bool,
+
+    /// Conceal lost packets in the Opus decoder (always on with --fec)
+    #[arg(long, env = "PLC", value_parser = clap::builder::BoolishValueParser::new())]
+    pub plc: bool,
+
+    /// Receive latency to aim for, in ms: sizes the jitterbuffer, the queue before the
+    /// sink and the sink buffer from this one number (--jitter-ms and --sink-*-us given
+    /// explicitly still win). `status` shows the latency the pipeline ends up with
+    #[arg(long, value_parser = clap::value_parser!(u32).range(20..=5_000))]
+    pub latency_ms: Option<u32>,
+
+    /// Playback buffer size in µs
+    #[arg(long, env = "SINK_BUFFER_US", default_value_t = 70_000,
+          value_parser = clap::value_parser!(u32).range(1_000..=2_000_000))]
+    pub sink_buffer_us: u32,
+
+    /// Playback period in µs (must not exceed --sink-buffer-us)
+    #[arg(long, env = "SINK_LATENCY_US", default_value_t = 15_000,
+          value_parser = clap::value_parser!(u32).range(1_000..=1_000_000))]
+    pub sink_latency_us: u32,
+
+    /// Sync playback to the pipeline clock (`--sink-sync false` plays as data arrives)
+    #[arg(long, env = "SINK_SYNC", default_value_t = true, action = clap::ArgAction::Set,
+          value_parser = clap::builder::BoolishValueParser::new())]
+    pub sink_sync: bool,
+
+    /// Linux: play through autoaudiosink instead of pulsesink (--output-device wins)
+    #[arg(long, env = "AUTO_SINK", value_parser = clap::builder::BoolishValueParser::new())]
+    pub auto_sink: bool,
+
+    /// Play on the system's default output, and move with it live when it changes (laptop
+    /// speakers → headphones); macOS does this anyway without --output-device
+    #[arg(long, conflicts_with_all = ["output_device", "auto_sink"])]
+    pub follow_default_sink: bool,
+
+    /// Close the audio device after this many seconds without packets (letting it sleep)
+    /// and reopen it when they come back
+    #[arg(long, value_name = "SECS", conflicts_with = "no_receive",
+          value_parser = clap::value_parser!(u64).range(1..))]
+    pub idle_release: Option<u64>,
+
+    /// Report a sender gone after this many seconds with neither audio nor RTCP; one that's
+    /// only quiet (--suppress-silence) still sends RTCP every ~5 s, or --keepalive frames
+    #[arg(long, value_name = "SECS", default_value_t = 10,
+          value_parser = clap::value_parser!(u64).range(2..=600))]
+    pub stream_timeout: u64,
+
+    /// Also play Scream (Windows virtual sound card) PCM, mixed with the regular streams
+    #[arg(long)]
+    pub scream: bool,
+
+    /// Multicast group Scream sends to (port 4010)
+    #[arg(long, default_value_t = ab_core::scream::DEFAULT_GROUP)]
+    pub scream_group: std::net::Ipv4Addr,
+
+    /// Serve the received mix as Ogg/Opus over HTTP on this port (open http://<host>:<port>/ in a browser)
+    #[arg(long)]
+    pub http_port: Option<u16>,
+
+    /// Record the received mix (before --volume) to this .wav or .flac file, alongside playback
+    #[arg(long, value_name = "PATH", conflicts_with = "no_receive")]
+    pub record: Option<PathBuf>,
+
+    /// Start a new recording file (numbered: NAME-0001.flac, …) once one reaches this many MB
+    #[arg(long, requires = "record", value_parser = clap::value_parser!(u64).range(1..))]
+    pub record_max_mb: Option<u64>,
+
+    /// Start a new recording file after this many minutes
+    #[arg(long, requires = "record", value_parser = clap::value_parser!(u64).range(1..))]
+    pub record_max_minutes: Option<u64>,
+
+    /// Configure the receiver (port, multicast group, FEC, RTX) from an SDP file
+    #[arg(long)]
+    pub sdp: Option<PathBuf>,
+
+    /// Send an AES67 stream (L24, 1 ms packets, PTP-clocked, SAP-announced) instead of Opus.
+    /// Goes to the first --send-to group, or 239.69.0.1 if none is given
+    #[arg(long)]
+    pub aes67: bool,
+
+    /// PTP domain to follow with --aes67 or --clock ptp
+    #[arg(long, default_value_t = 0, value_parser = clap::value_parser!(u32).range(0..=127))]
+    pub ptp_domain: u32,
+
+    /// Linux: serve org.audiobridge.Daemon on the D-Bus session bus (StartSend, StopSend,
+    /// SetVolume, SetMute, GetStatus; StreamStarted and PeerDiscovered signals)
+    #[arg(long)]
+    pub dbus: bool,
+
+    /// Linux: show what's received as an MPRIS media player, so media keys and volume applets
+    /// work on it (play/pause unmute/mute; the sender's name as the title)
+    #[arg(long, conflicts_with = "no_receive")]
+    pub mpris: bool,
+
+    /// Show a desktop notification when a stream starts or stops playing here, naming the sender
+    #[arg(long, conflicts_with = "no_receive")]
+    pub notify: bool,
+
+    /// Advertise & discover peers on mDNS (`--mdns false` to disable)
+    #[arg(long, default_value_t = true, action = clap::ArgAction::Set)]
     pub mdns: bool,
+
+    /// Answer `ab-daemon pair` requests from other machines (the PIN is logged here;
+    /// confirm it with `ab-daemon ctl pair-confirm <pin>`)
+    #[arg(long)]
+    pub pairable: bool,
+
+    /// Keep playing streams from unpaired senders even after pairing with someone
+    #[arg(long, conflicts_with_all = ["psk", "psk_file", "tofu"])]
+    pub accept_unpaired: bool,
+
+    /// Trust peers on first contact (pinned in peers.toml) instead of requiring
+    /// `ab-daemon pair`; a pinned peer whose key changes is still refused
+    #[arg(long)]
+    pub tofu: bool,
+
+    /// Shared secret: senders must prove they know it before the receiver plays them
+    /// (set the same value on both ends)
+    #[arg(long, conflicts_with = "psk_file")]
+    pub psk: Option<String>,
+
+    /// Read the shared secret from a file (first line), so it doesn't show up in `ps`
+    #[arg(long)]
+    pub psk_file: Option<PathBuf>,
+
+    /// Control socket path (default: $XDG_RUNTIME_DIR/audiobridge.sock)
+    #[arg(long)]
+    pub control_socket: Option<PathBuf>,
+
+    /// Encrypt media with SRTP; keys are exchanged per run over the handshake
+    /// (peers must be paired or share --psk). Use on both ends.
+    #[arg(long)]
+    pub srtp: bool,
+}
+
+impl Args {
+    /// A destination with config-file peer names replaced by their address.
+    pub fn named_peer(&self, dest: &str) -> String {
+        self.peer_names.get(dest).cloned().unwrap_or_else(|| dest.to_string())
+    }
+
+    /// Destinations with zones replaced by their receivers, then peer names
+    /// by addresses. A receiver in two of them is sent to once.
+    pub fn expand(&self, dests: &[String]) -> Vec<String> {
+        let mut out: Vec<String> = Vec::new();
+        for dest in dests {
+            let members = self.groups.get(dest).map_or(std::slice::from_ref(dest), Vec::as_slice);
+            for m in members.iter().map(|m| self.named_peer(m)) {
+                if !out.contains(&m) {
+                    out.push(m);
+                }
+            }
+        }
+        out
+    }
+}
+
+fn parse_frame_ms(s: &str) -> Result<f32, String> {
+    match s.parse::<f32>() {
+        Ok(v) if [2.5, 5.0, 10.0, 20.0, 40.0, 60.0].contains(&v) => Ok(v),
+        _ => Err(format!("'{s}' is not an Opus frame size (2.5, 5, 10, 20, 40, 60)")),
+    }
+}
+
+fn parse_source(s: &str) -> Result<Source, String> {
+    if let Some(path) = s.strip_prefix("file:").filter(|p| !p.is_empty()) {
+        return Ok(Source::File(path.into()));
+    }
+    let freq = match (s, s.strip_prefix("tone:")) {
+        ("capture", _) => return Ok(Source::Capture),
+        ("tone", _) => 440.0,
+        (_, Some(f)) => f.parse::<f64>().map_err(|_| format!("'{f}' is not a frequency in Hz"))?,
+        (_, None) => return Err(format!("unknown source '{s}' (capture, tone[:FREQ] or file:PATH)")),
+    };
+    if !(20.0..=20_000.0).contains(&freq) {
+        return Err(format!("tone frequency {freq} Hz out of range (20-20000)"));
+    }
+    Ok(Source::Tone(freq))
+}
+
+fn parse_silence_db(s: &str) -> Result<f64, String> {
+    let db: f64 = s.parse().map_err(|_| format!("'{s}' is not a number"))?;
+    if !(-120.0..=0.0).contains(&db) {
+        return Err(format!("silence threshold {db} dBFS out of range (-120 to 0)"));
+    }
+    Ok(db)
+}
+
+fn parse_volume(s: &str) -> Result<f64, String> {
+    let v: f64 = s.parse().map_err(|_| format!("'{s}' is not a number"))?;
+    if !(0.0..=10.0).contains(&v) {
+        return Err(format!("volume {v} out of range (0.0-10.0)"));
+    }
+    Ok(v)
+}
+
+/// DSCP as a number or a standard PHB name.
+fn parse_dscp(s: &str) -> Result<u8, String> {
+    let v = match s.to_ascii_uppercase().as_str() {
+        "EF" => 46,
+        "VA" | "VOICE-ADMIT" => 44,
+        "CS0" | "BE" => 0,
+        n if n.starts_with("CS") => n[2..].parse::<u8>().map_err(|e| e.to_string())? * 8,
+        n if n.starts_with("AF") && n.len() == 4 => {
+            let class = n[2..3].parse::<u8>().map_err(|e| e.to_string())?;
+            let drop = n[3..4].parse::<u8>().map_err(|e| e.to_string())?;
+            class * 8 + drop * 2
+        }
+        n => n.parse::<u8>().map_err(|_| format!("'{s}' is not a DSCP value or name"))?,
+    };
+    if v > 63 {
+        return Err(format!("DSCP {v} out of range (0-63)"));
+    }
+    Ok(v)
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Print an SDP description of the stream the sender flags describe, then exit
+    /// (e.g. `ab-daemon --send-to 192.168.1.20 sdp > stream.sdp`)
+    Sdp,
+    /// Pair with another machine (mDNS name, `.local` host or address) running
+    /// with --pairable; both sides show a PIN to compare
+    Pair {
+        peer: String,
+    },
+    /// Time the link to a running daemon (mDNS name, `.local` host or address) with
+    /// packets it echoes back, sized and paced like the stream these flags would send,
+    /// and print round trip, jitter, loss and the latency to expect
+    PingAudio {
+        peer: String,
+        /// Packets to send
+        #[arg(long, default_value_t = 1000, value_parser = clap::value_parser!(u32).range(1..=100_000))]
+        count: u32,
+    },
+    /// List capture sources and playback sinks with the IDs --capture-device
+    /// and --output-device take
+    Devices {
+        #[arg(long)]
+        json: bool,
+    },
+    /// List audio-bridge machines on the LAN (from the running daemon's discovery,
+    /// or a short mDNS browse if none is running)
+    Peers {
+        /// Keep running and print peers as they come and go
+        #[arg(long)]
+        watch: bool,
+        #[arg(long)]
+        json: bool,
+    },
+    /// Check that this machine can run with these flags: GStreamer version, the
+    /// elements they need, audio devices, the listen ports and mDNS, with a fix
+    /// for each problem (e.g. the plugin package to install)
+    Doctor,
+    /// macOS: find a loopback driver (BlackHole, Loopback, Soundflower) to capture what the Mac
+    /// plays, or say how to install BlackHole, and the --capture-device to give
+    SetupMacos {
+        /// Also make a Multi-Output device (your output plus the loopback) the default output,
+        /// so what plays is still heard while it's captured
+        #[arg(long)]
+        multi_output: bool,
+        /// Write the capture device to config.toml
+        #[arg(long)]
+        save: bool,
+    },
+    /// Write a systemd unit (Linux) or a LaunchAgent (macOS) that runs the daemon
+    /// with the flags after `--` (e.g. `install-service --user -- --send-to
+    /// studio-mac`), then enable and start it
+    InstallService {
+        /// A user unit (~/.config/systemd/user), in your session and with your audio;
+        /// otherwise a system unit in /etc/systemd/system (needs root and --run-as)
+        #[arg(long, conflicts_with = "run_as")]
+        user: bool,
+        /// Run the system unit as USER, with that user's audio session (Linux)
+        #[arg(long, value_name = "USER")]
+        run_as: Option<String>,
+        /// Also a .socket unit holding the control socket (socket activation; Linux)
+        #[arg(long)]
+        socket: bool,
+        /// Only write the units
+        #[arg(long)]
+        no_enable: bool,
+        #[arg(last = true, value_name = "FLAGS")]
+        flags: Vec<String>,
+    },
+    /// Stop and remove what install-service installed
+    UninstallService {
+        /// The user unit (Linux; macOS always has a user LaunchAgent)
+        #[arg(long)]
+        user: bool,
+    },
+    /// Show or edit the peers this machine trusts (peers.toml)
+    Trust {
+        #[command(subcommand)]
+        action: TrustAction,
+    },
+    /// Show what the running daemon is sending and receiving
+    Status {
+        /// Print the raw JSON reply
+        #[arg(long)]
+        json: bool,
+    },
+    /// Watch the running daemon live: VU meters, bitrate, loss, jitterbuffer and
+    /// peers, redrawn in place until Ctrl-C
+    Top {
+        /// How often to redraw
+        #[arg(long, value_name = "MS", default_value_t = 250,
+              value_parser = clap::value_parser!(u64).range(50..=10_000))]
+        refresh_ms: u64,
+    },
+    /// Send a command to the running daemon
+    Ctl {
+        #[command(subcommand)]
+        action: CtlAction,
+        /// Print the raw JSON reply
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum CtlAction {
+    /// Start sending (to these destinations, or those the daemon was started with),
+    /// e.g. `ctl start-send studio-mac 6006`; the receiver keeps playing throughout
+    StartSend {
+        /// Destinations, optionally followed by a port
+        to: Vec<String>,
+        #[arg(long)]
+        port: Option<u16>,
+    },
+    /// Move a running sender to other destinations, e.g. `ctl switch-send office-mac`;
+    /// over UDP capture keeps running, so there's no gap while it switches
+    SwitchSend {
+        /// Destinations, optionally followed by a port
+        #[arg(required = true)]
+        to: Vec<String>,
+        #[arg(long)]
+        port: Option<u16>,
+    },
+    /// Stop sending
+    StopSend,
+    /// Set playback volume (1.0 = unity); with --zone or --receiver, how loud the
+    /// receivers we send to play us
+    SetVolume {
+        volume: f64,
+        /// A zone from the config's `[groups]`
+        #[arg(long, conflicts_with = "receiver")]
+        zone: Option<String>,
+        /// One receiver we send to
+        #[arg(long)]
+        receiver: Option<String>,
+    },
+    /// Set the Opus bitrate in bit/s
+    SetBitrate {
+        #[arg(value_parser = clap::value_parser!(i32).range(4_000..=650_000))]
+        bitrate: i32,
+    },
+    /// List peers discovered on mDNS
+    ListPeers,
+    /// Confirm a pairing request (with --pairable) whose PIN matches the one the
+    /// other machine shows
+    PairConfirm {
+        pin: String,
+    },
+    /// Push-to-talk (with --ptt): `ptt on` while talking, `ptt off` after
+    Ptt {
+        #[arg(value_parser = clap::builder::BoolishValueParser::new())]
+        talk: bool,
+    },
+    /// Mute or unmute playback: `mute on` / `mute off` (the volume is kept)
+    Mute {
+        #[arg(value_parser = clap::builder::BoolishValueParser::new())]
+        mute: bool,
+    },
+    /// Add a route, e.g. `route-add mic=capture:alsa_input.usb->office`
+    RouteAdd { route: String },
+    /// Stop and remove a route
+    RouteRemove { name: String },
+    /// List routes
+    Routes,
+    /// List zones with their receivers' volumes
+    Zones,
+    /// Loudest recent levels of what's sent and played (up to the last 10 s)
+    Levels {
+        #[arg(long, value_name = "SECS")]
+        seconds: Option<f64>,
+    },
+    /// Sink underruns and queue/capture overruns, with when the recent ones happened
+    Xruns,
+    /// Write Graphviz snapshots of the running pipelines (into --dump-dot's directory
+    /// unless --dir says otherwise)
+    DumpDot {
+        #[arg(long, value_name = "DIR")]
+        dir: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum TrustAction {
+    /// List trusted peers with their key fingerprints
+    List,
+    /// Forget a peer (e.g. after it was reinstalled and its key changed)
+    Remove {
+        name: String,
+    },
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogFormatArg {
+    Text,
+    Json,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StatsFormatArg {
+    Json,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProfileArg {
+    /// 2.5 ms frames, 15 ms jitterbuffer, tight sink: for a wired LAN
+    LowLatency,
+    /// 5 ms frames, 30 ms jitterbuffer: the defaults, a little more forgiving
+    Balanced,
+    /// 20 ms frames, 80 ms jitterbuffer, in-band FEC: for Wi-Fi and lossy links
+    Robust,
+    /// 20 ms frames, 60 ms jitterbuffer, deep sink buffer: listening, not syncing to video
+    Music,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ClockArg {
+    System,
+    /// A GStreamer net clock served by the sender
+    Net,
+    /// IEEE 1588, from a grandmaster on the LAN
+    Ptp,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FecModeArg {
+    Inband,
+    Ulp,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TransportArg {
+    /// Plain RTP/UDP (RTP on port, RTCP on port+1)
+    Udp,
+    /// RTP/RTCP as encrypted QUIC datagrams on a single port
+    Quic,
+    /// RTP over SRT (RTCP stays on UDP port+1)
+    Srt,
+    /// RTP over TCP, for networks that block UDP (higher latency)
+    Tcp,
+    /// webrtcbin with ICE/STUN for NAT traversal; signaling over TCP on the same port
+    Webrtc,
+    /// VBAN PCM frames for VoiceMeeter (no Opus; use port 6980 to match VoiceMeeter's default)
+    Vban,
 }
@@ -0,0 +1,174 @@
+// What a node can receive, published in its mDNS TXT record, and how a
+// sender settles on a configuration every destination can play:
+//
+//   proto=1  codecs=opus  clock=48000  transports=udp  fec=none|inband|ulp  rtx=0|1  srtp=0|1
+//
+// `transports` lists what the receiver is listening with right now; FEC, RTX
+// and SRTP have to match on both ends, so the sender follows the receiver
+// (and refuses rather than silently dropping SRTP). Peers without `proto`
+// predate this and are assumed to run the defaults.
+use anyhow::{Result, bail};
+use std::collections::BTreeMap;
+use tracing::{info, warn};
+
+use ab_core::pipeline::{FecMode, ReceiverConfig, SenderConfig, Transport};
+
+use crate::args::Args;
+use crate::handshake;
+
+/// Bumped when a change would confuse older peers.
+pub const PROTOCOL_VERSION: u32 = 1;
+/// The only rate the pipelines run at.
+const CLOCK_RATE: u32 = 48_000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Caps {
+    pub proto: u32,
+    pub codecs: Vec<String>,
+    pub clock: u32,
+    pub transports: Vec<String>,
+    /// "none", "inband" or "ulp".
+    pub fec: String,
+    pub rtx: bool,
+    pub srtp: bool,
+}
+
+impl Caps {
+    /// What the receiver built from `cfg` plays.
+    pub fn of_receiver(cfg: &ReceiverConfig) -> Self {
+        Self {
+            proto: PROTOCOL_VERSION,
+            codecs: vec![codec_of(cfg.transport).to_string()],
+            clock: CLOCK_RATE,
+            transports: vec![cfg.transport.name().to_string()],
+            fec: fec_name(cfg.fec).to_string(),
+            rtx: cfg.rtx_max_ms.is_some(),
+            srtp: cfg.srtp_keys.is_some(),
+        }
+    }
+
+    pub fn to_txt(&self) -> Vec<(&'static str, String)> {
+        let flag = |b: bool| if b { "1" } else { "0" }.to_string();
+        vec![
+            ("proto", self.proto.to_string()),
+            ("codecs", self.codecs.join(",")),
+            ("clock", self.clock.to_string()),
+            ("transports", self.transports.join(",")),
+            ("fec", self.fec.clone()),
+            ("rtx", flag(self.rtx)),
+            ("srtp", flag(self.srtp)),
+        ]
+    }
+
+    /// None for peers that don't publish capabilities.
+    pub fn from_txt(txt: &BTreeMap<String, String>) -> Option<Self> {
+        let list = |key: &str| -> Vec<String> {
+            txt.get(key)
+                .map(|v| v.split(',').map(str::trim).filter(|s| !s.is_empty()).map(String::from).collect())
+                .unwrap_or_default()
+        };
+        Some(Self {
+            proto: txt.get("proto")?.parse().ok()?,
+            codecs: list("codecs"),
+            clock: txt.get("clock").and_then(|c| c.parse().ok()).unwrap_or(CLOCK_RATE),
+            transports: list("transports"),
+            fec: txt.get("fec").cloned().unwrap_or_else(|| "none".into()),
+            rtx: txt.get("rtx").is_some_and(|v| v == "1"),
+            srtp: txt.get("srtp").is_some_and(|v| v == "1"),
+        })
+    }
+}
+
+fn codec_of(transport: Transport) -> &'static str {
+    if transport == Transport::Vban { "pcm16" } else { "opus" }
+}
+
+fn fec_name(fec: Option<FecMode>) -> &'static str {
+    match fec {
+        None => "none",
+        Some(FecMode::InBand) => "inband",
+        Some(FecMode::Ulp { .. }) => "ulp",
+    }
+}
+
+fn transport_named(name: &str, a: &Args) -> Option<Transport> {
+    Some(match name {
+        "udp" => Transport::Udp,
+        "quic" => Transport::Quic,
+        "srt" => Transport::Srt { latency_ms: a.srt_latency_ms },
+        "tcp" => Transport::Tcp,
+        "webrtc" => Transport::WebRtc,
+        "vban" => Transport::Vban,
+        _ => return None,
+    })
+}
+
+/// Adjust `cfg` so `peer` (advertising `caps`) can play it.
+pub fn negotiate(cfg: &mut SenderConfig, peer: &str, caps: &Caps, a: &Args) -> Result<()> {
+    if caps.proto > PROTOCOL_VERSION {
+        warn!(
+            target: "caps",
+            "{peer} speaks protocol {} (we speak {PROTOCOL_VERSION}); update this machine",
+            caps.proto
+        );
+    }
+    if caps.clock != CLOCK_RATE {
+        bail!("{peer} runs at {} Hz; only {CLOCK_RATE} Hz is supported", caps.clock);
+    }
+
+    if !caps.transports.is_empty() && !caps.transports.iter().any(|t| t == cfg.transport.name()) {
+        let Some(t) = caps
+            .transports
+            .iter()
+            .filter_map(|t| transport_named(t, a))
+            .find(Transport::available)
+        else {
+            bail!("{peer} receives over {}, which this machine can't send", caps.transports.join("/"));
+        };
+        info!(target: "caps", "{peer} receives over {}; sending that instead of {}", t.name(), cfg.transport.name());
+        cfg.transport = t;
+    }
+    let codec = codec_of(cfg.transport);
+    if !caps.codecs.is_empty() && !caps.codecs.iter().any(|c| c == codec) {
+        bail!("{peer} decodes {}, not {codec}", caps.codecs.join("/"));
+    }
+
+    let fec = match caps.fec.as_str() {
+        "ulp" => match cfg.fec {
+            Some(FecMode::Ulp { .. }) => cfg.fec,
+            _ => Some(FecMode::Ulp { percentage: a.fec_percentage }),
+        },
+        "inband" => Some(FecMode::InBand),
+        // In-band FEC rides inside the Opus frames; any decoder copes with it
+        _ => cfg.fec.filter(|f| *f == FecMode::InBand),
+    };
+    if fec != cfg.fec {
+        info!(target: "caps", "{peer} expects FEC {}; switching from {}", caps.fec, fec_name(cfg.fec));
+        if cfg.fec.is_none() {
+            cfg.expected_loss_pct = a.expected_loss_pct;
+        }
+        cfg.fec = fec;
+    }
+    if caps.rtx != cfg.rtx_max_ms.is_some() {
+        info!(target: "caps", "{peer} has RTX {}; following it", if caps.rtx { "on" } else { "off" });
+        cfg.rtx_max_ms = caps.rtx.then_some(a.rtx_max_ms);
+    }
+    match (caps.srtp, cfg.srtp.is_some()) {
+        (true, false) => {
+            info!(target: "caps", "{peer} only plays SRTP; encrypting");
+            cfg.srtp = Some(handshake::new_srtp_session()?);
+        }
+        (false, true) => bail!("{peer} doesn't take SRTP; start it with --srtp (or drop --srtp here)"),
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Whether `cfg`, as negotiated with someone else, also suits `caps`.
+pub fn accepts(caps: &Caps, cfg: &SenderConfig) -> bool {
+    (caps.transports.is_empty() || caps.transports.iter().any(|t| t == cfg.transport.name()))
+        && (caps.codecs.is_empty() || caps.codecs.iter().any(|c| c == codec_of(cfg.transport)))
+        && (caps.fec == fec_name(cfg.fec) || (caps.fec == "none" && cfg.fec == Some(FecMode::InBand)))
+        && caps.rtx == cfg.rtx_max_ms.is_some()
+        && caps.srtp == cfg.srtp.is_some()
+}
@@ -0,0 +1,116 @@
+// Just enough Core Foundation for the CoreAudio calls that take or give CF
+// objects: strings, numbers, arrays and dictionaries (a device or tap
+// description), each held by a `Cf` that releases it. Objective-C objects are
+// CF objects as far as CFRelease is concerned, so a `Cf` holds those too.
+use anyhow::{Result, ensure};
+use std::ffi::{CStr, CString, c_char, c_void};
+
+use crate::macos::{self, AudioObjectId};
+
+/// kCFStringEncodingUTF8, kCFNumberSInt32Type
+const UTF8: u32 = 0x0800_0100;
+const SINT32: isize = 3;
+
+/// CFArrayCallBacks / CFDictionaryKeyCallBacks / …ValueCallBacks, only ever
+/// passed by address.
+#[repr(C)]
+struct CallBacks {
+    _opaque: [u8; 0],
+}
+
+#[link(name = "CoreFoundation", kind = "framework")]
+#[allow(non_upper_case_globals)]
+unsafe extern "C" {
+    safe static kCFTypeArrayCallBacks: CallBacks;
+    safe static kCFTypeDictionaryKeyCallBacks: CallBacks;
+    safe static kCFTypeDictionaryValueCallBacks: CallBacks;
+    fn CFStringCreateWithCString(alloc: *const c_void, s: *const c_char, encoding: u32) -> *const c_void;
+    fn CFStringGetCString(s: *const c_void, buffer: *mut c_char, size: isize, encoding: u32) -> u8;
+    fn CFNumberCreate(alloc: *const c_void, kind: isize, value: *const c_void) -> *const c_void;
+    fn CFArrayCreate(
+        alloc: *const c_void,
+        values: *const *const c_void,
+        count: isize,
+        callbacks: *const CallBacks,
+    ) -> *const c_void;
+    fn CFDictionaryCreate(
+        alloc: *const c_void,
+        keys: *const *const c_void,
+        values: *const *const c_void,
+        count: isize,
+        key_callbacks: *const CallBacks,
+        value_callbacks: *const CallBacks,
+    ) -> *const c_void;
+    fn CFRelease(cf: *const c_void);
+}
+
+/// A Core Foundation object we own, released on drop.
+pub struct Cf(pub *const c_void);
+
+impl Drop for Cf {
+    fn drop(&mut self) {
+        if !self.0.is_null() {
+            // SAFETY: we hold the one reference this wrapper was made with
+            unsafe { CFRelease(self.0) };
+        }
+    }
+}
+
+impl Cf {
+    pub fn string(s: &str) -> Result<Cf> {
+        let s = CString::new(s)?;
+        // SAFETY: `s` is a NUL-terminated UTF-8 string
+        Ok(Cf(unsafe { CFStringCreateWithCString(std::ptr::null(), s.as_ptr(), UTF8) }))
+    }
+
+    pub fn number(n: i32) -> Cf {
+        // SAFETY: the value is an SInt32, as the type says
+        Cf(unsafe { CFNumberCreate(std::ptr::null(), SINT32, (&raw const n).cast()) })
+    }
+
+    pub fn array(items: &[Cf]) -> Cf {
+        let values: Vec<_> = items.iter().map(|c| c.0).collect();
+        // SAFETY: `values` are live CF objects; the array retains them
+        Cf(unsafe { CFArrayCreate(std::ptr::null(), values.as_ptr(), values.len() as isize, &kCFTypeArrayCallBacks) })
+    }
+
+    pub fn dictionary(entries: &[(&str, Cf)]) -> Result<Cf> {
+        let names = entries.iter().map(|(k, _)| Cf::string(k)).collect::<Result<Vec<_>>>()?;
+        let keys: Vec<_> = names.iter().map(|k| k.0).collect();
+        let values: Vec<_> = entries.iter().map(|(_, v)| v.0).collect();
+        // SAFETY: keys and values are live CF objects, as many of each; the
+        // dictionary retains them
+        Ok(Cf(unsafe {
+            CFDictionaryCreate(
+                std::ptr::null(),
+                keys.as_ptr(),
+                values.as_ptr(),
+                values.len() as isize,
+                &kCFTypeDictionaryKeyCallBacks,
+                &kCFTypeDictionaryValueCallBacks,
+            )
+        }))
+    }
+
+    /// A CFString's text.
+    pub fn text(&self) -> Result<String> {
+        let mut buffer = [0 as c_char; 256];
+        // SAFETY: `buffer` is as long as we say; the string is NUL-terminated on success
+        let ok = unsafe { CFStringGetCString(self.0, buffer.as_mut_ptr(), buffer.len() as isize, UTF8) };
+        ensure!(ok != 0, "string too long");
+        // SAFETY: CFStringGetCString wrote a NUL-terminated string
+        Ok(unsafe { CStr::from_ptr(buffer.as_ptr()) }.to_string_lossy().into_owned())
+    }
+}
+
+/// A CoreAudio property that's a CFString (a device's or tap's UID, a
+/// process's bundle ID).
+pub fn string_property(object: AudioObjectId, selector: u32) -> Result<String> {
+    let mut s: *const c_void = std::ptr::null();
+    let mut size = std::mem::size_of::<*const c_void>() as u32;
+    let (address, data) = (macos::address(selector), (&raw mut s).cast());
+    // SAFETY: the property is one CFStringRef, which `s` holds, and `size` says so
+    let status = unsafe { macos::AudioObjectGetPropertyData(object, &address, 0, std::ptr::null(), &mut size, data) };
+    ensure!(status == 0 && !s.is_null(), "object {object} has no such property ({status})");
+    Cf(s).text()
+}
@@ -0,0 +1,532 @@
+// Config file: `config.toml` in the config directory (or `--config <path>`),
+// holding any of the daemon's long flags, spelled as in `--help` (dashes or
+// underscores both work):
+//
+//   listen-port = 6006
+//   send-to = ["studio"]
+//   capture-device = "bridge_out.monitor"
+//   fec = true
+//
+//   [peers]                  # names to use wherever a destination is expected
+//   studio = "192.168.1.20"
+//
+// A flag given on the command line wins over the file. The options are then
+// turned into the sender and receiver configs here.
+use anyhow::{Context, Result, bail};
+use clap::parser::ValueSource;
+use clap::{ArgAction, CommandFactory, FromArgMatches};
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+use tracing::{info, warn};
+
+use ab_core::aes67::{self, Aes67Config};
+use ab_core::devices;
+use ab_core::pipeline::{BitrateRange, Channel, FecMode, JitterRange, Output, ReceiverConfig, SenderConfig, Transport};
+use ab_core::netclock;
+use ab_core::record::RecordConfig;
+use ab_core::relay::RelayConfig;
+use ab_core::sdp;
+
+use crate::args::{Args, ClockArg, FecModeArg, ProfileArg, TransportArg};
+use crate::identity::config_dir;
+use crate::logging;
+use crate::routes::{Route, RouteSpec};
+
+pub fn default_path() -> Result<PathBuf> {
+    Ok(config_dir()?.join("config.toml"))
+}
+
+/// The command line on top of the config file. A missing default file is
+/// fine; a missing `--config` file is not.
+pub fn load_args() -> Result<Args> {
+    let argv: Vec<OsString> = std::env::args_os().collect();
+    let cmd = Args::command();
+    let cli = cmd.clone().get_matches_from(&argv);
+    let explicit = cli.get_one::<PathBuf>("config").cloned();
+    let path = match &explicit {
+        Some(p) => p.clone(),
+        None => default_path()?,
+    };
+    let text = match std::fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound && explicit.is_none() => {
+            let mut args = Args::from_arg_matches(&cli)?;
+            logging::init(&args)?;
+            warn_env(&cmd, &cli);
+            apply_profile(&mut args, &cli);
+            apply_latency(&mut args, &cli);
+            return Ok(args);
+        }
+        Err(e) => return Err(e).with_context(|| format!("read {}", path.display())),
+    };
+    let mut table: toml::Table = toml::from_str(&text).with_context(|| format!("parse {}", path.display()))?;
+    let file = path.display();
+
+    let peer_names = match table.remove("peers") {
+        None => BTreeMap::new(),
+        Some(toml::Value::Table(peers)) => peers
+            .into_iter()
+            .map(|(name, addr)| match addr {
+                toml::Value::String(addr) => Ok((name, addr)),
+                _ => bail!("{file}: [peers] {name} must be an address string"),
+            })
+            .collect::<Result<_>>()?,
+        Some(_) => bail!("{file}: [peers] must be a table of name = \"address\""),
+    };
+    let groups = match table.remove("groups") {
+        None => BTreeMap::new(),
+        Some(toml::Value::Table(groups)) => groups
+            .into_iter()
+            .map(|(name, members)| {
+                let members = match members {
+                    toml::Value::Array(ms) => ms.into_iter().map(|m| match m {
+                        toml::Value::String(m) => Ok(m),
+                        _ => bail!("{file}: [groups] {name} must list receivers by name or address"),
+                    }),
+                    _ => bail!("{file}: [groups] {name} must be a list of receivers"),
+                };
+                Ok((name, members.collect::<Result<Vec<_>>>()?))
+            })
+            .collect::<Result<_>>()?,
+        Some(_) => bail!("{file}: [groups] must be a table of name = [\"receiver\", …]"),
+    };
+
+    // Replay the file as flags ahead of the real ones
+    let mut merged = vec![argv[0].clone()];
+    for (key, value) in &table {
+        let long = key.replace('_', "-");
+        let arg = cmd
+            .get_arguments()
+            .find(|a| a.get_long() == Some(long.as_str()))
+            .filter(|a| long != "config" && !matches!(a.get_action(), ArgAction::Help | ArgAction::Version))
+            .with_context(|| format!("{file}: unknown option '{key}'"))?;
+        if cli.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine) {
+            continue;
+        }
+        if arg.get_action().takes_values() {
+            let values = match value {
+                toml::Value::Array(vs) => vs.iter().collect(),
+                v => vec![v],
+            };
+            for v in values {
+                let v = match v {
+                    toml::Value::String(s) => s.clone(),
+                    toml::Value::Integer(i) => i.to_string(),
+                    toml::Value::Float(f) => f.to_string(),
+                    toml::Value::Boolean(b) => b.to_string(),
+                    _ => bail!("{file}: '{key}' takes a string, number or boolean"),
+                };
+                merged.push(format!("--{long}={v}").into());
+            }
+        } else {
+            match value {
+                toml::Value::Boolean(true) => merged.push(format!("--{long}").into()),
+                toml::Value::Boolean(false) => {}
+                _ => bail!("{file}: '{key}' is a switch; use true or false"),
+            }
+        }
+    }
+    merged.extend(argv.into_iter().skip(1));
+
+    let matches = cmd.try_get_matches_from(merged).with_context(|| format!("options from {file}"))?;
+    let mut args = Args::from_arg_matches(&matches)?;
+    logging::init(&args)?;
+    warn_env(&cmd, &matches);
+    apply_profile(&mut args, &matches);
+    apply_latency(&mut args, &matches);
+    args.peer_names = peer_names;
+    args.groups = groups;
+    info!(target: "config", "loaded {file}");
+    Ok(args)
+}
+
+/// Fill in what `--profile` sets, for options not given explicitly.
+fn apply_profile(a: &mut Args, matches: &clap::ArgMatches) {
+    let Some(profile) = a.profile else { return };
+    // (frame ms, jitter ms, sink buffer µs, sink latency µs, FEC)
+    let (frame_ms, jitter_ms, sink_buffer_us, sink_latency_us, fec) = match profile {
+        ProfileArg::LowLatency => (2.5, 15, 40_000, 10_000, false),
+        ProfileArg::Balanced => (5.0, 30, 70_000, 15_000, false),
+        ProfileArg::Robust => (20.0, 80, 150_000, 30_000, true),
+        ProfileArg::Music => (20.0, 60, 200_000, 40_000, false),
+    };
+    let unset = |id: &str| matches!(matches.value_source(id), None | Some(ValueSource::DefaultValue));
+    if unset("frame_ms") {
+        a.frame_ms = Some(frame_ms);
+    }
+    if unset("jitter_ms") {
+        a.jitter_ms = jitter_ms;
+    }
+    if unset("sink_buffer_us") {
+        a.sink_buffer_us = sink_buffer_us;
+    }
+    if unset("sink_latency_us") {
+        a.sink_latency_us = sink_latency_us;
+    }
+    if unset("fec") && unset("fec_mode") {
+        a.fec = fec;
+    }
+    info!(target: "config", "profile {profile:?}");
+}
+
+/// Split `--latency-ms` over the receiver's buffers, for options not given
+/// explicitly (it wins over `--profile`). The sink plays in periods of about
+/// a sixth of the target (5-40 ms) and buffers three; the queue in front of
+/// it holds one period; the jitterbuffer, less any RTX budget, gets the rest.
+fn apply_latency(a: &mut Args, matches: &clap::ArgMatches) {
+    let Some(total) = a.latency_ms else { return };
+    let period_ms = (total / 6).clamp(5, 40);
+    let sink_ms = 3 * period_ms;
+    let rtx_ms = if a.rtx { a.rtx_max_ms } else { 0 };
+    let jitter_ms = total.saturating_sub(sink_ms + rtx_ms).max(5);
+    let unset = |id: &str| matches!(matches.value_source(id), None | Some(ValueSource::DefaultValue));
+    if unset("jitter_ms") {
+        a.jitter_ms = jitter_ms;
+    }
+    if unset("sink_buffer_us") {
+        a.sink_buffer_us = sink_ms * 1000;
+    }
+    if unset("sink_latency_us") {
+        a.sink_latency_us = period_ms * 1000;
+    }
+    info!(
+        target: "config",
+        "latency {total} ms: jitterbuffer {} ms{}, sink {} / {} µs",
+        a.jitter_ms,
+        if rtx_ms > 0 { format!(" + {rtx_ms} ms RTX") } else { String::new() },
+        a.sink_buffer_us,
+        a.sink_latency_us
+    );
+}
+
+/// The queue in front of the sink: one sink period with `--latency-ms`.
+fn queue_ms(a: &Args) -> u32 {
+    match a.latency_ms {
+        Some(_) => (a.sink_latency_us / 1000).max(5),
+        None => 20,
+    }
+}
+
+/// Tunables used to be environment variables only; they still work, for now.
+fn warn_env(cmd: &clap::Command, matches: &clap::ArgMatches) {
+    for arg in cmd.get_arguments() {
+        let (Some(var), Some(long)) = (arg.get_env(), arg.get_long()) else { continue };
+        if matches.value_source(arg.get_id().as_str()) == Some(ValueSource::EnvVariable) {
+            warn!(
+                target: "config",
+                "{} is deprecated; use --{long} or `{long} = …` in config.toml",
+                var.to_string_lossy()
+            );
+        }
+    }
+}
+
+/// Checks across options that clap can't express.
+pub fn validate(a: &Args) -> Result<()> {
+    anyhow::ensure!(
+        a.min_bitrate <= a.max_bitrate,
+        "--min-bitrate ({}) must not exceed --max-bitrate ({})",
+        a.min_bitrate,
+        a.max_bitrate
+    );
+    anyhow::ensure!(
+        a.sink_latency_us <= a.sink_buffer_us,
+        "--sink-latency-us ({}) must not exceed --sink-buffer-us ({})",
+        a.sink_latency_us,
+        a.sink_buffer_us
+    );
+    anyhow::ensure!(
+        a.min_jitter_ms <= a.max_jitter_ms,
+        "--min-jitter-ms ({}) must not exceed --max-jitter-ms ({})",
+        a.min_jitter_ms,
+        a.max_jitter_ms
+    );
+    anyhow::ensure!(a.duplex.is_none() || !a.aes67, "--duplex is Opus/RTP both ways; drop --aes67");
+    anyhow::ensure!(
+        a.ptt_key.is_none() || cfg!(target_os = "linux"),
+        "--ptt-key reads Linux input devices; use `ctl ptt on|off` here"
+    );
+    anyhow::ensure!(
+        a.virtual_sink.is_none() || cfg!(target_os = "linux"),
+        "--virtual-sink makes a PulseAudio/PipeWire sink; on macOS, capture BlackHole"
+    );
+    anyhow::ensure!(
+        a.capture_app.is_none() || cfg!(any(target_os = "linux", target_os = "macos")),
+        "--capture-app captures through PipeWire (Linux) or a Core Audio tap (macOS)"
+    );
+    anyhow::ensure!(
+        a.relay_to.is_empty() || !matches!(transport(a), Transport::WebRtc | Transport::Vban),
+        "--relay-to forwards RTP; it can't relay --transport {}",
+        transport(a).name()
+    );
+    anyhow::ensure!(
+        a.clock_from.is_none() || a.clock != ClockArg::Ptp,
+        "--clock-from follows a net clock; drop --clock ptp"
+    );
+    anyhow::ensure!(
+        a.speaker_pair.is_empty() || a.speaker_pair.len() == 2,
+        "--speaker-pair takes two receivers, LEFT,RIGHT"
+    );
+    anyhow::ensure!(!a.auto_send || a.mdns, "--auto-send needs mDNS discovery (drop --mdns false)");
+    anyhow::ensure!(
+        !(a.psk.is_some() || a.psk_file.is_some() || a.srtp || a.tofu) || transport(a) == Transport::Udp,
+        "--psk, --srtp and --tofu play only authenticated senders, which only --transport udp can tell apart \
+         (not --transport {}; QUIC's certificates, for one, aren't checked)",
+        transport(a).name()
+    );
+    anyhow::ensure!(!a.srtp || !a.aes67, "--srtp protects the Opus/RTP stream; drop --aes67");
+    Ok(())
+}
+
+fn fec(a: &Args) -> Option<FecMode> {
+    match (a.fec, a.fec_mode) {
+        (_, Some(FecModeArg::Ulp)) => Some(FecMode::Ulp { percentage: a.fec_percentage }),
+        (_, Some(FecModeArg::Inband)) | (true, None) => Some(FecMode::InBand),
+        (false, None) => None,
+    }
+}
+
+/// The clock in effect: `--clock-from` and `--speaker-pair` mean a net clock
+/// unless PTP was asked for.
+pub fn clock(a: &Args) -> ClockArg {
+    match a.clock {
+        ClockArg::System if a.clock_from.is_some() || !a.speaker_pair.is_empty() => ClockArg::Net,
+        clock => clock,
+    }
+}
+
+/// Whether we serve a net clock: `--clock net` without someone else's to follow.
+pub fn serves_clock(a: &Args) -> bool {
+    clock(a) == ClockArg::Net && a.clock_from.is_none()
+}
+
+/// Clock the sender runs on and stamps its RTCP sender reports from, for
+/// receivers following the same one. Needs GStreamer initialised.
+pub fn sender_clock(a: &Args) -> Result<Option<netclock::Clock>> {
+    match clock(a) {
+        ClockArg::System => Ok(None),
+        // Followers sync to what we serve, or to what we follow ourselves
+        ClockArg::Net => match &a.clock_from {
+            Some(from) => follow_clock(a, from).map(Some),
+            None => Ok(Some(netclock::system())),
+        },
+        ClockArg::Ptp => netclock::ptp(a.ptp_domain).map(Some),
+    }
+}
+
+/// `--clock-from HOST[:PORT]`.
+fn follow_clock(a: &Args, from: &str) -> Result<netclock::Clock> {
+    let default = a.listen_port + netclock::PORT_OFFSET;
+    let (host, port) = match from.rsplit_once(':') {
+        Some((host, port)) if !host.contains(':') || host.ends_with(']') => {
+            (host.trim_matches(['[', ']']), port.parse().with_context(|| format!("--clock-from: bad port '{port}'"))?)
+        }
+        _ => (from.trim_matches(['[', ']']), default),
+    };
+    netclock::follow(&a.named_peer(host), port)
+}
+
+/// The routes `--speaker-pair LEFT,RIGHT` stands for: one channel to each.
+pub fn speaker_pair_routes(a: &Args) -> Vec<RouteSpec> {
+    let [left, right] = a.speaker_pair.as_slice() else { return Vec::new() };
+    [("left", Channel::Left, left), ("right", Channel::Right, right)]
+        .into_iter()
+        .map(|(name, channel, host)| RouteSpec {
+            name: name.to_string(),
+            route: Route::Send { capture: a.capture_device.clone(), channel: Some(channel), to: vec![host.clone()], port: None },
+        })
+        .collect()
+}
+
+fn echo_cancel(a: &Args) -> bool {
+    a.echo_cancel.unwrap_or(a.duplex.is_some())
+}
+
+pub fn transport(a: &Args) -> Transport {
+    match a.transport {
+        TransportArg::Udp => Transport::Udp,
+        TransportArg::Quic => Transport::Quic,
+        TransportArg::Srt => Transport::Srt { latency_ms: a.srt_latency_ms },
+        TransportArg::Tcp => Transport::Tcp,
+        TransportArg::Webrtc => Transport::WebRtc,
+        TransportArg::Vban => Transport::Vban,
+    }
+}
+
+/// --interface pins everything to that NIC's address.
+pub fn bind_address(a: &Args) -> Result<Option<IpAddr>> {
+    match &a.interface {
+        Some(name) => Ok(Some(interface_address(name)?)),
+        None => Ok(a.bind_address),
+    }
+}
+
+/// Sender config from the flags; it only starts at launch if a destination was
+/// given (AES67 has a default group; --auto-send fills hosts in once discovery
+/// has found the peer). The control socket's start-send starts from it too.
+pub fn sender_config(a: &Args) -> Result<SenderConfig> {
+    let hosts = if a.auto_send {
+        Vec::new()
+    } else if let Some(peer) = &a.duplex {
+        vec![a.named_peer(peer)]
+    } else if a.send_to.is_empty() && a.aes67 {
+        vec![aes67::DEFAULT_GROUP.to_string()]
+    } else {
+        a.expand(&a.send_to)
+    };
+    let fec = fec(a);
+    Ok(SenderConfig {
+        capture_device: a.capture_device.clone().or_else(|| a.virtual_sink.as_ref().map(|n| format!("{n}.monitor"))),
+        source: a.source.clone(),
+        hosts,
+        // Both ends of an intercom run the same flags, so the peer listens where we do
+        port: if a.duplex.is_some() { a.listen_port } else { a.send_port },
+        bitrate: a.bitrate,
+        frame_ms: a.frame_ms,
+        adaptive_bitrate: a.adaptive_bitrate.then_some(BitrateRange {
+            min: a.min_bitrate,
+            max: a.max_bitrate,
+        }),
+        fec,
+        expected_loss_pct: if fec.is_some() { a.expected_loss_pct } else { 0 },
+        rtx_max_ms: a.rtx.then_some(a.rtx_max_ms),
+        multicast_ttl: a.multicast_ttl,
+        multicast_iface: a.multicast_iface.clone().or_else(|| a.interface.clone()),
+        transport: transport(a),
+        stun_server: a.stun_server.clone(),
+        bind_address: bind_address(a)?,
+        dscp: a.dscp,
+        aes67: a.aes67.then(|| Aes67Config {
+            ptp_domain: a.ptp_domain,
+            session_name: "audio-bridge".into(),
+        }),
+        ssrc: None,
+        srtp: None,
+        src_buffer_us: a.src_buffer_us,
+        src_latency_us: a.src_latency_us,
+        monitor_hint: a.monitor_hint.clone(),
+        capture_app: a.capture_app.clone(),
+        echo_cancel: echo_cancel(a),
+        ptt: a.ptt || a.ptt_key.is_some(),
+        suppress_silence: a.suppress_silence,
+        keepalive: a.keepalive.map(Duration::from_secs),
+        local_monitor: a.local_monitor,
+        channel: None,
+        // Needs GStreamer up; `Sending::start` picks it
+        clock: None,
+        measure_latency: a.measure_latency,
+    })
+}
+
+/// --output-device as a sink ID: given as one, or as part of a sink's name
+/// (`devices::find`). One that matches nothing is taken as an ID, of a device
+/// that may be plugged in later. macOS keeps the name, for the receiver to
+/// look up each time it's built (a replugged device gets another index).
+/// Call after `init_gst`.
+pub fn output_device(a: &Args) -> Result<Option<String>> {
+    let Some(query) = a.output_device.as_deref().filter(|_| !a.no_receive) else { return Ok(None) };
+    let sinks = devices::sinks()?;
+    match devices::find(query, &sinks).context("--output-device")? {
+        Some(id) => {
+            if id != query {
+                info!(target: "receiver", "--output-device {query:?}: {id}");
+            }
+            Ok(Some(if cfg!(target_os = "macos") { query.to_string() } else { id }))
+        }
+        None => {
+            let all: Vec<_> = sinks.iter().collect();
+            let listing = devices::listing(&all);
+            warn!(target: "receiver", "--output-device {query:?} matches no sink; taken as an ID. Sinks:\n{listing}");
+            Ok(Some(query.to_string()))
+        }
+    }
+}
+
+/// Receiver config from the flags, or from `--sdp`. The shared state (source
+/// gate, SRTP keys, sessions) is left for the caller to fill in.
+pub fn receiver_config(a: &Args) -> Result<ReceiverConfig> {
+    let described = match &a.sdp {
+        Some(path) => {
+            let text = std::fs::read_to_string(path).with_context(|| format!("read {}", path.display()))?;
+            let d = sdp::parse(&text).with_context(|| format!("parse {}", path.display()))?;
+            info!(target: "sdp", "receiver from {}: {d:?}", path.display());
+            Some(d)
+        }
+        None => None,
+    };
+    Ok(ReceiverConfig {
+        listen_port: described.as_ref().map_or(a.listen_port, |d| d.port),
+        fec: described.as_ref().map_or(fec(a), |d| d.fec),
+        rtx_max_ms: described.as_ref().map_or(a.rtx.then_some(a.rtx_max_ms), |d| d.rtx_max_ms),
+        multicast_group: described
+            .as_ref()
+            .and_then(|d| d.multicast_group.clone())
+            .or_else(|| a.multicast_group.clone()),
+        multicast_iface: a.multicast_iface.clone().or_else(|| a.interface.clone()),
+        transport: transport(a),
+        stun_server: a.stun_server.clone(),
+        output: match (&a.airplay, &a.snapcast) {
+            (Some(host), _) => Output::AirPlay { host: host.clone(), cmd: a.raop_cmd.clone() },
+            (None, Some(target)) => Output::Snapcast { target: target.clone() },
+            (None, None) => Output::Local,
+        },
+        scream_group: a.scream.then_some(a.scream_group),
+        http_port: a.http_port,
+        record: a.record.as_ref().map(|path| RecordConfig {
+            path: path.clone(),
+            max_bytes: a.record_max_mb.map(|mb| mb * 1_000_000),
+            max_duration: a.record_max_minutes.map(|min| Duration::from_secs(min * 60)),
+        }),
+        bind_address: bind_address(a)?,
+        source_gate: None,
+        srtp_keys: None,
+        sessions: None,
+        output_device: a.output_device.clone(),
+        auto_sink: a.auto_sink,
+        jitter_ms: a.jitter_ms,
+        adaptive_jitter: a.adaptive_jitter.then_some(JitterRange { min: a.min_jitter_ms, max: a.max_jitter_ms }),
+        drop_on_late: a.drop_on_late,
+        drift_correction: a.drift_correction,
+        plc: a.plc,
+        sink_buffer_us: a.sink_buffer_us,
+        sink_latency_us: a.sink_latency_us,
+        queue_ms: queue_ms(a),
+        sink_sync: a.sink_sync,
+        echo_probe: echo_cancel(a),
+        clock: match (clock(a), &a.clock_from) {
+            (ClockArg::Net, Some(host)) => Some(follow_clock(a, host)?),
+            (ClockArg::Ptp, _) => Some(netclock::ptp(a.ptp_domain)?),
+            _ => None,
+        },
+        relay: (!a.relay_to.is_empty()).then(|| RelayConfig {
+            hosts: a.relay_to.iter().map(|h| a.named_peer(h)).collect(),
+            port: a.relay_port.unwrap_or(a.listen_port),
+            multicast_ttl: a.multicast_ttl,
+            multicast_iface: a.multicast_iface.clone().or_else(|| a.interface.clone()),
+        }),
+        measure_latency: a.measure_latency,
+        idle_release: a.idle_release.map(Duration::from_secs),
+        stream_timeout: Duration::from_secs(a.stream_timeout),
+    })
+}
+
+/// Address to bind for `--interface`: its IPv4 address, else a global IPv6 one.
+fn interface_address(name: &str) -> Result<IpAddr> {
+    let addrs: Vec<_> = if_addrs::get_if_addrs()
+        .context("list network interfaces")?
+        .into_iter()
+        .filter(|i| i.name == name)
+        .map(|i| i.ip())
+        .collect();
+    let ip = addrs
+        .iter()
+        .find(|ip| ip.is_ipv4())
+        .or_else(|| addrs.iter().find(|ip| matches!(ip, IpAddr::V6(v6) if !v6.is_unicast_link_local())))
+        .copied()
+        .with_context(|| format!("interface '{name}' not found or has no usable address"))?;
+    info!(target: "net", "--interface {name} → binding to {ip}");
+    Ok(ip)
+}
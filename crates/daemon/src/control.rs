@@ -0,0 +1,871 @@
+// Control socket: a Unix domain socket (`$XDG_RUNTIME_DIR/audiobridge.sock`,
+// else in the config directory) taking newline-delimited JSON commands, one
+// reply line each:
+//
+//   {"cmd":"status"}                          → what's being sent and received
+//   {"cmd":"start-send","to":["studio-mac"],"port":5004}
+//                                             → "to"/"port" optional (default to the flags)
+//   {"cmd":"switch-send","to":["office-mac"],"port":5004}
+//                                             → move a running sender; "port" optional
+//                                               (default: keep it). "seamless" says
+//                                               whether capture kept running
+//   {"cmd":"stop-send"}
+//   {"cmd":"set-volume","volume":0.8}         → playback volume, 1.0 = unity
+//   {"cmd":"set-volume","volume":0.5,"zone":"downstairs"}
+//                                             → how loud the zone's receivers play what
+//                                               we send them ("receiver":"kitchen"
+//                                               for one); they're told over the handshake
+//   {"cmd":"set-mute","mute":true}            → mute playback, keeping the volume
+//   {"cmd":"list-zones"}                      → `[groups]` with each receiver's volume
+//   {"cmd":"levels","seconds":2}              → recent RMS/peak readings of what's sent and
+//                                               played, oldest first, "interval_ms" apart
+//                                               ("seconds" optional: all kept, up to 10 s)
+//   {"cmd":"xruns"}                           → sink underruns, queue and capture overruns:
+//                                               counts and recent events per side
+//   {"cmd":"dump-dot","dir":"/tmp/ab"}        → Graphviz snapshots of the running pipelines
+//                                               ("dir" optional: --dump-dot's); "files"
+//   {"cmd":"set-bitrate","bitrate":128000}
+//   {"cmd":"list-peers"}
+//   {"cmd":"pair-confirm","pin":"123456"}     → trust the peer asking to pair (--pairable)
+//                                               that shows this PIN; "name"
+//   {"cmd":"ptt","talk":true}                 → push-to-talk (--ptt): fade in / out
+//   {"cmd":"route-add","route":"mic=capture:alsa_input.usb->office"}
+//                                             → an extra pipeline (see routes.rs)
+//   {"cmd":"route-remove","name":"mic"}
+//   {"cmd":"list-routes"}
+//   {"cmd":"watch-peers"}                     → list-peers, then a line per change:
+//                                               {"type":"peer-up","peer":{…}}
+//                                               {"type":"peer-gone","name":"…"}
+//
+// Replies are {"type":"ok",…} or {"type":"error","reason":"…"}. Only the user
+// running the daemon can connect (the socket is 0600).
+use anyhow::{Context, Result, bail};
+use serde_json::{Value, json};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tracing::{debug, info, warn};
+
+use ab_core::e2e::E2eLatency;
+use ab_core::glitch::Glitches;
+use ab_core::pipeline::{LEVEL_INTERVAL, Level, Receiver, ReceiverConfig, RtcpReport, Sender, SenderConfig};
+use ab_core::rtpstats::RtpStats;
+use ab_core::session::Sessions;
+use ab_core::xrun::{Xrun, XrunCounts};
+
+use crate::dot;
+use crate::handshake::Pairings;
+use crate::identity::{config_dir, unhex};
+use crate::events::Bus;
+use crate::mdns::{Mdns, Peer, PeerEvent};
+use crate::routes::{self, RouteSpec, Routes};
+use crate::routes::Running;
+use crate::sending::{SendEnv, Sending};
+use crate::supervise::Watchdogs;
+use crate::trust::TrustStore;
+
+/// Where the daemon listens unless --control-socket says otherwise.
+pub fn default_path() -> Result<PathBuf> {
+    match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) if !dir.is_empty() => Ok(PathBuf::from(dir).join("audiobridge.sock")),
+        _ => Ok(config_dir()?.join("audiobridge.sock")),
+    }
+}
+
+/// Everything the control socket can see and drive. The locked parts are
+/// swapped when the config is reloaded.
+pub struct Control {
+    pub env: RwLock<SendEnv>,
+    /// Sender settings from the flags; `start-send` starts from these.
+    pub send_defaults: RwLock<SenderConfig>,
+    pub sending: tokio::sync::Mutex<Option<Sending>>,
+    /// None with --no-receive.
+    pub rx: RwLock<Option<Receiver>>,
+    pub rx_cfg: RwLock<ReceiverConfig>,
+    pub sessions: Sessions,
+    /// Our mDNS registration, if discovery is on.
+    pub mdns: Option<Mutex<Mdns>>,
+    /// Extra send/play pipelines (--route, `route-add`).
+    pub routes: Routes,
+    /// Recovery of the main sender's and receiver's pipelines.
+    pub watchdog: Watchdogs,
+    /// What happens, for whoever listens (see `events.rs`).
+    pub events: Bus,
+    /// Pairing requests waiting for `pair-confirm`.
+    pub pairings: Pairings,
+}
+
+impl Control {
+    fn peers(&self) -> crate::mdns::Peers {
+        self.env.read().unwrap().peers.clone()
+    }
+}
+
+/// Accept connections on `path` in the background, until the returned task is
+/// aborted. A socket file left behind by a daemon that is gone is replaced; a
+/// live one is an error.
+pub fn serve(path: &Path, control: Arc<Control>) -> Result<tokio::task::JoinHandle<()>> {
+    if path.exists() {
+        if std::os::unix::net::UnixStream::connect(path).is_ok() {
+            bail!("another daemon is already listening on {}", path.display());
+        }
+        std::fs::remove_file(path).with_context(|| format!("remove stale {}", path.display()))?;
+    }
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let listener = UnixListener::bind(path).with_context(|| format!("control: bind {}", path.display()))?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    info!(target: "control", "listening on {}", path.display());
+    Ok(accept(listener, control))
+}
+
+/// Accept connections on a socket systemd opened for us (see `systemd.rs`).
+pub fn serve_activated(
+    listener: std::os::unix::net::UnixListener,
+    control: Arc<Control>,
+) -> Result<tokio::task::JoinHandle<()>> {
+    let listener = UnixListener::from_std(listener).context("control: take the socket from systemd")?;
+    info!(target: "control", "listening on the socket from systemd");
+    Ok(accept(listener, control))
+}
+
+fn accept(listener: UnixListener, control: Arc<Control>) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            let Ok((stream, _)) = listener.accept().await else { continue };
+            let control = control.clone();
+            tokio::spawn(async move {
+                if let Err(e) = handle(stream, &control).await {
+                    warn!(target: "control", "{e:#}");
+                }
+            });
+        }
+    })
+}
+
+async fn handle(stream: UnixStream, control: &Control) -> Result<()> {
+    let (read, mut write) = stream.into_split();
+    let mut lines = BufReader::new(read).lines();
+    while let Some(line) = lines.next_line().await? {
+        // A watch holds the connection until the client hangs up
+        if serde_json::from_str::<Value>(&line).is_ok_and(|req| req["cmd"] == "watch-peers") {
+            info!(target: "control", "watch-peers");
+            let mut events = control.peers().subscribe();
+            let hello = json!({ "type": "ok", "peers": peers(control) });
+            write.write_all(format!("{hello}\n").as_bytes()).await?;
+            while let Some(event) = events.recv().await {
+                let line = match event {
+                    PeerEvent::Up(p) => json!({ "type": "peer-up", "peer": peer_json(&p) }),
+                    PeerEvent::Gone(name) => json!({ "type": "peer-gone", "name": name }),
+                };
+                write.write_all(format!("{line}\n").as_bytes()).await?;
+            }
+            return Ok(());
+        }
+        let reply = match serde_json::from_str::<Value>(&line) {
+            Ok(req) => answer(&req, control).await,
+            Err(e) => json!({ "type": "error", "reason": format!("bad request: {e}") }),
+        };
+        write.write_all(format!("{reply}\n").as_bytes()).await?;
+    }
+    Ok(())
+}
+
+/// The reply to one command: {"type":"ok",…} or {"type":"error",…}.
+pub async fn answer(req: &Value, control: &Control) -> Value {
+    match command(req, control).await {
+        Ok(mut v) => {
+            v["type"] = "ok".into();
+            v
+        }
+        Err(e) => json!({ "type": "error", "reason": format!("{e:#}") }),
+    }
+}
+
+async fn command(req: &Value, control: &Control) -> Result<Value> {
+    let cmd = req["cmd"].as_str().context("missing 'cmd'")?;
+    // Polled by `top` several times a second
+    if matches!(cmd, "status" | "levels" | "list-peers" | "xruns") {
+        debug!(target: "control", "{cmd}");
+    } else {
+        info!(target: "control", "{cmd}");
+    }
+    match cmd {
+        "status" => Ok(status(control).await),
+        "start-send" => {
+            let mut sending = control.sending.lock().await;
+            if control.env.read().unwrap().args.no_send {
+                bail!("this daemon runs with --no-send");
+            }
+            if sending.is_some() {
+                bail!("already sending (stop-send first)");
+            }
+            let mut cfg = control.send_defaults.read().unwrap().clone();
+            if let Some(to) = req["to"].as_array() {
+                cfg.hosts = to.iter().filter_map(|h| h.as_str().map(String::from)).collect();
+            }
+            if let Some(port) = req["port"].as_u64() {
+                cfg.port = u16::try_from(port).context("port out of range")?;
+            }
+            let env = control.env.read().unwrap().clone();
+            let started = Sending::start(cfg, &env).await?;
+            let reply = json!({
+                "to": started.targets,
+                "hosts": started.hosts(),
+                "port": started.cfg.port,
+                "transport": started.cfg.transport.name(),
+            });
+            *sending = Some(started);
+            Ok(reply)
+        }
+        "switch-send" => {
+            let to: Vec<String> = req["to"]
+                .as_array()
+                .context("missing 'to'")?
+                .iter()
+                .filter_map(|h| h.as_str().map(String::from))
+                .collect();
+            if to.is_empty() {
+                bail!("'to' is empty");
+            }
+            let mut sending = control.sending.lock().await;
+            let current = sending.as_mut().context("not sending (start-send first)")?;
+            let port = match req["port"].as_u64() {
+                Some(port) => u16::try_from(port).context("port out of range")?,
+                None => current.cfg.port,
+            };
+            let env = control.env.read().unwrap().clone();
+            let seamless = match current.retarget(to.clone(), port, &env).await {
+                Ok(()) => true,
+                Err(e) => {
+                    info!(target: "control", "can't switch in place ({e:#}); restarting the sender");
+                    if let Some(old) = sending.take() {
+                        old.stop();
+                    }
+                    let mut cfg = control.send_defaults.read().unwrap().clone();
+                    cfg.hosts = to;
+                    cfg.port = port;
+                    *sending = Some(Sending::start(cfg, &env).await?);
+                    false
+                }
+            };
+            let current = sending.as_ref().context("not sending")?;
+            Ok(json!({
+                "to": current.targets,
+                "hosts": current.hosts(),
+                "port": current.cfg.port,
+                "seamless": seamless,
+            }))
+        }
+        "stop-send" => {
+            let sending = control.sending.lock().await.take().context("not sending")?;
+            let to = sending.targets.clone();
+            sending.stop();
+            Ok(json!({ "to": to }))
+        }
+        "set-volume" => {
+            let volume = req["volume"].as_f64().context("missing 'volume'")?;
+            // Also refuses NaN, which no range contains
+            anyhow::ensure!((0.0..=10.0).contains(&volume), "'volume' must be 0-10");
+            let names = match (req["zone"].as_str(), req["receiver"].as_str()) {
+                (None, None) => {
+                    control.rx.read().unwrap().as_ref().context("not receiving (--no-receive)")?.set_volume(volume)?;
+                    return Ok(json!({ "volume": volume }));
+                }
+                (Some(zone), None) => {
+                    if !control.env.read().unwrap().args.groups.contains_key(zone) {
+                        bail!("no zone '{zone}' in [groups]");
+                    }
+                    vec![zone.to_string()]
+                }
+                (None, Some(receiver)) => vec![receiver.to_string()],
+                (Some(_), Some(_)) => bail!("give 'zone' or 'receiver', not both"),
+            };
+            let receivers = remote_volume(control, &names, volume).await;
+            if receivers.is_empty() {
+                bail!("not sending to {}", names[0]);
+            }
+            Ok(json!({ "volume": volume, "receivers": receivers }))
+        }
+        "set-mute" => {
+            let mute = req["mute"].as_bool().context("missing 'mute' (true/false)")?;
+            control.rx.read().unwrap().as_ref().context("not receiving (--no-receive)")?.set_muted(mute)?;
+            Ok(json!({ "muted": mute }))
+        }
+        "set-bitrate" => {
+            let bitrate = req["bitrate"]
+                .as_i64()
+                .and_then(|b| i32::try_from(b).ok())
+                .filter(|b| (4_000..=650_000).contains(b))
+                .context("'bitrate' must be 4000-650000")?;
+            let sending = control.sending.lock().await;
+            let sending = sending.as_ref().context("not sending")?;
+            sending.with_sender(|s| s.set_bitrate(bitrate)).context("sender is reconnecting")??;
+            Ok(json!({ "bitrate": bitrate }))
+        }
+        "list-peers" => Ok(json!({ "peers": peers(control) })),
+        "pair-confirm" => {
+            let pin = req["pin"].as_str().context("missing 'pin'")?;
+            Ok(json!({ "name": control.pairings.confirm(pin)? }))
+        }
+        "route-add" => {
+            let spec: RouteSpec = req["route"]
+                .as_str()
+                .context("missing 'route' (NAME=FROM->TO)")?
+                .parse()
+                .map_err(anyhow::Error::msg)?;
+            let name = spec.name.clone();
+            routes::add(control, spec).await?;
+            let listed = routes::list(control).await;
+            Ok(listed.into_iter().find(|r| r["name"] == name.as_str()).unwrap_or_default())
+        }
+        "route-remove" => {
+            let name = req["name"].as_str().context("missing 'name'")?;
+            let spec = routes::remove(control, name).await?;
+            Ok(json!({ "name": spec.name, "route": spec.to_string() }))
+        }
+        "list-routes" => Ok(json!({ "routes": routes::list(control).await })),
+        "list-zones" => Ok(json!({ "zones": zones(control).await })),
+        "levels" => Ok(levels(control, req["seconds"].as_f64()).await),
+        "xruns" => Ok(xruns(control).await),
+        "dump-dot" => {
+            let dir = match req["dir"].as_str() {
+                Some(dir) => PathBuf::from(dir),
+                None => {
+                    let dir = control.env.read().unwrap().args.dump_dot.clone();
+                    dir.context("missing 'dir' (and no --dump-dot)")?
+                }
+            };
+            let files = dot::dump(control, &dir, "ctl").await?;
+            if files.is_empty() {
+                bail!("neither sending nor receiving");
+            }
+            Ok(json!({ "files": files }))
+        }
+        "ptt" => {
+            let talk = req["talk"].as_bool().context("missing 'talk' (true/false)")?;
+            let sending = control.sending.lock().await;
+            let sending = sending.as_ref().context("not sending")?;
+            sending.with_sender(|s| s.set_talking(talk)).context("sender is reconnecting")??;
+            Ok(json!({ "talking": talk }))
+        }
+        other => bail!("unknown command '{other}'"),
+    }
+}
+
+/// Ask the receivers (or zones) in `names` that we send to, from the main
+/// sender and send routes, to play us at `volume`. Returns those asked.
+async fn remote_volume(control: &Control, names: &[String], volume: f64) -> Vec<String> {
+    let args = control.env.read().unwrap().args.clone();
+    let mut asked = Vec::new();
+    if let Some(s) = control.sending.lock().await.as_ref() {
+        asked.extend(s.set_volume(names, volume, &args));
+    }
+    for (_, running) in control.routes.table.lock().await.iter() {
+        if let Running::Send(s) = running {
+            asked.extend(s.set_volume(names, volume, &args));
+        }
+    }
+    asked.sort();
+    asked.dedup();
+    asked
+}
+
+/// `[groups]`, with the volume each member was asked to play the main sender at.
+async fn zones(control: &Control) -> Vec<Value> {
+    let args = control.env.read().unwrap().args.clone();
+    let volumes = control.sending.lock().await.as_ref().map(Sending::volumes).unwrap_or_default();
+    args.groups
+        .iter()
+        .map(|(name, members)| {
+            let receivers: Vec<Value> = members
+                .iter()
+                .map(|m| {
+                    let sent = volumes.iter().find(|(target, _)| *target == args.named_peer(m));
+                    json!({ "name": m, "sending": sent.is_some(), "volume": sent.and_then(|(_, v)| *v) })
+                })
+                .collect();
+            json!({ "name": name, "receivers": receivers })
+        })
+        .collect()
+}
+
+/// Recent levels of the main sender and receiver; `seconds` keeps only the
+/// last so many.
+async fn levels(control: &Control, seconds: Option<f64>) -> Value {
+    let recent = |levels: Vec<Level>| -> Value {
+        let n = seconds.map_or(levels.len(), |s| (s / LEVEL_INTERVAL.as_secs_f64()).ceil() as usize);
+        let skip = levels.len().saturating_sub(n);
+        levels.into_iter().skip(skip).map(level_json).collect()
+    };
+    let sending = control.sending.lock().await.as_ref().and_then(|s| s.with_sender(Sender::levels));
+    let receiving = control.rx.read().unwrap().as_ref().map(Receiver::levels);
+    json!({
+        "interval_ms": LEVEL_INTERVAL.as_millis() as u64,
+        "sending": sending.map(recent),
+        "receiving": receiving.map(recent),
+    })
+}
+
+/// Underrun/overrun counts and recent events of the main sender and receiver.
+async fn xruns(control: &Control) -> Value {
+    let side = |counts: XrunCounts, events: Vec<Xrun>| {
+        json!({ "counts": xrun_counts_json(counts), "events": events.iter().map(xrun_json).collect::<Vec<_>>() })
+    };
+    let sending = control.sending.lock().await;
+    let sending = sending.as_ref().and_then(|s| s.with_sender(|tx| side(tx.xrun_counts(), tx.xruns())));
+    let receiving = control.rx.read().unwrap().as_ref().map(|rx| side(rx.xrun_counts(), rx.xruns()));
+    json!({ "sending": sending, "receiving": receiving })
+}
+
+pub fn xrun_counts_json(c: XrunCounts) -> Value {
+    json!({ "underruns": c.underruns, "overruns": c.overruns, "capture_overruns": c.capture_overruns })
+}
+
+fn xrun_json(x: &Xrun) -> Value {
+    json!({
+        "time": x.at.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs_f64(),
+        "kind": x.kind.name(),
+        "element": x.element,
+        "count": x.count,
+        "detail": x.detail,
+    })
+}
+
+/// Mute playback if it's playing, else unmute it (SIGUSR2); whether it's
+/// muted now.
+pub fn toggle_mute(control: &Control) -> Result<bool> {
+    let rx = control.rx.read().unwrap();
+    let rx = rx.as_ref().context("not receiving (--no-receive)")?;
+    let muted = !rx.muted();
+    rx.set_muted(muted)?;
+    Ok(muted)
+}
+
+pub async fn status(control: &Control) -> Value {
+    let sending = control.sending.lock().await;
+    let sending = sending.as_ref().map(|s| {
+        let (bitrate, level, reports, talking, silent, queued_ms, xruns) = s
+            .with_sender(|tx| {
+                (tx.bitrate(), tx.level(), tx.reports(), tx.talking(), tx.silent(), tx.queued_ms(), tx.xrun_counts())
+            })
+            .unwrap_or_default();
+        json!({
+            "to": s.targets,
+            "hosts": s.hosts(),
+            "port": s.cfg.port,
+            "transport": s.cfg.transport.name(),
+            "bitrate": bitrate,
+            "srtp": s.cfg.srtp.is_some(),
+            "talking": talking,
+            "silent": s.cfg.suppress_silence.map(|_| silent),
+            "volumes": s.volumes().into_iter().filter_map(|(t, v)| Some((t, json!(v?)))).collect::<serde_json::Map<_, _>>(),
+            "level": level.map(level_json),
+            "queued_ms": queued_ms,
+            "xruns": xrun_counts_json(xruns),
+            "receivers": reports.iter().map(|r| report_json(r, None, None)).collect::<Vec<_>>(),
+            "recovery": control.watchdog.sender.json(),
+        })
+    });
+
+    let routes = routes::list(control).await;
+
+    let rx = control.rx.read().unwrap();
+    let receiving = rx.as_ref().map(|rx| {
+        let mut v = receiving_json(rx, &control.rx_cfg.read().unwrap(), &control.sessions);
+        v["recovery"] = control.watchdog.receiver.json();
+        v
+    });
+    json!({
+        "name": control.env.read().unwrap().creds.name,
+        "version": env!("CARGO_PKG_VERSION"),
+        "sending": sending,
+        "receiving": receiving,
+        "routes": routes,
+    })
+}
+
+fn receiving_json(rx: &Receiver, rx_cfg: &ReceiverConfig, sessions: &Sessions) -> Value {
+    // Reception stats per SSRC, labelled with the handshake session they belong to
+    let trusted = TrustStore::load().unwrap_or_default();
+    let live = sessions.live();
+    let e2e = rx.e2e_latency();
+    let states = rx.stream_states();
+    let rtp = rx.rtp_stats();
+    let glitches = rx.glitches();
+    let senders: Vec<Value> = rx
+        .reports()
+        .iter()
+        .map(|r| {
+            let session = live.iter().find(|(_, _, s)| *s == r.ssrc || s.wrapping_add(1) == r.ssrc);
+            let who = session.map(|(ip, owner, _)| {
+                let name = unhex(owner).and_then(|k| trusted.name_of(&k).map(String::from));
+                (ip.to_string(), name)
+            });
+            let mut v = report_json(r, who, e2e.iter().find(|e| e.ssrc == r.ssrc));
+            v["state"] = states.iter().find(|(ssrc, _)| *ssrc == r.ssrc).map(|(_, s)| s.name()).into();
+            v["rtp"] = rtp.iter().find(|s| s.ssrc == r.ssrc).map(rtp_json).into();
+            v["glitches"] = glitches.iter().find(|g| g.ssrc == r.ssrc).map(glitches_json).into();
+            v
+        })
+        .collect();
+    json!({
+        "port": rx_cfg.listen_port,
+        "transport": rx_cfg.transport.name(),
+        "volume": rx.volume(),
+        "muted": rx.muted(),
+        "latency_ms": rx.latency().map(|l| l.as_secs_f64() * 1000.0),
+        "jitterbuffer_ms": rx.jitter_ms(),
+        "adaptive_jitter": rx_cfg.adaptive_jitter.map(|r| json!({ "min_ms": r.min, "max_ms": r.max })),
+        "level": rx.level().map(level_json),
+        "queued_ms": rx.queued_ms(),
+        "xruns": xrun_counts_json(rx.xrun_counts()),
+        "idle": rx_cfg.idle_release.map(|_| rx.idle()),
+        "senders": senders,
+    })
+}
+
+pub fn level_json(l: Level) -> Value {
+    json!({ "rms_db": l.rms_db, "peak_db": l.peak_db })
+}
+
+/// `who` is the (address, trusted name) behind a received stream, if known;
+/// `e2e` its measured capture-to-speaker latency.
+pub fn report_json(r: &RtcpReport, who: Option<(String, Option<String>)>, e2e: Option<&E2eLatency>) -> Value {
+    let (addr, name) = who.unzip();
+    json!({
+        "ssrc": format!("{:08x}", r.ssrc),
+        "addr": addr,
+        "name": name.flatten(),
+        "packets": r.packets_received,
+        "lost": r.packets_lost,
+        "loss_pct": r.fraction_lost_pct,
+        "jitter_ms": r.jitter_ms,
+        "rtt_ms": r.rtt_ms,
+        "e2e": e2e.map(|e| json!({
+            "network_ms": e.network_ms,
+            "total_ms": e.total_ms,
+            "min_ms": e.min_ms,
+            "max_ms": e.max_ms,
+        })),
+    })
+}
+
+/// What the receiver counted from a stream's sequence numbers.
+pub fn rtp_json(s: &RtpStats) -> Value {
+    json!({
+        "received": s.received,
+        "expected": s.expected,
+        "lost": s.lost,
+        "gaps": s.gaps,
+        "reordered": s.reordered,
+        "duplicates": s.duplicates,
+        "late": s.late,
+        "loss_pct": s.loss_pct,
+        "window_loss_pct": s.window_loss_pct,
+    })
+}
+
+/// Clicks and dropouts heard in a stream's decoded audio.
+pub fn glitches_json(g: &Glitches) -> Value {
+    json!({ "clicks": g.clicks, "dropouts": g.dropouts })
+}
+
+fn peers(control: &Control) -> Vec<Value> {
+    control.peers().snapshot().iter().map(peer_json).collect()
+}
+
+pub fn peer_json(p: &Peer) -> Value {
+    json!({
+        "name": p.name,
+        "hostname": p.hostname,
+        "addr": p.addr().map(|a| a.to_string()),
+        "addrs": p.addrs.iter().map(|a| a.to_string()).collect::<Vec<_>>(),
+        "port": p.port,
+        "version": p.txt.get("version"),
+        "codecs": p.caps().map(|c| c.codecs).unwrap_or_default(),
+        "last_seen_s": p.last_seen.elapsed().as_secs(),
+        "txt": p.txt,
+    })
+}
+
+/* ---------------------------------- client ---------------------------------- */
+
+/// Send one command to the daemon listening on `path` and return its reply.
+pub fn request(path: &Path, req: Value) -> Result<Value> {
+    use std::io::{BufRead, Write};
+    let mut stream = std::os::unix::net::UnixStream::connect(path)
+        .with_context(|| format!("no daemon listening on {} (is ab-daemon running?)", path.display()))?;
+    stream.write_all(format!("{req}\n").as_bytes())?;
+    let mut line = String::new();
+    std::io::BufReader::new(stream).read_line(&mut line)?;
+    let reply: Value = serde_json::from_str(&line).context("bad reply from daemon")?;
+    if reply["type"] == "error" {
+        bail!("{}", reply["reason"].as_str().unwrap_or("refused"));
+    }
+    Ok(reply)
+}
+
+/// Send a command that keeps replying (`watch-peers`) and hand each reply
+/// line to `each` until the daemon goes away.
+pub fn stream(path: &Path, req: Value, mut each: impl FnMut(&Value)) -> Result<()> {
+    use std::io::{BufRead, Write};
+    let mut stream = std::os::unix::net::UnixStream::connect(path)
+        .with_context(|| format!("no daemon listening on {} (is ab-daemon running?)", path.display()))?;
+    stream.write_all(format!("{req}\n").as_bytes())?;
+    for line in std::io::BufReader::new(stream).lines() {
+        let reply: Value = serde_json::from_str(&line?).context("bad reply from daemon")?;
+        if reply["type"] == "error" {
+            bail!("{}", reply["reason"].as_str().unwrap_or("refused"));
+        }
+        each(&reply);
+    }
+    Ok(())
+}
+
+/// One line of `ab-daemon peers`.
+pub fn print_peer(p: &Value) {
+    let codecs = strings(&p["codecs"]).join("/");
+    let seen = p["last_seen_s"].as_u64().map(|s| format!("{s}s ago")).unwrap_or_default();
+    println!(
+        "{:<24} {:<24} {:<6} {:<10} {:<8} {seen}",
+        p["name"].as_str().unwrap_or("?"),
+        p["addr"].as_str().unwrap_or("-"),
+        p["port"],
+        p["version"].as_str().unwrap_or("-"),
+        if codecs.is_empty() { "-" } else { &codecs },
+    );
+}
+
+/// `ab-daemon status` for humans.
+pub fn print_status(s: &Value) {
+    println!("{} (ab-daemon {})", s["name"].as_str().unwrap_or("?"), s["version"].as_str().unwrap_or("?"));
+    let tx = &s["sending"];
+    if tx.is_null() {
+        println!("Sending:   off");
+    } else {
+        let to = strings(&tx["to"]).join(", ");
+        let hosts = strings(&tx["hosts"]).join(", ");
+        let bitrate = tx["bitrate"].as_i64().map(|b| format!(", {} kbit/s", b / 1000)).unwrap_or_default();
+        println!(
+            "Sending:   {to}{} :{} ({}{}){bitrate}",
+            if hosts != to { format!(" [{hosts}]") } else { String::new() },
+            tx["port"],
+            tx["transport"].as_str().unwrap_or("?"),
+            if tx["srtp"] == true { ", SRTP" } else { "" },
+        );
+        if let Some(talking) = tx["talking"].as_bool() {
+            println!("           push-to-talk: {}", if talking { "talking" } else { "muted" });
+        }
+        if tx["silent"] == true {
+            println!("           silent: not sending until there's signal");
+        }
+        print_recovery(&tx["recovery"]);
+        print_xrun_counts(&tx["xruns"]);
+        for (target, volume) in tx["volumes"].as_object().into_iter().flatten() {
+            println!("           played at {:.0}% on {target}", volume.as_f64().unwrap_or(1.0) * 100.0);
+        }
+        print_level(&tx["level"]);
+        for r in tx["receivers"].as_array().into_iter().flatten() {
+            println!("           receiver {}: {}", r["ssrc"].as_str().unwrap_or("?"), quality(r));
+        }
+    }
+    for r in s["routes"].as_array().into_iter().flatten() {
+        print_route(r);
+    }
+    let rx = &s["receiving"];
+    if rx.is_null() {
+        println!("Receiving: off");
+        return;
+    }
+    println!(
+        "Receiving: :{} ({}), volume {:.0}%{}{}",
+        rx["port"],
+        rx["transport"].as_str().unwrap_or("?"),
+        rx["volume"].as_f64().unwrap_or(1.0) * 100.0,
+        if rx["muted"] == true { " (muted)" } else { "" },
+        rx["latency_ms"].as_f64().map(|ms| format!(", latency {ms:.0} ms")).unwrap_or_default()
+    );
+    if let Some(jb) = rx["jitterbuffer_ms"].as_u64() {
+        let adaptive = &rx["adaptive_jitter"];
+        match (adaptive["min_ms"].as_u64(), adaptive["max_ms"].as_u64()) {
+            (Some(min), Some(max)) => println!("           jitterbuffer {jb} ms (adaptive, {min}-{max} ms)"),
+            _ => println!("           jitterbuffer {jb} ms"),
+        }
+    }
+    print_level(&rx["level"]);
+    if rx["idle"] == true {
+        println!("           idle: audio device released until packets arrive");
+    }
+    print_recovery(&rx["recovery"]);
+    print_xrun_counts(&rx["xruns"]);
+    let senders = rx["senders"].as_array().map(Vec::as_slice).unwrap_or_default();
+    if senders.is_empty() {
+        println!("           nobody is sending");
+    }
+    for r in senders {
+        let who = match (r["name"].as_str(), r["addr"].as_str()) {
+            (Some(name), Some(addr)) => format!("{name} ({addr})"),
+            (None, Some(addr)) => addr.to_string(),
+            _ => format!("ssrc {}", r["ssrc"].as_str().unwrap_or("?")),
+        };
+        match r["state"].as_str() {
+            Some(state) if state != "active" => println!("           from {who} ({state}): {}", quality(r)),
+            _ => println!("           from {who}: {}", quality(r)),
+        }
+    }
+}
+
+/// Underruns and overruns so far, when there have been any.
+fn print_xrun_counts(c: &Value) {
+    let kinds = [("underruns", "underrun(s)"), ("overruns", "overrun(s)"), ("capture_overruns", "capture overrun(s)")];
+    let counts: Vec<String> = kinds
+        .into_iter()
+        .filter_map(|(field, what)| c[field].as_u64().filter(|n| *n > 0).map(|n| format!("{n} {what}")))
+        .collect();
+    if !counts.is_empty() {
+        println!("           xruns: {} (`ctl xruns` for when)", counts.join(", "));
+    }
+}
+
+/// `ab-daemon ctl xruns`: counts, then each recent event with how long ago.
+pub fn print_xruns(reply: &Value) {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs_f64();
+    for (label, side) in [("Sending", "sending"), ("Receiving", "receiving")] {
+        let s = &reply[side];
+        if s.is_null() {
+            continue;
+        }
+        let c = &s["counts"];
+        println!(
+            "{label:<10} {} underrun(s), {} overrun(s), {} capture overrun(s)",
+            c["underruns"], c["overruns"], c["capture_overruns"]
+        );
+        for e in s["events"].as_array().into_iter().flatten() {
+            let ago = now - e["time"].as_f64().unwrap_or(now);
+            let times = e["count"].as_u64().filter(|n| *n > 1).map(|n| format!(" (×{n})")).unwrap_or_default();
+            println!(
+                "           {ago:>7.1} s ago  {} in {}: {}{times}",
+                e["kind"].as_str().unwrap_or("?"),
+                e["element"].as_str().unwrap_or("?"),
+                e["detail"].as_str().unwrap_or("")
+            );
+        }
+    }
+}
+
+/// What the watchdog is doing about a failed pipeline, if anything.
+fn print_recovery(r: &Value) {
+    if r.is_null() {
+        return;
+    }
+    let attempts = r["attempts"].as_u64().unwrap_or(0);
+    let error = r["error"].as_str().unwrap_or("?");
+    match r["retry_in_s"].as_u64() {
+        Some(s) => println!("           failed ({error}): {attempts} rebuild(s) so far, next in {s} s"),
+        None => println!("           recovering: rebuilt after {attempts} attempt(s), watching it"),
+    }
+}
+
+pub fn print_route(r: &Value) {
+    let detail = match (r["hosts"].as_array(), r["volume"].as_f64()) {
+        (Some(_), _) => format!(" [{}] :{}", strings(&r["hosts"]).join(", "), r["port"]),
+        (None, Some(volume)) => format!(", volume {:.0}%", volume * 100.0),
+        _ => String::new(),
+    };
+    println!("Route:     {}{detail}", r["route"].as_str().unwrap_or("?"));
+    print_level(&r["level"]);
+}
+
+pub fn print_zone(z: &Value) {
+    let receivers: Vec<String> = z["receivers"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|r| {
+            let name = r["name"].as_str().unwrap_or("?");
+            match (r["sending"].as_bool(), r["volume"].as_f64()) {
+                (Some(true), Some(v)) => format!("{name} {:.0}%", v * 100.0),
+                (Some(true), None) => format!("{name} 100%"),
+                _ => format!("{name} (not sent to)"),
+            }
+        })
+        .collect();
+    println!("Zone:      {}: {}", z["name"].as_str().unwrap_or("?"), receivers.join(", "));
+}
+
+pub fn strings(v: &Value) -> Vec<String> {
+    v.as_array().into_iter().flatten().filter_map(|s| s.as_str().map(String::from)).collect()
+}
+
+fn print_level(level: &Value) {
+    // Loudest channel; silence comes through as null (-inf dB)
+    let loudest = |field: &str| {
+        level[field]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|v| v.as_f64().unwrap_or(f64::NEG_INFINITY))
+            .fold(f64::NEG_INFINITY, f64::max)
+    };
+    if level.is_null() {
+        return;
+    }
+    let db = |v: f64| if v.is_finite() { format!("{v:.1} dBFS") } else { "silent".into() };
+    println!("           level {} (peak {})", db(loudest("rms_db")), db(loudest("peak_db")));
+}
+
+/// The loudest RMS and peak over each side's recent levels.
+pub fn print_levels(reply: &Value) {
+    let interval = reply["interval_ms"].as_f64().unwrap_or(100.0) / 1000.0;
+    for (label, side) in [("Sending", "sending"), ("Receiving", "receiving")] {
+        let Some(readings) = reply[side].as_array() else { continue };
+        let loudest = |field: &str| {
+            readings
+                .iter()
+                .flat_map(|l| l[field].as_array().into_iter().flatten())
+                .map(|v| v.as_f64().unwrap_or(f64::NEG_INFINITY))
+                .fold(f64::NEG_INFINITY, f64::max)
+        };
+        let db = |v: f64| if v.is_finite() { format!("{v:.1} dBFS") } else { "silent".into() };
+        println!(
+            "{label:<10} loudest {} (peak {}) over the last {:.1} s",
+            db(loudest("rms_db")),
+            db(loudest("peak_db")),
+            readings.len() as f64 * interval
+        );
+    }
+}
+
+pub fn quality(r: &Value) -> String {
+    let mut out = format!(
+        "loss {:.1}%, jitter {:.1} ms",
+        r["loss_pct"].as_f64().unwrap_or(0.0),
+        r["jitter_ms"].as_f64().unwrap_or(0.0)
+    );
+    if let Some(rtt) = r["rtt_ms"].as_f64() {
+        out.push_str(&format!(", rtt {rtt:.1} ms"));
+    }
+    let rtp = &r["rtp"];
+    if let Some(recent) = rtp["window_loss_pct"].as_f64() {
+        out.push_str(&format!(", {recent:.1}% lost over 10 s"));
+    }
+    for field in ["gaps", "reordered", "duplicates", "late"] {
+        if let Some(n) = rtp[field].as_u64().filter(|n| *n > 0) {
+            out.push_str(&format!(", {n} {field}"));
+        }
+    }
+    for field in ["clicks", "dropouts"] {
+        if let Some(n) = r["glitches"][field].as_u64().filter(|n| *n > 0) {
+            out.push_str(&format!(", {n} {field}"));
+        }
+    }
+    if let (Some(total), Some(network)) = (r["e2e"]["total_ms"].as_f64(), r["e2e"]["network_ms"].as_f64()) {
+        out.push_str(&format!(", capture→speaker {total:.0} ms (network {network:.0} ms)"));
+    }
+    out
+}
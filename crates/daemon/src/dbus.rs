@@ -0,0 +1,116 @@
+// D-Bus (Linux, --dbus): the daemon as `org.audiobridge.Daemon` on the session
+// bus, object /org/audiobridge/Daemon, for desktop integrations and scripts:
+//   busctl --user call org.audiobridge.Daemon /org/audiobridge/Daemon \
+//       org.audiobridge.Daemon SetVolume d 0.5
+// Methods run control socket commands:
+//   StartSend(as to) → as    peer names or hosts (empty: --send-to); where it sends
+//   StopSend()
+//   SetVolume(d volume)      1.0 = unity
+//   SetMute(b mute)
+//   GetStatus() → s          the `status` reply, as JSON
+// and fail with org.freedesktop.DBus.Error.Failed and the command's reason.
+// Signals pass events on: StreamStarted(s ssrc, s address, s peer),
+// StreamStopped(s ssrc), PeerDiscovered(s name, s address, q port) and
+// PeerGone(s name); what isn't known is an empty string.
+use anyhow::{Context, Result};
+use serde_json::{Value, json};
+use std::sync::Arc;
+use tracing::{debug, info};
+use zbus::object_server::SignalEmitter;
+use zbus::{fdo, interface};
+
+use crate::control::{self, Control};
+use crate::events::Event;
+
+const NAME: &str = "org.audiobridge.Daemon";
+const PATH: &str = "/org/audiobridge/Daemon";
+
+struct Daemon {
+    control: Arc<Control>,
+}
+
+impl Daemon {
+    /// Run a control command: its reply, or its reason as a D-Bus error.
+    async fn run(&self, req: Value) -> fdo::Result<Value> {
+        let reply = control::answer(&req, &self.control).await;
+        match reply["type"].as_str() {
+            Some("ok") => Ok(reply),
+            _ => Err(fdo::Error::Failed(reply["reason"].as_str().unwrap_or("failed").to_string())),
+        }
+    }
+}
+
+#[interface(name = "org.audiobridge.Daemon")]
+impl Daemon {
+    async fn start_send(&self, to: Vec<String>) -> fdo::Result<Vec<String>> {
+        let mut req = json!({ "cmd": "start-send" });
+        if !to.is_empty() {
+            req["to"] = json!(to);
+        }
+        Ok(control::strings(&self.run(req).await?["to"]))
+    }
+
+    async fn stop_send(&self) -> fdo::Result<()> {
+        self.run(json!({ "cmd": "stop-send" })).await.map(drop)
+    }
+
+    async fn set_volume(&self, volume: f64) -> fdo::Result<()> {
+        self.run(json!({ "cmd": "set-volume", "volume": volume })).await.map(drop)
+    }
+
+    async fn set_mute(&self, mute: bool) -> fdo::Result<()> {
+        self.run(json!({ "cmd": "set-mute", "mute": mute })).await.map(drop)
+    }
+
+    async fn get_status(&self) -> String {
+        control::status(&self.control).await.to_string()
+    }
+
+    #[zbus(signal)]
+    async fn stream_started(emitter: &SignalEmitter<'_>, ssrc: &str, address: &str, peer: &str) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn stream_stopped(emitter: &SignalEmitter<'_>, ssrc: &str) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn peer_discovered(emitter: &SignalEmitter<'_>, name: &str, address: &str, port: u16) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn peer_gone(emitter: &SignalEmitter<'_>, name: &str) -> zbus::Result<()>;
+}
+
+/// Take our name on the session bus and serve there, until the returned
+/// connection is dropped.
+pub async fn serve(control: Arc<Control>) -> Result<zbus::Connection> {
+    let mut events = control.events.subscribe();
+    let conn = zbus::connection::Builder::session()?
+        .name(NAME)?
+        .serve_at(PATH, Daemon { control })?
+        .build()
+        .await
+        .with_context(|| format!("D-Bus: can't take {NAME} on the session bus"))?;
+    let daemon = conn.object_server().interface::<_, Daemon>(PATH).await?;
+    info!(target: "dbus", "serving {NAME} on the session bus");
+    tokio::spawn(async move {
+        while let Some(event) = events.recv().await {
+            let emitter = daemon.signal_emitter();
+            let sent = match event {
+                Event::StreamStarted { ssrc, address, peer } => {
+                    let (address, peer) = (address.unwrap_or_default(), peer.unwrap_or_default());
+                    Daemon::stream_started(emitter, &format!("{ssrc:08x}"), &address, &peer).await
+                }
+                Event::StreamStopped { ssrc } => Daemon::stream_stopped(emitter, &format!("{ssrc:08x}")).await,
+                Event::PeerDiscovered(p) => {
+                    let address = p.addr().map(|a| a.to_string()).unwrap_or_default();
+                    Daemon::peer_discovered(emitter, &p.name, &address, p.port).await
+                }
+                Event::PeerGone(name) => Daemon::peer_gone(emitter, &name).await,
+                _ => continue,
+            };
+            if let Err(e) = sent {
+                debug!(target: "dbus", "signal: {e}");
+            }
+        }
+    });
+    Ok(conn)
+}
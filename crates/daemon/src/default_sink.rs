@@ -0,0 +1,57 @@
+// Linux (--follow-default-sink): playback moves when the default output does
+// (laptop speakers → headphones). pulsesink given no device opens on the
+// default sink, but its stream stays there when the default changes (and the
+// sound server may have remembered a device for us besides), so the default
+// is looked up every couple of seconds, and when it changes the receiver is
+// rebuilt on the new one, by name. macOS does this whenever there's no
+// --output-device (see `macos.rs`).
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+
+use ab_core::pipeline::Output;
+
+use crate::control::Control;
+use crate::{supervise, vsink};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Follow the default sink for as long as the daemon runs, while
+/// --follow-default-sink is given (a reload can turn it on or off).
+pub fn spawn(control: Arc<Control>) {
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(POLL_INTERVAL);
+        let mut last: Option<String> = None;
+        let mut complained = false;
+        loop {
+            tick.tick().await;
+            if !control.env.read().unwrap().args.follow_default_sink {
+                last = None;
+                continue;
+            }
+            let now = match vsink::pactl(&["get-default-sink"]) {
+                Ok(out) => out.trim().to_string(),
+                Err(e) => {
+                    if !std::mem::replace(&mut complained, true) {
+                        warn!(target: "receiver", "--follow-default-sink: {e:#}");
+                    }
+                    continue;
+                }
+            };
+            complained = false;
+            // The first look only says where we are
+            if last.replace(now.clone()).is_none_or(|previous| previous == now) {
+                continue;
+            }
+            {
+                let mut cfg = control.rx_cfg.write().unwrap();
+                if cfg.output != Output::Local {
+                    continue;
+                }
+                cfg.output_device = Some(now.clone());
+            }
+            info!(target: "receiver", "the default output is now {now}");
+            supervise::restart(&control, true, false, "default output changed").await;
+        }
+    });
+}
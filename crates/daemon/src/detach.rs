@@ -0,0 +1,180 @@
+// --detach: run in the background, for machines without systemd or launchd.
+// Before the runtime starts (fork keeps only the calling thread) the daemon
+// forks, leaves the terminal's session and forks again; the command returns
+// once the daemon is up, printing its pid, or fails if it exits first. The
+// pid goes to --pidfile (default: audiobridge.pid next to the control socket),
+// removed on exit; a pidfile naming a live process means it's already
+// running. Stdin is /dev/null and stdout/stderr (the log, stats to stdout,
+// whatever GStreamer prints) go to --log-file, by default ab-daemon.log in
+// the config directory. The working directory stays, so relative paths in
+// the flags still work.
+//
+// --log-file without --detach redirects a foreground daemon the same way.
+// Past --log-max-mb the file is rotated: ab-daemon.log becomes .1, .1
+// becomes .2 and so on, the oldest past `KEEP` is dropped, and writing goes
+// on in a fresh file.
+use anyhow::{Context, Result, bail};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::os::fd::AsRawFd;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::args::Args;
+use crate::identity::config_dir;
+
+/// Rotated log files kept.
+const KEEP: u32 = 3;
+const ROTATE_CHECK: Duration = Duration::from_secs(10);
+
+/// The pidfile we wrote, to remove on exit.
+static PIDFILE: OnceLock<PathBuf> = OnceLock::new();
+/// The log file stdout/stderr go to.
+static LOG: OnceLock<PathBuf> = OnceLock::new();
+/// Told once the daemon is up; the detaching command waits on the other end.
+static STARTED: Mutex<Option<UnixStream>> = Mutex::new(None);
+
+/// Detach (--detach) or only redirect (--log-file) as `a` says. Call before
+/// any other thread is started.
+pub fn apply(a: &Args) -> Result<()> {
+    if a.command.is_some() {
+        return Ok(());
+    }
+    let log = match (&a.log_file, a.detach) {
+        (Some(path), _) => path.clone(),
+        (None, true) => config_dir()?.join("ab-daemon.log"),
+        (None, false) => return Ok(()),
+    };
+    let file = open_log(&log)?;
+    if a.detach {
+        let pidfile = match &a.pidfile {
+            Some(path) => path.clone(),
+            None => crate::control_path(a)?.with_file_name("audiobridge.pid"),
+        };
+        if let Some(pid) = running(&pidfile) {
+            bail!("already running (pid {pid}, {})", pidfile.display());
+        }
+        detach(&pidfile, &log)?;
+        std::fs::write(&pidfile, format!("{}\n", std::process::id()))
+            .with_context(|| format!("write {}", pidfile.display()))?;
+        let _ = PIDFILE.set(pidfile);
+        redirect(Some(&File::open("/dev/null")?), &file)?;
+    } else {
+        redirect(None, &file)?;
+    }
+    let _ = LOG.set(log);
+    Ok(())
+}
+
+/// Fork twice, leaving the terminal's session, and return in the grandchild.
+/// The caller waits until `started` or until the daemon is gone, and exits.
+fn detach(pidfile: &Path, log: &Path) -> Result<()> {
+    let (mut waiting, started) = UnixStream::pair().context("detach")?;
+    // SAFETY: no other thread runs yet, so the child's copy of the process is whole
+    match unsafe { libc::fork() } {
+        -1 => return Err(std::io::Error::last_os_error()).context("fork"),
+        0 => {}
+        child => {
+            drop(started);
+            // SAFETY: reaping our own child, which exits right away
+            unsafe { libc::waitpid(child, std::ptr::null_mut(), 0) };
+            // A byte once it's up; nothing (EOF) if it exited
+            let mut up = [0u8; 1];
+            if waiting.read(&mut up).unwrap_or(0) == 0 {
+                eprintln!("ab-daemon didn't start; see {}", log.display());
+                std::process::exit(1);
+            }
+            let pid = std::fs::read_to_string(pidfile).unwrap_or_default();
+            println!("ab-daemon running in the background (pid {}), logging to {}", pid.trim(), log.display());
+            std::process::exit(0);
+        }
+    }
+    drop(waiting);
+    // SAFETY: setsid and fork have no preconditions; still single-threaded
+    unsafe {
+        if libc::setsid() == -1 {
+            return Err(std::io::Error::last_os_error()).context("setsid");
+        }
+        match libc::fork() {
+            -1 => return Err(std::io::Error::last_os_error()).context("fork"),
+            0 => {}
+            // Not a session leader, the daemon can't take a terminal again
+            _ => libc::_exit(0),
+        }
+    }
+    *STARTED.lock().unwrap() = Some(started);
+    Ok(())
+}
+
+/// Tell the waiting command the daemon is up (once; later calls do nothing).
+pub fn started() {
+    if let Some(mut tell) = STARTED.lock().unwrap().take() {
+        let _ = tell.write_all(b"1");
+    }
+}
+
+/// Remove our pidfile, on the way out.
+pub fn finish() {
+    if let Some(path) = PIDFILE.get() {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+/// The pid in `pidfile`, if that process is alive.
+fn running(pidfile: &Path) -> Option<i32> {
+    let pid: i32 = std::fs::read_to_string(pidfile).ok()?.trim().parse().ok()?;
+    // SAFETY: signal 0 only checks that the process exists
+    (pid > 0 && unsafe { libc::kill(pid, 0) } == 0).then_some(pid)
+}
+
+fn open_log(path: &Path) -> Result<File> {
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir).with_context(|| format!("create {}", dir.display()))?;
+    }
+    OpenOptions::new().create(true).append(true).open(path).with_context(|| format!("open {}", path.display()))
+}
+
+/// Point stdin (if given), stdout and stderr at these files.
+fn redirect(stdin: Option<&File>, out: &File) -> Result<()> {
+    let mut pairs = vec![(out.as_raw_fd(), libc::STDOUT_FILENO), (out.as_raw_fd(), libc::STDERR_FILENO)];
+    if let Some(stdin) = stdin {
+        pairs.push((stdin.as_raw_fd(), libc::STDIN_FILENO));
+    }
+    for (from, to) in pairs {
+        // SAFETY: both are open descriptors; dup2 swaps `to` atomically
+        if unsafe { libc::dup2(from, to) } == -1 {
+            return Err(std::io::Error::last_os_error()).context("redirect output");
+        }
+    }
+    Ok(())
+}
+
+/// Rotate the log file past `max_mb`, for as long as the daemon runs.
+pub fn spawn_rotation(max_mb: u64) {
+    let Some(path) = LOG.get() else { return };
+    let max = max_mb.max(1) * 1024 * 1024;
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(ROTATE_CHECK);
+        loop {
+            tick.tick().await;
+            if std::fs::metadata(path).is_ok_and(|m| m.len() > max) {
+                match rotate(path) {
+                    Ok(()) => info!(target: "daemon", "log rotated; the last one is {}.1", path.display()),
+                    Err(e) => warn!(target: "daemon", "can't rotate the log: {e:#}"),
+                }
+            }
+        }
+    });
+}
+
+fn rotate(path: &Path) -> Result<()> {
+    let numbered = |n: u32| PathBuf::from(format!("{}.{n}", path.display()));
+    for n in (1..KEEP).rev() {
+        let _ = std::fs::rename(numbered(n), numbered(n + 1));
+    }
+    std::fs::rename(path, numbered(1)).with_context(|| format!("rename {}", path.display()))?;
+    redirect(None, &open_log(path)?)
+}
@@ -0,0 +1,325 @@
+// `ab-daemon doctor`: what the flags given would need, checked before a first
+// run or when something doesn't work: the GStreamer version, every element
+// the sender and receiver would build, the audio devices, the ports the
+// receiver listens on, and mDNS. Each problem is printed with what to do
+// about it (for a missing element, the package to install); the exit status
+// says whether there were any.
+use anyhow::Result;
+use std::fmt::Display;
+use std::net::{IpAddr, TcpListener, UdpSocket};
+use std::path::Path;
+use std::time::Duration;
+
+use ab_core::pipeline::{FecMode, Output, ReceiverConfig, SenderConfig, Source, Transport, any_addr, init_gst};
+use ab_core::{apps, devices, netclock, plugins};
+
+use crate::args::Args;
+use crate::{config, handshake, mdns};
+
+/// Oldest GStreamer the bindings we build against support.
+const MIN_VERSION: (u32, u32) = (1, 14);
+/// How long to listen for peers.
+const BROWSE_FOR: Duration = Duration::from_secs(3);
+
+#[derive(Default)]
+struct Report {
+    problems: usize,
+}
+
+impl Report {
+    fn section(&self, title: &str) {
+        println!("\n{title}");
+    }
+
+    fn ok(&self, what: impl Display) {
+        println!("  ok    {what}");
+    }
+
+    fn warn(&self, what: impl Display, fix: impl Display) {
+        println!("  warn  {what}");
+        println!("        → {fix}");
+    }
+
+    fn fail(&mut self, what: impl Display, fix: impl Display) {
+        self.problems += 1;
+        println!("  FAIL  {what}");
+        println!("        → {fix}");
+    }
+}
+
+pub async fn run(a: &Args, control: &Path) -> Result<()> {
+    let mut r = Report::default();
+    let tx = (!a.no_send).then(|| config::sender_config(a)).transpose()?;
+    let rx = (!a.no_receive).then(|| config::receiver_config(a)).transpose()?;
+
+    r.section("GStreamer");
+    if let Err(e) = init_gst() {
+        r.fail(format!("{e:#}"), "install GStreamer (scripts/linux_setup.sh or scripts/macos_setup.sh)");
+        anyhow::bail!("GStreamer is unusable");
+    }
+    let ((major, minor, micro), description) = plugins::version();
+    if (major, minor) < MIN_VERSION {
+        r.fail(
+            format!("GStreamer {major}.{minor}.{micro} is older than {}.{}", MIN_VERSION.0, MIN_VERSION.1),
+            "upgrade GStreamer and its plugins",
+        );
+    } else {
+        r.ok(description);
+    }
+
+    r.section("Elements");
+    elements(&mut r, a, tx.as_ref(), rx.as_ref());
+
+    r.section("Audio devices");
+    audio_devices(&mut r, a, tx.as_ref(), rx.as_ref());
+
+    r.section("Ports");
+    if std::os::unix::net::UnixStream::connect(control).is_ok() {
+        r.ok("a daemon is running here and holds them; stop it to check them");
+    } else if let Some(rx) = &rx {
+        ports(&mut r, a, rx);
+    } else {
+        r.ok("none needed (--no-receive)");
+    }
+
+    r.section("mDNS");
+    if a.mdns {
+        discovery(&mut r).await;
+    } else {
+        r.ok("off (--mdns false)");
+    }
+
+    println!();
+    match r.problems {
+        0 => {
+            println!("No problems found.");
+            Ok(())
+        }
+        n => anyhow::bail!("{n} problem(s) found"),
+    }
+}
+
+/// Elements needed, each with what it's for: one element, or alternatives
+/// where the first installed one is used.
+#[derive(Default)]
+struct Needs(Vec<(&'static [&'static str], String)>);
+
+impl Needs {
+    fn all(&mut self, elements: &'static [&'static str], why: &str) {
+        for e in elements {
+            self.one_of(std::slice::from_ref(e), why);
+        }
+    }
+
+    fn one_of(&mut self, alternatives: &'static [&'static str], why: &str) {
+        if !self.0.iter().any(|(a, _)| a[0] == alternatives[0]) {
+            self.0.push((alternatives, why.to_string()));
+        }
+    }
+}
+
+/// What the sender and receiver would build.
+fn needed(a: &Args, tx: Option<&SenderConfig>, rx: Option<&ReceiverConfig>) -> Needs {
+    let mut needs = Needs::default();
+    if let Some(tx) = tx {
+        match &tx.source {
+            Source::Capture if cfg!(target_os = "macos") => needs.all(&["osxaudiosrc"], "capture"),
+            Source::Capture if tx.capture_app.is_some() => needs.all(&["pipewiresrc"], "--capture-app"),
+            Source::Capture => needs.one_of(plugins::LINUX_SOURCES, "capture"),
+            Source::Tone(_) => needs.all(&["audiotestsrc"], "--source tone"),
+            Source::File(_) => needs.all(&["filesrc", "decodebin"], "--source file"),
+        }
+        needs.all(
+            &["audioconvert", "audioresample", "capsfilter", "level", "queue", "tee", "opusenc", "rtpopuspay"],
+            "sending",
+        );
+        needs.all(tx.transport.elements(), &format!("--transport {}", tx.transport.name()));
+        if let Some(FecMode::Ulp { .. }) = tx.fec {
+            needs.all(&["rtpulpfecenc"], "--fec ulp");
+        }
+        if tx.rtx_max_ms.is_some() {
+            needs.all(&["rtprtxsend"], "--rtx");
+        }
+        if a.srtp {
+            needs.all(&["srtpenc"], "--srtp");
+        }
+        if tx.echo_cancel {
+            needs.all(&["webrtcdsp"], "--echo-cancel");
+        }
+        if tx.aes67.is_some() {
+            needs.all(&["rtpL24pay", "udpsink"], "--aes67");
+        }
+    }
+    if let Some(rx) = rx {
+        needs.all(
+            &["rtpopusdepay", "opusdec", "audiomixer", "audioconvert", "audioresample", "volume", "level", "queue"],
+            "receiving",
+        );
+        match &rx.output {
+            Output::Local if cfg!(target_os = "macos") => needs.all(&["osxaudiosink"], "playback"),
+            Output::Local if rx.auto_sink => needs.all(&["autoaudiosink"], "--auto-sink"),
+            Output::Local => needs.one_of(plugins::LINUX_SINKS, "playback"),
+            Output::AirPlay { .. } => needs.all(&["fdsink"], "--airplay"),
+            Output::Snapcast { .. } => {}
+        }
+        needs.all(rx.transport.elements(), &format!("--transport {}", rx.transport.name()));
+        if let Some(FecMode::Ulp { .. }) = rx.fec {
+            needs.all(&["rtpulpfecdec"], "--fec ulp");
+        }
+        if rx.rtx_max_ms.is_some() {
+            needs.all(&["rtprtxreceive"], "--rtx");
+        }
+        if a.srtp {
+            needs.all(&["srtpdec"], "--srtp");
+        }
+        if rx.echo_probe {
+            needs.all(&["webrtcechoprobe"], "--echo-cancel");
+        }
+        if let Some(record) = &rx.record {
+            match record.path.extension().and_then(|e| e.to_str()) {
+                Some("flac") => needs.all(&["flacenc", "filesink"], "--record"),
+                _ => needs.all(&["wavenc", "filesink"], "--record"),
+            }
+        }
+        if rx.http_port.is_some() {
+            needs.all(&["opusenc", "oggmux", "multifdsink"], "--http-port");
+        }
+    }
+    needs
+}
+
+fn elements(r: &mut Report, a: &Args, tx: Option<&SenderConfig>, rx: Option<&ReceiverConfig>) {
+    let needs = needed(a, tx, rx).0;
+    let mut present = 0;
+    for (alternatives, why) in &needs {
+        let best = alternatives[0];
+        match plugins::first_available(alternatives) {
+            Ok(e) if e == best => present += 1,
+            Ok(e) => {
+                present += 1;
+                r.warn(format!("{best} is missing (for {why}); {e} will do instead"), plugins::install_hint(best));
+            }
+            Err(_) => r.fail(
+                format!("{} is missing (for {why})", alternatives.join(" / ")),
+                plugins::install_hint(best),
+            ),
+        }
+    }
+    if present == needs.len() {
+        r.ok(format!("all {present} present"));
+    }
+}
+
+fn audio_devices(r: &mut Report, a: &Args, tx: Option<&SenderConfig>, rx: Option<&ReceiverConfig>) {
+    let server = if cfg!(target_os = "macos") {
+        "check System Settings › Sound"
+    } else {
+        "is PipeWire or PulseAudio running? (`pactl info`)"
+    };
+    let (sources, sinks) = match (devices::sources(), devices::sinks()) {
+        (Ok(sources), Ok(sinks)) => (sources, sinks),
+        (Err(e), _) | (_, Err(e)) => return r.fail(format!("can't list devices: {e:#}"), server),
+    };
+    if sources.is_empty() && sinks.is_empty() {
+        return r.fail("no audio devices found", server);
+    }
+    r.ok(format!("{} capture source(s), {} playback sink(s)", sources.len(), sinks.len()));
+
+    if let Some(tx) = tx.filter(|tx| tx.source == Source::Capture) {
+        match &tx.capture_device {
+            // macOS takes part of a name too
+            Some(query) => match devices::find(query, &sources) {
+                Ok(Some(id)) if &id == query => r.ok(format!("capture source '{id}'")),
+                Ok(Some(id)) if cfg!(target_os = "macos") => {
+                    r.ok(format!("capture source '{id}' (--capture-device '{query}')"))
+                }
+                Ok(_) => r.fail(format!("no capture source '{query}'"), "pick one from `ab-daemon devices`"),
+                Err(e) => r.fail(format!("{e:#}"), "give more of the name, or the index"),
+            },
+            None if tx.capture_app.is_none() && cfg!(target_os = "linux") && !sources.iter().any(|d| d.monitor) => {
+                r.warn(
+                    "no .monitor source to capture system audio from",
+                    "run `scripts/linux_setup.sh create_bridge_sink`, or pass --capture-device",
+                )
+            }
+            None => {}
+        }
+        // macOS taps the app when the daemon runs
+        if let Some(query) = tx.capture_app.as_ref().filter(|_| cfg!(target_os = "linux")) {
+            match apps::streams().and_then(|streams| Ok(apps::find(query, &streams)?.map(|s| s.app.clone()))) {
+                Ok(Some(app)) => r.ok(format!("'{app}' is playing (--capture-app '{query}')")),
+                Ok(None) => r.warn(format!("no app '{query}' is playing"), "silence is sent until it does"),
+                Err(e) => r.fail(format!("{e:#}"), "--capture-app needs PipeWire, and pw-dump"),
+            }
+        }
+    }
+    if let Some(query) = rx.and(a.output_device.as_ref()) {
+        match devices::find(query, &sinks) {
+            Ok(Some(id)) if &id == query => r.ok(format!("playback sink '{id}'")),
+            Ok(Some(id)) => r.ok(format!("playback sink '{id}' (--output-device '{query}')")),
+            Ok(None) => r.fail(format!("no playback sink '{query}'"), "pick one from `ab-daemon devices`"),
+            Err(e) => r.fail(format!("{e:#}"), "give more of the name, or the ID"),
+        }
+    }
+}
+
+/// Bind, and let go of, each port the daemon would listen on.
+fn ports(r: &mut Report, a: &Args, rx: &ReceiverConfig) {
+    let bind: IpAddr = rx.bind_address.unwrap_or_else(any_addr);
+    let port = rx.listen_port;
+    let mut listens: Vec<(&str, u16, &str)> = match rx.transport {
+        Transport::Udp | Transport::Srt { .. } => vec![("udp", port, "RTP"), ("udp", port + 1, "RTCP")],
+        Transport::Tcp => vec![("tcp", port, "RTP"), ("tcp", port + 1, "RTCP")],
+        Transport::Quic | Transport::Vban => vec![("udp", port, "audio")],
+        Transport::WebRtc => vec![("tcp", port, "signaling")],
+    };
+    listens.push(("tcp", port + handshake::PORT_OFFSET, "handshake"));
+    listens.push(("udp", port + handshake::PORT_OFFSET, "ping-audio"));
+    if config::serves_clock(a) {
+        listens.push(("udp", a.listen_port + netclock::PORT_OFFSET, "network clock"));
+    }
+    if let Some(http) = rx.http_port {
+        listens.push(("tcp", http, "--http-port"));
+    }
+    if let Some(metrics) = a.metrics_port {
+        listens.push(("tcp", metrics, "--metrics-port"));
+    }
+    for (proto, port, what) in listens {
+        let bound = match proto {
+            "udp" => UdpSocket::bind((bind, port)).map(drop),
+            _ => TcpListener::bind((bind, port)).map(drop),
+        };
+        match bound {
+            Ok(()) => r.ok(format!("{proto} :{port} ({what})")),
+            Err(e) if e.kind() == std::io::ErrorKind::AddrInUse => r.fail(
+                format!("{proto} :{port} ({what}) is in use"),
+                format!("find what holds it (`lsof -i :{port}`), or pick another --listen-port"),
+            ),
+            Err(e) => r.fail(format!("{proto} :{port} ({what}): {e}"), "check --bind-address and --listen-port"),
+        }
+    }
+}
+
+/// Browse for a few seconds and report who answered.
+async fn discovery(r: &mut Report) {
+    let fix = "mDNS needs multicast UDP 5353: let it through the firewall (e.g. `sudo ufw allow 5353/udp`)";
+    let mut m = match mdns::Mdns::start() {
+        Ok(m) => m,
+        Err(e) => return r.fail(format!("can't start: {e:#}"), fix),
+    };
+    let peers = match m.browse() {
+        Ok(peers) => peers,
+        Err(e) => return r.fail(format!("can't browse: {e:#}"), fix),
+    };
+    tokio::time::sleep(BROWSE_FOR).await;
+    let names: Vec<_> = peers.snapshot().into_iter().map(|p| p.name).collect();
+    m.shutdown();
+    if names.is_empty() {
+        r.warn(
+            format!("no peers answered in {} s", BROWSE_FOR.as_secs()),
+            format!("start ab-daemon on the other machine, on the same subnet; {fix}"),
+        );
+    } else {
+        r.ok(format!("{} peer(s): {}", names.len(), names.join(", ")));
+    }
+}
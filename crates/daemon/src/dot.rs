@@ -0,0 +1,48 @@
+// Pipeline graphs (--dump-dot DIR): Graphviz snapshots of the main sender's
+// and receiver's pipelines, written on the `dump-dot` control command and
+// when one fails, before the watchdog rebuilds it, so a bug report can carry
+// the pipeline as it actually was. Files are named
+// `<unix ms>-<sender|receiver>-<why>.dot`; `dot -Tsvg` renders them.
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
+
+use ab_core::pipeline::{Receiver, Sender};
+
+use crate::control::Control;
+
+/// Write `side`'s `graph` into `dir`.
+fn write(dir: &Path, side: &str, why: &str, graph: &str) -> Result<PathBuf> {
+    std::fs::create_dir_all(dir).with_context(|| format!("create {}", dir.display()))?;
+    let ms = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+    let path = dir.join(format!("{ms}-{side}-{why}.dot"));
+    std::fs::write(&path, graph).with_context(|| format!("write {}", path.display()))?;
+    Ok(path)
+}
+
+/// Whichever of the two pipelines are running, into `dir`.
+pub async fn dump(control: &Control, dir: &Path, why: &str) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let sending = control.sending.lock().await.as_ref().and_then(|s| s.with_sender(Sender::dot));
+    if let Some(graph) = sending {
+        files.push(write(dir, "sender", why, &graph)?);
+    }
+    let receiving = control.rx.read().unwrap().as_ref().map(Receiver::dot);
+    if let Some(graph) = receiving {
+        files.push(write(dir, "receiver", why, &graph)?);
+    }
+    Ok(files)
+}
+
+/// Keep the graph of `side`'s failed pipeline, with --dump-dot.
+pub fn on_error(control: &Control, side: &'static str, graph: impl FnOnce() -> Option<String>) {
+    let Some(dir) = control.env.read().unwrap().args.dump_dot.clone() else { return };
+    let Some(graph) = graph() else { return };
+    match (write(&dir, side, "error", &graph), side) {
+        (Ok(path), "sender") => info!(target: "sender", "pipeline graph in {}", path.display()),
+        (Ok(path), _) => info!(target: "receiver", "pipeline graph in {}", path.display()),
+        (Err(e), "sender") => warn!(target: "sender", "can't keep the pipeline graph: {e:#}"),
+        (Err(e), _) => warn!(target: "receiver", "can't keep the pipeline graph: {e:#}"),
+    }
+}
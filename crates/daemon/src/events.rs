@@ -0,0 +1,204 @@
+// Event bus: what happens to the daemon as structured events, for whatever
+// wants to react to it. Each is a JSON object with a "type" and the time
+// (unix s):
+//   peer-discovered   a peer appeared on mDNS ("peer" as in `list-peers`)
+//   peer-gone         …and left ("name")
+//   send-started      the main sender started ("to": its destinations)
+//   send-stopped
+//   stream-started    the receiver hears a sender ("ssrc", and the "address"
+//                     and "peer" name of its handshake session, if any)
+//   stream-stopped    …and doesn't any more (gone, or forgotten)
+//   silence-started   a stream heard carries only silence ("ssrc")
+//   silence-ended     …and sound again
+//   error             a pipeline failed ("side": sender/receiver, "error")
+//   reconnected       a pipeline is back: rebuilt after failing, or the sender
+//                     moved after a network change ("side", "why")
+//   device-added      an audio device was plugged in ("id", "name", "kind":
+//                     source/sink)
+//   device-removed    …or out
+// Peers and errors are passed on as they happen; the sender and the streams
+// are looked at once a second. With --on-event-url each event is POSTed to
+// the URL (plus "node", our name), one at a time and in order, so e.g. home
+// automation can turn the amp on when a stream starts.
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc;
+use tracing::{debug, info, warn};
+
+use ab_core::devices::AudioDevice;
+use ab_core::liveness::StreamState;
+
+use crate::control::{Control, peer_json};
+use crate::http::Endpoint;
+use crate::identity::unhex;
+use crate::mdns::{Peer, PeerEvent};
+use crate::trust::TrustStore;
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// The webhook's port when its URL names none.
+pub const WEBHOOK_PORT: u16 = 80;
+
+#[derive(Debug, Clone)]
+pub enum Event {
+    PeerDiscovered(Peer),
+    PeerGone(String),
+    SendStarted { to: Vec<String> },
+    SendStopped,
+    StreamStarted { ssrc: u32, address: Option<String>, peer: Option<String> },
+    StreamStopped { ssrc: u32 },
+    SilenceStarted { ssrc: u32 },
+    SilenceEnded { ssrc: u32 },
+    Error { side: &'static str, error: String },
+    Reconnected { side: &'static str, why: &'static str },
+    DeviceAdded(AudioDevice),
+    DeviceRemoved(AudioDevice),
+}
+
+impl Event {
+    pub fn json(&self) -> Value {
+        let ssrc = |s: &u32| format!("{s:08x}");
+        let kind = |d: &AudioDevice| if d.sink { "sink" } else { "source" };
+        let mut v = match self {
+            Event::PeerDiscovered(p) => json!({ "type": "peer-discovered", "peer": peer_json(p) }),
+            Event::PeerGone(name) => json!({ "type": "peer-gone", "name": name }),
+            Event::SendStarted { to } => json!({ "type": "send-started", "to": to }),
+            Event::SendStopped => json!({ "type": "send-stopped" }),
+            Event::StreamStarted { ssrc: s, address, peer } => {
+                json!({ "type": "stream-started", "ssrc": ssrc(s), "address": address, "peer": peer })
+            }
+            Event::StreamStopped { ssrc: s } => json!({ "type": "stream-stopped", "ssrc": ssrc(s) }),
+            Event::SilenceStarted { ssrc: s } => json!({ "type": "silence-started", "ssrc": ssrc(s) }),
+            Event::SilenceEnded { ssrc: s } => json!({ "type": "silence-ended", "ssrc": ssrc(s) }),
+            Event::Error { side, error } => json!({ "type": "error", "side": side, "error": error }),
+            Event::Reconnected { side, why } => json!({ "type": "reconnected", "side": side, "why": why }),
+            Event::DeviceAdded(d) => json!({ "type": "device-added", "id": d.id, "name": d.name, "kind": kind(d) }),
+            Event::DeviceRemoved(d) => {
+                json!({ "type": "device-removed", "id": d.id, "name": d.name, "kind": kind(d) })
+            }
+        };
+        v["time"] = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs_f64().into();
+        v
+    }
+}
+
+/// Where events are posted and who listens. Cheap to clone.
+#[derive(Clone, Default)]
+pub struct Bus {
+    watchers: Arc<Mutex<Vec<mpsc::UnboundedSender<Event>>>>,
+}
+
+impl Bus {
+    /// Events from now on.
+    pub fn subscribe(&self) -> mpsc::UnboundedReceiver<Event> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        self.watchers.lock().unwrap().push(tx);
+        rx
+    }
+
+    pub fn emit(&self, event: Event) {
+        debug!(target: "events", "{}", event.json());
+        self.watchers.lock().unwrap().retain(|w| w.send(event.clone()).is_ok());
+    }
+}
+
+/// Pass peers on, and watch the sender and the streams, for as long as the
+/// daemon runs.
+pub fn spawn(control: Arc<Control>) {
+    let mut peers = control.env.read().unwrap().peers.subscribe();
+    let bus = control.events.clone();
+    tokio::spawn(async move {
+        while let Some(event) = peers.recv().await {
+            bus.emit(match event {
+                PeerEvent::Up(p) => Event::PeerDiscovered(p),
+                PeerEvent::Gone(name) => Event::PeerGone(name),
+            });
+        }
+    });
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(CHECK_INTERVAL);
+        // The sender's SSRC and destinations, and each stream's state, as last seen
+        let mut sending: Option<(Option<u32>, Vec<String>)> = None;
+        let mut streams: HashMap<u32, StreamState> = HashMap::new();
+        loop {
+            tick.tick().await;
+            let now = control.sending.lock().await.as_ref().map(|s| (s.cfg.ssrc, s.targets.clone()));
+            if sending != now {
+                // Another SSRC (or other destinations) is another sender
+                if sending.is_some() {
+                    control.events.emit(Event::SendStopped);
+                }
+                if let Some((_, to)) = &now {
+                    control.events.emit(Event::SendStarted { to: to.clone() });
+                }
+                sending = now;
+            }
+            check_streams(&control, &mut streams);
+        }
+    });
+}
+
+fn check_streams(control: &Control, streams: &mut HashMap<u32, StreamState>) {
+    let states = control.rx.read().unwrap().as_ref().map(|rx| rx.stream_states()).unwrap_or_default();
+    let bus = &control.events;
+    let hearing = |ssrc: u32| states.iter().any(|&(s, state)| s == ssrc && state != StreamState::Gone);
+    for (&ssrc, &was) in streams.iter() {
+        if was != StreamState::Gone && !hearing(ssrc) {
+            bus.emit(Event::StreamStopped { ssrc });
+        }
+    }
+    for &(ssrc, state) in &states {
+        let was = streams.get(&ssrc).copied().filter(|s| *s != StreamState::Gone);
+        match (was, state) {
+            (_, StreamState::Gone) => {}
+            (None, _) => {
+                let (address, peer) = sender_of(control, ssrc);
+                bus.emit(Event::StreamStarted { ssrc, address, peer });
+                if state == StreamState::Silent {
+                    bus.emit(Event::SilenceStarted { ssrc });
+                }
+            }
+            (Some(StreamState::Active), StreamState::Silent) => bus.emit(Event::SilenceStarted { ssrc }),
+            (Some(StreamState::Silent), StreamState::Active) => bus.emit(Event::SilenceEnded { ssrc }),
+            _ => {}
+        }
+    }
+    *streams = states.into_iter().collect();
+}
+
+/// The address and trusted name of the handshake session `ssrc` belongs to.
+pub fn sender_of(control: &Control, ssrc: u32) -> (Option<String>, Option<String>) {
+    let live = control.sessions.live();
+    let Some((ip, owner, _)) = live.iter().find(|(_, _, s)| *s == ssrc || s.wrapping_add(1) == ssrc) else {
+        return (None, None);
+    };
+    let name = unhex(owner).and_then(|k| TrustStore::load().ok()?.name_of(&k).map(String::from));
+    (Some(ip.to_string()), name)
+}
+
+/// POST every event to `endpoint`, as `node`.
+pub fn webhook(bus: &Bus, endpoint: Endpoint, node: String) {
+    info!(target: "events", "posting events to {endpoint}");
+    let mut events = bus.subscribe();
+    tokio::spawn(async move {
+        let mut failing = false;
+        while let Some(event) = events.recv().await {
+            let mut body = event.json();
+            body["node"] = node.clone().into();
+            match endpoint.post("", &body).await {
+                Ok(()) if failing => {
+                    info!(target: "events", "webhook reachable again");
+                    failing = false;
+                }
+                Ok(()) => {}
+                // Once per outage; the events in it are lost
+                Err(e) if !failing => {
+                    warn!(target: "events", "webhook failed, dropping events until it answers: {e:#}");
+                    failing = true;
+                }
+                Err(_) => {}
+            }
+        }
+    });
+}
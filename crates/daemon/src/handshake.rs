@@ -0,0 +1,775 @@
+// Handshake port (listen port + 2): newline-delimited JSON over TCP, used to
+// pair two machines and, afterwards, for senders to prove who they are. Lines
+// are capped at 16 KiB and connections at 64 at a time, as anyone can connect.
+//
+// Pairing (`ab-daemon pair <peer>` → a daemon started with --pairable):
+//   {"type":"pair","name":"…","key":"<ed25519>","commit":"<sha256>"}  initiator → daemon
+//   {"type":"pair","name":"…","key":"<ed25519>","eph":"<x25519>"}     daemon → initiator
+//   {"type":"reveal","eph":"<x25519>"}                                initiator → daemon
+//   {"type":"confirm"}                                                initiator → daemon
+//   {"type":"ok"}                                                     daemon → initiator
+// Both ends derive a 6-digit PIN from the X25519 secret and both identity
+// keys and print it. The initiator commits to its X25519 key (SHA-256 over it
+// and its identity) before it sees the daemon's, and the daemon checks the
+// reveal against that, so neither side can pick its key to steer the PIN: a
+// man in the middle gets one guess, a 1 in 10^6 chance of matching PINs. Each
+// user confirms the PIN on their own machine: `pair` asks, and the daemon
+// waits for `ab-daemon ctl pair-confirm <pin>` before it trusts anyone.
+//
+// Stream admission (sender → receiver, kept open while streaming):
+//   {"type":"hello","name":"…","key":"…"}
+//   {"type":"challenge","nonce":"…","name":"…","key":"…","sig":"…"}
+//   {"type":"proof","session":<ssrc>,"sig":"…","mac":"…"}
+//   {"type":"ok"} | {"type":"error","reason":"…"}
+//   {"type":"ping"} every 15 s; the receiver admits the sender's address to
+//   its source gate, and its session's SSRC as the live one, until the
+//   connection goes away.
+//   {"type":"volume","volume":0.5} whenever the sender wants this receiver to
+//   play its session louder or softer (zones); 1.0 = as sent.
+// Each side signs the nonce with its identity, so both can pin the other in
+// the trust store on first contact (TOFU). The sender's `sig` also covers its
+// session SSRC (fresh every run) so nobody can announce a session for it. Its
+// `mac` is HMAC-SHA256(psk, …) over the same bytes and only sent with --psk;
+// a valid MAC or a trusted identity gets in.
+//
+// SRTP (--srtp on both ends): the challenge also carries an X25519 key
+// (covered by the receiver's `sig`, plus a `mac` with a PSK) and the sender
+// only proceeds if the receiver is trusted. The proof then adds
+//   "srtp":{"eph":"…","key":"<AES-256-GCM sealed master key>"}
+// wrapped under HKDF(X25519 secret, nonce); the sender's `sig`/`mac` cover its
+// `eph` too. Every run gets a fresh master key, nothing is configured. This is
+// an interim stand-in for DTLS-SRTP; docs/doc.md ("SRTP key transport") has
+// the protocol and the reasoning behind it.
+use anyhow::{Context, Result, bail};
+use ring::agreement::{self, EphemeralPrivateKey, UnparsedPublicKey, X25519};
+use ring::{aead, hkdf, hmac};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde_json::{Value, json};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{IpAddr, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+use ab_core::gate::SourceGate;
+use ab_core::session::Sessions;
+use ab_core::srtp::{MASTER_KEY_LEN, SrtpKeys, SrtpSession};
+
+use crate::identity::{Identity, fingerprint, hex, unhex, verify};
+use crate::trust::{self, Seen, Trust, TrustStore};
+
+/// Offset of the handshake port from a node's listen port.
+pub const PORT_OFFSET: u16 = 2;
+
+const HELLO_CONTEXT: &[u8] = b"audiobridge-hello";
+const CHALLENGE_CONTEXT: &[u8] = b"audiobridge-challenge";
+const SRTP_CONTEXT: &[u8] = b"audiobridge-srtp";
+const COMMIT_CONTEXT: &[u8] = b"audiobridge-pair-commit";
+const PING_INTERVAL: Duration = Duration::from_secs(15);
+const IDLE_TIMEOUT: Duration = Duration::from_secs(45);
+const CONFIRM_TIMEOUT: Duration = Duration::from_secs(120);
+const RETRY_INTERVAL: Duration = Duration::from_secs(5);
+/// Loudest a sender may ask a receiver to play it.
+const MAX_VOLUME: f64 = 10.0;
+/// Longest message we read; real ones are a few hundred bytes.
+const MAX_LINE: u64 = 16 * 1024;
+/// Handshake connections served at once; more are turned away.
+const MAX_CONNECTIONS: usize = 64;
+
+/// One JSON line each way.
+struct Conn {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl Conn {
+    fn new(stream: TcpStream) -> Result<Self> {
+        let writer = stream.try_clone()?;
+        Ok(Self { reader: BufReader::new(stream), writer })
+    }
+
+    fn send(&mut self, msg: Value) -> Result<()> {
+        self.writer.write_all(format!("{msg}\n").as_bytes())?;
+        Ok(())
+    }
+
+    fn recv(&mut self) -> Result<Value> {
+        let mut line = String::new();
+        if (&mut self.reader).take(MAX_LINE).read_line(&mut line)? == 0 {
+            bail!("connection closed");
+        }
+        if !line.ends_with('\n') && line.len() as u64 == MAX_LINE {
+            bail!("handshake message longer than {MAX_LINE} bytes");
+        }
+        let msg: Value = serde_json::from_str(&line).context("bad handshake message")?;
+        if msg["type"] == "error" {
+            bail!("{}", msg["reason"].as_str().unwrap_or("refused"));
+        }
+        Ok(msg)
+    }
+
+    fn timeout(&self, t: Option<Duration>) {
+        let _ = self.reader.get_ref().set_read_timeout(t);
+    }
+}
+
+fn field_bytes(msg: &Value, field: &str) -> Result<Vec<u8>> {
+    msg[field]
+        .as_str()
+        .and_then(unhex)
+        .with_context(|| format!("handshake: missing or malformed '{field}'"))
+}
+
+/* ---------------------------------- server ---------------------------------- */
+
+/// Who we are on the handshake port.
+#[derive(Clone)]
+pub struct Credentials {
+    pub identity: Arc<Identity>,
+    pub name: String,
+    /// Shared secret from --psk, if any.
+    pub psk: Option<Arc<[u8]>>,
+    /// Trust identities pinned on first contact like paired ones (--tofu).
+    pub tofu: bool,
+}
+
+impl Credentials {
+    fn psk_key(&self) -> Option<hmac::Key> {
+        Some(hmac::Key::new(hmac::HMAC_SHA256, self.psk.as_ref()?))
+    }
+
+    fn mac(&self, msg: &[u8]) -> Option<Vec<u8>> {
+        Some(hmac::sign(&self.psk_key()?, msg).as_ref().to_vec())
+    }
+}
+
+/// What the handshake server needs from the daemon.
+pub struct Server {
+    pub creds: Credentials,
+    /// Gate to admit authenticated senders to; None = nothing is enforced.
+    pub gate: Option<SourceGate>,
+    /// Answer pairing requests.
+    pub pairable: bool,
+    /// Where they wait for the user here to confirm them.
+    pub pairings: Pairings,
+    /// Take SRTP master keys from senders into this table.
+    pub srtp_keys: Option<SrtpKeys>,
+    /// Where senders' live SSRCs are recorded.
+    pub sessions: Sessions,
+}
+
+/// Pairing requests waiting for the user here to confirm their PIN
+/// (`ab-daemon ctl pair-confirm <pin>`), shared with the control socket.
+#[derive(Clone, Default)]
+pub struct Pairings {
+    waiting: Arc<Mutex<Vec<Waiting>>>,
+}
+
+struct Waiting {
+    pin: String,
+    name: String,
+    confirm: mpsc::Sender<()>,
+}
+
+impl Pairings {
+    /// Confirm the pairing request showing `pin`; the peer's name.
+    pub fn confirm(&self, pin: &str) -> Result<String> {
+        let mut waiting = self.waiting.lock().unwrap();
+        let i = waiting
+            .iter()
+            .position(|w| w.pin == pin.trim())
+            .context("no pairing request is waiting with that PIN")?;
+        let w = waiting.remove(i);
+        // Gone already if it timed out meanwhile; it fails on its own then
+        let _ = w.confirm.send(());
+        Ok(w.name)
+    }
+
+    /// None when another request shows the same PIN: the two couldn't be
+    /// told apart.
+    fn wait(&self, pin: &str, name: &str) -> Option<mpsc::Receiver<()>> {
+        let mut waiting = self.waiting.lock().unwrap();
+        if waiting.iter().any(|w| w.pin == pin) {
+            return None;
+        }
+        let (confirm, confirmed) = mpsc::channel();
+        waiting.push(Waiting { pin: pin.to_string(), name: name.to_string(), confirm });
+        Some(confirmed)
+    }
+
+    fn forget(&self, pin: &str) {
+        self.waiting.lock().unwrap().retain(|w| w.pin != pin);
+    }
+}
+
+/// Listen on `bind:port` for pairing requests and sender hellos.
+pub fn serve(bind: IpAddr, port: u16, server: Server) -> Result<()> {
+    let listener = TcpListener::bind((bind, port)).with_context(|| format!("handshake: bind :{port}"))?;
+    let pairable = server.pairable;
+    let server = Arc::new(server);
+    let open = Arc::new(AtomicUsize::new(0));
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else { continue };
+            let peer = stream.peer_addr().map(|a| a.ip().to_string()).unwrap_or_default();
+            // Each holds a thread; don't let anyone on the network take them all
+            if open.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+                open.fetch_sub(1, Ordering::SeqCst);
+                debug!(target: "handshake", "{peer}: turned away ({MAX_CONNECTIONS} connections open)");
+                continue;
+            }
+            let (server, open) = (server.clone(), open.clone());
+            std::thread::spawn(move || {
+                if let Err(e) = handle(stream, &server) {
+                    warn!(target: "handshake", "{peer}: {e:#}");
+                }
+                open.fetch_sub(1, Ordering::SeqCst);
+            });
+        }
+    });
+    info!(
+        target: "handshake",
+        "tcp :{port}{}",
+        if pairable { " (accepting pairing requests)" } else { "" }
+    );
+    Ok(())
+}
+
+fn handle(stream: TcpStream, server: &Server) -> Result<()> {
+    let peer_ip = stream.peer_addr()?.ip();
+    let mut conn = Conn::new(stream)?;
+    conn.timeout(Some(IDLE_TIMEOUT));
+    let first = conn.recv()?;
+    match first["type"].as_str() {
+        Some("pair") => answer_pairing(&mut conn, &first, server),
+        Some("hello") => admit(&mut conn, &first, peer_ip, server),
+        other => bail!("unexpected handshake message {other:?}"),
+    }
+}
+
+fn answer_pairing(conn: &mut Conn, msg: &Value, server: &Server) -> Result<()> {
+    if !server.pairable {
+        conn.send(json!({ "type": "error", "reason": "not accepting pairing (start it with --pairable)" }))?;
+        bail!("refused pairing request (not --pairable)");
+    }
+    let name = msg["name"].as_str().unwrap_or("?").to_string();
+    let their_key = field_bytes(msg, "key")?;
+    let commitment = field_bytes(msg, "commit")?;
+
+    let (eph, our_eph) = ephemeral()?;
+    conn.send(json!({
+        "type": "pair",
+        "name": server.creds.name,
+        "key": hex(server.creds.identity.public_key()),
+        "eph": hex(&our_eph),
+    }))?;
+    let reveal = conn.recv().context("pairing abandoned")?;
+    let their_eph = field_bytes(&reveal, "eph")?;
+    if reveal["type"] != "reveal" || commit(&their_key, &their_eph) != commitment {
+        conn.send(json!({ "type": "error", "reason": "the revealed key doesn't match the commitment" }))?;
+        bail!("pairing with {name}: the revealed key doesn't match the commitment");
+    }
+    let pin = pin(eph, &their_eph, &our_eph, &their_key, server.creds.identity.public_key())?;
+    let Some(confirmed) = server.pairings.wait(&pin, &name) else {
+        conn.send(json!({ "type": "error", "reason": "busy with another pairing request, try again" }))?;
+        bail!("refused pairing request from {name}: another one shows the same PIN");
+    };
+    info!(
+        target: "pair",
+        "{name} ({}) wants to pair. PIN: {pin}. If {name} shows the same PIN, confirm it there and here \
+         with `ab-daemon ctl pair-confirm {pin}`.",
+        fingerprint(&their_key)
+    );
+    let result = confirm_pairing(conn, &name, &confirmed);
+    server.pairings.forget(&pin);
+    if let Err(e) = result {
+        let _ = conn.send(json!({ "type": "error", "reason": format!("{e:#}") }));
+        return Err(e);
+    }
+    {
+        let _lock = trust::lock();
+        let mut store = TrustStore::load()?;
+        store.insert(&name, &their_key);
+        store.save()?;
+    }
+    conn.send(json!({ "type": "ok" }))?;
+    info!(target: "pair", "paired with {name} ({})", fingerprint(&their_key));
+    Ok(())
+}
+
+/// Wait for both users to confirm the PIN: the initiator's over `conn`, ours
+/// through the control socket.
+fn confirm_pairing(conn: &mut Conn, name: &str, confirmed: &mpsc::Receiver<()>) -> Result<()> {
+    let deadline = Instant::now() + CONFIRM_TIMEOUT;
+    conn.timeout(Some(CONFIRM_TIMEOUT));
+    let reply = conn.recv().context("pairing abandoned")?;
+    if reply["type"] != "confirm" {
+        bail!("pairing with {name} not confirmed");
+    }
+    info!(target: "pair", "{name} confirmed the PIN; waiting for `ab-daemon ctl pair-confirm` here");
+    match confirmed.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+        Ok(()) => Ok(()),
+        Err(_) => bail!("pairing with {name} not confirmed on this machine"),
+    }
+}
+
+fn admit(conn: &mut Conn, msg: &Value, peer_ip: IpAddr, server: &Server) -> Result<()> {
+    let key = field_bytes(msg, "key")?;
+    let claimed = msg["name"].as_str().unwrap_or("?");
+    let mut nonce = [0u8; 32];
+    SystemRandom::new()
+        .fill(&mut nonce)
+        .map_err(|_| anyhow::anyhow!("random nonce"))?;
+    // For SRTP the receiver brings a key for the sender to wrap its master key to
+    let srtp_eph = server.srtp_keys.as_ref().map(|_| ephemeral()).transpose()?;
+    let eph = srtp_eph.as_ref().map(|(_, e)| e.clone()).unwrap_or_default();
+    let signed = [CHALLENGE_CONTEXT, &nonce, &eph].concat();
+    let mut challenge = json!({
+        "type": "challenge",
+        "nonce": hex(&nonce),
+        "name": server.creds.name,
+        "key": hex(server.creds.identity.public_key()),
+        "sig": hex(&server.creds.identity.sign(&signed)),
+    });
+    if srtp_eph.is_some() {
+        challenge["eph"] = hex(&eph).into();
+    }
+    if let Some(mac) = server.creds.mac(&signed) {
+        challenge["mac"] = hex(&mac).into();
+    }
+    conn.send(challenge)?;
+    let proof = conn.recv()?;
+    let ssrc = proof["session"]
+        .as_u64()
+        .and_then(|v| u32::try_from(v).ok())
+        .context("handshake: missing or malformed 'session'")?;
+    let verdict = authenticate(claimed, &proof, &key, &nonce, ssrc, server);
+
+    if let Some(gate) = &server.gate {
+        let accepted = verdict.and_then(|name| {
+            if let (Some(keys), Some((eph, _))) = (&server.srtp_keys, srtp_eph) {
+                keys.insert(ssrc, unwrap_srtp(&proof, eph, &nonce)?);
+            }
+            Ok(name)
+        });
+        let name = match accepted {
+            Ok(v) => v,
+            Err(e) => {
+                conn.send(json!({ "type": "error", "reason": format!("{e}") }))?;
+                return Err(e);
+            }
+        };
+        conn.send(json!({ "type": "ok" }))?;
+        info!(target: "handshake", "{name} authenticated from {peer_ip}");
+        gate.admit(peer_ip);
+        server.sessions.begin(peer_ip, &hex(&key), ssrc);
+        // Pings keep the admission alive; silence or EOF ends it
+        follow(conn, ssrc, server);
+        server.sessions.end(peer_ip, ssrc);
+        gate.release(peer_ip);
+        if let Some(keys) = &server.srtp_keys {
+            keys.remove(ssrc);
+        }
+    } else {
+        // Nothing to protect: the verdict only served to pin the sender. The
+        // session still counts, so a restarted sender's old packets are dropped
+        conn.send(json!({ "type": "ok" }))?;
+        server.sessions.begin(peer_ip, &hex(&key), ssrc);
+        follow(conn, ssrc, server);
+        server.sessions.end(peer_ip, ssrc);
+    }
+    Ok(())
+}
+
+/// Serve an admitted sender's connection until it goes away: pings, and the
+/// volume it wants its session played at.
+fn follow(conn: &mut Conn, ssrc: u32, server: &Server) {
+    while let Ok(msg) = conn.recv() {
+        if msg["type"] == "volume" {
+            if let Some(volume) = msg["volume"].as_f64().filter(|v| v.is_finite()) {
+                server.sessions.set_volume(ssrc, volume.clamp(0.0, MAX_VOLUME));
+            }
+        }
+    }
+}
+
+/// Name of the sender if `proof` holds a valid PSK MAC or a signature by a
+/// trusted identity. With --tofu, identities that sign correctly are pinned on
+/// first contact.
+fn authenticate(claimed: &str, proof: &Value, key: &[u8], nonce: &[u8], ssrc: u32, server: &Server) -> Result<String> {
+    let eph = proof["srtp"]["eph"].as_str().and_then(unhex).unwrap_or_default();
+    let signed = [HELLO_CONTEXT, nonce, &ssrc.to_be_bytes(), &eph].concat();
+    let sig_ok = field_bytes(proof, "sig").is_ok_and(|sig| verify(key, &signed, &sig));
+    if let (Some(psk), Ok(mac)) = (server.creds.psk_key(), field_bytes(proof, "mac")) {
+        if hmac::verify(&psk, &signed, &mac).is_err() {
+            bail!("wrong pre-shared key");
+        }
+        if sig_ok {
+            trust::record(claimed, key, server.creds.tofu)?;
+        }
+        return Ok(format!("{claimed} (psk)"));
+    }
+    if !sig_ok {
+        bail!("{claimed}: bad signature");
+    }
+    judge(claimed, key, &server.creds)
+}
+
+/// Whether a peer that proved it holds `key` while calling itself `name` is
+/// trusted: paired, or (with --tofu) pinned on first contact.
+fn judge(name: &str, key: &[u8], creds: &Credentials) -> Result<String> {
+    match trust::record(name, key, creds.tofu)? {
+        Seen::Known { name, trust: Trust::Paired } => Ok(name),
+        Seen::Known { name, trust: Trust::Seen } if creds.tofu => Ok(name),
+        Seen::New => Ok(name.to_string()),
+        Seen::Changed => bail!("identity of '{name}' changed since it was pinned"),
+        _ => bail!(
+            "{name} ({}) is not paired; run `ab-daemon pair` (or use --tofu)",
+            fingerprint(key)
+        ),
+    }
+}
+
+/// The sender's SRTP master key out of `proof`.
+fn unwrap_srtp(proof: &Value, eph: EphemeralPrivateKey, nonce: &[u8]) -> Result<Vec<u8>> {
+    let srtp = &proof["srtp"];
+    if srtp.is_null() {
+        bail!("this receiver only plays SRTP (start the sender with --srtp)");
+    }
+    let their_eph = field_bytes(srtp, "eph")?;
+    let sealed = field_bytes(srtp, "key")?;
+    let master = open(&wrap_key(eph, &their_eph, nonce)?, &sealed)?;
+    anyhow::ensure!(master.len() == MASTER_KEY_LEN, "SRTP master key has the wrong length");
+    Ok(master)
+}
+
+/* ---------------------------------- client ---------------------------------- */
+
+/// `ab-daemon pair`: pair with the daemon at `addr`, asking the user to
+/// compare PINs. Returns the peer's name once both sides have stored each other.
+pub fn pair(addr: SocketAddr, identity: &Identity, name: &str) -> Result<String> {
+    let stream = TcpStream::connect_timeout(&addr, Duration::from_secs(5))
+        .with_context(|| format!("connect to {addr}"))?;
+    let mut conn = Conn::new(stream)?;
+    let (eph, our_eph) = ephemeral()?;
+    // Our X25519 key only once theirs is fixed
+    conn.send(json!({
+        "type": "pair",
+        "name": name,
+        "key": hex(identity.public_key()),
+        "commit": hex(&commit(identity.public_key(), &our_eph)),
+    }))?;
+    let reply = conn.recv()?;
+    let peer = reply["name"].as_str().unwrap_or("?").to_string();
+    let their_key = field_bytes(&reply, "key")?;
+    let their_eph = field_bytes(&reply, "eph")?;
+    conn.send(json!({ "type": "reveal", "eph": hex(&our_eph) }))?;
+    let pin = pin(eph, &their_eph, &our_eph, &their_key, identity.public_key())?;
+
+    println!("Pairing with {peer} ({})", fingerprint(&their_key));
+    println!("PIN: {pin}");
+    print!("Does {peer} show the same PIN? [y/N] ");
+    std::io::stdout().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    if !matches!(answer.trim(), "y" | "Y" | "yes") {
+        bail!("pairing cancelled");
+    }
+
+    conn.send(json!({ "type": "confirm" }))?;
+    println!("Waiting for {peer} to confirm the PIN too (ab-daemon ctl pair-confirm {pin} there)…");
+    conn.timeout(Some(CONFIRM_TIMEOUT));
+    conn.recv().context("peer did not finish pairing")?;
+    let _lock = trust::lock();
+    let mut store = TrustStore::load()?;
+    store.insert(&peer, &their_key);
+    store.save()?;
+    Ok(peer)
+}
+
+/// Keeps an authenticated connection to each destination's handshake port
+/// while the sender runs, reconnecting as needed. Destinations can be swapped
+/// when a peer's address moves.
+#[derive(Clone)]
+pub struct Hello {
+    hosts: Arc<Mutex<Vec<String>>>,
+    /// Open connection per destination, so `stop` can end them right away.
+    conns: Arc<Mutex<Vec<Option<TcpStream>>>>,
+    /// Volume asked of each destination, sent again on every reconnect.
+    volumes: Arc<Mutex<Vec<Option<f64>>>>,
+    /// Wakes a connected destination's thread to send its new volume.
+    wakers: Arc<Mutex<Vec<Option<mpsc::Sender<()>>>>>,
+    stopped: Arc<AtomicBool>,
+    port: u16,
+    bind: Option<IpAddr>,
+    creds: Credentials,
+    /// The sender's session SSRC (`SenderConfig::ssrc`).
+    ssrc: u32,
+    srtp: Option<SrtpSession>,
+}
+
+impl Hello {
+    pub fn spawn(
+        hosts: &[String],
+        port: u16,
+        bind: Option<IpAddr>,
+        creds: Credentials,
+        ssrc: u32,
+        srtp: Option<SrtpSession>,
+    ) -> Self {
+        let this = Self {
+            hosts: Arc::new(Mutex::new(hosts.to_vec())),
+            conns: Arc::new(Mutex::new(hosts.iter().map(|_| None).collect())),
+            volumes: Arc::new(Mutex::new(hosts.iter().map(|_| None).collect())),
+            wakers: Arc::new(Mutex::new(hosts.iter().map(|_| None).collect())),
+            stopped: Arc::new(AtomicBool::new(false)),
+            port,
+            bind,
+            creds,
+            ssrc,
+            srtp,
+        };
+        for i in 0..hosts.len() {
+            let this = this.clone();
+            std::thread::spawn(move || {
+                let mut last_err = String::new();
+                while !this.stopped.load(Ordering::Relaxed) {
+                    let host = this.hosts.lock().unwrap()[i].clone();
+                    // A multicast group has nobody to answer
+                    if host.parse::<IpAddr>().is_ok_and(|ip| ip.is_multicast()) {
+                        return;
+                    }
+                    if let Err(e) = this.prove(i, &host) {
+                        if this.stopped.load(Ordering::Relaxed) {
+                            return;
+                        }
+                        let e = format!("{e:#}");
+                        if e != last_err {
+                            warn!(target: "handshake", "{host}:{port}: {e}");
+                            last_err = e;
+                        }
+                    }
+                    std::thread::sleep(RETRY_INTERVAL);
+                }
+            });
+        }
+        this
+    }
+
+    pub fn set_hosts(&self, hosts: &[String]) {
+        let mut current = self.hosts.lock().unwrap();
+        for (slot, host) in current.iter_mut().zip(hosts) {
+            slot.clone_from(host);
+        }
+    }
+
+    pub fn hosts(&self) -> Vec<String> {
+        self.hosts.lock().unwrap().clone()
+    }
+
+    /// Ask the destination in `slot` to play us at `volume`, now and after
+    /// any reconnect.
+    pub fn set_volume(&self, slot: usize, volume: f64) {
+        let Some(v) = self.volumes.lock().unwrap().get_mut(slot) else { return };
+        *v = Some(volume);
+        if let Some(waker) = &self.wakers.lock().unwrap()[slot] {
+            let _ = waker.send(());
+        }
+    }
+
+    /// Volumes asked of each destination (None = as sent).
+    pub fn volumes(&self) -> Vec<Option<f64>> {
+        self.volumes.lock().unwrap().clone()
+    }
+
+    /// Close every connection (receivers release us at once) and stop reconnecting.
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::Relaxed);
+        for conn in self.conns.lock().unwrap().iter_mut() {
+            if let Some(stream) = conn.take() {
+                let _ = stream.shutdown(std::net::Shutdown::Both);
+            }
+        }
+    }
+
+    /// One authenticated session: hello, answer the challenge, then ping (and
+    /// pass on volume changes) until the connection fails.
+    fn prove(&self, slot: usize, host: &str) -> Result<()> {
+        let (port, creds, ssrc) = (self.port, &self.creds, self.ssrc);
+        let addr = (host, port)
+            .to_socket_addrs()?
+            .next()
+            .with_context(|| format!("{host} did not resolve"))?;
+        let stream = connect(addr, self.bind)?;
+        self.conns.lock().unwrap()[slot] = Some(stream.try_clone()?);
+        if self.stopped.load(Ordering::Relaxed) {
+            bail!("stopped");
+        }
+        let mut conn = Conn::new(stream)?;
+        let key = hex(creds.identity.public_key());
+        conn.send(json!({ "type": "hello", "name": creds.name, "key": key }))?;
+        let challenge = conn.recv()?;
+        let nonce = field_bytes(&challenge, "nonce")?;
+        // Pin whoever answered; SRTP keys only go to a trusted receiver
+        let receiver = check_receiver(&challenge, &nonce, creds);
+        let (offer, eph) = match &self.srtp {
+            Some(session) => {
+                receiver?;
+                let (offer, eph) = offer_srtp(&challenge, &nonce, session)?;
+                (Some(offer), eph)
+            }
+            None => (None, Vec::new()),
+        };
+        let signed = [HELLO_CONTEXT, &nonce, &ssrc.to_be_bytes(), &eph].concat();
+        let mut proof = json!({ "type": "proof", "session": ssrc, "sig": hex(&creds.identity.sign(&signed)) });
+        if let Some(mac) = creds.mac(&signed) {
+            proof["mac"] = hex(&mac).into();
+        }
+        if let Some(offer) = offer {
+            proof["srtp"] = offer;
+        }
+        conn.send(proof)?;
+        conn.recv()?;
+        info!(target: "handshake", "authenticated to {host}");
+        let (waker, woken) = mpsc::channel();
+        self.wakers.lock().unwrap()[slot] = Some(waker);
+        // Whatever was asked while we were away
+        let mut sent = None;
+        loop {
+            let volume = self.volumes.lock().unwrap()[slot];
+            if volume != sent {
+                if let Some(v) = volume {
+                    conn.send(json!({ "type": "volume", "volume": v }))?;
+                }
+                sent = volume;
+            }
+            match woken.recv_timeout(PING_INTERVAL) {
+                Ok(()) => {}
+                Err(RecvTimeoutError::Timeout) => conn.send(json!({ "type": "ping" }))?,
+                Err(RecvTimeoutError::Disconnected) => bail!("stopped"),
+            }
+        }
+    }
+}
+
+/// Verify the receiver's signature (and PSK MAC) over the challenge and
+/// judge its identity like the receiver judges ours.
+fn check_receiver(challenge: &Value, nonce: &[u8], creds: &Credentials) -> Result<String> {
+    let name = challenge["name"].as_str().unwrap_or("?");
+    let key = field_bytes(challenge, "key")?;
+    let eph = challenge["eph"].as_str().and_then(unhex).unwrap_or_default();
+    let signed = [CHALLENGE_CONTEXT, nonce, &eph].concat();
+    let sig_ok = field_bytes(challenge, "sig").is_ok_and(|sig| verify(&key, &signed, &sig));
+    if let (Some(psk), Ok(mac)) = (creds.psk_key(), field_bytes(challenge, "mac")) {
+        if hmac::verify(&psk, &signed, &mac).is_err() {
+            bail!("receiver {name} has a different pre-shared key");
+        }
+        if sig_ok {
+            trust::record(name, &key, creds.tofu)?;
+        }
+        return Ok(format!("{name} (psk)"));
+    }
+    if !sig_ok {
+        bail!("receiver {name}: bad signature");
+    }
+    judge(name, &key, creds)
+}
+
+/// Wrap our SRTP master key for the receiver's X25519 key. Returns the
+/// `srtp` object and our own X25519 key.
+fn offer_srtp(challenge: &Value, nonce: &[u8], session: &SrtpSession) -> Result<(Value, Vec<u8>)> {
+    let their_eph = field_bytes(challenge, "eph").context("receiver does not do SRTP (start it with --srtp)")?;
+    let (eph, our_eph) = ephemeral()?;
+    let sealed = seal(&wrap_key(eph, &their_eph, nonce)?, &session.key)?;
+    let offer = json!({ "eph": hex(&our_eph), "key": hex(&sealed) });
+    Ok((offer, our_eph))
+}
+
+/// TCP connect from `bind` so the receiver sees the same source address as
+/// our RTP.
+fn connect(addr: SocketAddr, bind: Option<IpAddr>) -> Result<TcpStream> {
+    use socket2::{Domain, Socket, Type};
+    let sock = Socket::new(Domain::for_address(addr), Type::STREAM, None)?;
+    if let Some(ip) = bind {
+        sock.bind(&SocketAddr::new(ip, 0).into())?;
+    }
+    sock.connect_timeout(&addr.into(), Duration::from_secs(5))?;
+    Ok(sock.into())
+}
+
+/* ---------------------------------- crypto ---------------------------------- */
+
+/// Fresh SSRC for this run: the session ID receivers tell our streams apart by.
+pub fn new_ssrc() -> Result<u32> {
+    let mut ssrc = [0u8; 4];
+    SystemRandom::new().fill(&mut ssrc).map_err(|_| anyhow::anyhow!("random SSRC"))?;
+    Ok(u32::from_be_bytes(ssrc))
+}
+
+/// Fresh master key for this run's SRTP session.
+pub fn new_srtp_session() -> Result<SrtpSession> {
+    let mut key = vec![0u8; MASTER_KEY_LEN];
+    SystemRandom::new().fill(&mut key).map_err(|_| anyhow::anyhow!("random SRTP key"))?;
+    Ok(SrtpSession { key })
+}
+
+/// The pairing initiator's commitment to its X25519 key `eph`.
+fn commit(key: &[u8], eph: &[u8]) -> Vec<u8> {
+    ring::digest::digest(&ring::digest::SHA256, &[COMMIT_CONTEXT, key, eph].concat()).as_ref().to_vec()
+}
+
+fn ephemeral() -> Result<(EphemeralPrivateKey, Vec<u8>)> {
+    let rng = SystemRandom::new();
+    let key = EphemeralPrivateKey::generate(&X25519, &rng).map_err(|_| anyhow::anyhow!("X25519 keygen"))?;
+    let public = key
+        .compute_public_key()
+        .map_err(|_| anyhow::anyhow!("X25519 public key"))?
+        .as_ref()
+        .to_vec();
+    Ok((key, public))
+}
+
+/// Six digits from SHA-256(shared secret ‖ both ephemeral keys ‖ both identity
+/// keys), each pair sorted so both ends hash the same bytes.
+fn pin(eph: EphemeralPrivateKey, their_eph: &[u8], our_eph: &[u8], key_a: &[u8], key_b: &[u8]) -> Result<String> {
+    let sorted = |a: &[u8], b: &[u8]| if a <= b { [a, b].concat() } else { [b, a].concat() };
+    let transcript = [sorted(our_eph, their_eph), sorted(key_a, key_b)].concat();
+    agreement::agree_ephemeral(eph, &UnparsedPublicKey::new(&X25519, their_eph), |shared| {
+        let digest = ring::digest::digest(&ring::digest::SHA256, &[shared, &transcript].concat());
+        let n = u32::from_be_bytes(digest.as_ref()[..4].try_into().unwrap());
+        format!("{:06}", n % 1_000_000)
+    })
+    .map_err(|_| anyhow::anyhow!("X25519 agreement failed"))
+}
+
+/// AES-256-GCM key for the SRTP master key: HKDF-SHA256 over the X25519
+/// secret, salted with the challenge nonce. Used for exactly one message.
+fn wrap_key(eph: EphemeralPrivateKey, their_eph: &[u8], nonce: &[u8]) -> Result<aead::LessSafeKey> {
+    agreement::agree_ephemeral(eph, &UnparsedPublicKey::new(&X25519, their_eph), |shared| {
+        let okm = hkdf::Salt::new(hkdf::HKDF_SHA256, nonce)
+            .extract(shared)
+            .expand(&[SRTP_CONTEXT], &aead::AES_256_GCM)
+            .expect("HKDF output length fits AES-256");
+        aead::LessSafeKey::new(aead::UnboundKey::from(okm))
+    })
+    .map_err(|_| anyhow::anyhow!("X25519 agreement failed"))
+}
+
+/// Seal under a fixed nonce: `key` is fresh from `wrap_key` and seals this
+/// one message only, so no key and nonce pair can repeat.
+fn seal(key: &aead::LessSafeKey, plain: &[u8]) -> Result<Vec<u8>> {
+    let mut buf = plain.to_vec();
+    key.seal_in_place_append_tag(aead::Nonce::assume_unique_for_key([0; 12]), aead::Aad::empty(), &mut buf)
+        .map_err(|_| anyhow::anyhow!("seal SRTP key"))?;
+    Ok(buf)
+}
+
+fn open(key: &aead::LessSafeKey, sealed: &[u8]) -> Result<Vec<u8>> {
+    let mut buf = sealed.to_vec();
+    let plain = key
+        .open_in_place(aead::Nonce::assume_unique_for_key([0; 12]), aead::Aad::empty(), &mut buf)
+        .map_err(|_| anyhow::anyhow!("SRTP key does not decrypt (tampered?)"))?;
+    Ok(plain.to_vec())
+}
@@ -0,0 +1,151 @@
+// Home Assistant MQTT discovery (--ha-discovery): retained configs under
+// `homeassistant/` (--ha-discovery-prefix) that make each node a device in
+// Home Assistant, with entities over the topics `mqtt.rs` publishes:
+//   number         Volume: playback volume, in percent of unity
+//   switch         Mute, and Sending (start-send / stop-send, to --send-to)
+//   binary_sensor  Playing: a stream is coming in
+//   sensor         Playing from (the trusted names of its senders), Level
+//                  (RMS of what's played, dBFS)
+//   event          Event: each event's type, to trigger automations on
+// Home Assistant's MQTT integration has no media player, so these stand in
+// for one. The receiver's entities are left out with --no-receive, Sending
+// with --no-send. The configs are sent on each connect, and again whenever
+// Home Assistant says it's back online.
+use serde_json::{Value, json};
+
+/// The event entity's types: those of `events.rs`.
+const EVENT_TYPES: &[&str] = &[
+    "peer-discovered",
+    "peer-gone",
+    "send-started",
+    "send-stopped",
+    "stream-started",
+    "stream-stopped",
+    "silence-started",
+    "silence-ended",
+    "error",
+    "reconnected",
+    "device-added",
+    "device-removed",
+];
+
+/// Where a node called `node`, publishing under `prefix`, puts its config
+/// for each entity, and the config.
+pub fn configs(discovery: &str, prefix: &str, node: &str, receiving: bool, sending: bool) -> Vec<(String, Value)> {
+    // Discovery topics and IDs take [a-zA-Z0-9_-] only
+    let id: String = node.chars().map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' }).collect();
+    let status = format!("{prefix}/status");
+    let command = format!("{prefix}/command");
+    let streams = "value_json.receiving.senders | rejectattr('state', 'eq', 'gone')";
+    let mut entities = Vec::new();
+    if receiving {
+        entities.push((
+            "number",
+            "volume",
+            json!({
+                "name": "Volume",
+                "icon": "mdi:volume-high",
+                "state_topic": status,
+                "value_template": "{{ (value_json.receiving.volume * 100) | round(0) }}",
+                "command_topic": command,
+                "command_template": r#"{"cmd":"set-volume","volume":{{ value / 100 }}}"#,
+                "min": 0,
+                "max": 200,
+                "step": 1,
+                "unit_of_measurement": "%",
+                "mode": "slider",
+            }),
+        ));
+        entities.push((
+            "switch",
+            "mute",
+            json!({
+                "name": "Mute",
+                "icon": "mdi:volume-off",
+                "state_topic": status,
+                "value_template": "{{ 'ON' if value_json.receiving.muted else 'OFF' }}",
+                "command_topic": command,
+                "payload_on": r#"{"cmd":"set-mute","mute":true}"#,
+                "payload_off": r#"{"cmd":"set-mute","mute":false}"#,
+                "state_on": "ON",
+                "state_off": "OFF",
+            }),
+        ));
+        entities.push((
+            "binary_sensor",
+            "playing",
+            json!({
+                "name": "Playing",
+                "icon": "mdi:speaker-wireless",
+                "state_topic": status,
+                "value_template": format!("{{{{ 'ON' if {streams} | list else 'OFF' }}}}"),
+            }),
+        ));
+        entities.push((
+            "sensor",
+            "playing_from",
+            json!({
+                "name": "Playing from",
+                "icon": "mdi:account-music",
+                "state_topic": status,
+                "value_template": format!("{{{{ {streams} | map(attribute='name') | select | join(', ') }}}}"),
+            }),
+        ));
+        entities.push((
+            "sensor",
+            "level",
+            json!({
+                "name": "Level",
+                "icon": "mdi:waveform",
+                "state_topic": format!("{prefix}/levels"),
+                "value_template": "{{ value_json.receiving.rms_db | round(1) if value_json.receiving else none }}",
+                "unit_of_measurement": "dBFS",
+                "state_class": "measurement",
+            }),
+        ));
+    }
+    if sending {
+        entities.push((
+            "switch",
+            "sending",
+            json!({
+                "name": "Sending",
+                "icon": "mdi:broadcast",
+                "state_topic": status,
+                "value_template": "{{ 'ON' if value_json.sending else 'OFF' }}",
+                "command_topic": command,
+                "payload_on": r#"{"cmd":"start-send"}"#,
+                "payload_off": r#"{"cmd":"stop-send"}"#,
+                "state_on": "ON",
+                "state_off": "OFF",
+            }),
+        ));
+    }
+    entities.push((
+        "event",
+        "event",
+        json!({
+            "name": "Event",
+            "icon": "mdi:bell-ring",
+            "state_topic": format!("{prefix}/event"),
+            "value_template": "{{ {'event_type': value_json.type} | tojson }}",
+            "event_types": EVENT_TYPES,
+        }),
+    ));
+    let device = json!({
+        "identifiers": [format!("audio-bridge-{id}")],
+        "name": node,
+        "manufacturer": "audio-bridge",
+        "model": "ab-daemon",
+        "sw_version": env!("CARGO_PKG_VERSION"),
+    });
+    entities
+        .into_iter()
+        .map(|(component, object, mut config)| {
+            config["unique_id"] = format!("ab_{id}_{object}").into();
+            config["availability_topic"] = format!("{prefix}/availability").into();
+            config["device"] = device.clone();
+            (format!("{discovery}/{component}/{id}/{object}/config"), config)
+        })
+        .collect()
+}
@@ -0,0 +1,115 @@
+// Audio devices plugged in and out, for as long as the daemon runs. The
+// DeviceMonitor `devices::watch` keeps running is what devices are listed
+// from (the monitor picked when there's no --capture-device, the names
+// followed after a sound server restart), and each device that comes or goes
+// is an event: device-added / device-removed.
+//
+// Linux: when the device a pipeline is meant to be on goes away, the sound
+// server moves its stream elsewhere (a USB DAC unplugged: the speakers) and
+// nothing fails, so nothing would move it back. When that device comes back,
+// the pipeline is rebuilt onto it: the --output-device, the --capture-device
+// (under whatever name it has now, see `devices::follow`), or with neither,
+// the monitor --monitor-hint prefers, or any monitor at all while the sender
+// is on something else (the microphone it fell back to). macOS rebuilds on
+// device changes of its own (see `macos.rs`).
+use std::collections::HashSet;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tracing::info;
+
+use ab_core::devices::{self, DeviceChange};
+
+use crate::control::Control;
+use crate::events::Event;
+
+/// Report and act on `changes` for as long as the daemon runs.
+pub fn spawn(control: Arc<Control>, mut changes: mpsc::UnboundedReceiver<DeviceChange>) {
+    tokio::spawn(async move {
+        // Providers may report what's there as added; only news is news
+        let mut present: HashSet<(bool, String)> = [devices::sources(), devices::sinks()]
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|d| (d.sink, d.id))
+            .collect();
+        while let Some(change) = changes.recv().await {
+            match change {
+                DeviceChange::Added(d) => {
+                    if !present.insert((d.sink, d.id.clone())) {
+                        continue;
+                    }
+                    info!(target: "devices", "plugged in: {} ({})", d.id, d.name);
+                    control.events.emit(Event::DeviceAdded(d.clone()));
+                    #[cfg(target_os = "linux")]
+                    reselect(&control, &d).await;
+                }
+                DeviceChange::Removed(d) => {
+                    if !present.remove(&(d.sink, d.id.clone())) {
+                        continue;
+                    }
+                    info!(target: "devices", "unplugged: {} ({})", d.id, d.name);
+                    control.events.emit(Event::DeviceRemoved(d));
+                }
+            }
+        }
+    });
+}
+
+/// Linux: move whichever pipeline `device` is the one for back onto it.
+#[cfg(target_os = "linux")]
+async fn reselect(control: &Control, device: &devices::AudioDevice) {
+    use ab_core::pipeline::{Output, Source};
+
+    use crate::supervise;
+
+    if device.sink {
+        let wanted = {
+            let cfg = control.rx_cfg.read().unwrap();
+            cfg.output == Output::Local && cfg.output_device.as_deref().is_some_and(|d| comes_back(d, device))
+        };
+        // Following the default output, which may have moved on for good
+        let wanted = wanted && !control.env.read().unwrap().args.follow_default_sink;
+        let current = control.rx.read().unwrap().as_ref().map(|rx| rx.current_device());
+        let elsewhere = current.is_some_and(|c| c.as_deref() != Some(device.id.as_str()));
+        if wanted && elsewhere {
+            supervise::restart(control, true, false, &format!("{} is back", device.id)).await;
+        }
+        return;
+    }
+    {
+        let mut sending = control.sending.lock().await;
+        let Some(s) = sending.as_mut() else { return };
+        if s.cfg.source != Source::Capture || s.cfg.capture_app.is_some() {
+            return;
+        }
+        let current = s.with_sender(|tx| tx.current_device()).flatten();
+        if current.as_deref() == Some(device.id.as_str()) {
+            return;
+        }
+        let wanted = match s.cfg.capture_device.as_deref() {
+            Some(d) => comes_back(d, device),
+            None if s.cfg.echo_cancel || !device.monitor => false,
+            None => {
+                let hinted = s.cfg.monitor_hint.as_deref().is_some_and(|h| {
+                    let h = h.to_lowercase();
+                    device.id.to_lowercase().contains(&h) || device.name.to_lowercase().contains(&h)
+                });
+                hinted || !current.is_some_and(|c| c.ends_with(".monitor"))
+            }
+        };
+        if !wanted {
+            return;
+        }
+        // Under the name it has now
+        if s.cfg.capture_device.is_some() {
+            s.cfg.capture_device = Some(device.id.clone());
+        }
+    }
+    supervise::restart(control, false, true, &format!("{} is back", device.id)).await;
+}
+
+/// Whether `device` is `id`, or the same card under a new name.
+#[cfg(target_os = "linux")]
+fn comes_back(id: &str, device: &devices::AudioDevice) -> bool {
+    devices::follow(id, std::slice::from_ref(device)).is_some()
+}
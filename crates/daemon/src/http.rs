@@ -0,0 +1,80 @@
+// A minimal HTTP/1.1 client for POSTing JSON to services on the LAN: the
+// OpenTelemetry collector (--otlp-endpoint) and the event webhook
+// (--on-event-url). Plain http:// only; one connection per request.
+use anyhow::{Context, Result, bail, ensure};
+use serde_json::Value;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// How long one request may take.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// `http://host[:port][/prefix]`.
+#[derive(Debug, Clone)]
+pub struct Endpoint {
+    host: String,
+    port: u16,
+    prefix: String,
+}
+
+impl Endpoint {
+    /// `url`, on `default_port` if it names none.
+    pub fn parse(url: &str, default_port: u16) -> Result<Self> {
+        let Some(rest) = url.strip_prefix("http://") else {
+            bail!("'{url}' isn't an http:// URL (https isn't supported)");
+        };
+        let (authority, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+        let (host, port) = match authority.strip_prefix('[').and_then(|a| a.split_once(']')) {
+            Some((host, port)) => (host, port.strip_prefix(':')),
+            None => match authority.rsplit_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (authority, None),
+            },
+        };
+        ensure!(!host.is_empty(), "no host in {url}");
+        let port = port.map_or(Ok(default_port), str::parse).with_context(|| format!("bad port in {url}"))?;
+        let prefix = prefix.trim_matches('/');
+        let prefix = if prefix.is_empty() { String::new() } else { format!("/{prefix}") };
+        Ok(Self { host: host.to_string(), port, prefix })
+    }
+
+    /// The host, bracketed if it's an IPv6 address.
+    fn authority(&self) -> String {
+        if self.host.contains(':') { format!("[{}]", self.host) } else { self.host.clone() }
+    }
+
+    /// POST `body` to `path` under the URL's own; any 2xx answer will do.
+    pub async fn post(&self, path: &str, body: &Value) -> Result<()> {
+        let body = body.to_string();
+        let target = match format!("{}{path}", self.prefix) {
+            t if t.is_empty() => "/".to_string(),
+            t => t,
+        };
+        let request = format!(
+            "POST {target} HTTP/1.1\r\nHost: {}:{}\r\nContent-Type: application/json\r\n\
+             Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            self.authority(),
+            self.port,
+            body.len()
+        );
+        let exchange = async {
+            let mut stream = TcpStream::connect((self.host.as_str(), self.port)).await?;
+            stream.write_all(request.as_bytes()).await?;
+            let mut reply = Vec::new();
+            stream.read_to_end(&mut reply).await?;
+            anyhow::Ok(reply)
+        };
+        let reply = tokio::time::timeout(TIMEOUT, exchange).await.context("timed out")??;
+        let reply = String::from_utf8_lossy(&reply);
+        let status = reply.lines().next().unwrap_or_default();
+        ensure!(status.split_whitespace().nth(1).is_some_and(|c| c.starts_with('2')), "answered '{status}'");
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "http://{}:{}{}", self.authority(), self.port, self.prefix)
+    }
+}
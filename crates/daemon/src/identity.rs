@@ -0,0 +1,90 @@
+// This node's long-term identity: an Ed25519 key pair created on first use
+// and kept in the config directory. Peers pin its public key when pairing.
+use anyhow::{Context, Result};
+use ring::rand::SystemRandom;
+use ring::signature::{self, Ed25519KeyPair, KeyPair};
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::PathBuf;
+use tracing::info;
+
+/// `$XDG_CONFIG_HOME/audiobridge`, else `~/.config/audiobridge`.
+pub fn config_dir() -> Result<PathBuf> {
+    let base = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME").context("HOME is not set")?).join(".config"),
+    };
+    Ok(base.join("audiobridge"))
+}
+
+pub struct Identity {
+    key: Ed25519KeyPair,
+}
+
+impl Identity {
+    /// Load `identity.pk8` from the config directory, generating it if missing.
+    pub fn load_or_create() -> Result<Self> {
+        let path = config_dir()?.join("identity.pk8");
+        let pkcs8 = match std::fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                let doc = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new())
+                    .map_err(|_| anyhow::anyhow!("generate identity key"))?;
+                std::fs::create_dir_all(path.parent().unwrap())
+                    .with_context(|| format!("create {}", path.parent().unwrap().display()))?;
+                std::fs::OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .mode(0o600)
+                    .open(&path)
+                    .and_then(|mut f| f.write_all(doc.as_ref()))
+                    .with_context(|| format!("write {}", path.display()))?;
+                info!(target: "identity", "created {}", path.display());
+                doc.as_ref().to_vec()
+            }
+            Err(e) => return Err(e).with_context(|| format!("read {}", path.display())),
+        };
+        let key = Ed25519KeyPair::from_pkcs8(&pkcs8)
+            .map_err(|e| anyhow::anyhow!("{}: bad identity key: {e}", path.display()))?;
+        Ok(Self { key })
+    }
+
+    pub fn public_key(&self) -> &[u8] {
+        self.key.public_key().as_ref()
+    }
+
+    pub fn sign(&self, msg: &[u8]) -> Vec<u8> {
+        self.key.sign(msg).as_ref().to_vec()
+    }
+}
+
+/// Check an Ed25519 signature by `public_key`.
+pub fn verify(public_key: &[u8], msg: &[u8], sig: &[u8]) -> bool {
+    signature::UnparsedPublicKey::new(&signature::ED25519, public_key)
+        .verify(msg, sig)
+        .is_ok()
+}
+
+/// Short, human-comparable form of a public key: "3f9a:1c0e:…" (SHA-256, first 8 bytes).
+pub fn fingerprint(public_key: &[u8]) -> String {
+    let digest = ring::digest::digest(&ring::digest::SHA256, public_key);
+    digest.as_ref()[..8]
+        .chunks(2)
+        .map(hex)
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+pub fn unhex(s: &str) -> Option<Vec<u8>> {
+    if s.len() % 2 != 0 {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
@@ -0,0 +1,115 @@
+// Keys, for a daemon running in the foreground on a terminal (unless
+// --no-keys): m mutes or unmutes playback, + and - turn its volume up and
+// down by 10%, s starts or stops sending (to --send-to), d prints the status
+// and q shuts down as Ctrl-C does. The terminal reads single keys without
+// echoing them while the daemon runs (Ctrl-C and Ctrl-Z still work) and is
+// put back as it was on the way out.
+use anyhow::Result;
+use serde_json::json;
+use std::io::{IsTerminal, Read};
+use std::sync::Arc;
+use tokio::sync::{Notify, mpsc};
+use tracing::{info, warn};
+
+use crate::control::{self, Control};
+
+/// How much + and - change the volume (1.0 = unity).
+const VOLUME_STEP: f64 = 0.1;
+const HELP: &str = "keys: m mute, +/- volume, s send on/off, d status, q quit";
+
+/// The terminal's settings before we changed them, put back on drop.
+pub struct Terminal(libc::termios);
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        // SAFETY: restoring settings tcgetattr filled in, on the same descriptor
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.0) };
+    }
+}
+
+/// Take keys from the terminal, if stdin is one; `quit` is notified on q.
+/// Keys are read until the returned guard is dropped.
+pub fn spawn(control: Arc<Control>, quit: Arc<Notify>) -> Result<Option<Terminal>> {
+    if !std::io::stdin().is_terminal() {
+        return Ok(None);
+    }
+    let terminal = single_keys()?;
+    let (tx, mut keys) = mpsc::unbounded_channel();
+    // A thread, not the runtime's blocking pool: a read that never returns
+    // mustn't hold up shutdown
+    std::thread::spawn(move || {
+        for key in std::io::stdin().lock().bytes() {
+            let Ok(key) = key else { break };
+            if tx.send(key).is_err() {
+                break;
+            }
+        }
+    });
+    info!(target: "keys", "{HELP}");
+    tokio::spawn(async move {
+        while let Some(key) = keys.recv().await {
+            match key {
+                b'm' => match control::toggle_mute(&control) {
+                    Ok(muted) => info!(target: "keys", "{}", if muted { "muted" } else { "unmuted" }),
+                    Err(e) => warn!(target: "keys", "{e:#}"),
+                },
+                b'+' | b'=' => volume(&control, VOLUME_STEP),
+                b'-' => volume(&control, -VOLUME_STEP),
+                b's' => toggle_sending(&control).await,
+                b'd' => control::print_status(&control::status(&control).await),
+                b'q' => {
+                    quit.notify_one();
+                    break;
+                }
+                b'\n' | b'\r' | b' ' => {}
+                _ => info!(target: "keys", "{HELP}"),
+            }
+        }
+    });
+    Ok(Some(terminal))
+}
+
+/// Turn off line buffering and echo, keeping signals and output as they are.
+fn single_keys() -> Result<Terminal> {
+    // SAFETY: termios is plain data, filled in by tcgetattr before it's read
+    let mut saved: libc::termios = unsafe { std::mem::zeroed() };
+    // SAFETY: stdin is a terminal, and `saved` is a valid termios to fill
+    if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut saved) } == -1 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    let mut raw = saved;
+    raw.c_lflag &= !(libc::ICANON | libc::ECHO);
+    raw.c_cc[libc::VMIN] = 1;
+    raw.c_cc[libc::VTIME] = 0;
+    // SAFETY: as above, with settings derived from the terminal's own
+    if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) } == -1 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(Terminal(saved))
+}
+
+fn volume(control: &Control, step: f64) {
+    let rx = control.rx.read().unwrap();
+    let Some(rx) = rx.as_ref() else {
+        warn!(target: "keys", "not receiving (--no-receive)");
+        return;
+    };
+    // Round, so steps land on whole tens
+    let volume = ((rx.volume() + step) * 10.0).round().clamp(0.0, 100.0) / 10.0;
+    match rx.set_volume(volume) {
+        Ok(()) => info!(target: "keys", "volume {:.0}%", volume * 100.0),
+        Err(e) => warn!(target: "keys", "{e:#}"),
+    }
+}
+
+async fn toggle_sending(control: &Control) {
+    let cmd = if control.sending.lock().await.is_some() { "stop-send" } else { "start-send" };
+    let reply = control::answer(&json!({ "cmd": cmd }), control).await;
+    match reply["reason"].as_str() {
+        Some(reason) => warn!(target: "keys", "{cmd}: {reason}"),
+        None if cmd == "start-send" => {
+            info!(target: "keys", "sending to {}", control::strings(&reply["to"]).join(", "))
+        }
+        None => info!(target: "keys", "stopped sending"),
+    }
+}
@@ -0,0 +1,124 @@
+// launchd (macOS): `ab-daemon install-service [-- FLAGS…]` writes a
+// LaunchAgent, ~/Library/LaunchAgents/org.audiobridge.daemon.plist, running
+// the daemon with these flags in the user's session (where the audio
+// devices are) from every login on, restarted if it dies but not when it
+// stops cleanly. It's loaded right away; logs go to
+// ~/Library/Logs/audiobridge.log. PATH and the GST_* variables are kept from
+// the shell it's installed from, so the agent finds the same GStreamer.
+// `uninstall-service` unloads and removes it.
+use anyhow::{Context, Result, ensure};
+use std::path::PathBuf;
+use std::process::Command;
+
+const LABEL: &str = "org.audiobridge.daemon";
+
+fn home() -> Result<PathBuf> {
+    Ok(PathBuf::from(std::env::var_os("HOME").context("HOME is not set")?))
+}
+
+fn plist_path() -> Result<PathBuf> {
+    Ok(home()?.join("Library/LaunchAgents").join(format!("{LABEL}.plist")))
+}
+
+/// The login session's launchd domain.
+fn domain() -> Result<String> {
+    let out = Command::new("id").arg("-u").output().context("run id -u")?;
+    ensure!(out.status.success(), "id -u failed");
+    Ok(format!("gui/{}", String::from_utf8_lossy(&out.stdout).trim()))
+}
+
+/// Write the agent, then (if `load`) load it.
+pub fn install(load: bool, flags: &[String]) -> Result<()> {
+    let exe = std::env::current_exe().context("can't tell where ab-daemon is")?;
+    let log = home()?.join("Library/Logs/audiobridge.log");
+    let args: String = std::iter::once(exe.to_string_lossy().into_owned())
+        .chain(flags.iter().cloned())
+        .map(|a| format!("        <string>{}</string>\n", xml(&a)))
+        .collect();
+    let env: String = std::env::vars()
+        .filter(|(k, _)| k == "PATH" || k.starts_with("GST_"))
+        .map(|(k, v)| format!("        <key>{}</key>\n        <string>{}</string>\n", xml(&k), xml(&v)))
+        .collect();
+    let log = xml(&log.to_string_lossy());
+    let plist = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>{LABEL}</string>
+    <key>ProgramArguments</key>
+    <array>
+{args}    </array>
+    <key>EnvironmentVariables</key>
+    <dict>
+{env}    </dict>
+    <key>RunAtLoad</key>
+    <true/>
+    <key>KeepAlive</key>
+    <dict>
+        <key>SuccessfulExit</key>
+        <false/>
+    </dict>
+    <key>ThrottleInterval</key>
+    <integer>5</integer>
+    <key>ProcessType</key>
+    <string>Interactive</string>
+    <key>StandardOutPath</key>
+    <string>{log}</string>
+    <key>StandardErrorPath</key>
+    <string>{log}</string>
+</dict>
+</plist>
+"#
+    );
+    let path = plist_path()?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).with_context(|| format!("create {}", dir.display()))?;
+    }
+    // A loaded older version would keep running the old flags
+    let domain = domain()?;
+    let loaded = launchctl(&["print", &format!("{domain}/{LABEL}")]).is_ok();
+    if loaded {
+        launchctl(&["bootout", &format!("{domain}/{LABEL}")])?;
+    }
+    std::fs::write(&path, plist).with_context(|| format!("write {}", path.display()))?;
+    println!("Wrote {}", path.display());
+    if !load {
+        println!("Load it with: launchctl bootstrap {domain} {}", path.display());
+        return Ok(());
+    }
+    launchctl(&["bootstrap", &domain, &path.to_string_lossy()])?;
+    println!("Loaded; it starts at every login. Logs: {log}");
+    Ok(())
+}
+
+/// Unload the agent and remove it.
+pub fn uninstall() -> Result<()> {
+    let path = plist_path()?;
+    let domain = domain()?;
+    if launchctl(&["bootout", &format!("{domain}/{LABEL}")]).is_ok() {
+        println!("Unloaded {LABEL}");
+    }
+    match std::fs::remove_file(&path) {
+        Ok(()) => println!("Removed {}", path.display()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => println!("{} isn't installed", path.display()),
+        Err(e) => return Err(e).with_context(|| format!("remove {}", path.display())),
+    }
+    Ok(())
+}
+
+fn launchctl(args: &[&str]) -> Result<()> {
+    let out = Command::new("launchctl").args(args).output().context("run launchctl")?;
+    ensure!(
+        out.status.success(),
+        "launchctl {} failed: {}",
+        args.join(" "),
+        String::from_utf8_lossy(&out.stderr).trim()
+    );
+    Ok(())
+}
+
+fn xml(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
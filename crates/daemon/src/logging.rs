@@ -0,0 +1,61 @@
+// Logging. The daemon and ab-core report through `tracing`, each subsystem
+// under a target of its own (sender, receiver, mdns, control, handshake,
+// config, net…). Lines go to stderr as `[target] message`, with the level
+// after the target unless it's info (`[receiver][warn] …`), or with
+// --log-format json one JSON object per line. --log-level takes a level or
+// filter directives (`info,receiver=debug`) and follows SIGHUP.
+use anyhow::{Context, Result};
+use std::sync::OnceLock;
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::fmt::format::{FormatEvent, FormatFields, Writer};
+use tracing_subscriber::fmt::{self, FmtContext};
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::{EnvFilter, Registry, reload};
+
+use crate::args::{Args, LogFormatArg};
+
+/// Swaps the filter on reload; set once logging is up.
+static FILTER: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+
+/// Start logging as `a` says, or, when it's already running, apply its
+/// --log-level (the format stays until a restart).
+pub fn init(a: &Args) -> Result<()> {
+    let filter = EnvFilter::try_new(&a.log_level).with_context(|| format!("--log-level {:?}", a.log_level))?;
+    if let Some(handle) = FILTER.get() {
+        return handle.reload(filter).context("change the log level");
+    }
+    let (filter, handle) = reload::Layer::new(filter);
+    let registry = tracing_subscriber::registry().with(filter);
+    let stderr = fmt::layer().with_writer(std::io::stderr);
+    match a.log_format {
+        LogFormatArg::Text => registry.with(stderr.event_format(Tagged)).try_init(),
+        LogFormatArg::Json => registry.with(stderr.json().flatten_event(true)).try_init(),
+    }
+    .context("set up logging")?;
+    let _ = FILTER.set(handle);
+    Ok(())
+}
+
+/// The `[target] message` lines the daemon has always printed.
+struct Tagged;
+
+impl<S, N> FormatEvent<S, N> for Tagged
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(&self, ctx: &FmtContext<'_, S, N>, mut w: Writer<'_>, event: &Event<'_>) -> std::fmt::Result {
+        let meta = event.metadata();
+        write!(w, "[{}]", meta.target())?;
+        match *meta.level() {
+            Level::ERROR => write!(w, "[error] ")?,
+            Level::WARN => write!(w, "[warn] ")?,
+            Level::INFO => write!(w, " ")?,
+            Level::DEBUG => write!(w, "[debug] ")?,
+            Level::TRACE => write!(w, "[trace] ")?,
+        }
+        ctx.field_format().format_fields(w.by_ref(), event)?;
+        writeln!(w)
+    }
+}
@@ -0,0 +1,231 @@
+// macOS device changes the CoreAudio elements don't follow: after the Mac
+// sleeps, osxaudiosrc stays silent, and osxaudiosrc/osxaudiosink without a
+// --capture-device / --output-device keep the device that was the default
+// when they opened. A --capture-device given by name ("BlackHole 2ch") is
+// looked up each time the sender is built, as a device gets another index
+// when it's replugged, and so is an --output-device. A thread polls the default input and output devices
+// and the list of devices, and notices sleep as the wall clock jumping ahead
+// of the monotonic one (which stands still while the Mac sleeps); each change
+// rebuilds what it affects: a device plugged in or out rebuilds the sender
+// or receiver if its named device now has another index (or is back).
+use std::ffi::c_void;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+use ab_core::devices;
+use ab_core::pipeline::{Output, Source};
+
+use crate::control::Control;
+use crate::supervise;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// Wall-clock time unaccounted for that means the Mac slept.
+const SLEEP_GAP: Duration = Duration::from_secs(5);
+
+pub type AudioObjectId = u32;
+
+#[repr(C)]
+pub struct PropertyAddress {
+    selector: u32,
+    scope: u32,
+    element: u32,
+}
+
+pub const fn fourcc(c: &[u8; 4]) -> u32 {
+    u32::from_be_bytes(*c)
+}
+
+/// kAudioObjectSystemObject
+pub const SYSTEM_OBJECT: AudioObjectId = 1;
+/// kAudioHardwarePropertyDefaultInputDevice / …OutputDevice
+const DEFAULT_INPUT: u32 = fourcc(b"dIn ");
+pub const DEFAULT_OUTPUT: u32 = fourcc(b"dOut");
+/// kAudioHardwarePropertyDevices
+const DEVICES: u32 = fourcc(b"dev#");
+/// kAudioDevicePropertyDeviceUID
+pub const DEVICE_UID: u32 = fourcc(b"uid ");
+/// More devices (or audio processes) than anyone has.
+const MAX_DEVICES: usize = 256;
+/// kAudioHardwarePropertyRunLoop
+const RUN_LOOP: u32 = fourcc(b"rnlp");
+/// kAudioObjectPropertyScopeGlobal, kAudioObjectPropertyElementMain
+const SCOPE_GLOBAL: u32 = fourcc(b"glob");
+const ELEMENT_MAIN: u32 = 0;
+
+#[link(name = "CoreAudio", kind = "framework")]
+unsafe extern "C" {
+    pub fn AudioObjectGetPropertyData(
+        object: AudioObjectId,
+        address: *const PropertyAddress,
+        qualifier_size: u32,
+        qualifier: *const c_void,
+        data_size: *mut u32,
+        data: *mut c_void,
+    ) -> i32;
+    pub fn AudioObjectSetPropertyData(
+        object: AudioObjectId,
+        address: *const PropertyAddress,
+        qualifier_size: u32,
+        qualifier: *const c_void,
+        data_size: u32,
+        data: *const c_void,
+    ) -> i32;
+    pub fn AudioHardwareCreateAggregateDevice(description: *const c_void, device: *mut AudioObjectId) -> i32;
+    pub fn AudioHardwareDestroyAggregateDevice(device: AudioObjectId) -> i32;
+}
+
+pub fn address(selector: u32) -> PropertyAddress {
+    PropertyAddress { selector, scope: SCOPE_GLOBAL, element: ELEMENT_MAIN }
+}
+
+/// The current default device for `selector`.
+pub fn default_device(selector: u32) -> Option<AudioObjectId> {
+    let mut id: AudioObjectId = 0;
+    let mut size = std::mem::size_of::<AudioObjectId>() as u32;
+    // SAFETY: `id` is a valid AudioObjectID-sized buffer and `size` says so
+    let status = unsafe {
+        AudioObjectGetPropertyData(SYSTEM_OBJECT, &address(selector), 0, std::ptr::null(), &mut size, (&raw mut id).cast())
+    };
+    (status == 0).then_some(id)
+}
+
+/// Every audio device there is, by ID.
+pub fn devices() -> Vec<AudioObjectId> {
+    objects(DEVICES)
+}
+
+/// A list of objects the system object has, like its devices.
+pub fn objects(selector: u32) -> Vec<AudioObjectId> {
+    let mut ids = [0 as AudioObjectId; MAX_DEVICES];
+    let mut size = std::mem::size_of_val(&ids) as u32;
+    // SAFETY: `ids` has room for `size` bytes, and `size` says so
+    let data = ids.as_mut_ptr().cast();
+    let status =
+        unsafe { AudioObjectGetPropertyData(SYSTEM_OBJECT, &address(selector), 0, std::ptr::null(), &mut size, data) };
+    if status != 0 {
+        return Vec::new();
+    }
+    ids[..size as usize / std::mem::size_of::<AudioObjectId>()].to_vec()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    Woke,
+    DefaultInput,
+    DefaultOutput,
+    Devices,
+}
+
+impl Change {
+    fn describe(self) -> &'static str {
+        match self {
+            Change::Woke => "woke from sleep",
+            Change::DefaultInput => "default input device changed",
+            Change::DefaultOutput => "default output device changed",
+            Change::Devices => "audio devices plugged in or out",
+        }
+    }
+}
+
+/// Start watching; changes arrive on the returned channel.
+pub fn watch() -> mpsc::Receiver<Change> {
+    // Without a run loop of ours the HAL only refreshes properties on its own
+    // thread if told to (a NULL CFRunLoopRef)
+    let run_loop: *const c_void = std::ptr::null();
+    // SAFETY: the data is one CFRunLoopRef, as the property expects
+    let status = unsafe {
+        AudioObjectSetPropertyData(
+            SYSTEM_OBJECT,
+            &address(RUN_LOOP),
+            0,
+            std::ptr::null(),
+            std::mem::size_of::<*const c_void>() as u32,
+            (&raw const run_loop).cast(),
+        )
+    };
+    if status != 0 {
+        warn!(target: "macos", "can't detach CoreAudio from the main run loop ({status}); device changes may go unseen");
+    }
+
+    let (tx, rx) = mpsc::channel(8);
+    std::thread::spawn(move || {
+        let (mut input, mut output) = (default_device(DEFAULT_INPUT), default_device(DEFAULT_OUTPUT));
+        let mut present = devices();
+        let (mut mono, mut wall) = (Instant::now(), SystemTime::now());
+        loop {
+            std::thread::sleep(POLL_INTERVAL);
+            let slept = wall.elapsed().unwrap_or_default().saturating_sub(mono.elapsed());
+            (mono, wall) = (Instant::now(), SystemTime::now());
+            let mut changes = Vec::new();
+            if slept >= SLEEP_GAP {
+                changes.push(Change::Woke);
+            }
+            let now = default_device(DEFAULT_INPUT);
+            if std::mem::replace(&mut input, now) != now {
+                changes.push(Change::DefaultInput);
+            }
+            let now = default_device(DEFAULT_OUTPUT);
+            if std::mem::replace(&mut output, now) != now {
+                changes.push(Change::DefaultOutput);
+            }
+            let now = devices();
+            if now != present {
+                present = now;
+                changes.push(Change::Devices);
+            }
+            for change in changes {
+                if tx.blocking_send(change).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+    rx
+}
+
+/// Rebuild whatever `change` leaves silent.
+pub async fn apply(control: &Control, change: Change) {
+    let (receiver, sender) = match change {
+        // A wake covers a device change too
+        Change::Woke => (true, true),
+        Change::DefaultInput => (false, captures_default(control).await),
+        Change::DefaultOutput => (plays_default(control), false),
+        Change::Devices => (output_moved(control), capture_moved(control).await),
+    };
+    info!(target: "macos", "{}", change.describe());
+    supervise::restart(control, receiver, sender, change.describe()).await;
+}
+
+async fn captures_default(control: &Control) -> bool {
+    let sending = control.sending.lock().await;
+    sending.as_ref().is_some_and(|s| s.cfg.source == Source::Capture && s.cfg.capture_device.is_none())
+}
+
+/// Whether a --capture-device given by name now has another index than the
+/// one captured from (or there's no pipeline, maybe for want of it).
+async fn capture_moved(control: &Control) -> bool {
+    let sending = control.sending.lock().await;
+    let Some(s) = sending.as_ref().filter(|s| s.cfg.source == Source::Capture) else { return false };
+    let Some(name) = s.cfg.capture_device.as_deref().filter(|d| d.parse::<i32>().is_err()) else { return false };
+    let Ok(index) = devices::index(name, devices::sources) else { return false };
+    s.with_sender(|tx| tx.device_index()) != Some(Some(index))
+}
+
+/// The same for an --output-device given by name.
+fn output_moved(control: &Control) -> bool {
+    let name = {
+        let cfg = control.rx_cfg.read().unwrap();
+        match &cfg.output_device {
+            Some(d) if cfg.output == Output::Local && d.parse::<i32>().is_err() => d.clone(),
+            _ => return false,
+        }
+    };
+    let Ok(index) = devices::index(&name, devices::sinks) else { return false };
+    control.rx.read().unwrap().as_ref().is_some_and(|rx| rx.device_index() != Some(index))
+}
+
+fn plays_default(control: &Control) -> bool {
+    let cfg = control.rx_cfg.read().unwrap();
+    cfg.output == Output::Local && cfg.output_device.is_none()
+}
@@ -1,31 +1,739 @@
-use anyhow::Result;
-use clap::Parser;
-use ab_core::pipeline::{init_gst, build_receiver, build_sender};
+use anyhow::{Context, Result};
+use ab_core::devices;
+use ab_core::gate::SourceGate;
+use ab_core::session::Sessions;
+use ab_core::srtp::SrtpKeys;
+use ab_core::netclock;
+use ab_core::pcap::Pcap;
+use ab_core::sdp;
+use ab_core::pipeline::{
+    any_addr, init_gst, build_receiver, ReceiverConfig, Transport,
+};
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+mod apps;
 mod args;
+mod caps;
+#[cfg(target_os = "macos")]
+mod cf;
+mod config;
+mod control;
+#[cfg(target_os = "linux")]
+mod dbus;
+#[cfg(target_os = "linux")]
+mod default_sink;
+mod detach;
+mod doctor;
+mod dot;
+mod events;
+mod handshake;
+mod homeassistant;
+mod hotplug;
+mod http;
+mod identity;
+mod keys;
+#[cfg(target_os = "macos")]
+mod launchd;
+mod logging;
+#[cfg(target_os = "macos")]
+mod macos;
 mod mdns;
+#[cfg(target_os = "linux")]
+mod mpris;
+mod metrics;
+mod mqtt;
+mod netwatch;
+mod notify;
+mod otel;
+mod ping;
+#[cfg(target_os = "linux")]
+mod ptt;
+mod reload;
+mod routes;
+mod sending;
+#[cfg(target_os = "macos")]
+mod setup_macos;
+mod stats;
+mod supervise;
+mod systemd;
+#[cfg(target_os = "macos")]
+mod tap;
+mod targets;
+mod top;
+mod trust;
+#[cfg(target_os = "linux")]
+mod vsink;
+
+use std::net::ToSocketAddrs;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+/// How long each pipeline may take to drain on shutdown…
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(2);
+/// …and the whole shutdown.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+fn main() -> Result<()> {
+    let a = config::load_args()?;
+    config::validate(&a)?;
+    // --detach forks, so before the runtime's threads exist
+    detach::apply(&a)?;
+    let runtime = tokio::runtime::Builder::new_multi_thread().enable_all().build()?;
+    let result = runtime.block_on(run(Arc::new(a)));
+    detach::finish();
+    result
+}
+
+async fn run(a: Arc<args::Args>) -> Result<()> {
+    // --log-file: keep it from growing without bound
+    detach::spawn_rotation(a.log_max_mb);
+    let mut send_defaults = config::sender_config(&a)?;
+    let transport = send_defaults.transport;
+    let bind_address = send_defaults.bind_address;
+    let mut sender_cfg =
+        (!a.no_send && (!a.send_to.is_empty() || a.duplex.is_some() || a.aes67 || a.auto_send)).then(|| send_defaults.clone());
+
+    if let Some(args::Command::Sdp) = a.command {
+        let cfg = sender_cfg.context("`sdp` describes the sender: pass --send-to")?;
+        print!("{}", sdp::sender_sdp(&cfg)?);
+        return Ok(());
+    }
+    if let Some(args::Command::Devices { json }) = &a.command {
+        init_gst()?;
+        return devices_cmd(*json);
+    }
+    if let Some(args::Command::Doctor) = &a.command {
+        return doctor::run(&a, &control_path(&a)?).await;
+    }
+    if let Some(args::Command::SetupMacos { multi_output, save }) = &a.command {
+        #[cfg(target_os = "macos")]
+        return setup_macos::run(*multi_output, *save);
+        #[cfg(not(target_os = "macos"))]
+        {
+            let _ = (multi_output, save);
+            anyhow::bail!("setup-macos is for macOS; on Linux, see --virtual-sink");
+        }
+    }
+    if let Some(args::Command::InstallService { user, run_as, socket, no_enable, flags }) = &a.command {
+        #[cfg(target_os = "macos")]
+        {
+            anyhow::ensure!(!*socket, "--socket is for systemd; launchd agents bind the control socket themselves");
+            anyhow::ensure!(run_as.is_none(), "--run-as is for systemd; LaunchAgents run as whoever installs them");
+            let _ = user;
+            return launchd::install(!*no_enable, flags);
+        }
+        #[cfg(not(target_os = "macos"))]
+        return systemd::install(*user, run_as.as_deref(), *socket, !*no_enable, flags);
+    }
+    if let Some(args::Command::UninstallService { user }) = &a.command {
+        #[cfg(target_os = "macos")]
+        {
+            let _ = user;
+            return launchd::uninstall();
+        }
+        #[cfg(not(target_os = "macos"))]
+        return systemd::uninstall(*user);
+    }
+    if let Some(args::Command::Trust { action }) = &a.command {
+        return trust_cmd(action);
+    }
+    if let Some(args::Command::Status { json }) = &a.command {
+        let reply = control::request(&control_path(&a)?, serde_json::json!({ "cmd": "status" }))?;
+        if *json {
+            println!("{reply:#}");
+        } else {
+            control::print_status(&reply);
+        }
+        return Ok(());
+    }
+    if let Some(args::Command::Top { refresh_ms }) = &a.command {
+        return top::run(&control_path(&a)?, Duration::from_millis(*refresh_ms)).await;
+    }
+    if let Some(args::Command::Peers { watch, json }) = &a.command {
+        return peers_cmd(&control_path(&a)?, *watch, *json).await;
+    }
+    if let Some(args::Command::Ctl { action, json }) = &a.command {
+        return ctl_cmd(&control_path(&a)?, action, *json);
+    }
+
+    let identity = Arc::new(identity::Identity::load_or_create()?);
+    let psk = match (&a.psk, &a.psk_file) {
+        (Some(secret), _) => Some(secret.clone()),
+        (None, Some(path)) => {
+            let text = std::fs::read_to_string(path).with_context(|| format!("read {}", path.display()))?;
+            Some(text.lines().next().unwrap_or_default().to_string())
+        }
+        (None, None) => None,
+    };
+    anyhow::ensure!(psk.as_ref().is_none_or(|p| !p.is_empty()), "the pre-shared key is empty");
+    let creds = handshake::Credentials {
+        identity: identity.clone(),
+        name: mdns::local_hostname(),
+        psk: psk.map(|p| Arc::from(p.into_bytes())),
+        tofu: a.tofu,
+    };
+    if let Some(args::Command::Pair { peer }) = &a.command {
+        return pair_with(&a.named_peer(peer), &a, &identity).await;
+    }
+    if let Some(args::Command::PingAudio { peer, count }) = &a.command {
+        let addr = handshake_addr(&a.named_peer(peer), &a).await?;
+        return ping::ping_audio(addr, peer, *count, &a);
+    }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    let a = args::Args::parse();
     init_gst()?;
 
-    // Receiver always on (so the other side can send anytime)
-    let rx = build_receiver(a.listen_port)?;
-    rx.start()?;
+    // --virtual-sink: a sink for apps to play into, there until we exit
+    #[cfg(target_os = "linux")]
+    let _virtual_sink = a.virtual_sink.as_deref().map(|name| vsink::create(name, a.virtual_sink_default)).transpose()?;
+    #[cfg(target_os = "linux")]
+    if a.virtual_sink_default && !a.no_receive && a.output_device.is_none() {
+        warn!(target: "vsink", "what's received plays into the sink too, and is sent on; pin it with --output-device");
+    }
+
+    // Devices plugged in and out, watched from here on (and listed from then)
+    let device_changes = devices::watch()
+        .inspect_err(|e| warn!(target: "devices", "not watching for devices plugged in or out: {e:#}"))
+        .ok();
+
+    // --pcap: one file for everything sent and received, across rebuilds
+    let pcap = a.pcap.as_deref().map(Pcap::create).transpose()?;
+    send_defaults.pcap = pcap.clone();
+    if let Some(cfg) = &mut sender_cfg {
+        cfg.pcap = pcap.clone();
+    }
+
+    // With a PSK, SRTP or --tofu, or once paired with anyone, only authenticated senders are played
+    let trusted = trust::TrustStore::load()?;
+    let enforce = creds.psk.is_some() || a.srtp || a.tofu || (trusted.has_paired() && !a.accept_unpaired);
+    let source_gate = enforce.then(SourceGate::default);
+    if source_gate.is_some() && !a.no_receive {
+        info!(
+            target: "handshake",
+            "playing only authenticated senders ({} trusted{})",
+            trusted.peers.values().filter(|p| a.tofu || p.trust == trust::Trust::Paired).count(),
+            if creds.psk.is_some() { ", or holding the PSK" } else if a.tofu { ", or new on first contact" } else { "" }
+        );
+        // Other transports can't tell senders apart; playing everyone would
+        // let anyone who knows the port in
+        if transport != Transport::Udp {
+            anyhow::bail!(
+                "paired with someone, so only authenticated senders are played, which only --transport udp \
+                 can tell apart (not --transport {}); use udp, or --accept-unpaired to play anyone",
+                transport.name()
+            );
+        }
+    }
+
+    // Receiver on unless --no-receive (so the other side can send anytime)
+    let srtp_keys = a.srtp.then(SrtpKeys::default);
+    let sessions = Sessions::default();
+    let rx_cfg = ReceiverConfig {
+        source_gate: source_gate.clone(),
+        srtp_keys: srtp_keys.clone(),
+        sessions: Some(sessions.clone()),
+        pcap,
+        output_device: config::output_device(&a)?,
+        ..config::receiver_config(&a)?
+    };
+    let listen_port = rx_cfg.listen_port;
+    let pairings = handshake::Pairings::default();
+    let rx = if a.no_receive {
+        info!(target: "receiver", "off (--no-receive)");
+        None
+    } else {
+        let rx = build_receiver(&rx_cfg)?;
+        rx.start()?;
+        rx.set_volume(a.volume)?;
+
+        handshake::serve(
+            bind_address.unwrap_or_else(any_addr),
+            listen_port + handshake::PORT_OFFSET,
+            handshake::Server {
+                creds: creds.clone(),
+                gate: source_gate,
+                pairable: a.pairable,
+                pairings: pairings.clone(),
+                srtp_keys,
+                sessions: sessions.clone(),
+            },
+        )?;
+        // `ping-audio` markers, on the handshake port's UDP side
+        if let Err(e) = ping::serve(bind_address.unwrap_or_else(any_addr), listen_port + handshake::PORT_OFFSET) {
+            warn!(target: "ping", "{e:#}");
+        }
+        Some(rx)
+    };
 
-    // Optional: advertise listen_port for others
-    let _reg = if a.mdns {
-        Some(mdns::advertise_instance("ab-node", a.listen_port)?)
-    } else { None };
+    // mDNS: advertise listen_port for others (if we play anything) and keep a live table of theirs
+    let (mdns, peers) = if a.mdns {
+        let mut m = mdns::Mdns::start()?;
+        if rx.is_some() {
+            m.advertise(&mdns::local_hostname(), a.listen_port, &caps::Caps::of_receiver(&rx_cfg))?;
+        }
+        let peers = m.browse()?;
+        (Some(m), peers)
+    } else {
+        (None, mdns::Peers::default())
+    };
 
-    // Optional sender if send_to provided
-    let _tx = if let Some(host) = a.send_to.as_deref() {
-        let tx = build_sender(a.capture_device.as_deref(), host, a.send_port)?;
-        tx.start()?;
-        Some(tx)
-    } else { None };
+    // Optional sender; peer names in --send-to are resolved through discovery
+    let env = sending::SendEnv {
+        args: a.clone(),
+        peers: peers.clone(),
+        resolver: mdns.as_ref().map(|m| m.resolver()),
+        creds: creds.clone(),
+    };
+    let started = match &sender_cfg {
+        Some(cfg) => Some(sending::Sending::start(cfg.clone(), &env).await?),
+        None => None,
+    };
+
+    // Runtime control (status, start/stop sending, volume, bitrate, peers)
+    let control_path = control_path(&a)?;
+    let ctl = Arc::new(control::Control {
+        env: RwLock::new(env),
+        send_defaults: RwLock::new(send_defaults),
+        sending: tokio::sync::Mutex::new(started),
+        rx: RwLock::new(rx),
+        rx_cfg: RwLock::new(rx_cfg),
+        sessions,
+        mdns: mdns.map(Mutex::new),
+        routes: routes::Routes::default(),
+        watchdog: supervise::Watchdogs::default(),
+        events: events::Bus::default(),
+        pairings,
+    });
+    // A .socket unit may hold the control socket for us
+    let activated = systemd::activated_socket()?;
+    let owns_socket = activated.is_none();
+    let control_task = match activated {
+        Some(listener) => control::serve_activated(listener, ctl.clone())?,
+        None => control::serve(&control_path, ctl.clone())?,
+    };
+    // Rebuild the sender or receiver when its pipeline fails
+    supervise::spawn(ctl.clone());
+    // Roaming, Wi-Fi ↔ Ethernet: re-resolve, handshake again, rebind
+    netwatch::spawn(ctl.clone());
+    // Peers, streams, errors as events; --on-event-url posts them
+    events::spawn(ctl.clone());
+    if let Some(url) = &a.on_event_url {
+        let endpoint = http::Endpoint::parse(url, events::WEBHOOK_PORT).context("--on-event-url")?;
+        events::webhook(&ctl.events, endpoint, mdns::local_hostname());
+    }
+    // --notify: a desktop popup when someone starts or stops playing here
+    if a.notify {
+        notify::spawn(&ctl);
+    }
+    // --mqtt-broker: status, levels and events out, commands in
+    if a.mqtt_broker.is_some() {
+        mqtt::spawn(ctl.clone(), &a, &mdns::local_hostname()).context("--mqtt-broker")?;
+    }
+    // --stats-interval: a record of the link's state, for graphing
+    if let Some(secs) = a.stats_interval {
+        stats::spawn(ctl.clone(), Duration::from_secs(secs), a.stats_format, stats::open(&a)?);
+    }
+    if let Some(port) = a.metrics_port {
+        metrics::serve((bind_address.unwrap_or_else(any_addr), port).into(), ctl.clone()).await?;
+    }
+    // --otlp-endpoint: the session lifecycle as traces, and the metrics
+    let otel = match &a.otlp_endpoint {
+        Some(url) => {
+            let endpoint = http::Endpoint::parse(url, otel::DEFAULT_PORT).context("--otlp-endpoint")?;
+            Some(otel::spawn(ctl.clone(), endpoint, Duration::from_secs(a.otlp_interval)))
+        }
+        None => None,
+    };
+    // --clock net (and a speaker pair): receivers follow the clock we serve
+    let _clock = config::serves_clock(&a)
+        .then(|| netclock::serve(bind_address.unwrap_or_else(any_addr), a.listen_port + netclock::PORT_OFFSET));
+    for spec in config::speaker_pair_routes(&a).iter().chain(&a.route) {
+        routes::add(&ctl, spec.clone()).await?;
+    }
+
+    // --dbus: org.audiobridge.Daemon on the session bus, held until we exit
+    #[cfg(target_os = "linux")]
+    let _dbus = if a.dbus { Some(dbus::serve(ctl.clone()).await?) } else { None };
+    // --mpris: the receiver as a media player
+    #[cfg(target_os = "linux")]
+    let _mpris = if a.mpris { Some(mpris::serve(ctl.clone(), &mdns::local_hostname()).await?) } else { None };
+
+    // Push-to-talk hotkey
+    #[cfg(target_os = "linux")]
+    if let Some(key) = &a.ptt_key {
+        let mut presses = ptt::watch(ptt::key_code(key)?, &a.ptt_device)?;
+        let ctl = ctl.clone();
+        tokio::spawn(async move {
+            while let Some(talking) = presses.recv().await {
+                if let Some(Err(e)) = ctl.sending.lock().await.as_ref().and_then(|s| s.with_sender(|tx| tx.set_talking(talking))) {
+                    warn!(target: "ptt", "{e:#}");
+                }
+            }
+        });
+    }
+
+    if let Some(changes) = device_changes {
+        hotplug::spawn(ctl.clone(), changes);
+    }
+    // --follow-default-sink (macOS follows the default anyway, see macos.rs)
+    #[cfg(target_os = "linux")]
+    default_sink::spawn(ctl.clone());
+
+    // --capture-app: onto the app's stream (Linux) or a tap on it (macOS)
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    apps::spawn(ctl.clone());
+
+    // macOS: follow sleep/wake and default device changes
+    #[cfg(target_os = "macos")]
+    {
+        let mut changes = macos::watch();
+        let ctl = ctl.clone();
+        tokio::spawn(async move {
+            while let Some(change) = changes.recv().await {
+                macos::apply(&ctl, change).await;
+            }
+        });
+    }
+
+    // Under systemd: up and running, and alive while the pipelines are
+    systemd::notify("READY=1");
+    // --detach: the command that started us can return
+    detach::started();
+    // On a terminal, single keys drive the daemon; q quits
+    let quit = Arc::new(tokio::sync::Notify::new());
+    let keys = if a.no_keys { None } else { keys::spawn(ctl.clone(), quit.clone())? };
+    systemd::spawn_watchdog(ctl.clone());
+
+    // Keep running until Ctrl-C, SIGTERM or q; SIGHUP re-reads the config file,
+    // SIGUSR1 logs a report of everything, SIGUSR2 mutes or unmutes playback
+    use tokio::signal::unix::{SignalKind, signal};
+    let mut hangup = signal(SignalKind::hangup())?;
+    let mut terminate = signal(SignalKind::terminate())?;
+    let mut user1 = signal(SignalKind::user_defined1())?;
+    let mut user2 = signal(SignalKind::user_defined2())?;
+    loop {
+        tokio::select! {
+            r = tokio::signal::ctrl_c() => break r?,
+            _ = terminate.recv() => break,
+            _ = quit.notified() => break,
+            _ = hangup.recv() => {
+                info!(target: "config", "SIGHUP: reloading");
+                systemd::notify("RELOADING=1");
+                if let Err(e) = reload::reload(&ctl).await {
+                    warn!(target: "config", "reload failed, nothing changed: {e:#}");
+                }
+                systemd::notify("READY=1");
+            }
+            _ = user1.recv() => info!(target: "report", "{}", stats::report(&ctl).await),
+            _ = user2.recv() => match control::toggle_mute(&ctl) {
+                Ok(muted) => info!(target: "receiver", "SIGUSR2: {}", if muted { "muted" } else { "unmuted" }),
+                Err(e) => warn!(target: "receiver", "SIGUSR2: {e:#}"),
+            },
+        }
+    }
+
+    // The terminal as it was, even if a second Ctrl-C cuts the rest short
+    drop(keys);
+
+    // Shut down in bounded time: no more commands, drain and stop the
+    // pipelines, leave mDNS. A second Ctrl-C skips the rest.
+    info!(target: "daemon", "shutting down…");
+    systemd::notify("STOPPING=1");
+    tokio::spawn(async {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            info!(target: "daemon", "interrupted again: exiting now");
+            std::process::exit(130);
+        }
+    });
+    control_task.abort();
+    if owns_socket {
+        let _ = std::fs::remove_file(&control_path);
+    }
+    let deadline = Instant::now() + SHUTDOWN_TIMEOUT;
+    let left = || deadline.saturating_duration_since(Instant::now()).min(DRAIN_TIMEOUT);
+    let sending = ctl.sending.lock().await.take();
+    if let Some(sending) = sending {
+        sending.shutdown(left());
+    }
+    routes::stop_all(&ctl, left()).await;
+    let rx = ctl.rx.write().unwrap().take();
+    if let Some(rx) = rx {
+        rx.shutdown(left());
+    }
+    if let Some(m) = &ctl.mdns {
+        m.lock().unwrap().shutdown();
+    }
+    if let Some(otel) = otel {
+        otel.finish(deadline.saturating_duration_since(Instant::now())).await;
+    }
+    info!(target: "daemon", "stopped");
+    Ok(())
+}
+
+/// `ab-daemon peers`: the running daemon's peer table, else a browse of our own.
+async fn peers_cmd(path: &std::path::Path, watch: bool, json: bool) -> Result<()> {
+    use serde_json::json;
+    let print = |v: &serde_json::Value| match v["type"].as_str() {
+        _ if json => println!("{v}"),
+        Some("peer-up") => {
+            print!("+ ");
+            control::print_peer(&v["peer"]);
+        }
+        Some("peer-gone") => println!("- {}", v["name"].as_str().unwrap_or("?")),
+        _ => print_peers(&v["peers"]),
+    };
+    if std::os::unix::net::UnixStream::connect(path).is_ok() {
+        if watch {
+            return control::stream(path, json!({ "cmd": "watch-peers" }), print);
+        }
+        print(&control::request(path, json!({ "cmd": "list-peers" }))?);
+        return Ok(());
+    }
+
+    info!(target: "peers", "no daemon running; browsing mDNS for a few seconds");
+    let m = mdns::Mdns::start()?;
+    let peers = m.browse()?;
+    let mut events = peers.subscribe();
+    tokio::time::sleep(std::time::Duration::from_secs(3)).await;
+    let list: Vec<_> = peers.snapshot().iter().map(control::peer_json).collect();
+    print(&json!({ "type": "ok", "peers": list }));
+    if watch {
+        // Already listed; only report what changes from here
+        while events.try_recv().is_ok() {}
+        while let Some(event) = events.recv().await {
+            print(&match event {
+                mdns::PeerEvent::Up(p) => json!({ "type": "peer-up", "peer": control::peer_json(&p) }),
+                mdns::PeerEvent::Gone(name) => json!({ "type": "peer-gone", "name": name }),
+            });
+        }
+    }
+    Ok(())
+}
+
+fn print_peers(peers: &serde_json::Value) {
+    let peers = peers.as_array().map(Vec::as_slice).unwrap_or_default();
+    if peers.is_empty() {
+        println!("No peers discovered.");
+    }
+    for p in peers {
+        control::print_peer(p);
+    }
+}
+
+/// `ab-daemon pair <peer>`: find the peer, then run the PIN exchange.
+async fn pair_with(peer: &str, a: &args::Args, identity: &identity::Identity) -> Result<()> {
+    let addr = handshake_addr(peer, a).await?;
+    let name = handshake::pair(addr, identity, &mdns::local_hostname())?;
+    println!("Paired with {name}; each side now accepts the other's streams.");
+    Ok(())
+}
+
+/// Handshake port of `peer` (an mDNS name, host or address).
+async fn handshake_addr(peer: &str, a: &args::Args) -> Result<std::net::SocketAddr> {
+    let (host, port) = if a.mdns && targets::is_peer_name(peer) {
+        let m = mdns::Mdns::start()?;
+        let peers = m.browse()?;
+        let t = targets::Targets::new(vec![peer.to_string()], peers.clone(), Some(m.resolver()));
+        let host = t.resolve_initial().await.remove(0);
+        // Its advertised listen port, else assume it listens on our --send-port
+        let port = peers
+            .get(peer)
+            .or_else(|| peers.by_hostname(peer))
+            .map_or(a.send_port, |p| p.port);
+        (host, port)
+    } else {
+        (peer.to_string(), a.send_port)
+    };
+    (host.as_str(), port + handshake::PORT_OFFSET)
+        .to_socket_addrs()?
+        .next()
+        .with_context(|| format!("{host} did not resolve"))
+}
+
+fn control_path(a: &args::Args) -> Result<std::path::PathBuf> {
+    match &a.control_socket {
+        Some(path) => Ok(path.clone()),
+        None => control::default_path(),
+    }
+}
+
+/// `ab-daemon ctl …`: one command to the running daemon.
+fn ctl_cmd(path: &std::path::Path, action: &args::CtlAction, json: bool) -> Result<()> {
+    use serde_json::json;
+    let req = match action {
+        args::CtlAction::StartSend { to, port } | args::CtlAction::SwitchSend { to, port } => {
+            let (to, port) = split_port(to, *port);
+            let cmd = if let args::CtlAction::StartSend { .. } = action { "start-send" } else { "switch-send" };
+            let mut req = json!({ "cmd": cmd });
+            if !to.is_empty() {
+                req["to"] = json!(to);
+            }
+            if let Some(port) = port {
+                req["port"] = json!(port);
+            }
+            req
+        }
+        args::CtlAction::StopSend => json!({ "cmd": "stop-send" }),
+        args::CtlAction::SetVolume { volume, zone, receiver } => {
+            let mut req = json!({ "cmd": "set-volume", "volume": volume });
+            if let Some(zone) = zone {
+                req["zone"] = json!(zone);
+            }
+            if let Some(receiver) = receiver {
+                req["receiver"] = json!(receiver);
+            }
+            req
+        }
+        args::CtlAction::SetBitrate { bitrate } => json!({ "cmd": "set-bitrate", "bitrate": bitrate }),
+        args::CtlAction::ListPeers => json!({ "cmd": "list-peers" }),
+        args::CtlAction::PairConfirm { pin } => json!({ "cmd": "pair-confirm", "pin": pin }),
+        args::CtlAction::Ptt { talk } => json!({ "cmd": "ptt", "talk": talk }),
+        args::CtlAction::Mute { mute } => json!({ "cmd": "set-mute", "mute": mute }),
+        args::CtlAction::RouteAdd { route } => json!({ "cmd": "route-add", "route": route }),
+        args::CtlAction::RouteRemove { name } => json!({ "cmd": "route-remove", "name": name }),
+        args::CtlAction::Routes => json!({ "cmd": "list-routes" }),
+        args::CtlAction::Zones => json!({ "cmd": "list-zones" }),
+        args::CtlAction::Xruns => json!({ "cmd": "xruns" }),
+        args::CtlAction::DumpDot { dir } => {
+            let mut req = json!({ "cmd": "dump-dot" });
+            if let Some(dir) = dir {
+                // The daemon may run somewhere else
+                req["dir"] = json!(std::path::absolute(dir)?);
+            }
+            req
+        }
+        args::CtlAction::Levels { seconds } => {
+            let mut req = json!({ "cmd": "levels" });
+            if let Some(seconds) = seconds {
+                req["seconds"] = json!(seconds);
+            }
+            req
+        }
+    };
+    let reply = control::request(path, req)?;
+    if json {
+        println!("{reply:#}");
+    } else if let args::CtlAction::ListPeers = action {
+        print_peers(&reply["peers"]);
+    } else if let args::CtlAction::PairConfirm { .. } = action {
+        println!("Confirmed; pairing with {}", reply["name"].as_str().unwrap_or("?"));
+    } else if let args::CtlAction::Routes = action {
+        let routes = reply["routes"].as_array().map(Vec::as_slice).unwrap_or_default();
+        if routes.is_empty() {
+            println!("No routes.");
+        }
+        routes.iter().for_each(control::print_route);
+    } else if let args::CtlAction::Zones = action {
+        let zones = reply["zones"].as_array().map(Vec::as_slice).unwrap_or_default();
+        if zones.is_empty() {
+            println!("No zones (add [groups] to the config file).");
+        }
+        zones.iter().for_each(control::print_zone);
+    } else if let args::CtlAction::SetVolume { zone: Some(_), .. } | args::CtlAction::SetVolume { receiver: Some(_), .. } =
+        action
+    {
+        println!("Volume {} on {}", reply["volume"], control::strings(&reply["receivers"]).join(", "));
+    } else if let args::CtlAction::Levels { .. } = action {
+        control::print_levels(&reply);
+    } else if let args::CtlAction::Xruns = action {
+        control::print_xruns(&reply);
+    } else if let args::CtlAction::DumpDot { .. } = action {
+        for file in control::strings(&reply["files"]) {
+            println!("Wrote {file}");
+        }
+    } else if let args::CtlAction::RouteAdd { .. } = action {
+        control::print_route(&reply);
+    } else if let args::CtlAction::RouteRemove { .. } = action {
+        println!("Removed {}", reply["route"].as_str().unwrap_or("?"));
+    } else if let args::CtlAction::StartSend { .. } = action {
+        println!(
+            "Sending to {} [{}] :{} ({})",
+            control::strings(&reply["to"]).join(", "),
+            control::strings(&reply["hosts"]).join(", "),
+            reply["port"],
+            reply["transport"].as_str().unwrap_or("?")
+        );
+    } else if let args::CtlAction::SwitchSend { .. } = action {
+        println!(
+            "Now sending to {} [{}] :{}{}",
+            control::strings(&reply["to"]).join(", "),
+            control::strings(&reply["hosts"]).join(", "),
+            reply["port"],
+            if reply["seamless"].as_bool() == Some(true) { "" } else { " (sender restarted)" }
+        );
+    } else if let args::CtlAction::StopSend = action {
+        println!("Stopped sending to {}", control::strings(&reply["to"]).join(", "));
+    } else {
+        println!("ok");
+    }
+    Ok(())
+}
+
+/// `<host>… [port]`: a trailing number is the port unless `--port` was given.
+fn split_port(to: &[String], port: Option<u16>) -> (&[String], Option<u16>) {
+    match to.split_last() {
+        Some((last, hosts)) if !hosts.is_empty() && port.is_none() => match last.parse::<u16>() {
+            Ok(p) => (hosts, Some(p)),
+            Err(_) => (to, None),
+        },
+        _ => (to, port),
+    }
+}
+
+/// `ab-daemon devices [--json]`.
+fn devices_cmd(json: bool) -> Result<()> {
+    let (sources, sinks) = (devices::sources()?, devices::sinks()?);
+    if json {
+        let list = |ds: &[devices::AudioDevice]| {
+            ds.iter()
+                .map(|d| serde_json::json!({ "id": d.id, "name": d.name, "monitor": d.monitor }))
+                .collect::<Vec<_>>()
+        };
+        println!("{:#}", serde_json::json!({ "sources": list(&sources), "sinks": list(&sinks) }));
+        return Ok(());
+    }
+    println!("Capture sources (--capture-device):");
+    for d in &sources {
+        // On Linux the monitors are what carries system audio
+        let tag = if d.monitor { "  [monitor]" } else { "" };
+        println!("  {:<56} {}{tag}", d.id, d.name);
+    }
+    println!("Playback sinks (--output-device):");
+    for d in &sinks {
+        println!("  {:<56} {}", d.id, d.name);
+    }
+    if sources.is_empty() && sinks.is_empty() {
+        println!("  (none found; is the audio server running?)");
+    }
+    Ok(())
+}
 
-    // Keep running
-    tokio::signal::ctrl_c().await?;
+/// `ab-daemon trust list|remove`.
+fn trust_cmd(action: &args::TrustAction) -> Result<()> {
+    let _lock = trust::lock();
+    let mut store = trust::TrustStore::load()?;
+    match action {
+        args::TrustAction::List => {
+            if store.is_empty() {
+                println!("No trusted peers ({}).", trust::TrustStore::path()?.display());
+            }
+            let now = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0);
+            for (name, peer) in &store.peers {
+                let trust = match peer.trust {
+                    trust::Trust::Paired => "paired",
+                    trust::Trust::Seen => "seen",
+                };
+                let days = now.saturating_sub(peer.added) / 86_400;
+                println!("{name:<24} {}  {trust:<6}  added {days}d ago", peer.fingerprint());
+            }
+        }
+        args::TrustAction::Remove { name } => {
+            anyhow::ensure!(store.remove(name), "no trusted peer named '{name}'");
+            store.save()?;
+            println!("Removed {name}; pair again (or let --tofu pin it on next contact).");
+        }
+    }
     Ok(())
 }
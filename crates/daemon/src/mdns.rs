@@ -1,39 +1,251 @@
-// use zeroconf::{MdnsService, ServiceRegistration, ServiceType, TxtRecord};
-// use zeroconf::prelude::*; // brings the trait methods (new, set_txt_record, register) into scope
-// use anyhow::Result;
-// // use std::net::Ipv4Addr;
-
-// pub fn advertise_instance(instance_name: &str, port: u16) -> Result<ServiceRegistration> {
-//     // name + protocol (e.g., "_http","_tcp"). We publish "_audiobridge" over UDP.
-//     let ty = ServiceType::new("_audiobridge", "_udp")?;
-//     // Minimal constructor: (service_type, port)
-//     let mut service = MdnsService::new(ty, port);
-//     service.set_name(instance_name);
-
-//     let mut txt = TxtRecord::new();
-//     txt.insert("codec", "opus")?;
-//     txt.insert("clock", "48000")?;
-//     service.set_txt_record(txt);
-
-//     // Register and return the handle to keep it alive.
-//     let reg = service.register()?;
-//     Ok(reg)
-// }
-
-// // Discovery example (blocking, minimal)
-// pub fn discover_once() -> Result<()> {
-//     let ty = ServiceType::new("_audiobridge._udp")?;
-//     let discovery = ServiceDiscovery::browse(ty)?;
-//     for event in discovery {
-//         // Handle events (add/remove); wire to your UI or logs
-//         println!("mDNS event: {:?}", event);
-//     }
-//     Ok(())
-// }
-use anyhow::Result;
-
-// v1 stub: we’ll add real mDNS advertise/discover in v1.1
-pub fn advertise_instance(_instance_name: &str, _port: u16) -> Result<()> {
-    // No-op; keep a matching signature so main.rs doesn't change much.
-    Ok(())
-}
\ No newline at end of file
+use anyhow::{Context, Result};
+use mdns_sd::{HostnameResolutionEvent, ServiceDaemon, ServiceEvent, ServiceInfo};
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex, RwLock};
+use tokio::sync::mpsc;
+use std::time::{Duration, Instant};
+use tracing::info;
+
+use crate::caps::Caps;
+
+/// DNS-SD service type every node registers under.
+pub const SERVICE_TYPE: &str = "_audiobridge._udp.local.";
+
+/// Another node seen on the LAN.
+#[derive(Debug, Clone)]
+pub struct Peer {
+    /// Instance name (the peer's hostname unless configured otherwise).
+    pub name: String,
+    /// `host.local.` the addresses belong to.
+    pub hostname: String,
+    pub addrs: Vec<IpAddr>,
+    /// The peer's listen port.
+    pub port: u16,
+    pub txt: BTreeMap<String, String>,
+    pub last_seen: Instant,
+}
+
+impl Peer {
+    /// Address to send to; see `preferred_addr`.
+    pub fn addr(&self) -> Option<IpAddr> {
+        preferred_addr(self.addrs.iter().copied())
+    }
+
+    /// What its receiver plays, if it says.
+    pub fn caps(&self) -> Option<Caps> {
+        Caps::from_txt(&self.txt)
+    }
+}
+
+/// IPv4 first, then a routable IPv6 address (link-local ones would need a scope id).
+pub fn preferred_addr(addrs: impl IntoIterator<Item = IpAddr>) -> Option<IpAddr> {
+    let addrs: Vec<_> = addrs.into_iter().collect();
+    addrs.iter().find(|a| a.is_ipv4()).copied().or_else(|| {
+        addrs
+            .iter()
+            .find(|a| matches!(a, IpAddr::V6(v6) if !v6.is_unicast_link_local()))
+            .copied()
+    })
+}
+
+/// A peer appearing on or leaving the LAN.
+#[derive(Debug, Clone)]
+pub enum PeerEvent {
+    Up(Peer),
+    Gone(String),
+}
+
+/// Live table of discovered peers, keyed by instance name. Cheap to clone.
+#[derive(Clone, Default)]
+pub struct Peers {
+    table: Arc<RwLock<HashMap<String, Peer>>>,
+    watchers: Arc<Mutex<Vec<mpsc::UnboundedSender<PeerEvent>>>>,
+}
+
+impl Peers {
+    /// All current peers, sorted by name.
+    pub fn snapshot(&self) -> Vec<Peer> {
+        let mut v: Vec<_> = self.table.read().unwrap().values().cloned().collect();
+        v.sort_by(|a, b| a.name.cmp(&b.name));
+        v
+    }
+
+    /// Peer by instance name (case-insensitive).
+    pub fn get(&self, name: &str) -> Option<Peer> {
+        let table = self.table.read().unwrap();
+        table
+            .values()
+            .find(|p| p.name.eq_ignore_ascii_case(name))
+            .cloned()
+    }
+
+    /// Peer whose `host.local` is `hostname` (trailing dot and case ignored).
+    pub fn by_hostname(&self, hostname: &str) -> Option<Peer> {
+        let want = hostname.trim_end_matches('.');
+        let table = self.table.read().unwrap();
+        table
+            .values()
+            .find(|p| p.hostname.trim_end_matches('.').eq_ignore_ascii_case(want))
+            .cloned()
+    }
+
+    /// Peers coming and going from now on.
+    pub fn subscribe(&self) -> mpsc::UnboundedReceiver<PeerEvent> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        self.watchers.lock().unwrap().push(tx);
+        rx
+    }
+
+    fn notify(&self, event: PeerEvent) {
+        self.watchers.lock().unwrap().retain(|w| w.send(event.clone()).is_ok());
+    }
+}
+
+/// Resolves `.local` hostnames through the shared responder. Cheap to clone.
+#[derive(Clone)]
+pub struct HostResolver(ServiceDaemon);
+
+impl HostResolver {
+    /// Address `hostname` answers with within `timeout` (blocking).
+    pub fn resolve(&self, hostname: &str, timeout: Duration) -> Option<IpAddr> {
+        let fqdn = format!("{}.", hostname.trim_end_matches('.'));
+        let events = self.0.resolve_hostname(&fqdn, Some(timeout.as_millis() as u64)).ok()?;
+        let mut found = None;
+        while let Ok(event) = events.recv() {
+            match event {
+                HostnameResolutionEvent::AddressesFound(_, addrs) => {
+                    found = preferred_addr(addrs);
+                    if found.is_some() {
+                        break;
+                    }
+                }
+                HostnameResolutionEvent::SearchTimeout(_) | HostnameResolutionEvent::SearchStopped(_) => break,
+                _ => {}
+            }
+        }
+        let _ = self.0.stop_resolve_hostname(&fqdn);
+        found
+    }
+}
+
+/// The mDNS responder: our registration plus the browse loop feeding `Peers`.
+/// Dropping it withdraws the registration (goodbye packets).
+pub struct Mdns {
+    daemon: ServiceDaemon,
+    registered: Option<String>,
+    peers: Peers,
+}
+
+impl Drop for Mdns {
+    fn drop(&mut self) {
+        if let Some(fullname) = &self.registered {
+            let _ = self.daemon.unregister(fullname);
+        }
+        let _ = self.daemon.shutdown();
+    }
+}
+
+/// This machine's short hostname, used as the instance name and in TXT.
+pub fn local_hostname() -> String {
+    hostname::get()
+        .ok()
+        .and_then(|h| h.into_string().ok())
+        .map(|h| h.split('.').next().unwrap_or(&h).to_string())
+        .unwrap_or_else(|| "ab-node".into())
+}
+
+impl Mdns {
+    pub fn start() -> Result<Self> {
+        let daemon = ServiceDaemon::new().context("mDNS: start responder")?;
+        Ok(Self { daemon, registered: None, peers: Peers::default() })
+    }
+
+    pub fn resolver(&self) -> HostResolver {
+        HostResolver(self.daemon.clone())
+    }
+
+    /// Advertise `instance_name` on `port` with what our receiver plays. Addresses
+    /// (A and AAAA) follow the machine's interfaces automatically, so IPv6-only
+    /// peers can resolve us too.
+    pub fn advertise(&mut self, instance_name: &str, port: u16, caps: &Caps) -> Result<()> {
+        let host = local_hostname();
+        let mut txt = caps.to_txt();
+        txt.push(("version", env!("CARGO_PKG_VERSION").to_string()));
+        txt.push(("hostname", host.clone()));
+        let info = ServiceInfo::new(SERVICE_TYPE, instance_name, &format!("{host}.local."), "", port, &txt[..])
+            .context("mDNS: build service info")?
+            .enable_addr_auto();
+        let fullname = info.get_fullname().to_string();
+        self.daemon.register(info).context("mDNS: register")?;
+        info!(target: "mdns", "advertising {fullname} on port {port}");
+        self.registered = Some(fullname);
+        Ok(())
+    }
+
+    /// Withdraw our registration, with goodbye packets so peers drop us now
+    /// rather than when the record expires, and stop the responder.
+    pub fn shutdown(&mut self) {
+        if let Some(fullname) = self.registered.take() {
+            if let Ok(done) = self.daemon.unregister(&fullname) {
+                // The goodbyes must be out before the responder stops
+                let _ = done.recv_timeout(Duration::from_secs(1));
+            }
+            info!(target: "mdns", "withdrew {fullname}");
+        }
+        let _ = self.daemon.shutdown();
+    }
+
+    /// Keep browsing `_audiobridge._udp` in the background; returns the live table.
+    pub fn browse(&self) -> Result<Peers> {
+        let events = self.daemon.browse(SERVICE_TYPE).context("mDNS: browse")?;
+        let peers = self.peers.clone();
+        let own = self.registered.clone();
+        std::thread::Builder::new().name("mdns-browse".into()).spawn(move || {
+            while let Ok(event) = events.recv() {
+                match event {
+                    ServiceEvent::ServiceResolved(info) => {
+                        if Some(info.get_fullname()) == own.as_deref() {
+                            continue;
+                        }
+                        let peer = Peer {
+                            name: instance_name(info.get_fullname()),
+                            hostname: info.get_hostname().to_string(),
+                            addrs: info.get_addresses().iter().copied().collect(),
+                            port: info.get_port(),
+                            txt: info
+                                .get_properties()
+                                .iter()
+                                .map(|p| (p.key().to_string(), p.val_str().to_string()))
+                                .collect(),
+                            last_seen: Instant::now(),
+                        };
+                        let new = peers.table.write().unwrap().insert(peer.name.clone(), peer.clone()).is_none();
+                        if new {
+                            info!(target: "mdns", "peer up: {} {:?}:{}", peer.name, peer.addrs, peer.port);
+                            peers.notify(PeerEvent::Up(peer));
+                        }
+                    }
+                    ServiceEvent::ServiceRemoved(_, fullname) => {
+                        let name = instance_name(&fullname);
+                        if peers.table.write().unwrap().remove(&name).is_some() {
+                            info!(target: "mdns", "peer gone: {name}");
+                            peers.notify(PeerEvent::Gone(name));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        })?;
+        Ok(self.peers.clone())
+    }
+}
+
+/// "studio-mac._audiobridge._udp.local." → "studio-mac"
+fn instance_name(fullname: &str) -> String {
+    fullname
+        .strip_suffix(SERVICE_TYPE)
+        .map(|s| s.trim_end_matches('.'))
+        .unwrap_or(fullname)
+        .to_string()
+}
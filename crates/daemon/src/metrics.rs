@@ -0,0 +1,231 @@
+// Prometheus metrics (--metrics-port): `GET /metrics` answers with the
+// main sender's and receiver's counters and gauges in the text exposition
+// format, read from the pipelines at scrape time. Per-stream series carry an
+// `ssrc` label, levels a `channel` label. Everything else is a 404.
+use anyhow::{Context, Result};
+use std::fmt::Write as _;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{info, warn};
+
+use ab_core::glitch::Glitches;
+use ab_core::pipeline::{Level, RtcpReport};
+use ab_core::rtpstats::RtpStats;
+
+use crate::control::Control;
+
+/// Request headers read (and ignored) before answering.
+const MAX_HEADERS: usize = 100;
+
+/// Serve `/metrics` on `addr` in the background.
+pub async fn serve(addr: SocketAddr, control: Arc<Control>) -> Result<()> {
+    let listener = TcpListener::bind(addr).await.with_context(|| format!("metrics: bind {addr}"))?;
+    info!(target: "metrics", "serving Prometheus metrics on http://{addr}/metrics");
+    tokio::spawn(async move {
+        loop {
+            let Ok((stream, _)) = listener.accept().await else { continue };
+            let control = control.clone();
+            tokio::spawn(async move {
+                if let Err(e) = respond(stream, &control).await {
+                    warn!(target: "metrics", "{e:#}");
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+async fn respond(stream: TcpStream, control: &Control) -> Result<()> {
+    let (read, mut write) = stream.into_split();
+    let mut lines = BufReader::new(read).lines();
+    let request = lines.next_line().await?.unwrap_or_default();
+    // Read up to the blank line, so closing doesn't reset the connection
+    for _ in 0..MAX_HEADERS {
+        match lines.next_line().await? {
+            Some(line) if !line.is_empty() => {}
+            _ => break,
+        }
+    }
+    let (status, body) = match request.split_whitespace().nth(1) {
+        Some("/metrics") => ("200 OK", collect(control).await.text()),
+        _ => ("404 Not Found", String::new()),
+    };
+    let head = format!(
+        "HTTP/1.0 {status}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\n\r\n",
+        body.len()
+    );
+    write.write_all(head.as_bytes()).await?;
+    write.write_all(body.as_bytes()).await?;
+    Ok(())
+}
+
+/// A sample's labels, e.g. `[("ssrc", "1a2b3c4d")]`.
+pub type Labels = Vec<(&'static str, String)>;
+
+/// One metric family: a "gauge" or "counter" and its samples.
+pub struct Family {
+    pub name: String,
+    pub kind: &'static str,
+    pub help: &'static str,
+    pub samples: Vec<(Labels, f64)>,
+}
+
+/// The metrics as read at one moment; also what --otlp-endpoint exports.
+#[derive(Default)]
+pub struct Exposition(pub Vec<Family>);
+
+impl Exposition {
+    fn family(&mut self, name: &str, kind: &'static str, help: &'static str, samples: &[(Labels, f64)]) {
+        self.0.push(Family { name: name.to_string(), kind, help, samples: samples.to_vec() });
+    }
+
+    fn one(&mut self, name: &str, kind: &'static str, help: &'static str, value: Option<f64>) {
+        let samples: Vec<_> = value.map(|v| (Labels::new(), v)).into_iter().collect();
+        self.family(name, kind, help, &samples);
+    }
+
+    /// In the text format.
+    fn text(&self) -> String {
+        let mut out = String::new();
+        for Family { name, kind, help, samples } in &self.0 {
+            let _ = writeln!(out, "# HELP {name} {help}\n# TYPE {name} {kind}");
+            for (labels, value) in samples {
+                let labels: Vec<_> = labels.iter().map(|(k, v)| format!("{k}=\"{v}\"")).collect();
+                let labels = if labels.is_empty() { String::new() } else { format!("{{{}}}", labels.join(",")) };
+                let _ = writeln!(out, "{name}{labels} {}", number(*value));
+            }
+        }
+        out
+    }
+}
+
+fn number(v: f64) -> String {
+    match v {
+        v if v.is_nan() => "NaN".into(),
+        v if v.is_infinite() => if v > 0.0 { "+Inf" } else { "-Inf" }.into(),
+        v => v.to_string(),
+    }
+}
+
+fn flag(b: bool) -> f64 {
+    if b { 1.0 } else { 0.0 }
+}
+
+fn ssrc(ssrc: u32) -> Labels {
+    vec![("ssrc", format!("{ssrc:08x}"))]
+}
+
+/// `{ssrc="…"}` for a report, with `f` of it.
+fn per_stream(reports: &[RtcpReport], f: impl Fn(&RtcpReport) -> Option<f64>) -> Vec<(Labels, f64)> {
+    reports.iter().filter_map(|r| Some((ssrc(r.ssrc), f(r)?))).collect()
+}
+
+/// `{ssrc="…"}` per stream the receiver counted, with `f` of it.
+fn per_rtp_stream(stats: &[RtpStats], f: impl Fn(&RtpStats) -> Option<f64>) -> Vec<(Labels, f64)> {
+    stats.iter().filter_map(|s| Some((ssrc(s.ssrc), f(s)?))).collect()
+}
+
+/// `{channel="…"}` per channel of a level reading.
+fn per_channel(values: Option<&[f64]>) -> Vec<(Labels, f64)> {
+    values
+        .unwrap_or_default()
+        .iter()
+        .enumerate()
+        .map(|(i, v)| (vec![("channel", i.to_string())], *v))
+        .collect()
+}
+
+fn levels(out: &mut Exposition, side: &str, level: Option<Level>) {
+    let rms = level.as_ref().map(|l| l.rms_db.as_slice());
+    let peak = level.as_ref().map(|l| l.peak_db.as_slice());
+    out.family(&format!("ab_{side}_level_rms_dbfs"), "gauge", "Latest RMS level per channel", &per_channel(rms));
+    out.family(&format!("ab_{side}_level_peak_dbfs"), "gauge", "Latest peak level per channel", &per_channel(peak));
+}
+
+/// Read the main sender's and receiver's metrics.
+pub async fn collect(control: &Control) -> Exposition {
+    let mut out = Exposition::default();
+
+    let sending = control.sending.lock().await;
+    let tx = sending.as_ref().and_then(|s| {
+        s.with_sender(|tx| {
+            let (sent, xruns) = (tx.sent(), tx.xrun_counts());
+            (tx.playing(), tx.error().is_some(), sent, xruns, tx.bitrate(), tx.queued_ms(), tx.level(), tx.reports())
+        })
+    });
+    drop(sending);
+    out.one("ab_sender_running", "gauge", "Whether a sender is running", Some(flag(tx.is_some())));
+    if let Some((playing, failed, sent, xruns, bitrate, queued_ms, level, reports)) = tx {
+        out.one("ab_sender_playing", "gauge", "Whether the sender's pipeline is in PLAYING", Some(flag(playing)));
+        out.one("ab_sender_failed", "gauge", "Whether the sender's pipeline has posted an error", Some(flag(failed)));
+        out.one("ab_sender_packets_total", "counter", "Packets sent", Some(sent.packets as f64));
+        out.one("ab_sender_bytes_total", "counter", "Bytes sent", Some(sent.bytes as f64));
+        out.one("ab_sender_bitrate_bps", "gauge", "Opus encoder bitrate", bitrate.map(f64::from));
+        out.one("ab_sender_queued_seconds", "gauge", "Captured audio waiting to be encoded", queued_ms.map(|ms| ms / 1e3));
+        out.one("ab_sender_capture_overruns_total", "counter", "Times capture skipped samples", Some(xruns.capture_overruns as f64));
+        out.one("ab_sender_queue_overruns_total", "counter", "Times the capture queue filled up", Some(xruns.overruns as f64));
+        levels(&mut out, "sender", level);
+        let help = "As reported by each receiver";
+        out.family("ab_sender_remote_packets_lost", "gauge", help, &per_stream(&reports, |r| Some(r.packets_lost as f64)));
+        out.family("ab_sender_remote_loss_ratio", "gauge", help, &per_stream(&reports, |r| Some(r.fraction_lost_pct / 100.0)));
+        out.family("ab_sender_remote_jitter_seconds", "gauge", help, &per_stream(&reports, |r| Some(r.jitter_ms / 1e3)));
+        out.family("ab_sender_rtt_seconds", "gauge", "Round trip to each receiver", &per_stream(&reports, |r| Some(r.rtt_ms? / 1e3)));
+    }
+
+    let rx = control.rx.read().unwrap();
+    out.one("ab_receiver_running", "gauge", "Whether the receiver is running", Some(flag(rx.is_some())));
+    if let Some(rx) = rx.as_ref() {
+        out.one("ab_receiver_playing", "gauge", "Whether the receiver's pipeline is in PLAYING", Some(flag(rx.playing())));
+        out.one("ab_receiver_failed", "gauge", "Whether the receiver's pipeline has posted an error", Some(flag(rx.error().is_some())));
+        out.one("ab_receiver_idle", "gauge", "Whether the audio device is released for lack of packets", Some(flag(rx.idle())));
+        let jitter_ms = rx.jitter_ms().map(|ms| f64::from(ms) / 1e3);
+        out.one("ab_receiver_jitterbuffer_seconds", "gauge", "Jitterbuffer latency", jitter_ms);
+        out.one("ab_receiver_latency_seconds", "gauge", "Pipeline latency", rx.latency().map(|l| l.as_secs_f64()));
+        out.one("ab_receiver_queued_seconds", "gauge", "Decoded audio waiting for the sink", rx.queued_ms().map(|ms| ms / 1e3));
+        let xruns = rx.xrun_counts();
+        out.one("ab_receiver_underruns_total", "counter", "Times the sink got audio too late", Some(xruns.underruns as f64));
+        out.one("ab_receiver_queue_overruns_total", "counter", "Times a receive queue filled up", Some(xruns.overruns as f64));
+        levels(&mut out, "receiver", rx.level());
+        let reports = rx.reports();
+        let packets = per_stream(&reports, |r| Some(r.packets_received as f64));
+        out.family("ab_receiver_packets_total", "counter", "Packets received per sender", &packets);
+        let lost = per_stream(&reports, |r| Some(r.packets_lost.max(0) as f64));
+        out.family("ab_receiver_packets_lost_total", "counter", "Packets lost per sender", &lost);
+        let loss = per_stream(&reports, |r| Some(r.fraction_lost_pct / 100.0));
+        out.family("ab_receiver_loss_ratio", "gauge", "Recent loss per sender", &loss);
+        let jitter = per_stream(&reports, |r| Some(r.jitter_ms / 1e3));
+        out.family("ab_receiver_jitter_seconds", "gauge", "Interarrival jitter per sender", &jitter);
+        let rtp = rx.rtp_stats();
+        let gaps = per_rtp_stream(&rtp, |s| Some(s.gaps as f64));
+        out.family("ab_receiver_sequence_gaps_total", "counter", "Runs of missing sequence numbers per sender", &gaps);
+        let reordered = per_rtp_stream(&rtp, |s| Some(s.reordered as f64));
+        out.family("ab_receiver_reordered_total", "counter", "Packets arriving after a later one per sender", &reordered);
+        let duplicates = per_rtp_stream(&rtp, |s| Some(s.duplicates as f64));
+        out.family("ab_receiver_duplicates_total", "counter", "Packets arriving twice per sender", &duplicates);
+        let late = per_rtp_stream(&rtp, |s| Some(s.late as f64));
+        out.family("ab_receiver_late_total", "counter", "Packets too late to play per sender", &late);
+        let recent = per_rtp_stream(&rtp, |s| Some(s.window_loss_pct? / 100.0));
+        out.family("ab_receiver_recent_loss_ratio", "gauge", "Loss over the last 10 s per sender", &recent);
+        let glitches = rx.glitches();
+        let per_glitch_stream = |f: fn(&Glitches) -> u64| -> Vec<(Labels, f64)> {
+            glitches.iter().map(|g| (ssrc(g.ssrc), f(g) as f64)).collect()
+        };
+        let clicks = per_glitch_stream(|g| g.clicks);
+        out.family("ab_receiver_clicks_total", "counter", "Clicks in the decoded audio per sender", &clicks);
+        let dropouts = per_glitch_stream(|g| g.dropouts);
+        out.family("ab_receiver_dropouts_total", "counter", "Dropouts in the decoded audio per sender", &dropouts);
+        let states: Vec<_> = rx
+            .stream_states()
+            .iter()
+            .map(|(s, state)| {
+                let mut labels = ssrc(*s);
+                labels.push(("state", state.name().to_string()));
+                (labels, 1.0)
+            })
+            .collect();
+        out.family("ab_receiver_stream_state", "gauge", "Each sender's stream state", &states);
+    }
+    out
+}
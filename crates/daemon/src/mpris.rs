@@ -0,0 +1,264 @@
+// MPRIS (Linux, --mpris): what the receiver plays as a media player on the
+// session bus (org.mpris.MediaPlayer2.audiobridge), so desktop volume applets
+// and media keys work on the bridge. Play and Pause unmute and mute playback
+// (Stop mutes too), Volume is the playback volume, and the metadata names who
+// is sending: the trusted name of the stream's sender, else its address. The
+// status is Stopped while no stream comes in, Paused while muted and Playing
+// otherwise; changes are looked for once a second and signalled. There's
+// nothing to skip, seek or raise.
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, info};
+use zbus::zvariant::{ObjectPath, OwnedValue, Value};
+use zbus::{fdo, interface};
+
+use ab_core::liveness::StreamState;
+use ab_core::pipeline::Receiver;
+
+use crate::control::Control;
+use crate::events::sender_of;
+
+const NAME: &str = "org.mpris.MediaPlayer2.audiobridge";
+const PATH: &str = "/org/mpris/MediaPlayer2";
+const NO_TRACK: &str = "/org/mpris/MediaPlayer2/TrackList/NoTrack";
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The root interface: who we are, and that there's no window to raise.
+struct Root {
+    identity: String,
+}
+
+#[interface(name = "org.mpris.MediaPlayer2")]
+impl Root {
+    fn raise(&self) {}
+
+    fn quit(&self) {}
+
+    #[zbus(property)]
+    fn can_quit(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn can_raise(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn has_track_list(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn identity(&self) -> String {
+        self.identity.clone()
+    }
+
+    #[zbus(property)]
+    fn supported_uri_schemes(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    #[zbus(property)]
+    fn supported_mime_types(&self) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+/// What the player shows, as last looked at.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct State {
+    /// The stream played: an active one over a silent one.
+    ssrc: Option<u32>,
+    muted: bool,
+    volume: f64,
+}
+
+impl State {
+    fn status(self) -> &'static str {
+        match (self.ssrc, self.muted) {
+            (None, _) => "Stopped",
+            (Some(_), true) => "Paused",
+            (Some(_), false) => "Playing",
+        }
+    }
+}
+
+struct Player {
+    control: Arc<Control>,
+}
+
+impl Player {
+    fn state(&self) -> State {
+        let rx = self.control.rx.read().unwrap();
+        let Some(rx) = rx.as_ref() else { return State::default() };
+        let mut heard: Vec<_> = rx.stream_states().into_iter().filter(|(_, s)| *s != StreamState::Gone).collect();
+        heard.sort_by_key(|(_, s)| *s != StreamState::Active);
+        State { ssrc: heard.first().map(|(ssrc, _)| *ssrc), muted: rx.muted(), volume: rx.volume() }
+    }
+
+    /// Do `f` to the receiver, failing as D-Bus does.
+    fn receiver<T>(&self, f: impl FnOnce(&Receiver) -> Result<T>) -> fdo::Result<T> {
+        let rx = self.control.rx.read().unwrap();
+        let rx = rx.as_ref().ok_or_else(|| fdo::Error::Failed("not receiving".into()))?;
+        f(rx).map_err(|e| fdo::Error::Failed(format!("{e:#}")))
+    }
+}
+
+#[interface(name = "org.mpris.MediaPlayer2.Player")]
+impl Player {
+    fn play(&self) -> fdo::Result<()> {
+        self.receiver(|rx| rx.set_muted(false))
+    }
+
+    fn pause(&self) -> fdo::Result<()> {
+        self.receiver(|rx| rx.set_muted(true))
+    }
+
+    fn play_pause(&self) -> fdo::Result<()> {
+        self.receiver(|rx| rx.set_muted(!rx.muted()))
+    }
+
+    fn stop(&self) -> fdo::Result<()> {
+        self.receiver(|rx| rx.set_muted(true))
+    }
+
+    fn next(&self) {}
+
+    fn previous(&self) {}
+
+    fn seek(&self, _offset: i64) {}
+
+    fn set_position(&self, _track: ObjectPath<'_>, _position: i64) {}
+
+    fn open_uri(&self, _uri: &str) {}
+
+    #[zbus(property)]
+    fn playback_status(&self) -> String {
+        self.state().status().to_string()
+    }
+
+    #[zbus(property)]
+    fn metadata(&self) -> HashMap<String, OwnedValue> {
+        let Some(ssrc) = self.state().ssrc else {
+            return entries(vec![("mpris:trackid", ObjectPath::from_static_str_unchecked(NO_TRACK).into())]);
+        };
+        let (address, peer) = sender_of(&self.control, ssrc);
+        let who = peer.or(address).unwrap_or_else(|| format!("stream {ssrc:08x}"));
+        let track = ObjectPath::try_from(format!("/org/audiobridge/stream/{ssrc:08x}"))
+            .unwrap_or_else(|_| ObjectPath::from_static_str_unchecked(NO_TRACK));
+        entries(vec![
+            ("mpris:trackid", track.into()),
+            ("xesam:title", who.clone().into()),
+            ("xesam:artist", vec![who].into()),
+            ("xesam:album", "Audio Bridge".into()),
+        ])
+    }
+
+    #[zbus(property)]
+    fn volume(&self) -> f64 {
+        self.state().volume
+    }
+
+    #[zbus(property)]
+    fn set_volume(&self, volume: f64) -> fdo::Result<()> {
+        self.receiver(|rx| rx.set_volume(volume.clamp(0.0, 10.0)))
+    }
+
+    #[zbus(property)]
+    fn rate(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn minimum_rate(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn maximum_rate(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn position(&self) -> i64 {
+        0
+    }
+
+    #[zbus(property)]
+    fn can_go_next(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn can_go_previous(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn can_play(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_pause(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_seek(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn can_control(&self) -> bool {
+        true
+    }
+}
+
+fn entries(entries: Vec<(&str, Value<'static>)>) -> HashMap<String, OwnedValue> {
+    entries.into_iter().filter_map(|(key, value)| Some((key.to_string(), OwnedValue::try_from(value).ok()?))).collect()
+}
+
+/// Take the player's name on the session bus, as `node`, until the returned
+/// connection is dropped.
+pub async fn serve(control: Arc<Control>, node: &str) -> Result<zbus::Connection> {
+    let conn = zbus::connection::Builder::session()?
+        .name(NAME)?
+        .serve_at(PATH, Root { identity: format!("Audio Bridge ({node})") })?
+        .serve_at(PATH, Player { control })?
+        .build()
+        .await
+        .with_context(|| format!("MPRIS: can't take {NAME} on the session bus"))?;
+    let player = conn.object_server().interface::<_, Player>(PATH).await?;
+    info!(target: "mpris", "playing as {NAME}");
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(CHECK_INTERVAL);
+        let mut was = State::default();
+        loop {
+            tick.tick().await;
+            let p = player.get().await;
+            let now = p.state();
+            let emitter = player.signal_emitter();
+            let sent = async {
+                if now.ssrc != was.ssrc {
+                    p.metadata_changed(emitter).await?;
+                }
+                if now.status() != was.status() {
+                    p.playback_status_changed(emitter).await?;
+                }
+                if now.volume != was.volume {
+                    p.volume_changed(emitter).await?;
+                }
+                zbus::Result::Ok(())
+            };
+            if let Err(e) = sent.await {
+                debug!(target: "mpris", "signal: {e}");
+            }
+            was = now;
+        }
+    });
+    Ok(conn)
+}
@@ -0,0 +1,418 @@
+// MQTT (--mqtt-broker): the daemon's state on a broker and commands from it,
+// so Home Assistant (or any MQTT client) can show and drive it without a
+// bridge of its own. Under the prefix (--mqtt-topic, default
+// `audio-bridge/<hostname>`):
+//   <prefix>/availability  "online", or "offline" once we're gone (retained;
+//                          it's our will, so the broker publishes it if we die)
+//   <prefix>/status        what {"cmd":"status"} answers, every --mqtt-interval
+//                          and after each command (retained)
+//   <prefix>/levels        the latest RMS/peak of what's sent and played, once
+//                          a second
+//   <prefix>/event         each event (see `events.rs`)
+//   <prefix>/command       read by us: a control socket command, e.g.
+//                            {"cmd":"set-volume","volume":0.5}
+//                            {"cmd":"set-mute","mute":true}
+//                            {"cmd":"start-send","to":["kitchen"]}, {"cmd":"stop-send"}
+//   <prefix>/reply         …and its reply, with the command's "id" if it had one
+// With --ha-discovery, Home Assistant is told about them too (see
+// `homeassistant.rs`).
+// Only the commands in `COMMANDS` are taken, as anyone who can publish on the
+// broker can send them, and start-send/switch-send only to destinations this
+// daemon already knows (zones, [peers], the trust store, mDNS): the captured
+// audio mustn't go wherever a publisher says. MQTT 3.1.1 at QoS 0 is all this
+// needs, and small enough to speak here; a lost broker is retried with a growing delay, and what
+// happens meanwhile isn't published.
+use anyhow::{Context, Result, bail, ensure};
+use serde_json::{Value, json};
+use std::convert::Infallible;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::sync::mpsc;
+use tokio::time::{Instant, interval, timeout};
+use tracing::{debug, info, warn};
+
+use ab_core::pipeline::{Receiver, Sender};
+
+use crate::args::Args;
+use crate::control::{self, Control, level_json};
+use crate::events::Event;
+use crate::homeassistant;
+use crate::trust::TrustStore;
+
+/// The broker's port when --mqtt-broker names none.
+const DEFAULT_PORT: u16 = 1883;
+const KEEPALIVE: Duration = Duration::from_secs(30);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const LEVELS_INTERVAL: Duration = Duration::from_secs(1);
+const RETRY_MIN: Duration = Duration::from_secs(2);
+const RETRY_MAX: Duration = Duration::from_secs(60);
+/// Larger packets from the broker end the connection; commands are small.
+const MAX_PACKET: usize = 64 * 1024;
+/// What <prefix>/command takes.
+const COMMANDS: &[&str] =
+    &["status", "start-send", "switch-send", "stop-send", "set-volume", "set-mute", "set-bitrate", "ptt"];
+/// Those of `COMMANDS` whose "to" must name known destinations.
+const SENDS: &[&str] = &["start-send", "switch-send"];
+
+// Packet types, in the fixed header's high nibble
+const CONNECT: u8 = 0x10;
+const CONNACK: u8 = 0x20;
+const PUBLISH: u8 = 0x30;
+/// With the flags SUBSCRIBE requires.
+const SUBSCRIBE: u8 = 0x82;
+const SUBACK: u8 = 0x90;
+const PINGREQ: u8 = 0xc0;
+
+/// Where to connect, as whom, and under which topic.
+struct Broker {
+    host: String,
+    port: u16,
+    /// Our name.
+    node: String,
+    prefix: String,
+    /// Home Assistant's discovery prefix, with --ha-discovery.
+    discovery: Option<String>,
+    client_id: String,
+    username: Option<String>,
+    password: Option<String>,
+    interval: Duration,
+}
+
+impl Broker {
+    fn from_args(a: &Args, node: &str) -> Result<Self> {
+        let broker = a.mqtt_broker.as_deref().context("no --mqtt-broker")?;
+        let authority = broker.strip_prefix("mqtt://").unwrap_or(broker).trim_end_matches('/');
+        let (host, port) = match authority.strip_prefix('[').and_then(|a| a.split_once(']')) {
+            Some((host, port)) => (host, port.strip_prefix(':')),
+            None => match authority.rsplit_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (authority, None),
+            },
+        };
+        ensure!(!host.is_empty(), "no host in '{broker}'");
+        let port = port.map_or(Ok(DEFAULT_PORT), str::parse).with_context(|| format!("bad port in '{broker}'"))?;
+        let prefix = match &a.mqtt_topic {
+            Some(topic) => topic.trim_matches('/').to_string(),
+            None => format!("audio-bridge/{node}"),
+        };
+        ensure!(!prefix.is_empty() && !prefix.contains(['+', '#']), "bad --mqtt-topic '{prefix}'");
+        ensure!(a.mqtt_password.is_none() || a.mqtt_username.is_some(), "--mqtt-password needs --mqtt-username");
+        Ok(Self {
+            host: host.to_string(),
+            port,
+            node: node.to_string(),
+            prefix,
+            discovery: a.ha_discovery.then(|| a.ha_discovery_prefix.trim_matches('/').to_string()),
+            client_id: format!("ab-daemon-{node}"),
+            username: a.mqtt_username.clone(),
+            password: a.mqtt_password.clone(),
+            interval: Duration::from_secs(a.mqtt_interval),
+        })
+    }
+
+    fn topic(&self, leaf: &str) -> String {
+        format!("{}/{leaf}", self.prefix)
+    }
+
+    /// CONNECT, with "offline" on <prefix>/availability as our will.
+    fn connect_packet(&self) -> Vec<u8> {
+        // Clean session; a retained will at QoS 0
+        let mut flags = 0x02 | 0x04 | 0x20;
+        if self.username.is_some() {
+            flags |= 0x80;
+        }
+        if self.password.is_some() {
+            flags |= 0x40;
+        }
+        let mut body = Vec::new();
+        string(&mut body, b"MQTT");
+        // Protocol level 4: 3.1.1
+        body.extend([4, flags]);
+        body.extend((KEEPALIVE.as_secs() as u16).to_be_bytes());
+        string(&mut body, self.client_id.as_bytes());
+        string(&mut body, self.topic("availability").as_bytes());
+        string(&mut body, b"offline");
+        for s in [&self.username, &self.password].into_iter().flatten() {
+            string(&mut body, s.as_bytes());
+        }
+        packet(CONNECT, &body)
+    }
+}
+
+impl std::fmt::Display for Broker {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.host.contains(':') {
+            write!(f, "mqtt://[{}]:{}", self.host, self.port)
+        } else {
+            write!(f, "mqtt://{}:{}", self.host, self.port)
+        }
+    }
+}
+
+/// Stay connected to --mqtt-broker for as long as the daemon runs; `node` is
+/// our name, for the default prefix and the client ID.
+pub fn spawn(control: Arc<Control>, a: &Args, node: &str) -> Result<()> {
+    let broker = Broker::from_args(a, node)?;
+    info!(target: "mqtt", "publishing to {broker} under {}/", broker.prefix);
+    let mut events = control.events.subscribe();
+    tokio::spawn(async move {
+        let mut retry = RETRY_MIN;
+        loop {
+            match connect(&broker).await {
+                Ok((read, write)) => {
+                    info!(target: "mqtt", "connected to {broker}");
+                    retry = RETRY_MIN;
+                    // Events from while we were away are stale now
+                    while events.try_recv().is_ok() {}
+                    let Err(e) = run(&control, &broker, read, write, &mut events).await;
+                    warn!(target: "mqtt", "lost {broker}, reconnecting: {e:#}");
+                }
+                // Once per outage
+                Err(e) if retry == RETRY_MIN => warn!(target: "mqtt", "can't connect to {broker}, retrying: {e:#}"),
+                Err(e) => debug!(target: "mqtt", "can't connect to {broker}: {e:#}"),
+            }
+            tokio::time::sleep(retry).await;
+            retry = (retry * 2).min(RETRY_MAX);
+        }
+    });
+    Ok(())
+}
+
+/// Connect and log in.
+async fn connect(broker: &Broker) -> Result<(BufReader<OwnedReadHalf>, OwnedWriteHalf)> {
+    let login = async {
+        let stream = TcpStream::connect((broker.host.as_str(), broker.port)).await?;
+        stream.set_nodelay(true)?;
+        let (read, mut write) = stream.into_split();
+        let mut read = BufReader::new(read);
+        write.write_all(&broker.connect_packet()).await?;
+        let (kind, body) = read_packet(&mut read).await?;
+        ensure!(kind & 0xf0 == CONNACK && body.len() == 2, "expected CONNACK");
+        let why = match body[1] {
+            0 => return Ok((read, write)),
+            1 => "unacceptable protocol version",
+            2 => "client ID rejected",
+            3 => "server unavailable",
+            4 => "bad user name or password",
+            5 => "not authorized",
+            _ => "unknown reason",
+        };
+        bail!("refused: {why}")
+    };
+    timeout(CONNECT_TIMEOUT, login).await.context("timed out")?
+}
+
+/// Publish, and take commands, until the connection fails.
+async fn run(
+    control: &Control,
+    broker: &Broker,
+    mut read: BufReader<OwnedReadHalf>,
+    mut write: OwnedWriteHalf,
+    events: &mut mpsc::UnboundedReceiver<Event>,
+) -> Result<Infallible> {
+    // Reading a packet can't be cancelled halfway, so it gets a task of its own
+    let (tx, mut incoming) = mpsc::unbounded_channel();
+    let reader = tokio::spawn(async move {
+        loop {
+            let packet = read_packet(&mut read).await;
+            let failed = packet.is_err();
+            if tx.send(packet).is_err() || failed {
+                break;
+            }
+        }
+    });
+    let result = exchange(control, broker, &mut write, &mut incoming, events).await;
+    reader.abort();
+    result
+}
+
+async fn exchange(
+    control: &Control,
+    broker: &Broker,
+    write: &mut OwnedWriteHalf,
+    incoming: &mut mpsc::UnboundedReceiver<Result<(u8, Vec<u8>)>>,
+    events: &mut mpsc::UnboundedReceiver<Event>,
+) -> Result<Infallible> {
+    let command_topic = broker.topic("command");
+    // Home Assistant's own availability: "online" when it (re)starts
+    let ha_status = broker.discovery.as_ref().map(|d| format!("{d}/status"));
+    let mut subscribe = 1u16.to_be_bytes().to_vec();
+    for topic in std::iter::once(&command_topic).chain(&ha_status) {
+        string(&mut subscribe, topic.as_bytes());
+        subscribe.push(0);
+    }
+    write.write_all(&packet(SUBSCRIBE, &subscribe)).await?;
+    write.write_all(&publish(&broker.topic("availability"), b"online", true)).await?;
+    for p in discovery(control, broker) {
+        write.write_all(&p).await?;
+    }
+    let mut status_tick = interval(broker.interval);
+    let mut levels_tick = interval(LEVELS_INTERVAL);
+    let mut ping_tick = interval(KEEPALIVE / 2);
+    let mut heard = Instant::now();
+    loop {
+        let out = tokio::select! {
+            packet = incoming.recv() => {
+                let (kind, body) = packet.context("connection closed")??;
+                heard = Instant::now();
+                match kind & 0xf0 {
+                    PUBLISH => {
+                        let (topic, payload) = parse_publish(kind, &body)?;
+                        if topic == command_topic {
+                            let reply = take_command(control, payload).await.to_string();
+                            let reply = publish(&broker.topic("reply"), reply.as_bytes(), false);
+                            vec![reply, status(control, broker).await]
+                        } else if ha_status.as_deref() == Some(topic) && payload == b"online" {
+                            discovery(control, broker)
+                        } else {
+                            continue;
+                        }
+                    }
+                    SUBACK => {
+                        let refused = body.get(2..).unwrap_or_default().contains(&0x80);
+                        ensure!(!refused, "the broker refused a subscription");
+                        continue;
+                    }
+                    // PINGRESP
+                    _ => continue,
+                }
+            }
+            _ = status_tick.tick() => vec![status(control, broker).await],
+            _ = levels_tick.tick() => {
+                vec![publish(&broker.topic("levels"), current_levels(control).await.to_string().as_bytes(), false)]
+            }
+            _ = ping_tick.tick() => {
+                ensure!(heard.elapsed() < KEEPALIVE, "no answer in {} s", KEEPALIVE.as_secs());
+                vec![vec![PINGREQ, 0]]
+            }
+            Some(event) = events.recv() => {
+                vec![publish(&broker.topic("event"), event.json().to_string().as_bytes(), false)]
+            }
+        };
+        for p in out {
+            write.write_all(&p).await?;
+        }
+    }
+}
+
+/// Run a command from <prefix>/command, if it's one of `COMMANDS`.
+async fn take_command(control: &Control, payload: &[u8]) -> Value {
+    let req: Value = match serde_json::from_slice(payload) {
+        Ok(req) => req,
+        Err(e) => return json!({ "type": "error", "reason": format!("bad request: {e}") }),
+    };
+    let mut reply = match req["cmd"].as_str() {
+        Some(cmd) if !COMMANDS.contains(&cmd) => {
+            json!({ "type": "error", "reason": format!("'{cmd}' isn't taken over MQTT") })
+        }
+        _ => match unknown_destination(control, &req) {
+            Some(dest) => json!({ "type": "error", "reason": format!("'{dest}' isn't a zone or a known peer") }),
+            None => control::answer(&req, control).await,
+        },
+    };
+    if !req["id"].is_null() {
+        reply["id"] = req["id"].clone();
+    }
+    reply
+}
+
+/// For `SENDS`, the first of the request's "to" that isn't a zone, a [peers]
+/// name, a trusted peer or one found over mDNS. No "to" means --send-to.
+fn unknown_destination(control: &Control, req: &Value) -> Option<String> {
+    if !SENDS.contains(&req["cmd"].as_str()?) {
+        return None;
+    }
+    let dests = req["to"].as_array()?;
+    let env = control.env.read().unwrap();
+    let trusted = TrustStore::load().unwrap_or_default();
+    dests.iter().filter_map(Value::as_str).find_map(|dest| {
+        let known = env.args.groups.contains_key(dest)
+            || env.args.peer_names.contains_key(dest)
+            || trusted.peers.contains_key(dest)
+            || env.peers.get(dest).is_some();
+        (!known).then(|| dest.to_string())
+    })
+}
+
+/// Home Assistant's discovery configs (retained), with --ha-discovery.
+fn discovery(control: &Control, broker: &Broker) -> Vec<Vec<u8>> {
+    let Some(prefix) = &broker.discovery else { return Vec::new() };
+    let receiving = control.rx.read().unwrap().is_some();
+    let sending = !control.env.read().unwrap().args.no_send;
+    homeassistant::configs(prefix, &broker.prefix, &broker.node, receiving, sending)
+        .into_iter()
+        .map(|(topic, config)| publish(&topic, config.to_string().as_bytes(), true))
+        .collect()
+}
+
+/// The retained status.
+async fn status(control: &Control, broker: &Broker) -> Vec<u8> {
+    publish(&broker.topic("status"), control::status(control).await.to_string().as_bytes(), true)
+}
+
+/// The latest level of what's sent and played.
+async fn current_levels(control: &Control) -> Value {
+    let sending = control.sending.lock().await.as_ref().and_then(|s| s.with_sender(Sender::level)).flatten();
+    let receiving = control.rx.read().unwrap().as_ref().and_then(Receiver::level);
+    json!({ "sending": sending.map(level_json), "receiving": receiving.map(level_json) })
+}
+
+/// A length-prefixed string.
+fn string(out: &mut Vec<u8>, s: &[u8]) {
+    out.extend((s.len() as u16).to_be_bytes());
+    out.extend(s);
+}
+
+/// `body` behind a fixed header: `kind`, and the length in 7-bit groups.
+fn packet(kind: u8, body: &[u8]) -> Vec<u8> {
+    let mut out = vec![kind];
+    let mut len = body.len();
+    loop {
+        let byte = (len % 128) as u8;
+        len /= 128;
+        out.push(if len > 0 { byte | 0x80 } else { byte });
+        if len == 0 {
+            break;
+        }
+    }
+    out.extend(body);
+    out
+}
+
+/// A QoS 0 PUBLISH.
+fn publish(topic: &str, payload: &[u8], retain: bool) -> Vec<u8> {
+    let mut body = Vec::with_capacity(2 + topic.len() + payload.len());
+    string(&mut body, topic.as_bytes());
+    body.extend(payload);
+    packet(PUBLISH | u8::from(retain), &body)
+}
+
+/// A PUBLISH's topic and payload.
+fn parse_publish(kind: u8, body: &[u8]) -> Result<(&str, &[u8])> {
+    let [a, b, ref rest @ ..] = *body else { bail!("short PUBLISH") };
+    let topic = rest.get(..usize::from(u16::from_be_bytes([a, b]))).context("short PUBLISH")?;
+    // QoS 1 and 2 carry a packet ID; we subscribe at 0, so none needs an answer
+    let id = if kind & 0x06 != 0 { 2 } else { 0 };
+    let payload = rest.get(topic.len() + id..).context("short PUBLISH")?;
+    Ok((std::str::from_utf8(topic)?, payload))
+}
+
+/// One packet: its first byte and its body.
+async fn read_packet(read: &mut (impl AsyncRead + Unpin)) -> Result<(u8, Vec<u8>)> {
+    let kind = read.read_u8().await?;
+    let mut len = 0;
+    for shift in (0..28).step_by(7) {
+        let byte = read.read_u8().await?;
+        len |= usize::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            ensure!(len <= MAX_PACKET, "{len}-byte packet");
+            let mut body = vec![0; len];
+            read.read_exact(&mut body).await?;
+            return Ok((kind, body));
+        }
+    }
+    bail!("bad packet length")
+}
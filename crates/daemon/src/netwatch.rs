@@ -0,0 +1,114 @@
+// Network changes: roaming between access points, Wi-Fi to Ethernet, a new
+// DHCP lease. The interface list is polled (the same view `--interface`
+// uses, on Linux and macOS alike); once a change has settled the sender
+// re-resolves its destinations and handshakes again from the new address
+// (UDP moves in place, other transports reconnect), and the receiver is
+// rebuilt if it's tied to an address: `--interface` or a multicast join.
+use anyhow::{Context, Result};
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+
+use ab_core::pipeline::Transport;
+
+use crate::config;
+use crate::control::Control;
+use crate::events::Event;
+use crate::sending::Sending;
+use crate::supervise;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// What counts as the network: interfaces that are up with an address, and
+/// their IPv4 addresses. IPv6 addresses only count as presence, since
+/// temporary ones rotate on their own.
+fn snapshot() -> Result<BTreeSet<String>> {
+    let ifaces = if_addrs::get_if_addrs().context("list network interfaces")?;
+    Ok(ifaces
+        .into_iter()
+        .filter(|i| !i.is_loopback())
+        .map(|i| match i.ip() {
+            std::net::IpAddr::V4(ip) => format!("{} {ip}", i.name),
+            std::net::IpAddr::V6(_) => i.name,
+        })
+        .collect())
+}
+
+/// Follow network changes for as long as the daemon runs.
+pub fn spawn(control: Arc<Control>) {
+    tokio::spawn(async move {
+        let Ok(mut current) = snapshot() else { return };
+        let mut last = current.clone();
+        loop {
+            tokio::time::sleep(POLL_INTERVAL).await;
+            let Ok(now) = snapshot() else { continue };
+            // Act once it has held still for a poll; DHCP and roaming come in steps
+            let settled = now == last;
+            last = now.clone();
+            if !settled || now == current {
+                continue;
+            }
+            let gone: Vec<_> = current.difference(&now).cloned().collect();
+            let new: Vec<_> = now.difference(&current).cloned().collect();
+            info!(target: "net", "network changed (gone: {}; new: {})", list(&gone), list(&new));
+            current = now;
+            if let Err(e) = follow(&control).await {
+                warn!(target: "net", "{e:#}");
+            }
+        }
+    });
+}
+
+fn list(items: &[String]) -> String {
+    if items.is_empty() { "-".into() } else { items.join(", ") }
+}
+
+async fn follow(control: &Control) -> Result<()> {
+    let args = control.env.read().unwrap().args.clone();
+    // --interface: bind to its address now, if it still has one
+    let moved = match &args.interface {
+        Some(_) => {
+            let bind = config::bind_address(&args)?;
+            let moved = bind != control.rx_cfg.read().unwrap().bind_address;
+            control.send_defaults.write().unwrap().bind_address = bind;
+            control.rx_cfg.write().unwrap().bind_address = bind;
+            if moved {
+                warn!(target: "net", "the handshake listener stays on the old address; restart to move it");
+            }
+            moved
+        }
+        None => false,
+    };
+
+    let multicast = control.rx_cfg.read().unwrap().multicast_group.is_some();
+    if moved || multicast {
+        supervise::restart(control, true, false, "network changed").await;
+    }
+
+    let mut sending = control.sending.lock().await;
+    let Some(current) = sending.as_mut() else { return Ok(()) };
+    let env = control.env.read().unwrap().clone();
+    let (targets, port) = (current.targets.clone(), current.cfg.port);
+    // UDP just needs the destinations re-resolved and new handshakes
+    if !moved && current.cfg.transport == Transport::Udp {
+        match current.retarget(targets.clone(), port, &env).await {
+            Ok(()) => {
+                info!(target: "net", "sending to {} again", current.hosts().join(", "));
+                control.events.emit(Event::Reconnected { side: "sender", why: "network changed" });
+                return Ok(());
+            }
+            Err(e) => info!(target: "net", "can't move the sender in place ({e:#}); restarting it"),
+        }
+    }
+    let mut cfg = control.send_defaults.read().unwrap().clone();
+    cfg.hosts = targets;
+    cfg.port = port;
+    // Keep the old sender (and the watchdog on it) if there's no way out yet
+    let fresh = Sending::start(cfg, &env).await.context("restart the sender")?;
+    if let Some(old) = sending.replace(fresh) {
+        old.stop();
+    }
+    control.events.emit(Event::Reconnected { side: "sender", why: "network changed" });
+    Ok(())
+}
@@ -0,0 +1,95 @@
+// Desktop notifications (--notify): a popup when a stream starts or stops
+// playing here, naming who sends it (the trusted name, else the address), so
+// sound out of nowhere has an explanation. Linux asks the desktop's
+// notification server over D-Bus (org.freedesktop.Notifications, on the
+// session bus); macOS runs `osascript`, so they show as Script Editor's. A
+// notification that can't be shown is warned about once and nothing else
+// changes.
+use anyhow::Result;
+use std::collections::HashMap;
+use tracing::{info, warn};
+
+use crate::control::Control;
+use crate::events::Event;
+
+const APP: &str = "Audio Bridge";
+
+/// Show what the event bus says starts and stops playing, for as long as the
+/// daemon runs.
+pub fn spawn(control: &Control) {
+    let mut events = control.events.subscribe();
+    tokio::spawn(async move {
+        let mut desktop = Desktop::default();
+        // Who each stream is from, to name it when it stops
+        let mut playing: HashMap<u32, String> = HashMap::new();
+        let mut failing = false;
+        while let Some(event) = events.recv().await {
+            let body = match event {
+                Event::StreamStarted { ssrc, address, peer } => {
+                    let who = peer.or(address).unwrap_or_else(|| format!("stream {ssrc:08x}"));
+                    playing.insert(ssrc, who.clone());
+                    format!("{who} started playing here")
+                }
+                Event::StreamStopped { ssrc } => {
+                    let who = playing.remove(&ssrc).unwrap_or_else(|| format!("stream {ssrc:08x}"));
+                    format!("{who} stopped playing")
+                }
+                _ => continue,
+            };
+            match desktop.show(APP, &body).await {
+                Ok(()) if failing => {
+                    info!(target: "notify", "notifications work again");
+                    failing = false;
+                }
+                Ok(()) => {}
+                Err(e) if !failing => {
+                    warn!(target: "notify", "can't show notifications: {e:#}");
+                    failing = true;
+                }
+                Err(_) => {}
+            }
+        }
+    });
+}
+
+#[derive(Default)]
+struct Desktop {
+    /// The session bus, once connected.
+    #[cfg(target_os = "linux")]
+    bus: Option<zbus::Connection>,
+}
+
+impl Desktop {
+    #[cfg(target_os = "linux")]
+    async fn show(&mut self, summary: &str, body: &str) -> Result<()> {
+        let bus = match &self.bus {
+            Some(bus) => bus.clone(),
+            None => self.bus.insert(zbus::Connection::session().await?).clone(),
+        };
+        let hints: HashMap<&str, zbus::zvariant::Value> = HashMap::new();
+        // Notify(app_name, replaces_id, app_icon, summary, body, actions, hints, expire_timeout)
+        let args = (APP, 0u32, "audio-speakers", summary, body, Vec::<&str>::new(), hints, -1i32);
+        let notifications = "org.freedesktop.Notifications";
+        let sent = bus
+            .call_method(Some(notifications), "/org/freedesktop/Notifications", Some(notifications), "Notify", &args)
+            .await;
+        if sent.is_err() {
+            // Try a fresh connection next time
+            self.bus = None;
+        }
+        sent?;
+        Ok(())
+    }
+
+    #[cfg(target_os = "macos")]
+    async fn show(&mut self, summary: &str, body: &str) -> Result<()> {
+        let quote = |s: &str| format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""));
+        let script = format!("display notification {} with title {}", quote(body), quote(summary));
+        let status = tokio::task::spawn_blocking(move || {
+            std::process::Command::new("osascript").arg("-e").arg(script).status()
+        })
+        .await??;
+        anyhow::ensure!(status.success(), "osascript: {status}");
+        Ok(())
+    }
+}
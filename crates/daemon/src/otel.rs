@@ -0,0 +1,452 @@
+// OpenTelemetry export (--otlp-endpoint URL): the session lifecycle as traces
+// and the --metrics-port metrics, POSTed as OTLP/HTTP JSON to a collector
+// every --otlp-interval, so machines running the bridge can be followed in
+// one place. The daemon is looked at once a second, as the watchdog does, and
+// what changed becomes spans:
+//   send          a sender, start to stop (destinations, transport, SSRC), with
+//                 an event when its destinations' addresses change
+//   receive       the receiver, start to stop
+//     session     a sender that authenticated over the handshake, until it
+//                 leaves
+//       stream    its SSRC as heard, until forgotten, with an event per state
+//                 change (active, silent, gone)
+//   recovery      under send or receive, from a pipeline failing until the
+//                 rebuilt one has stayed up, with an event per rebuild
+// Pipelines starting and stopping to play, and failing, are events on send
+// and receive. A span goes out once it ends; those still open are ended on
+// shutdown. Metrics carry their Prometheus names, counters as cumulative sums.
+use ring::rand::{SecureRandom, SystemRandom};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
+
+use crate::control::Control;
+use crate::http::Endpoint;
+use crate::identity::{fingerprint, hex, unhex};
+use crate::metrics::{self, Exposition};
+use crate::trust::TrustStore;
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// Ended spans kept while the collector can't be reached.
+const MAX_QUEUED: usize = 1000;
+/// OTLP/HTTP's port.
+pub const DEFAULT_PORT: u16 = 4318;
+
+fn random<const N: usize>() -> [u8; N] {
+    let mut bytes = [0u8; N];
+    let _ = SystemRandom::new().fill(&mut bytes);
+    bytes
+}
+
+fn nanos(t: SystemTime) -> String {
+    t.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos().to_string()
+}
+
+/// An attribute value in OTLP's JSON.
+fn any_value(v: &Value) -> Value {
+    match v {
+        Value::Bool(b) => json!({ "boolValue": b }),
+        Value::Number(n) if n.is_f64() => json!({ "doubleValue": n.as_f64() }),
+        Value::Number(n) => json!({ "intValue": n.to_string() }),
+        Value::String(s) => json!({ "stringValue": s }),
+        Value::Array(a) => json!({ "arrayValue": { "values": a.iter().map(any_value).collect::<Vec<_>>() } }),
+        other => json!({ "stringValue": other.to_string() }),
+    }
+}
+
+/// Key/value attributes, nulls left out.
+fn attributes(list: &[(&str, Value)]) -> Value {
+    list.iter()
+        .filter(|(_, v)| !v.is_null())
+        .map(|(k, v)| json!({ "key": k, "value": any_value(v) }))
+        .collect::<Vec<_>>()
+        .into()
+}
+
+struct Span {
+    name: &'static str,
+    trace: [u8; 16],
+    id: [u8; 8],
+    parent: Option<[u8; 8]>,
+    start: SystemTime,
+    attributes: Vec<(&'static str, Value)>,
+    events: Vec<Value>,
+}
+
+impl Span {
+    fn root(name: &'static str, attributes: Vec<(&'static str, Value)>) -> Self {
+        let (trace, id) = (random(), random());
+        Self { name, trace, id, parent: None, start: SystemTime::now(), attributes, events: Vec::new() }
+    }
+
+    fn child(&self, name: &'static str, attributes: Vec<(&'static str, Value)>) -> Self {
+        Self { trace: self.trace, parent: Some(self.id), ..Self::root(name, attributes) }
+    }
+
+    fn set(&mut self, key: &'static str, value: Value) {
+        match self.attributes.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, v)) => *v = value,
+            None => self.attributes.push((key, value)),
+        }
+    }
+
+    fn event(&mut self, name: &str, list: &[(&str, Value)]) {
+        self.events.push(json!({
+            "timeUnixNano": nanos(SystemTime::now()),
+            "name": name,
+            "attributes": attributes(list),
+        }));
+    }
+
+    /// As exported; `error` marks it failed.
+    fn end(self, error: Option<&str>) -> Value {
+        let mut span = json!({
+            "traceId": hex(&self.trace),
+            "spanId": hex(&self.id),
+            "name": self.name,
+            // INTERNAL
+            "kind": 1,
+            "startTimeUnixNano": nanos(self.start),
+            "endTimeUnixNano": nanos(SystemTime::now()),
+            "attributes": attributes(&self.attributes),
+            "events": self.events,
+        });
+        if let Some(parent) = self.parent {
+            span["parentSpanId"] = hex(&parent).into();
+        }
+        if let Some(e) = error {
+            // STATUS_CODE_ERROR
+            span["status"] = json!({ "code": 2, "message": e });
+        }
+        span
+    }
+}
+
+/// A sender's or the receiver's span, with the pipeline's state as last seen.
+struct Side {
+    span: Span,
+    playing: Option<bool>,
+    error: Option<String>,
+    recovery: Option<Span>,
+}
+
+impl Side {
+    fn new(span: Span) -> Self {
+        Self { span, playing: None, error: None, recovery: None }
+    }
+
+    /// Take in the pipeline's state and the watchdog's `recovery` (as `status` shows it).
+    fn update(&mut self, playing: bool, error: Option<String>, recovery: &Value, done: &mut Vec<Value>) {
+        if self.playing != Some(playing) {
+            self.span.event(if playing { "playing" } else { "not playing" }, &[]);
+            self.playing = Some(playing);
+        }
+        if let Some(e) = error.as_ref().filter(|_| self.error.is_none()) {
+            self.span.event("failed", &[("error", json!(e))]);
+        }
+        self.error = error;
+        match (recovery.is_null(), self.recovery.as_mut()) {
+            (false, None) => {
+                let attributes = vec![("error", recovery["error"].clone()), ("attempts", json!(0))];
+                self.recovery = Some(self.span.child("recovery", attributes));
+            }
+            (false, Some(r)) => {
+                let attempts = &recovery["attempts"];
+                if r.attributes.iter().any(|(k, v)| *k == "attempts" && v != attempts) {
+                    r.event("rebuild", &[("attempt", attempts.clone())]);
+                }
+                r.set("attempts", attempts.clone());
+                r.set("error", recovery["error"].clone());
+            }
+            (true, Some(_)) => done.extend(self.recovery.take().map(|r| r.end(None))),
+            (true, None) => {}
+        }
+    }
+
+    fn end(self, done: &mut Vec<Value>) {
+        done.extend(self.recovery.map(|r| r.end(Some("stopped before recovering"))));
+        done.push(self.span.end(self.error.as_deref()));
+    }
+}
+
+struct Sending {
+    side: Side,
+    ssrc: Option<u32>,
+    hosts: Vec<String>,
+}
+
+struct Receiving {
+    side: Side,
+    /// Per (address, SSRC) from the handshake.
+    sessions: HashMap<(IpAddr, u32), Span>,
+    /// Per SSRC, with its state.
+    streams: HashMap<u32, (Span, &'static str)>,
+}
+
+impl Receiving {
+    fn end(self, done: &mut Vec<Value>) {
+        done.extend(self.streams.into_values().map(|(s, _)| s.end(None)));
+        done.extend(self.sessions.into_values().map(|s| s.end(None)));
+        self.side.end(done);
+    }
+}
+
+/// Spans open and ended, and the export state.
+struct Exporter {
+    endpoint: Endpoint,
+    resource: Value,
+    since: SystemTime,
+    sending: Option<Sending>,
+    receiving: Option<Receiving>,
+    /// Ended spans not exported yet.
+    done: Vec<Value>,
+    /// The last export failed (logged once until one works).
+    failing: bool,
+}
+
+impl Exporter {
+    async fn check(&mut self, control: &Control) {
+        let done = &mut self.done;
+
+        let tx = control.sending.lock().await.as_ref().map(|s| {
+            let pipeline = s.with_sender(|tx| (tx.playing(), tx.error()));
+            (s.cfg.ssrc, s.hosts(), s.targets.clone(), s.cfg.transport.name(), s.cfg.port, pipeline)
+        });
+        // A new SSRC is a new sender
+        if self.sending.as_ref().is_some_and(|o| tx.as_ref().is_none_or(|t| t.0 != o.ssrc)) {
+            self.sending.take().unwrap().side.end(done);
+        }
+        if let Some((ssrc, hosts, targets, transport, port, pipeline)) = tx {
+            let o = self.sending.get_or_insert_with(|| {
+                let span = Span::root(
+                    "send",
+                    vec![
+                        ("destinations", json!(targets)),
+                        ("transport", json!(transport)),
+                        ("port", json!(port)),
+                        ("ssrc", json!(ssrc.map(|s| format!("{s:08x}")))),
+                    ],
+                );
+                Sending { side: Side::new(span), ssrc, hosts: hosts.clone() }
+            });
+            if o.hosts != hosts {
+                o.side.span.event("destinations moved", &[("hosts", json!(hosts))]);
+                o.hosts = hosts;
+            }
+            // No pipeline at all: a rebuild failed
+            let (playing, error) = pipeline.unwrap_or((false, Some("no pipeline".into())));
+            o.side.update(playing, error, &control.watchdog.sender.json(), done);
+        }
+
+        let rx = control.rx.read().unwrap().as_ref().map(|rx| (rx.playing(), rx.error(), rx.stream_states()));
+        let Some((playing, error, states)) = rx else {
+            if let Some(o) = self.receiving.take() {
+                o.end(done);
+            }
+            return;
+        };
+        let o = self.receiving.get_or_insert_with(|| {
+            let cfg = control.rx_cfg.read().unwrap();
+            let attributes = vec![("port", json!(cfg.listen_port)), ("transport", json!(cfg.transport.name()))];
+            let side = Side::new(Span::root("receive", attributes));
+            Receiving { side, sessions: HashMap::new(), streams: HashMap::new() }
+        });
+        o.side.update(playing, error, &control.watchdog.receiver.json(), done);
+
+        let live = control.sessions.live();
+        let is_live = |&(ip, ssrc): &(IpAddr, u32)| live.iter().any(|(i, _, s)| (*i, *s) == (ip, ssrc));
+        let ended: Vec<_> = o.sessions.keys().copied().filter(|k| !is_live(k)).collect();
+        for key in ended {
+            done.extend(o.sessions.remove(&key).map(|s| s.end(None)));
+        }
+        for (ip, owner, ssrc) in live {
+            if o.sessions.contains_key(&(ip, ssrc)) {
+                continue;
+            }
+            let key = unhex(&owner);
+            let name = key.as_ref().and_then(|k| TrustStore::load().ok()?.name_of(k).map(String::from));
+            let attributes = vec![
+                ("peer.address", json!(ip.to_string())),
+                ("peer.name", json!(name)),
+                ("peer.fingerprint", json!(key.as_deref().map(fingerprint))),
+                ("ssrc", json!(format!("{ssrc:08x}"))),
+            ];
+            let span = o.side.span.child("session", attributes);
+            o.sessions.insert((ip, ssrc), span);
+        }
+
+        let gone: Vec<_> = o.streams.keys().filter(|s| !states.iter().any(|(ssrc, _)| ssrc == *s)).copied().collect();
+        for ssrc in gone {
+            done.extend(o.streams.remove(&ssrc).map(|(s, _)| s.end(None)));
+        }
+        for (ssrc, state) in states {
+            let state = state.name();
+            match o.streams.get_mut(&ssrc) {
+                Some((span, was)) if *was != state => {
+                    span.event(state, &[]);
+                    *was = state;
+                }
+                Some(_) => {}
+                None => {
+                    // Under its handshake session (which may count the RTX SSRC)
+                    let parent = o
+                        .sessions
+                        .iter()
+                        .find(|((_, s), _)| *s == ssrc || s.wrapping_add(1) == ssrc)
+                        .map_or(&o.side.span, |(_, span)| span);
+                    let mut span = parent.child("stream", vec![("ssrc", json!(format!("{ssrc:08x}")))]);
+                    span.event(state, &[]);
+                    o.streams.insert(ssrc, (span, state));
+                }
+            }
+        }
+    }
+
+    async fn export(&mut self, control: &Control) {
+        let scope = json!({ "name": "ab-daemon", "version": env!("CARGO_PKG_VERSION") });
+        let mut result = Ok(());
+        if !self.done.is_empty() {
+            let body = json!({ "resourceSpans": [{
+                "resource": self.resource,
+                "scopeSpans": [{ "scope": scope, "spans": self.done }],
+            }] });
+            result = self.endpoint.post("/v1/traces", &body).await;
+            if result.is_ok() {
+                self.done.clear();
+            }
+        }
+        if result.is_ok() {
+            let metrics = metrics_json(&metrics::collect(control).await, self.since);
+            let body = json!({ "resourceMetrics": [{
+                "resource": self.resource,
+                "scopeMetrics": [{ "scope": scope, "metrics": metrics }],
+            }] });
+            result = self.endpoint.post("/v1/metrics", &body).await;
+        }
+        match result {
+            Ok(()) if self.failing => {
+                info!(target: "otel", "exporting again");
+                self.failing = false;
+            }
+            Ok(()) => {}
+            Err(e) if !self.failing => {
+                warn!(target: "otel", "export failed, keeping up to {MAX_QUEUED} spans: {e:#}");
+                self.failing = true;
+            }
+            Err(_) => {}
+        }
+        // Oldest first
+        if self.done.len() > MAX_QUEUED {
+            let extra = self.done.len() - MAX_QUEUED;
+            self.done.drain(..extra);
+        }
+    }
+}
+
+/// OTLP metrics from the Prometheus families; counters as sums since `since`.
+fn metrics_json(e: &Exposition, since: SystemTime) -> Vec<Value> {
+    let (start, now) = (nanos(since), nanos(SystemTime::now()));
+    e.0.iter()
+        .filter_map(|f| {
+            let points: Vec<_> = f
+                .samples
+                .iter()
+                // -inf dB (silence) has no JSON number
+                .filter(|(_, v)| v.is_finite())
+                .map(|(labels, v)| {
+                    let labels: Vec<_> = labels.iter().map(|(k, v)| (*k, json!(v))).collect();
+                    json!({
+                        "attributes": attributes(&labels),
+                        "startTimeUnixNano": start,
+                        "timeUnixNano": now,
+                        "asDouble": v,
+                    })
+                })
+                .collect();
+            if points.is_empty() {
+                return None;
+            }
+            let mut metric = json!({ "name": f.name, "description": f.help });
+            match f.kind {
+                // AGGREGATION_TEMPORALITY_CUMULATIVE
+                "counter" => {
+                    metric["sum"] = json!({ "aggregationTemporality": 2, "isMonotonic": true, "dataPoints": points })
+                }
+                _ => metric["gauge"] = json!({ "dataPoints": points }),
+            }
+            Some(metric)
+        })
+        .collect()
+}
+
+/// The running exporter; `finish` sends what's left.
+pub struct Otel {
+    exporter: Arc<tokio::sync::Mutex<Exporter>>,
+    task: tokio::task::JoinHandle<()>,
+}
+
+/// Follow the daemon and export to `endpoint` every `interval` for as long as it runs.
+pub fn spawn(control: Arc<Control>, endpoint: Endpoint, interval: Duration) -> Otel {
+    let name = control.env.read().unwrap().creds.name.clone();
+    let resource = json!({ "attributes": attributes(&[
+        ("service.name", json!("ab-daemon")),
+        ("service.version", json!(env!("CARGO_PKG_VERSION"))),
+        ("service.instance.id", json!(name)),
+        ("host.name", json!(crate::mdns::local_hostname())),
+    ]) });
+    info!(target: "otel", "exporting traces and metrics to {endpoint}");
+    let exporter = Arc::new(tokio::sync::Mutex::new(Exporter {
+        endpoint,
+        resource,
+        since: SystemTime::now(),
+        sending: None,
+        receiving: None,
+        done: Vec::new(),
+        failing: false,
+    }));
+    let shared = exporter.clone();
+    let task = tokio::spawn(async move {
+        let mut tick = tokio::time::interval(CHECK_INTERVAL);
+        let mut exported = Instant::now();
+        loop {
+            tick.tick().await;
+            let mut exporter = shared.lock().await;
+            exporter.check(&control).await;
+            if exported.elapsed() >= interval {
+                exported = Instant::now();
+                exporter.export(&control).await;
+            }
+        }
+    });
+    Otel { exporter, task }
+}
+
+impl Otel {
+    /// End the spans still open and send them, within `timeout`.
+    pub async fn finish(self, timeout: Duration) {
+        self.task.abort();
+        let mut exporter = self.exporter.lock().await;
+        let e = &mut *exporter;
+        if let Some(o) = e.sending.take() {
+            o.side.end(&mut e.done);
+        }
+        if let Some(o) = e.receiving.take() {
+            o.end(&mut e.done);
+        }
+        if e.done.is_empty() {
+            return;
+        }
+        let body = json!({ "resourceSpans": [{
+            "resource": e.resource,
+            "scopeSpans": [{ "scope": { "name": "ab-daemon", "version": env!("CARGO_PKG_VERSION") }, "spans": e.done }],
+        }] });
+        match tokio::time::timeout(timeout, e.endpoint.post("/v1/traces", &body)).await {
+            Ok(Ok(())) => {}
+            Ok(Err(err)) => warn!(target: "otel", "last export failed: {err:#}"),
+            Err(_) => warn!(target: "otel", "last export timed out"),
+        }
+    }
+}
@@ -0,0 +1,120 @@
+// Round-trip link test (`ab-daemon ping-audio <peer>`). A receiving daemon
+// echoes marker packets sent to its handshake port over UDP (listen port + 2;
+// the handshake itself is TCP):
+//   "ABPING" <seq: u32 BE> <padding up to an Opus packet's size>
+// The initiator sends them paced and sized like the stream its flags would
+// send, times the echoes and reports round trip, jitter and loss, the one-way
+// delay they imply and what capture to speaker would come to on top.
+use anyhow::{Context, Result, bail};
+use std::io::ErrorKind;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+use crate::args::Args;
+
+const MAGIC: &[u8] = b"ABPING";
+/// RTP header in front of each Opus frame.
+const RTP_HEADER: usize = 12;
+/// How long to wait for stragglers after the last marker.
+const LINGER: Duration = Duration::from_secs(1);
+
+/// Echo markers arriving on `bind:port` back to where they came from.
+pub fn serve(bind: IpAddr, port: u16) -> Result<()> {
+    let sock = UdpSocket::bind((bind, port)).with_context(|| format!("ping: bind udp :{port}"))?;
+    std::thread::spawn(move || {
+        let mut buf = [0u8; 2048];
+        loop {
+            let Ok((n, from)) = sock.recv_from(&mut buf) else { continue };
+            if buf[..n].starts_with(MAGIC) {
+                let _ = sock.send_to(&buf[..n], from);
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Send `count` markers of `len` bytes to `addr`, one per `interval`, and
+/// return each one's round trip (None = lost), in send order.
+pub fn run(addr: SocketAddr, count: u32, len: usize, interval: Duration) -> Result<Vec<Option<Duration>>> {
+    let local: IpAddr = if addr.is_ipv4() { Ipv4Addr::UNSPECIFIED.into() } else { Ipv6Addr::UNSPECIFIED.into() };
+    let sock = UdpSocket::bind((local, 0)).context("ping: bind")?;
+    sock.connect(addr).with_context(|| format!("ping: {addr}"))?;
+    sock.set_read_timeout(Some((interval / 4).max(Duration::from_micros(500))))?;
+
+    let mut packet = vec![0u8; len.max(MAGIC.len() + 4)];
+    packet[..MAGIC.len()].copy_from_slice(MAGIC);
+    let mut sent: Vec<Instant> = Vec::with_capacity(count as usize);
+    let mut rtts = vec![None; count as usize];
+    let mut buf = [0u8; 2048];
+    let mut next = Instant::now();
+    loop {
+        let now = Instant::now();
+        if sent.len() < count as usize && now >= next {
+            packet[MAGIC.len()..MAGIC.len() + 4].copy_from_slice(&(sent.len() as u32).to_be_bytes());
+            sock.send(&packet).with_context(|| format!("ping: send to {addr}"))?;
+            sent.push(now);
+            next += interval;
+        }
+        let answered = rtts.iter().all(Option::is_some);
+        if sent.len() == count as usize && (answered || sent.last().is_some_and(|t| t.elapsed() > LINGER)) {
+            return Ok(rtts);
+        }
+        match sock.recv(&mut buf) {
+            Ok(n) if n >= MAGIC.len() + 4 && buf.starts_with(MAGIC) => {
+                let seq = u32::from_be_bytes(buf[MAGIC.len()..MAGIC.len() + 4].try_into().unwrap()) as usize;
+                if let (Some(at), Some(rtt)) = (sent.get(seq), rtts.get_mut(seq)) {
+                    rtt.get_or_insert(at.elapsed());
+                }
+            }
+            Ok(_) => {}
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+            Err(e) if e.kind() == ErrorKind::ConnectionRefused => {
+                bail!("nothing answers on {addr} (is ab-daemon running there, receiving?)")
+            }
+            Err(e) => return Err(e).context("ping: receive"),
+        }
+    }
+}
+
+/// `ab-daemon ping-audio`: test the link to `addr` with markers like the
+/// stream these flags would send and print what it's good for.
+pub fn ping_audio(addr: SocketAddr, peer: &str, count: u32, a: &Args) -> Result<()> {
+    let frame_ms = f64::from(a.frame_ms.unwrap_or(2.5));
+    let len = RTP_HEADER + (f64::from(a.bitrate) / 8.0 * frame_ms / 1000.0).round() as usize;
+    println!("ping-audio {peer} ({addr}): {count} packets of {len} bytes, {frame_ms} ms apart");
+    let rtts = run(addr, count, len, Duration::from_secs_f64(frame_ms / 1000.0))?;
+
+    let got: Vec<f64> = rtts.iter().flatten().map(|d| d.as_secs_f64() * 1000.0).collect();
+    let lost = rtts.len() - got.len();
+    let loss_pct = lost as f64 * 100.0 / rtts.len().max(1) as f64;
+    if got.is_empty() {
+        bail!("no answer from {addr} ({lost} packets lost)");
+    }
+    let min = got.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = got.iter().copied().fold(0.0, f64::max);
+    let avg = got.iter().sum::<f64>() / got.len() as f64;
+    // Mean change between consecutive round trips, as RTCP reports jitter
+    let jitter = got.windows(2).map(|w| (w[1] - w[0]).abs()).sum::<f64>() / (got.len() - 1).max(1) as f64;
+    println!("round trip min/avg/max {min:.1}/{avg:.1}/{max:.1} ms, jitter {jitter:.1} ms, {lost} lost ({loss_pct:.1}%)");
+
+    // What a stream adds on top of the network: the frame, the jitterbuffer, the sink buffer
+    let one_way = avg / 2.0;
+    let buffers = frame_ms + f64::from(a.jitter_ms) + f64::from(a.sink_buffer_us) / 1000.0;
+    println!(
+        "one way ≈ {one_way:.1} ms; capture→speaker ≈ {:.0} ms with these flags \
+         (frame {frame_ms} ms + jitterbuffer {} ms + sink buffer {} ms)",
+        one_way + buffers,
+        a.jitter_ms,
+        a.sink_buffer_us / 1000
+    );
+
+    // Spikes beyond the jitterbuffer arrive too late to play
+    let spike = (max - min) / 2.0;
+    if spike > f64::from(a.jitter_ms) {
+        println!("warning: delay spikes of {spike:.0} ms exceed --jitter-ms {}; expect dropouts (raise it or use --adaptive-jitter)", a.jitter_ms);
+    }
+    if loss_pct >= 1.0 {
+        println!("warning: {loss_pct:.1}% loss; consider --fec or --rtx");
+    }
+    Ok(())
+}
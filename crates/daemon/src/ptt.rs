@@ -0,0 +1,106 @@
+// Push-to-talk on a key held anywhere on the desktop (Linux): key events are
+// read straight from evdev (/dev/input/event*, which takes membership in the
+// `input` group), so it works the same under X11, Wayland and on a console.
+// Devices aren't grabbed; the key still reaches other programs.
+use anyhow::{Context, Result, bail};
+use std::fs::File;
+use std::io::Read;
+use std::path::PathBuf;
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+const EV_KEY: u16 = 1;
+/// `struct input_event`: a timeval, then type, code and value.
+const EVENT_SIZE: usize = if cfg!(target_pointer_width = "64") { 24 } else { 16 };
+
+/// Key codes from linux/input-event-codes.h for the usual PTT choices.
+const KEYS: &[(&str, u16)] = &[
+    ("capslock", 58),
+    ("scrolllock", 70),
+    ("pause", 119),
+    ("insert", 110),
+    ("menu", 127),
+    ("leftctrl", 29),
+    ("rightctrl", 97),
+    ("leftalt", 56),
+    ("rightalt", 100),
+    ("leftmeta", 125),
+    ("rightmeta", 126),
+    ("space", 57),
+    ("f1", 59),
+    ("f2", 60),
+    ("f3", 61),
+    ("f4", 62),
+    ("f5", 63),
+    ("f6", 64),
+    ("f7", 65),
+    ("f8", 66),
+    ("f9", 67),
+    ("f10", 68),
+    ("f11", 87),
+    ("f12", 88),
+    ("f13", 183),
+    ("f14", 184),
+    ("f15", 185),
+    ("mouse-middle", 0x112),
+    ("mouse-side", 0x113),
+    ("mouse-extra", 0x114),
+];
+
+/// The key code for a name from `KEYS` (`f13`, `scrolllock`, `mouse-side`…)
+/// or a number (`evtest` shows them).
+pub fn key_code(name: &str) -> Result<u16> {
+    let name = name.to_lowercase();
+    if let Some((_, code)) = KEYS.iter().find(|(n, _)| *n == name) {
+        return Ok(*code);
+    }
+    name.parse().ok().with_context(|| {
+        let names: Vec<_> = KEYS.iter().map(|(n, _)| *n).collect();
+        format!("unknown key '{name}'; use a key code or one of {}", names.join(", "))
+    })
+}
+
+/// Watch `devices` (default: every readable /dev/input/event*) for `code`:
+/// true when it's pressed, false when it's released.
+pub fn watch(code: u16, devices: &[PathBuf]) -> Result<mpsc::UnboundedReceiver<bool>> {
+    let paths = if devices.is_empty() {
+        std::fs::read_dir("/dev/input")
+            .context("read /dev/input")?
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| p.file_name().and_then(|n| n.to_str()).is_some_and(|n| n.starts_with("event")))
+            .collect()
+    } else {
+        devices.to_vec()
+    };
+    let (tx, rx) = mpsc::unbounded_channel();
+    let mut opened = 0;
+    for path in paths {
+        let mut file = match File::open(&path) {
+            Ok(f) => f,
+            // Scanning: skip what we may not read
+            Err(_) if devices.is_empty() => continue,
+            Err(e) => return Err(e).with_context(|| format!("open {}", path.display())),
+        };
+        opened += 1;
+        let tx = tx.clone();
+        std::thread::spawn(move || {
+            let mut ev = [0u8; EVENT_SIZE];
+            while file.read_exact(&mut ev).is_ok() {
+                let at = EVENT_SIZE - 8;
+                let kind = u16::from_ne_bytes([ev[at], ev[at + 1]]);
+                let key = u16::from_ne_bytes([ev[at + 2], ev[at + 3]]);
+                let value = i32::from_ne_bytes([ev[at + 4], ev[at + 5], ev[at + 6], ev[at + 7]]);
+                // 1 = press, 0 = release, 2 = autorepeat
+                if kind == EV_KEY && key == code && value != 2 && tx.send(value == 1).is_err() {
+                    return;
+                }
+            }
+            warn!(target: "ptt", "{} stopped delivering events", path.display());
+        });
+    }
+    if opened == 0 {
+        bail!("no readable input devices in /dev/input (add this user to the `input` group)");
+    }
+    info!(target: "ptt", "watching key {code} on {opened} input device(s)");
+    Ok(rx)
+}
@@ -0,0 +1,174 @@
+// SIGHUP: re-read the config file (the command line still wins) and apply
+// what changed without restarting. Bitrate, jitterbuffer latency and volume
+// change in place; a sender or receiver is rebuilt only when something it was
+// built from (codec, transport, device, ports…) changed. Options the
+// handshake, discovery or control socket were set up with need a restart.
+use anyhow::Result;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+use ab_core::pipeline::{ReceiverConfig, Transport, build_receiver};
+
+use crate::args::Args;
+use crate::caps::Caps;
+use crate::config;
+use crate::control::Control;
+use crate::mdns;
+use crate::sending::Sending;
+
+/// Names (as flags) of the listed options that differ between two `Args`.
+macro_rules! changed {
+    ($old:expr, $new:expr; $($field:ident),+ $(,)?) => {{
+        let mut names: Vec<String> = Vec::new();
+        $(
+            if $old.$field != $new.$field {
+                names.push(format!("--{}", stringify!($field).replace('_', "-")));
+            }
+        )+
+        names
+    }};
+}
+
+fn needs_restart(old: &Args, new: &Args) -> Vec<String> {
+    changed!(old, new;
+        listen_port, bind_address, interface, mdns, pairable, accept_unpaired, tofu, psk, psk_file,
+        srtp, control_socket, no_send, no_receive, ptt_key, ptt_device, dbus, mpris, notify,
+        route, speaker_pair, log_format, stats_interval, stats_format, stats_file, metrics_port,
+        otlp_endpoint, otlp_interval, pcap, on_event_url, mqtt_broker, mqtt_topic, mqtt_username,
+        mqtt_password, mqtt_interval, ha_discovery, ha_discovery_prefix,
+        detach, pidfile, log_file, log_max_mb, no_keys, virtual_sink, virtual_sink_default)
+}
+
+fn receiver_changed(old: &Args, new: &Args) -> Vec<String> {
+    changed!(old, new;
+        sdp, fec, fec_mode, rtx, multicast_group, multicast_iface, transport, srt_latency_ms,
+        stun_server, airplay, raop_cmd, snapcast, scream, scream_group, http_port, record, record_max_mb,
+        record_max_minutes, output_device, auto_sink, follow_default_sink, idle_release, stream_timeout, drop_on_late,
+        drift_correction, plc,
+        latency_ms, adaptive_jitter, min_jitter_ms, max_jitter_ms, sink_buffer_us, sink_latency_us, sink_sync,
+        duplex, echo_cancel, relay_to, relay_port, clock, clock_from, ptp_domain, measure_latency)
+}
+
+fn sender_changed(old: &Args, new: &Args) -> Vec<String> {
+    changed!(old, new;
+        capture_device, capture_app, source, send_to, auto_send, auto_send_hint, send_port, frame_ms, adaptive_bitrate,
+        min_bitrate, max_bitrate, fec, fec_mode, fec_percentage, expected_loss_pct, rtx, rtx_max_ms,
+        multicast_ttl, multicast_iface, dscp, transport, srt_latency_ms, stun_server, aes67, ptp_domain,
+        src_buffer_us, src_latency_us, monitor_hint, peer_names, groups, duplex, echo_cancel, ptt,
+        suppress_silence, keepalive, local_monitor, clock, clock_from, measure_latency)
+}
+
+pub async fn reload(control: &Control) -> Result<()> {
+    let new = Arc::new(config::load_args()?);
+    config::validate(&new)?;
+    let old = control.env.read().unwrap().args.clone();
+
+    let restart = needs_restart(&old, &new);
+    if !restart.is_empty() {
+        warn!(target: "config", "{} changed; restart the daemon to apply", restart.join(", "));
+    }
+    let mut send_defaults = config::sender_config(&new)?;
+    // The capture file stays open across reloads
+    send_defaults.pcap = control.send_defaults.read().unwrap().pcap.clone();
+
+    // Receiver: rebuild, or retune in place
+    let rebuild = receiver_changed(&old, &new);
+    let receiving = control.rx.read().unwrap().is_some();
+    let gated = control.rx_cfg.read().unwrap().source_gate.is_some();
+    anyhow::ensure!(
+        !receiving || !gated || config::transport(&new) == Transport::Udp,
+        "only authenticated senders are played, which only --transport udp can tell apart"
+    );
+    if receiving && !rebuild.is_empty() {
+        info!(target: "config", "{} changed: rebuilding the receiver", rebuild.join(", "));
+        let fresh = {
+            let current = control.rx_cfg.read().unwrap();
+            ReceiverConfig {
+                // Sockets and the shared state stay as the handshake knows them
+                listen_port: current.listen_port,
+                bind_address: current.bind_address,
+                source_gate: current.source_gate.clone(),
+                srtp_keys: current.srtp_keys.clone(),
+                sessions: current.sessions.clone(),
+                pcap: current.pcap.clone(),
+                output_device: config::output_device(&new)?,
+                ..config::receiver_config(&new)?
+            }
+        };
+        let mut guard = control.rx.write().unwrap();
+        let Some(rx) = guard.as_mut() else { return Ok(()) };
+        let volume = if new.volume != old.volume { new.volume } else { rx.volume() };
+        let muted = rx.muted();
+        // The old pipeline holds the ports; let go first
+        rx.stop();
+        let next = build_receiver(&fresh).and_then(|next| next.start().map(|_| next));
+        match next {
+            Ok(next) => {
+                next.set_volume(volume)?;
+                next.set_muted(muted)?;
+                *rx = next;
+            }
+            Err(e) => {
+                // Stopping it closed its sockets, so the old one is built afresh
+                warn!(target: "config", "new receiver failed, rebuilding the old one: {e:#}");
+                let current = control.rx_cfg.read().unwrap().clone();
+                let again = build_receiver(&current).and_then(|again| again.start().map(|_| again))?;
+                again.set_volume(rx.volume())?;
+                again.set_muted(muted)?;
+                *rx = again;
+                return Err(e);
+            }
+        }
+        drop(guard);
+        if let Some(m) = &control.mdns {
+            m.lock().unwrap().advertise(&mdns::local_hostname(), fresh.listen_port, &Caps::of_receiver(&fresh))?;
+        }
+        *control.rx_cfg.write().unwrap() = fresh;
+    } else if let Some(rx) = control.rx.read().unwrap().as_ref() {
+        if new.jitter_ms != old.jitter_ms || new.rtx_max_ms != old.rtx_max_ms {
+            let mut cfg = control.rx_cfg.write().unwrap();
+            cfg.jitter_ms = new.jitter_ms;
+            let budget = cfg.rtx_max_ms.map(|_| new.rtx_max_ms);
+            cfg.rtx_max_ms = budget;
+            if let Err(e) = rx.set_latency_ms(new.jitter_ms + budget.unwrap_or(0)) {
+                warn!(target: "config", "{e:#}");
+            }
+        }
+        if new.volume != old.volume {
+            rx.set_volume(new.volume)?;
+        }
+    }
+
+    // Sender: restart what's running with the new settings, or just retune it
+    let mut sending = control.sending.lock().await;
+    let restart_sender = sender_changed(&old, &new);
+    if let Some(current) = sending.as_ref() {
+        if !restart_sender.is_empty() {
+            info!(target: "config", "{} changed: restarting the sender", restart_sender.join(", "));
+            let mut cfg = send_defaults.clone();
+            // Destinations given over the control socket outlive a reload
+            let from_flags = current.targets == control.send_defaults.read().unwrap().hosts;
+            if !from_flags {
+                cfg.hosts = current.targets.clone();
+                cfg.port = current.cfg.port;
+            }
+            if let Some(stale) = sending.take() {
+                stale.stop();
+            }
+            let env = {
+                let mut env = control.env.write().unwrap();
+                env.args = new.clone();
+                env.clone()
+            };
+            *sending = Some(Sending::start(cfg, &env).await?);
+        } else if new.bitrate != old.bitrate {
+            if let Some(r) = current.with_sender(|tx| tx.set_bitrate(new.bitrate)) {
+                r?;
+            }
+        }
+    }
+    control.env.write().unwrap().args = new;
+    *control.send_defaults.write().unwrap() = send_defaults;
+    info!(target: "config", "reloaded");
+    Ok(())
+}
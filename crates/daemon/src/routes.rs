@@ -0,0 +1,266 @@
+// Routing table: named source → destination pairs that run next to the main
+// sender and receiver, each as its own pipeline, so one daemon can carry
+// several streams at once:
+//
+//   mic=capture:alsa_input.usb-mic->office        a capture device to receivers
+//   tv=capture->livingroom,kitchen:5004           the default source, fanned out
+//   left=capture@left->pi-left                    one channel (a speaker pair)
+//   phones=listen:6004->output:bluez_sink.headset what arrives on :6004 to a sink
+//
+// Routes come from --route and are added or removed at runtime over the
+// control socket. Play routes share the main receiver's source gate, SRTP
+// keys and sessions, with a handshake listener of their own on port + 2.
+use anyhow::{Context, Result, bail};
+use serde_json::{Value, json};
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::Duration;
+use tracing::info;
+
+use ab_core::pipeline::{Channel, Receiver, ReceiverConfig, Source, any_addr, build_receiver};
+
+use crate::control::{self, Control};
+use crate::handshake;
+use crate::sending::Sending;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Route {
+    /// Capture `capture` (None = the default source) and send it, or just
+    /// `channel` of it, to `to` on `port` (None = --send-port).
+    Send { capture: Option<String>, channel: Option<Channel>, to: Vec<String>, port: Option<u16> },
+    /// Play what arrives on `port` on `output` (None = the default sink).
+    Play { port: u16, output: Option<String> },
+}
+
+/// `NAME=FROM->TO`, as given to --route and `ctl route-add`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RouteSpec {
+    pub name: String,
+    pub route: Route,
+}
+
+impl FromStr for RouteSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let usage =
+            "expected NAME=capture[:DEVICE][@left|@right]->HOST[,HOST…][:PORT] or NAME=listen:PORT->output[:DEVICE]";
+        let (name, rest) = s.split_once('=').ok_or(usage)?;
+        let (from, to) = rest.split_once("->").ok_or(usage)?;
+        let (name, from, to) = (name.trim(), from.trim(), to.trim());
+        if name.is_empty() {
+            return Err(usage.into());
+        }
+        let device = |s: &str, kind: &str| match s.strip_prefix(kind) {
+            Some("") => Ok(None),
+            Some(d) => d.strip_prefix(':').filter(|d| !d.is_empty()).map(|d| Some(d.to_string())).ok_or(usage),
+            None => Err(usage),
+        };
+        let route = if let Some(port) = from.strip_prefix("listen:") {
+            let port = port.parse().map_err(|_| format!("bad port '{port}'"))?;
+            Route::Play { port, output: device(to, "output")? }
+        } else {
+            let (from, channel) = match from.rsplit_once('@') {
+                Some((from, "left")) => (from, Some(Channel::Left)),
+                Some((from, "right")) => (from, Some(Channel::Right)),
+                Some((_, other)) => return Err(format!("unknown channel '{other}' (left or right)")),
+                None => (from, None),
+            };
+            let capture = device(from, "capture")?;
+            let (mut hosts, mut port) = (Vec::new(), None);
+            for dest in to.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+                let (host, p) = split_dest(dest)?;
+                if p.is_some() && port.is_some() && p != port {
+                    return Err("one route sends to a single port".into());
+                }
+                port = port.or(p);
+                hosts.push(host);
+            }
+            if hosts.is_empty() {
+                return Err(usage.into());
+            }
+            Route::Send { capture, channel, to: hosts, port }
+        };
+        Ok(Self { name: name.to_string(), route })
+    }
+}
+
+impl fmt::Display for RouteSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}=", self.name)?;
+        match &self.route {
+            Route::Send { capture, channel, to, port } => {
+                write!(f, "capture{}", capture.as_ref().map(|d| format!(":{d}")).unwrap_or_default())?;
+                match channel {
+                    Some(Channel::Left) => write!(f, "@left->")?,
+                    Some(Channel::Right) => write!(f, "@right->")?,
+                    None => write!(f, "->")?,
+                }
+                let hosts: Vec<_> = to.iter().map(|h| if h.contains(':') { format!("[{h}]") } else { h.clone() }).collect();
+                write!(f, "{}{}", hosts.join(","), port.map(|p| format!(":{p}")).unwrap_or_default())
+            }
+            Route::Play { port, output } => {
+                write!(f, "listen:{port}->output{}", output.as_ref().map(|d| format!(":{d}")).unwrap_or_default())
+            }
+        }
+    }
+}
+
+/// `host`, `host:port`, `[v6]` or `[v6]:port`; a bare IPv6 address has no port.
+fn split_dest(dest: &str) -> Result<(String, Option<u16>), String> {
+    let bad_port = |p: &str| format!("bad port '{p}' in '{dest}'");
+    if let Some(rest) = dest.strip_prefix('[') {
+        let (host, after) = rest.split_once(']').ok_or_else(|| format!("unclosed '[' in '{dest}'"))?;
+        let port = match after.strip_prefix(':') {
+            Some(p) => Some(p.parse().map_err(|_| bad_port(p))?),
+            None if after.is_empty() => None,
+            None => return Err(format!("unexpected '{after}' in '{dest}'")),
+        };
+        return Ok((host.to_string(), port));
+    }
+    match dest.split_once(':') {
+        Some((host, p)) if !p.contains(':') => Ok((host.to_string(), Some(p.parse().map_err(|_| bad_port(p))?))),
+        _ => Ok((dest.to_string(), None)),
+    }
+}
+
+/// A route's pipeline.
+pub enum Running {
+    Send(Sending),
+    Play(Receiver),
+}
+
+impl Running {
+    pub fn stop(self) {
+        match self {
+            Running::Send(s) => s.stop(),
+            Running::Play(rx) => rx.stop(),
+        }
+    }
+
+    /// Stop after draining what's queued, for at most `timeout`.
+    pub fn shutdown(self, timeout: Duration) {
+        match self {
+            Running::Send(s) => s.shutdown(timeout),
+            Running::Play(rx) => rx.shutdown(timeout),
+        }
+    }
+
+    pub fn json(&self, spec: &RouteSpec) -> Value {
+        match self {
+            Running::Send(s) => json!({
+                "name": spec.name,
+                "route": spec.to_string(),
+                "hosts": s.hosts(),
+                "port": s.cfg.port,
+                "level": s.with_sender(|tx| tx.level()).flatten().map(control::level_json),
+            }),
+            Running::Play(rx) => json!({
+                "name": spec.name,
+                "route": spec.to_string(),
+                "volume": rx.volume(),
+                "level": rx.level().map(control::level_json),
+            }),
+        }
+    }
+}
+
+/// Running routes by name, and the ports play routes have handshake listeners
+/// on (those can't be closed, so a port keeps its listener once started).
+#[derive(Default)]
+pub struct Routes {
+    pub table: tokio::sync::Mutex<Vec<(RouteSpec, Running)>>,
+    handshakes: Mutex<HashSet<u16>>,
+}
+
+/// Build and start `spec` and add it to the table.
+pub async fn add(control: &Control, spec: RouteSpec) -> Result<()> {
+    let mut table = control.routes.table.lock().await;
+    if table.iter().any(|(s, _)| s.name == spec.name) {
+        bail!("route '{}' already exists (remove it first)", spec.name);
+    }
+    let env = control.env.read().unwrap().clone();
+    let running = match &spec.route {
+        Route::Send { capture, channel, to, port } => {
+            if env.args.no_send {
+                bail!("this daemon runs with --no-send");
+            }
+            let mut cfg = control.send_defaults.read().unwrap().clone();
+            if capture.is_some() {
+                // A device named on the route is captured, whatever --source says
+                cfg.source = Source::Capture;
+            }
+            cfg.capture_device = capture.clone();
+            cfg.channel = *channel;
+            cfg.hosts = to.clone();
+            cfg.port = port.unwrap_or(cfg.port);
+            // Only the main sender answers to push-to-talk, plays locally, or is captured
+            cfg.ptt = false;
+            cfg.local_monitor = None;
+            cfg.pcap = None;
+            Running::Send(Sending::start(cfg, &env).await.with_context(|| format!("route '{}'", spec.name))?)
+        }
+        Route::Play { port, output } => {
+            let main = control.rx_cfg.read().unwrap().clone();
+            if *port == main.listen_port {
+                bail!("port {port} is the main receiver's");
+            }
+            let cfg = ReceiverConfig {
+                listen_port: *port,
+                output_device: output.clone(),
+                http_port: None,
+                record: None,
+                scream_group: None,
+                relay: None,
+                echo_probe: false,
+                pcap: None,
+                ..main
+            };
+            let rx = build_receiver(&cfg).and_then(|rx| rx.start().map(|_| rx));
+            let rx = rx.with_context(|| format!("route '{}'", spec.name))?;
+            rx.set_volume(env.args.volume)?;
+            if control.routes.handshakes.lock().unwrap().insert(*port) {
+                handshake::serve(
+                    cfg.bind_address.unwrap_or_else(any_addr),
+                    port + handshake::PORT_OFFSET,
+                    handshake::Server {
+                        creds: env.creds.clone(),
+                        gate: cfg.source_gate.clone(),
+                        pairable: false,
+                        pairings: control.pairings.clone(),
+                        srtp_keys: cfg.srtp_keys.clone(),
+                        sessions: control.sessions.clone(),
+                    },
+                )?;
+            }
+            Running::Play(rx)
+        }
+    };
+    info!(target: "route", "{spec} started");
+    table.push((spec, running));
+    Ok(())
+}
+
+/// Stop the route called `name` and drop it from the table.
+pub async fn remove(control: &Control, name: &str) -> Result<RouteSpec> {
+    let mut table = control.routes.table.lock().await;
+    let at = table.iter().position(|(s, _)| s.name == name).with_context(|| format!("no route '{name}'"))?;
+    let (spec, running) = table.remove(at);
+    running.stop();
+    info!(target: "route", "{spec} stopped");
+    Ok(spec)
+}
+
+/// Routes as the control socket lists them.
+pub async fn list(control: &Control) -> Vec<Value> {
+    control.routes.table.lock().await.iter().map(|(spec, running)| running.json(spec)).collect()
+}
+
+/// Stop every route (daemon shutdown), each drained for at most `timeout`.
+pub async fn stop_all(control: &Control, timeout: Duration) {
+    for (_, running) in control.routes.table.lock().await.drain(..) {
+        running.shutdown(timeout);
+    }
+}
@@ -0,0 +1,256 @@
+// The daemon's sender as one unit that can be started and stopped while the
+// daemon runs (at startup from flags, later from the control socket): the
+// pipeline, the handshake connections to each receiver and the watcher that
+// follows peers' addresses.
+use anyhow::{Context, Result, bail};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::warn;
+
+use ab_core::pipeline::{Sender, SenderConfig, build_sender};
+
+use crate::args::Args;
+use crate::caps;
+use crate::config;
+use crate::handshake::{self, Credentials, Hello};
+use crate::mdns::{HostResolver, Peers};
+use crate::targets::{self, Targets};
+
+/// What starting a sender needs besides its config.
+#[derive(Clone)]
+pub struct SendEnv {
+    pub args: Arc<Args>,
+    pub peers: Peers,
+    /// Present when mDNS is on.
+    pub resolver: Option<HostResolver>,
+    pub creds: Credentials,
+}
+
+pub struct Sending {
+    /// Destinations as given (peer names kept).
+    pub targets: Vec<String>,
+    /// The configuration in use after resolving and negotiating.
+    pub cfg: SenderConfig,
+    tx: Arc<Mutex<Option<Sender>>>,
+    hello: Hello,
+    watcher: Option<tokio::task::JoinHandle<()>>,
+}
+
+impl Sending {
+    /// Resolve `cfg.hosts` (or pick the `--auto-send` peer if it's empty),
+    /// agree on settings with discovered receivers, and start streaming.
+    pub async fn start(mut cfg: SenderConfig, env: &SendEnv) -> Result<Self> {
+        let a = &env.args;
+        if cfg.hosts.is_empty() {
+            if !a.auto_send {
+                bail!("no destinations to send to");
+            }
+            // Follow the peer by name so its address can move later
+            let peer = targets::pick_auto(&env.peers, a.auto_send_hint.as_deref()).await?;
+            cfg.hosts = vec![peer.name];
+            cfg.port = peer.port;
+        }
+        cfg.hosts = a.expand(&cfg.hosts);
+        let entries = cfg.hosts.clone();
+        let targets = Targets::new(entries.clone(), env.peers.clone(), env.resolver.clone());
+        if targets.any_named() {
+            cfg.hosts = targets.resolve_initial().await;
+        }
+        // An intercom peer listens where it advertises
+        if a.duplex.as_ref().is_some_and(|d| entries == [a.named_peer(d)]) {
+            if let Some(peer) = targets.discovered().first() {
+                cfg.port = peer.port;
+            }
+        }
+        // Receivers on a shared clock map our reports onto it (AES67 brings its own PTP)
+        if cfg.clock.is_none() && cfg.aes67.is_none() {
+            cfg.clock = config::sender_clock(a)?;
+        }
+        // A fresh SSRC per run lets receivers drop what's left of the previous one
+        let ssrc = handshake::new_ssrc()?;
+        cfg.ssrc = Some(ssrc);
+        if a.srtp {
+            cfg.srtp = Some(handshake::new_srtp_session()?);
+        }
+        // Match what the discovered destinations advertise they can play
+        if cfg.aes67.is_none() {
+            let known: Vec<_> = targets.discovered().into_iter().filter_map(|p| Some((p.caps()?, p.name))).collect();
+            for (caps, name) in &known {
+                caps::negotiate(&mut cfg, name, caps, a)?;
+            }
+            if let Some((_, name)) = known.iter().find(|(caps, _)| !caps::accepts(caps, &cfg)) {
+                bail!("{name} can't play what the other destinations need; send to them separately");
+            }
+        }
+        let sender = build_sender(&cfg)?;
+        sender.start()?;
+        let tx = Arc::new(Mutex::new(Some(sender)));
+        // Prove who we are to receivers that only play paired senders
+        let hello = Hello::spawn(
+            &cfg.hosts,
+            cfg.port + handshake::PORT_OFFSET,
+            cfg.bind_address,
+            env.creds.clone(),
+            ssrc,
+            cfg.srtp.clone(),
+        );
+
+        let watcher = watch(targets, env, &tx, &hello, &cfg);
+        Ok(Self { targets: entries, cfg, tx, hello, watcher })
+    }
+
+    /// Send to other destinations without stopping capture and encode: the
+    /// UDP sink is re-pointed in place and handshakes move to the new
+    /// receivers. Fails, leaving everything as it was, when that isn't
+    /// possible (another transport, or a receiver that needs other settings);
+    /// restart the sender then.
+    pub async fn retarget(&mut self, entries: Vec<String>, port: u16, env: &SendEnv) -> Result<()> {
+        let entries = env.args.expand(&entries);
+        let targets = Targets::new(entries.clone(), env.peers.clone(), env.resolver.clone());
+        let hosts = if targets.any_named() { targets.resolve_initial().await } else { entries.clone() };
+        if let Some(peer) = targets.discovered().iter().find(|p| p.caps().is_some_and(|c| !caps::accepts(&c, &self.cfg))) {
+            bail!("{} needs different stream settings", peer.name);
+        }
+        let ssrc = self.cfg.ssrc.context("sender has no session")?;
+        // Handshake with the new receivers first so their gates are open when audio arrives
+        let hello = Hello::spawn(
+            &hosts,
+            port + handshake::PORT_OFFSET,
+            self.cfg.bind_address,
+            env.creds.clone(),
+            ssrc,
+            self.cfg.srtp.clone(),
+        );
+        let moved = self.with_sender(|s| s.set_hosts(&hosts, port)).context("sender is reconnecting");
+        if let Err(e) = moved.and_then(|r| r) {
+            hello.stop();
+            return Err(e);
+        }
+        // The old receivers let go of our session
+        std::mem::replace(&mut self.hello, hello).stop();
+        if let Some(w) = self.watcher.take() {
+            w.abort();
+        }
+        self.cfg.hosts = hosts;
+        self.cfg.port = port;
+        self.targets = entries;
+        self.watcher = watch(targets, env, &self.tx, &self.hello, &self.cfg);
+        Ok(())
+    }
+
+    /// Stop streaming and let the receivers go.
+    pub fn stop(self) {
+        if let Some(w) = self.watcher {
+            w.abort();
+        }
+        self.hello.stop();
+        if let Some(sender) = self.tx.lock().unwrap().take() {
+            sender.stop();
+        }
+    }
+
+    /// Like `stop`, once what's been captured has gone out (at most `timeout`);
+    /// the receivers keep us until then.
+    pub fn shutdown(self, timeout: Duration) {
+        if let Some(w) = self.watcher {
+            w.abort();
+        }
+        if let Some(sender) = self.tx.lock().unwrap().take() {
+            sender.shutdown(timeout);
+        }
+        self.hello.stop();
+    }
+
+    /// Addresses currently streamed to.
+    pub fn hosts(&self) -> Vec<String> {
+        self.hello.hosts()
+    }
+
+    /// Ask the destinations among `names` (receivers or zones) to play us at
+    /// `volume`. Returns the destinations asked.
+    pub fn set_volume(&self, names: &[String], volume: f64, args: &Args) -> Vec<String> {
+        let wanted = args.expand(names);
+        let mut asked = Vec::new();
+        for (slot, target) in self.targets.iter().enumerate() {
+            if wanted.contains(target) {
+                self.hello.set_volume(slot, volume);
+                asked.push(target.clone());
+            }
+        }
+        asked
+    }
+
+    /// Volume asked of each destination, by target (None = as sent).
+    pub fn volumes(&self) -> Vec<(String, Option<f64>)> {
+        self.targets.iter().cloned().zip(self.hello.volumes()).collect()
+    }
+
+    /// Replace the pipeline (after an error) with a new one sending to the
+    /// same receivers; the handshakes carry on.
+    pub fn rebuild(&self) -> Result<()> {
+        // A new srtpenc counts packets from zero again, so under the same key and
+        // SSRC it would repeat the old pipeline's keystream
+        if self.cfg.srtp.is_some() {
+            bail!("an SRTP sender can't be rebuilt under its key; restart it (stop-send, start-send)");
+        }
+        let mut cfg = self.cfg.clone();
+        // Named destinations may have moved since the start
+        cfg.hosts = self.hosts();
+        let mut tx = self.tx.lock().unwrap();
+        if let Some(old) = tx.take() {
+            old.stop();
+        }
+        let sender = build_sender(&cfg)?;
+        if let Err(e) = sender.start() {
+            sender.stop();
+            return Err(e);
+        }
+        *tx = Some(sender);
+        Ok(())
+    }
+
+    /// Run `f` on the live pipeline, if there is one (a rebuild may have failed).
+    pub fn with_sender<R>(&self, f: impl FnOnce(&Sender) -> R) -> Option<R> {
+        self.tx.lock().unwrap().as_ref().map(f)
+    }
+}
+
+/// Follow named destinations' addresses while the sender runs.
+fn watch(
+    targets: Targets,
+    env: &SendEnv,
+    tx: &Arc<Mutex<Option<Sender>>>,
+    hello: &Hello,
+    cfg: &SenderConfig,
+) -> Option<tokio::task::JoinHandle<()>> {
+    if !targets.any_named() || env.resolver.is_none() {
+        return None;
+    }
+    let tx = tx.clone();
+    let hello = hello.clone();
+    let mut cfg = cfg.clone();
+    let current = cfg.hosts.clone();
+    Some(targets.watch(current, move |hosts| {
+        hello.set_hosts(hosts);
+        let mut tx = tx.lock().unwrap();
+        // UDP can be re-pointed in place; everything else reconnects
+        if let Some(sender) = tx.as_ref() {
+            if sender.set_hosts(hosts, cfg.port).is_ok() {
+                return;
+            }
+            // See `Sending::rebuild`
+            if cfg.srtp.is_some() {
+                warn!(target: "sender", "can't follow the move without rebuilding the SRTP sender; restart it");
+                return;
+            }
+        }
+        if let Some(old) = tx.take() {
+            old.stop();
+        }
+        cfg.hosts = hosts.to_vec();
+        match build_sender(&cfg).and_then(|s| s.start().map(|_| s)) {
+            Ok(s) => *tx = Some(s),
+            Err(e) => warn!(target: "sender", "reconnect failed: {e:#}"),
+        }
+    }))
+}
@@ -0,0 +1,157 @@
+// `ab-daemon setup-macos`: the capture side of a Mac, which is the hard part
+// of getting started. Capturing what the Mac plays takes a loopback driver
+// (BlackHole, else Rogue Amoeba's Loopback or Soundflower); this finds one
+// and says how to install BlackHole if there's none. --multi-output then
+// makes a Multi-Output device, "Audio Bridge Output", playing on the current
+// default output and the loopback device at once, and makes it the default,
+// so what plays is still heard here while it's captured (it's kept, like one
+// made in Audio MIDI Setup; macOS gives such a device no volume control).
+// Last, the capture device: printed as a --capture-device flag, or with
+// --save written to config.toml. It's given by name, which survives
+// replugging where the index doesn't.
+use anyhow::{Context, Result, bail, ensure};
+
+use ab_core::devices::{self, AudioDevice};
+use ab_core::pipeline::init_gst;
+
+use crate::cf::{self, Cf};
+use crate::macos::{self, AudioObjectId};
+
+/// Loopback drivers, best first, as their devices' names start.
+const LOOPBACKS: [&str; 3] = ["BlackHole", "Loopback", "Soundflower"];
+const MULTI_OUTPUT_NAME: &str = "Audio Bridge Output";
+const MULTI_OUTPUT_UID: &str = "org.audiobridge.multi-output";
+
+pub fn run(multi_output: bool, save: bool) -> Result<()> {
+    init_gst()?;
+    let sources = devices::sources()?;
+    let found: Vec<&AudioDevice> =
+        LOOPBACKS.iter().flat_map(|name| sources.iter().filter(move |d| d.name.starts_with(name))).collect();
+    let Some(loopback) = found.first() else {
+        println!("No loopback device (BlackHole, Loopback or Soundflower) is installed.");
+        println!("Install BlackHole, then run this again:");
+        println!("  brew install blackhole-2ch      (or https://existential.audio/blackhole/)");
+        bail!("nothing to capture what the Mac plays from");
+    };
+    for d in &found {
+        println!("Found {} (index {})", d.name, d.id);
+    }
+    println!("Capturing from {}", loopback.name);
+
+    if multi_output {
+        make_multi_output(loopback)?;
+    } else {
+        println!();
+        println!("To hear what plays while it's captured: ab-daemon setup-macos --multi-output");
+    }
+
+    println!();
+    if save {
+        let path = save_capture_device(&loopback.name)?;
+        println!("Saved capture-device = {:?} to {}", loopback.name, path.display());
+    } else {
+        println!("Run the daemon with: --capture-device {:?}", loopback.name);
+        println!("(or setup-macos --save puts it in config.toml)");
+    }
+    Ok(())
+}
+
+/// The Multi-Output device, made if need be, as the default output.
+fn make_multi_output(loopback: &AudioDevice) -> Result<()> {
+    let existing = macos::devices().into_iter().find(|&d| uid(d).is_ok_and(|u| u == MULTI_OUTPUT_UID));
+    let device = match existing {
+        Some(device) => {
+            println!("{MULTI_OUTPUT_NAME} is already there");
+            device
+        }
+        None => {
+            let loopback_id: AudioObjectId = loopback.id.parse().context("the loopback device has no index")?;
+            let speakers = macos::default_device(macos::DEFAULT_OUTPUT).context("no default output device")?;
+            ensure!(
+                speakers != loopback_id,
+                "the default output is {}: make your speakers the default (System Settings › Sound) and \
+                 run this again",
+                loopback.name
+            );
+            let device = create_multi_output(&uid(speakers)?, &uid(loopback_id)?)?;
+            println!("Made {MULTI_OUTPUT_NAME}: your current output and {} together", loopback.name);
+            device
+        }
+    };
+    if macos::default_device(macos::DEFAULT_OUTPUT) != Some(device) {
+        set_default_output(device)?;
+        println!("{MULTI_OUTPUT_NAME} is the default output now (change it back in System Settings › Sound)");
+    }
+    Ok(())
+}
+
+/// A stacked aggregate (a Multi-Output device): `main` sets the clock,
+/// `other` follows it with drift compensation.
+fn create_multi_output(main: &str, other: &str) -> Result<AudioObjectId> {
+    let sub = |uid: &str, drift: bool| {
+        Cf::dictionary(&[("uid", Cf::string(uid)?), ("drift", Cf::number(drift.into()))])
+    };
+    let description = Cf::dictionary(&[
+        ("uid", Cf::string(MULTI_OUTPUT_UID)?),
+        ("name", Cf::string(MULTI_OUTPUT_NAME)?),
+        ("subdevices", Cf::array(&[sub(main, false)?, sub(other, true)?])),
+        ("master", Cf::string(main)?),
+        ("stacked", Cf::number(1)),
+        ("private", Cf::number(0)),
+    ])?;
+    let mut device: AudioObjectId = 0;
+    // SAFETY: the description is a CFDictionary; `device` is written on success
+    let status = unsafe { macos::AudioHardwareCreateAggregateDevice(description.0, &mut device) };
+    ensure!(status == 0, "CoreAudio couldn't make the Multi-Output device ({status})");
+    Ok(device)
+}
+
+fn set_default_output(device: AudioObjectId) -> Result<()> {
+    let size = std::mem::size_of::<AudioObjectId>() as u32;
+    // SAFETY: the data is one AudioObjectID, and `size` says so
+    let status = unsafe {
+        macos::AudioObjectSetPropertyData(
+            macos::SYSTEM_OBJECT,
+            &macos::address(macos::DEFAULT_OUTPUT),
+            0,
+            std::ptr::null(),
+            size,
+            (&raw const device).cast(),
+        )
+    };
+    ensure!(status == 0, "can't change the default output ({status})");
+    Ok(())
+}
+
+/// The device's UID, which (unlike its ID) stays the same across reboots.
+fn uid(device: AudioObjectId) -> Result<String> {
+    cf::string_property(device, macos::DEVICE_UID).with_context(|| format!("device {device} has no UID"))
+}
+
+/// Set `capture-device` in config.toml, keeping the rest of the file.
+fn save_capture_device(name: &str) -> Result<std::path::PathBuf> {
+    let path = crate::config::default_path()?;
+    let text = match std::fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e).with_context(|| format!("read {}", path.display())),
+    };
+    let line = format!("capture-device = {}", toml::Value::String(name.to_string()));
+    let mut lines: Vec<String> = text.lines().map(str::to_string).collect();
+    let key = |l: &str| {
+        let l = l.trim_start();
+        let sets = |k: &str| l.strip_prefix(k).is_some_and(|rest| rest.trim_start().starts_with('='));
+        sets("capture-device") || sets("capture_device")
+    };
+    // Top-level keys come before the first [table]
+    let tables = lines.iter().position(|l| l.trim_start().starts_with('[')).unwrap_or(lines.len());
+    match lines[..tables].iter().position(|l| key(l)) {
+        Some(i) => lines[i] = line,
+        None => lines.insert(tables, line),
+    }
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).with_context(|| format!("create {}", dir.display()))?;
+    }
+    std::fs::write(&path, lines.join("\n") + "\n").with_context(|| format!("write {}", path.display()))?;
+    Ok(path)
+}
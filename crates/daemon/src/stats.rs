@@ -0,0 +1,155 @@
+// Periodic stats (--stats-interval): a record of the link's state every
+// interval, to stdout or --stats-file, for graphing instead of reading logs.
+// The pipelines keep totals; rates are the change since the previous record
+// (null in the first one, or after a rebuild reset the totals). As JSON, one
+// object per line:
+//   {"time":<unix s>,
+//    "sending":{"packets":…,"bytes":…,"packets_per_s":…,"kbit_per_s":…,"bitrate":…,
+//               "queued_ms":…,"xruns":{…},"level":{"rms_db":[…],"peak_db":[…]},
+//               "receivers":[<report>…]},
+//    "receiving":{"jitterbuffer_ms":…,"latency_ms":…,"queued_ms":…,"xruns":{…},"level":{…},
+//                 "senders":[<report> + "packets_per_s","state","rtp","glitches"…]}}
+// with reports as in `status` (packets, lost, loss_pct, jitter_ms, rtt_ms…),
+// "rtp" what the receiver counted from sequence numbers (gaps, reordered,
+// duplicates, late, loss_pct, window_loss_pct…), "glitches" the clicks and
+// dropouts heard in its decoded audio, and a side null while it isn't running.
+// "xruns" counts underruns, overruns and capture overruns.
+//
+// `report` is everything at once, for SIGUSR1: the `status` reply, a record
+// (totals, no rates), the peers, zones and recent xruns.
+use anyhow::{Context, Result};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+use ab_core::pipeline::TxTotals;
+
+use crate::args::{Args, StatsFormatArg};
+use crate::control::{self, Control};
+
+/// Where records go.
+pub type Out = Box<dyn Write + Send>;
+
+/// `--stats-file`, appended to, or stdout.
+pub fn open(a: &Args) -> Result<Out> {
+    Ok(match &a.stats_file {
+        Some(path) => Box::new(
+            std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .with_context(|| format!("open {}", path.display()))?,
+        ),
+        None => Box::new(std::io::stdout()),
+    })
+}
+
+/// What the previous record counted.
+#[derive(Default)]
+struct Previous {
+    at: Option<Instant>,
+    sent: Option<TxTotals>,
+    /// Packets received per SSRC.
+    received: HashMap<u32, u64>,
+}
+
+/// Write a record every `interval` for as long as the daemon runs.
+pub fn spawn(control: Arc<Control>, interval: Duration, format: StatsFormatArg, mut out: Out) {
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(interval);
+        let mut previous = Previous::default();
+        loop {
+            tick.tick().await;
+            let record = record(&control, &mut previous).await;
+            let line = match format {
+                StatsFormatArg::Json => record.to_string(),
+            };
+            if let Err(e) = writeln!(out, "{line}").and_then(|()| out.flush()) {
+                warn!(target: "stats", "can't write stats ({e}); stopping them");
+                return;
+            }
+        }
+    });
+}
+
+async fn record(control: &Control, previous: &mut Previous) -> Value {
+    let now = Instant::now();
+    let secs = previous.at.replace(now).map(|t| now.duration_since(t).as_secs_f64());
+    let per_s = |count: u64, before: Option<u64>| {
+        Some(count.checked_sub(before?)? as f64 / secs.filter(|s| *s > 0.0)?)
+    };
+
+    let sending = control.sending.lock().await;
+    let sending = sending.as_ref().and_then(|s| {
+        s.with_sender(|tx| {
+            let sent = tx.sent();
+            let before = previous.sent.replace(sent);
+            json!({
+                "packets": sent.packets,
+                "bytes": sent.bytes,
+                "packets_per_s": per_s(sent.packets, before.map(|b| b.packets)),
+                "kbit_per_s": per_s(sent.bytes, before.map(|b| b.bytes)).map(|b| b * 8.0 / 1000.0),
+                "bitrate": tx.bitrate(),
+                "queued_ms": tx.queued_ms(),
+                "xruns": control::xrun_counts_json(tx.xrun_counts()),
+                "level": tx.level().map(control::level_json),
+                "receivers": tx.reports().iter().map(|r| control::report_json(r, None, None)).collect::<Vec<_>>(),
+            })
+        })
+    });
+    if sending.is_none() {
+        previous.sent = None;
+    }
+
+    let rx = control.rx.read().unwrap();
+    let mut received = HashMap::new();
+    let receiving = rx.as_ref().map(|rx| {
+        let states = rx.stream_states();
+        let rtp = rx.rtp_stats();
+        let glitches = rx.glitches();
+        let senders: Vec<Value> = rx
+            .reports()
+            .iter()
+            .map(|r| {
+                received.insert(r.ssrc, r.packets_received);
+                let mut v = control::report_json(r, None, None);
+                v["packets_per_s"] = per_s(r.packets_received, previous.received.get(&r.ssrc).copied()).into();
+                v["state"] = states.iter().find(|(ssrc, _)| *ssrc == r.ssrc).map(|(_, s)| s.name()).into();
+                v["rtp"] = rtp.iter().find(|s| s.ssrc == r.ssrc).map(control::rtp_json).into();
+                v["glitches"] = glitches.iter().find(|g| g.ssrc == r.ssrc).map(control::glitches_json).into();
+                v
+            })
+            .collect();
+        json!({
+            "jitterbuffer_ms": rx.jitter_ms(),
+            "latency_ms": rx.latency().map(|l| l.as_secs_f64() * 1000.0),
+            "queued_ms": rx.queued_ms(),
+            "xruns": control::xrun_counts_json(rx.xrun_counts()),
+            "level": rx.level().map(control::level_json),
+            "senders": senders,
+        })
+    });
+    previous.received = received;
+
+    json!({
+        "time": SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs_f64(),
+        "sending": sending,
+        "receiving": receiving,
+    })
+}
+
+/// The daemon's whole state, as one object.
+pub async fn report(control: &Control) -> Value {
+    let peers = control::answer(&json!({ "cmd": "list-peers" }), control).await;
+    let zones = control::answer(&json!({ "cmd": "list-zones" }), control).await;
+    json!({
+        "status": control::status(control).await,
+        "stats": record(control, &mut Previous::default()).await,
+        "peers": peers["peers"],
+        "zones": zones["zones"],
+        "xruns": control::answer(&json!({ "cmd": "xruns" }), control).await,
+    })
+}
@@ -0,0 +1,282 @@
+// Pipeline watchdog. A pipeline that posts an ERROR (device unplugged, sound
+// server restarted, an element failing) stops making sound but stays up, so
+// once a second the supervisor looks at the main sender and receiver and
+// rebuilds a failed one from its config. While rebuilds fail, or the new
+// pipeline fails again, it waits twice as long each time, up to a minute; one
+// that stays up for `STABLE` has recovered. Attempts show in `status`.
+//
+// When PulseAudio or PipeWire restarts, pulsesrc/pulsesink lose it for good.
+// Rebuilds then wait for its socket to come back, and devices it renamed on
+// the way (see `devices::follow`) are followed.
+use anyhow::Result;
+use serde_json::{Value, json};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use ab_core::devices::{self, AudioDevice};
+use ab_core::pipeline::{Receiver, ReceiverConfig, Sender, build_receiver};
+
+use crate::control::Control;
+use crate::dot;
+use crate::events::Event;
+
+/// Log under the "sender" or "receiver" target, picked at run time.
+macro_rules! log_as {
+    ($level:ident, $side:expr, $($arg:tt)+) => {
+        match $side {
+            "sender" => tracing::$level!(target: "sender", $($arg)+),
+            _ => tracing::$level!(target: "receiver", $($arg)+),
+        }
+    };
+}
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// How long a rebuilt pipeline must keep working to count as recovered.
+const STABLE: Duration = Duration::from_secs(30);
+/// Rebuilds without recovering after which a pipeline is stuck, and a
+/// restart of the whole daemon may do better (see `systemd.rs`).
+const STUCK_AFTER: u32 = 5;
+
+/// One pipeline's recovery, while there is one.
+struct Recovery {
+    /// Rebuilds tried so far.
+    attempts: u32,
+    /// What broke it last.
+    error: String,
+    /// When to try next; None while a new pipeline is on probation.
+    next: Option<Instant>,
+    /// Since when the current pipeline has been working.
+    working: Option<Instant>,
+}
+
+/// Recovery state of one pipeline.
+#[derive(Default)]
+pub struct Watchdog {
+    state: Mutex<Option<Recovery>>,
+}
+
+/// The main sender's and receiver's.
+#[derive(Default)]
+pub struct Watchdogs {
+    pub sender: Watchdog,
+    pub receiver: Watchdog,
+}
+
+impl Watchdog {
+    /// Take in the pipeline's current `error`; true when it's time to rebuild.
+    fn due(&self, side: &'static str, error: Option<String>) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        match (error, state.as_mut()) {
+            (None, None) => false,
+            (None, Some(r)) => {
+                if r.working.get_or_insert(now).elapsed() >= STABLE {
+                    log_as!(info, side, "recovered after {} attempt(s)", r.attempts);
+                    *state = None;
+                }
+                false
+            }
+            (Some(error), None) => {
+                log_as!(warn, side, "pipeline failed ({error}); rebuilding");
+                *state = Some(Recovery { attempts: 0, error, next: Some(now), working: None });
+                true
+            }
+            (Some(error), Some(r)) => {
+                if r.working.take().is_some() {
+                    let wait = backoff(r.attempts);
+                    log_as!(warn, side, "failed again ({error}); next try in {} s", wait.as_secs());
+                    r.error = error;
+                    r.next = Some(now + wait);
+                }
+                r.next.is_some_and(|t| now >= t)
+            }
+        }
+    }
+
+    /// Record how a rebuild went.
+    fn rebuilt(&self, side: &'static str, result: Result<()>) {
+        let mut state = self.state.lock().unwrap();
+        let Some(r) = state.as_mut() else { return };
+        r.attempts += 1;
+        match result {
+            Ok(()) => {
+                log_as!(info, side, "rebuilt the pipeline (attempt {})", r.attempts);
+                r.next = None;
+                r.working = Some(Instant::now());
+            }
+            Err(e) => {
+                let wait = backoff(r.attempts);
+                log_as!(warn, side, "rebuild failed: {e:#}; next try in {} s", wait.as_secs());
+                r.error = format!("{e:#}");
+                r.next = Some(Instant::now() + wait);
+            }
+        }
+    }
+
+    /// A failure is being dealt with.
+    fn failing(&self) -> bool {
+        self.state.lock().unwrap().is_some()
+    }
+
+    /// `STUCK_AFTER` rebuilds haven't brought it back.
+    pub fn stuck(&self) -> bool {
+        self.state.lock().unwrap().as_ref().is_some_and(|r| r.attempts >= STUCK_AFTER && r.working.is_none())
+    }
+
+    fn reset(&self) {
+        *self.state.lock().unwrap() = None;
+    }
+
+    /// As `status` shows it; null while the pipeline is healthy.
+    pub fn json(&self) -> Value {
+        let state = self.state.lock().unwrap();
+        let Some(r) = state.as_ref() else { return Value::Null };
+        json!({
+            "attempts": r.attempts,
+            "error": r.error,
+            "retry_in_s": r.next.map(|t| t.saturating_duration_since(Instant::now()).as_secs()),
+        })
+    }
+}
+
+/// 1 s after the first failed attempt, doubling up to `MAX_BACKOFF`.
+fn backoff(attempts: u32) -> Duration {
+    Duration::from_secs(1 << attempts.min(6)).min(MAX_BACKOFF)
+}
+
+/// Watch the main pipelines for as long as the daemon runs.
+pub fn spawn(control: Arc<Control>) {
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(CHECK_INTERVAL);
+        loop {
+            tick.tick().await;
+            check_receiver(&control);
+            check_sender(&control).await;
+        }
+    });
+}
+
+fn check_receiver(control: &Control) {
+    let dog = &control.watchdog.receiver;
+    let error = match control.rx.read().unwrap().as_ref() {
+        Some(rx) => rx.error(),
+        None => return dog.reset(),
+    };
+    if let Some(e) = error.as_ref().filter(|_| !dog.failing()) {
+        control.events.emit(Event::Error { side: "receiver", error: e.clone() });
+        dot::on_error(control, "receiver", || control.rx.read().unwrap().as_ref().map(Receiver::dot));
+    }
+    if dog.due("receiver", error.clone()) && server_back(error.as_deref()) {
+        let result = rebuild_receiver(control);
+        if result.is_ok() {
+            control.events.emit(Event::Reconnected { side: "receiver", why: "rebuilt after failing" });
+        }
+        dog.rebuilt("receiver", result);
+    }
+}
+
+/// A new receiver from the current config, at the old one's volume (and mute).
+fn rebuild_receiver(control: &Control) -> Result<()> {
+    let mut cfg = control.rx_cfg.read().unwrap().clone();
+    follow_device("receiver", &mut cfg.output_device, devices::sinks);
+    replace_receiver(control, cfg)
+}
+
+/// Swap the receiver for one built from `cfg`, at the old one's volume (and mute).
+fn replace_receiver(control: &Control, cfg: ReceiverConfig) -> Result<()> {
+    {
+        let mut guard = control.rx.write().unwrap();
+        let Some(rx) = guard.as_mut() else { return Ok(()) };
+        let (volume, muted) = (rx.volume(), rx.muted());
+        // The old pipeline holds the ports; let go first
+        rx.stop();
+        let next = build_receiver(&cfg)?;
+        if let Err(e) = next.start() {
+            next.stop();
+            return Err(e);
+        }
+        next.set_volume(volume)?;
+        next.set_muted(muted)?;
+        *rx = next;
+    }
+    *control.rx_cfg.write().unwrap() = cfg;
+    Ok(())
+}
+
+/// Rebuild the receiver and/or the sender though nothing failed: the devices
+/// under them changed (see `macos.rs`).
+pub async fn restart(control: &Control, receiver: bool, sender: bool, why: &str) {
+    if receiver && control.rx.read().unwrap().is_some() {
+        info!(target: "receiver", "{why}: rebuilding the pipeline");
+        if let Err(e) = rebuild_receiver(control) {
+            // Stopping the old pipeline closed its sockets: build it again
+            warn!(target: "receiver", "rebuild failed, rebuilding the old pipeline: {e:#}");
+            let cfg = control.rx_cfg.read().unwrap().clone();
+            if let Err(e) = replace_receiver(control, cfg) {
+                warn!(target: "receiver", "{e:#}");
+            }
+        }
+    }
+    if !sender {
+        return;
+    }
+    // A sender left without a pipeline is the watchdog's to retry
+    if let Some(s) = control.sending.lock().await.as_ref() {
+        info!(target: "sender", "{why}: rebuilding the pipeline");
+        if let Err(e) = s.rebuild() {
+            warn!(target: "sender", "rebuild failed: {e:#}");
+        }
+    }
+}
+
+async fn check_sender(control: &Control) {
+    let dog = &control.watchdog.sender;
+    let mut sending = control.sending.lock().await;
+    let Some(s) = sending.as_mut() else { return dog.reset() };
+    // No pipeline at all: a rebuild (here, or after a destination moved) failed
+    let error = s.with_sender(Sender::error).unwrap_or_else(|| Some("no pipeline".into()));
+    if let Some(e) = error.as_ref().filter(|_| !dog.failing()) {
+        control.events.emit(Event::Error { side: "sender", error: e.clone() });
+        dot::on_error(control, "sender", || s.with_sender(Sender::dot));
+    }
+    if dog.due("sender", error.clone()) && server_back(error.as_deref()) {
+        follow_device("sender", &mut s.cfg.capture_device, devices::sources);
+        let result = s.rebuild();
+        if result.is_ok() {
+            control.events.emit(Event::Reconnected { side: "sender", why: "rebuilt after failing" });
+        }
+        dog.rebuilt("sender", result);
+    }
+}
+
+/// False while the sound server an `error` lost isn't accepting connections
+/// again; nothing would come up before it does.
+fn server_back(error: Option<&str>) -> bool {
+    if !error.is_some_and(devices::is_disconnect) {
+        return true;
+    }
+    // A remote or custom server: no telling, just try
+    if std::env::var_os("PULSE_SERVER").is_some() {
+        return true;
+    }
+    // PulseAudio's socket, which pipewire-pulse serves too
+    let Some(dir) = std::env::var_os("XDG_RUNTIME_DIR") else { return true };
+    std::os::unix::net::UnixStream::connect(std::path::Path::new(&dir).join("pulse/native")).is_ok()
+}
+
+/// Point `device` at what the sound server calls it now.
+fn follow_device(side: &'static str, device: &mut Option<String>, list: fn() -> Result<Vec<AudioDevice>>) {
+    // macOS devices are indices, nothing to follow
+    let Some(id) = device.as_deref().filter(|_| cfg!(target_os = "linux")) else { return };
+    let Ok(found) = list() else { return };
+    match devices::follow(id, &found) {
+        Some(now) if now != id => {
+            log_as!(info, side, "device {id} is now {now}");
+            *device = Some(now);
+        }
+        Some(_) => {}
+        None => log_as!(warn, side, "device {id} is gone; trying it anyway"),
+    }
+}
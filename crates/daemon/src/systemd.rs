@@ -0,0 +1,252 @@
+// systemd integration. Under a Type=notify unit the daemon says when it's
+// ready (the control socket is up and the pipelines are built), reloading
+// (SIGHUP) and stopping. With WatchdogSec= it pings the watchdog at half the
+// timeout for as long as it's healthy: the runtime is turning and neither
+// main pipeline is stuck, that is, still failing after `STUCK_AFTER` rebuilds
+// (see `supervise.rs`). Then the pings stop and systemd restarts the daemon.
+// A .socket unit can hold the control socket (socket activation); it's taken
+// over from systemd instead of bound, and left in place on exit.
+//
+// `ab-daemon install-service [--user | --run-as USER] [--socket] [-- FLAGS…]`
+// writes such units, with these flags, and enables and starts them;
+// `uninstall-service [--user]` stops, disables and removes them. A system unit
+// needs --run-as: as root the daemon couldn't reach anyone's PipeWire/Pulse
+// session, so it runs as USER with USER's XDG_RUNTIME_DIR.
+//
+// Outside systemd (no NOTIFY_SOCKET, WATCHDOG_USEC or LISTEN_FDS) all of
+// this does nothing.
+use anyhow::{Context, Result, bail, ensure};
+use std::os::fd::FromRawFd;
+use std::os::unix::net::{UnixDatagram, UnixListener};
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+use crate::control::Control;
+use crate::identity::config_dir;
+
+/// The first file descriptor systemd passes (SD_LISTEN_FDS_START).
+const LISTEN_FDS_START: i32 = 3;
+const UNIT: &str = "ab-daemon";
+/// The watchdog timeout install-service sets.
+const WATCHDOG_SEC: u64 = 30;
+
+/// Tell systemd `state` (e.g. "READY=1"), if it's waiting to hear.
+pub fn notify(state: &str) {
+    let Some(path) = std::env::var_os("NOTIFY_SOCKET") else { return };
+    let sent = UnixDatagram::unbound().and_then(|socket| {
+        // '@' names a socket in the abstract namespace
+        #[cfg(target_os = "linux")]
+        if let Some(name) = path.as_encoded_bytes().strip_prefix(b"@") {
+            use std::os::linux::net::SocketAddrExt;
+            let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+            return socket.send_to_addr(state.as_bytes(), &addr);
+        }
+        socket.send_to(state.as_bytes(), &path)
+    });
+    if let Err(e) = sent {
+        debug!(target: "systemd", "notify {state:?}: {e}");
+    }
+}
+
+/// Ping the watchdog while the daemon is healthy, if systemd keeps one on us.
+pub fn spawn_watchdog(control: Arc<Control>) {
+    let Some(timeout) = watchdog_timeout() else { return };
+    info!(target: "systemd", "watchdog: pinging every {} ms", (timeout / 2).as_millis());
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(timeout / 2);
+        let mut healthy = true;
+        loop {
+            tick.tick().await;
+            let stuck = control.watchdog.sender.stuck() || control.watchdog.receiver.stuck();
+            if !stuck {
+                notify("WATCHDOG=1");
+            } else if healthy {
+                warn!(target: "systemd", "a pipeline can't be rebuilt; leaving it to the watchdog to restart us");
+            }
+            healthy = !stuck;
+        }
+    });
+}
+
+fn watchdog_timeout() -> Option<Duration> {
+    let usec: u64 = std::env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+    // Set for another process
+    if let Ok(pid) = std::env::var("WATCHDOG_PID") {
+        if pid.parse() != Ok(std::process::id()) {
+            return None;
+        }
+    }
+    (usec > 0).then(|| Duration::from_micros(usec))
+}
+
+/// The control socket a .socket unit passed us, if started that way.
+pub fn activated_socket() -> Result<Option<UnixListener>> {
+    let pid: Option<u32> = std::env::var("LISTEN_PID").ok().and_then(|p| p.parse().ok());
+    let fds: u32 = std::env::var("LISTEN_FDS").ok().and_then(|n| n.parse().ok()).unwrap_or(0);
+    if pid != Some(std::process::id()) || fds == 0 {
+        return Ok(None);
+    }
+    ensure!(fds == 1, "systemd passed {fds} sockets; only the control socket is taken");
+    // SAFETY: LISTEN_PID says the descriptors from LISTEN_FDS_START on were
+    // passed to this process, and nothing else has taken the first one
+    let listener = unsafe { UnixListener::from_raw_fd(LISTEN_FDS_START) };
+    listener.set_nonblocking(true)?;
+    Ok(Some(listener))
+}
+
+/// `install-service`: write the units, then (unless `!enable`) enable and
+/// start them.
+pub fn install(user: bool, run_as: Option<&str>, socket: bool, enable: bool, flags: &[String]) -> Result<()> {
+    if !cfg!(target_os = "linux") {
+        bail!("systemd units are for Linux");
+    }
+    ensure!(
+        user || run_as.is_some(),
+        "a system unit would run the daemon as root, away from your audio session; \
+         use --user, or --run-as USER"
+    );
+    let account = match run_as {
+        Some(name) => format!("User={name}\nEnvironment=XDG_RUNTIME_DIR=/run/user/{}\n", uid_of(name)?),
+        None => String::new(),
+    };
+    let dir = if user {
+        config_dir()?.parent().context("no config directory")?.join("systemd/user")
+    } else {
+        PathBuf::from("/etc/systemd/system")
+    };
+    let exe = std::env::current_exe().context("can't tell where ab-daemon is")?;
+    let mut exec = vec![quote(&exe.to_string_lossy())];
+    if socket && !user {
+        // Where the .socket unit puts it; %t is /run for system units
+        exec.push("--control-socket %t/audiobridge.sock".into());
+    }
+    exec.extend(flags.iter().map(|f| quote(f)));
+    let service = format!(
+        "[Unit]\n\
+         Description=audio-bridge LAN audio daemon\n\
+         After=network-online.target{after}\n\
+         {socket_dep}\
+         \n\
+         [Service]\n\
+         Type=notify\n\
+         NotifyAccess=main\n\
+         {account}\
+         ExecStart={exec}\n\
+         ExecReload=/bin/kill -HUP $MAINPID\n\
+         Restart=on-failure\n\
+         RestartSec=2\n\
+         WatchdogSec={WATCHDOG_SEC}\n\
+         \n\
+         [Install]\n\
+         WantedBy={wanted_by}\n",
+        after = if user { " pipewire.service pipewire-pulse.service pulseaudio.service" } else { "" },
+        socket_dep = if socket { format!("Requires={UNIT}.socket\nAfter={UNIT}.socket\n") } else { String::new() },
+        exec = exec.join(" "),
+        wanted_by = if user { "default.target" } else { "multi-user.target" },
+    );
+    std::fs::create_dir_all(&dir).with_context(|| format!("create {}", dir.display()))?;
+    let mut written = vec![write(&dir.join(format!("{UNIT}.service")), &service)?];
+    if socket {
+        // The socket is 0600, so it belongs to whoever runs `ctl`
+        let owner = run_as.map(|name| format!("SocketUser={name}\n")).unwrap_or_default();
+        let unit = format!(
+            "[Unit]\n\
+             Description=audio-bridge control socket\n\
+             \n\
+             [Socket]\n\
+             ListenStream=%t/audiobridge.sock\n\
+             SocketMode=0600\n\
+             {owner}\
+             \n\
+             [Install]\n\
+             WantedBy=sockets.target\n"
+        );
+        written.push(write(&dir.join(format!("{UNIT}.socket")), &unit)?);
+    }
+    for path in &written {
+        println!("Wrote {}", path.display());
+    }
+    let scope = if user { "--user " } else { "" };
+    if !enable {
+        println!("Start it with: systemctl {scope}enable --now {UNIT}.service");
+        return Ok(());
+    }
+    systemctl(user, &["daemon-reload"])?;
+    if socket {
+        systemctl(user, &["enable", &format!("{UNIT}.socket")])?;
+    }
+    systemctl(user, &["enable", "--now", &format!("{UNIT}.service")])?;
+    println!("Enabled and started; follow it with: journalctl {scope}-u {UNIT} -f");
+    if user {
+        println!("To keep it running while you're logged out: loginctl enable-linger");
+    }
+    Ok(())
+}
+
+/// `uninstall-service`: stop and disable the units, then remove them.
+pub fn uninstall(user: bool) -> Result<()> {
+    if !cfg!(target_os = "linux") {
+        bail!("systemd units are for Linux");
+    }
+    let dir = if user {
+        config_dir()?.parent().context("no config directory")?.join("systemd/user")
+    } else {
+        PathBuf::from("/etc/systemd/system")
+    };
+    let mut removed = false;
+    for kind in ["socket", "service"] {
+        let path = dir.join(format!("{UNIT}.{kind}"));
+        if !path.exists() {
+            continue;
+        }
+        // Already stopped or disabled is fine
+        let _ = systemctl(user, &["disable", "--now", &format!("{UNIT}.{kind}")]);
+        std::fs::remove_file(&path).with_context(|| format!("remove {}", path.display()))?;
+        println!("Removed {}", path.display());
+        removed = true;
+    }
+    if !removed {
+        println!("{UNIT} isn't installed in {}", dir.display());
+        return Ok(());
+    }
+    systemctl(user, &["daemon-reload"])
+}
+
+/// `name`'s uid, from the password database.
+fn uid_of(name: &str) -> Result<u32> {
+    let cname = std::ffi::CString::new(name).with_context(|| format!("bad user name '{name}'"))?;
+    // SAFETY: `cname` is NUL-terminated and outlives the call
+    let entry = unsafe { libc::getpwnam(cname.as_ptr()) };
+    ensure!(!entry.is_null(), "no user '{name}'");
+    // SAFETY: non-null, and nothing else has called getpw* since
+    Ok(unsafe { (*entry).pw_uid })
+}
+
+fn write(path: &std::path::Path, text: &str) -> Result<PathBuf> {
+    std::fs::write(path, text).with_context(|| format!("write {}", path.display()))?;
+    Ok(path.to_path_buf())
+}
+
+fn systemctl(user: bool, args: &[&str]) -> Result<()> {
+    let mut cmd = Command::new("systemctl");
+    if user {
+        cmd.arg("--user");
+    }
+    let status = cmd.args(args).status().context("run systemctl")?;
+    ensure!(status.success(), "systemctl {} failed ({status})", args.join(" "));
+    Ok(())
+}
+
+/// `arg` as one ExecStart= word: quoted if it needs to be, with systemd's
+/// specifiers and variables escaped.
+fn quote(arg: &str) -> String {
+    let escaped = arg.replace('\\', "\\\\").replace('"', "\\\"").replace('%', "%%").replace('$', "$$");
+    if escaped.is_empty() || escaped.contains(|c: char| c.is_whitespace() || c == '\'' || c == ';') {
+        format!("\"{escaped}\"")
+    } else {
+        escaped
+    }
+}
@@ -0,0 +1,202 @@
+// macOS (--capture-app): one app's audio without a loopback driver, through a
+// Core Audio process tap (macOS 14.4 and later). The tap takes a stereo mix
+// of what the app's processes play, which still plays as before; a private
+// aggregate device holding the tap (and the default output, for its clock)
+// then has that mix as its input, and osxaudiosrc captures it like any other
+// device. The tap is described with a CATapDescription, made through the
+// Objective-C runtime, and the tap calls are looked up when first needed so
+// older macOS runs without them. The first tap asks for permission to record
+// system audio (for the terminal or whatever started the daemon); denied, the
+// tap is silent.
+use anyhow::{Context, Result, bail, ensure};
+use std::ffi::{CStr, c_char, c_void};
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicU32, Ordering};
+use tracing::warn;
+
+use crate::cf::{self, Cf};
+use crate::macos::{self, AudioObjectId};
+
+/// kAudioHardwarePropertyProcessObjectList
+const PROCESSES: u32 = macos::fourcc(b"prs#");
+/// kAudioProcessPropertyPID
+const PROCESS_PID: u32 = macos::fourcc(b"ppid");
+/// kAudioProcessPropertyBundleID
+const PROCESS_BUNDLE_ID: u32 = macos::fourcc(b"pbid");
+/// kAudioTapPropertyUID
+const TAP_UID: u32 = macos::fourcc(b"tuid");
+const TAP_NAME: &str = "Audio Bridge Tap";
+
+#[link(name = "objc")]
+unsafe extern "C" {
+    fn objc_getClass(name: *const c_char) -> *const c_void;
+    fn sel_registerName(name: *const c_char) -> *const c_void;
+    /// Called through a pointer of each message's own type.
+    fn objc_msgSend();
+}
+
+/// AudioHardwareCreateProcessTap / AudioHardwareDestroyProcessTap.
+struct TapApi {
+    create: unsafe extern "C" fn(description: *const c_void, tap: *mut AudioObjectId) -> i32,
+    destroy: unsafe extern "C" fn(tap: AudioObjectId) -> i32,
+}
+
+/// A process playing (or that played) audio, as CoreAudio sees it.
+#[derive(Debug, Clone)]
+pub struct Process {
+    /// Its CoreAudio process object, what a tap is made of.
+    pub object: AudioObjectId,
+    pub pid: i32,
+    /// e.g. "Spotify", "Google Chrome Helper".
+    pub name: String,
+    /// e.g. "com.spotify.client"; may be empty.
+    pub bundle_id: String,
+}
+
+/// The processes whose name or bundle ID contains `query`, ignoring case. An
+/// app can have several (a browser plays from a helper), and all are tapped.
+pub fn processes(query: &str) -> Vec<Process> {
+    let q = query.to_lowercase();
+    macos::objects(PROCESSES)
+        .into_iter()
+        .filter_map(process)
+        .filter(|p| [&p.name, &p.bundle_id].iter().any(|n| n.to_lowercase().contains(&q)))
+        .collect()
+}
+
+fn process(object: AudioObjectId) -> Option<Process> {
+    let mut pid: i32 = 0;
+    let mut size = std::mem::size_of::<i32>() as u32;
+    let (address, data) = (macos::address(PROCESS_PID), (&raw mut pid).cast());
+    // SAFETY: the property is one pid_t, which `pid` is, and `size` says so
+    let status = unsafe { macos::AudioObjectGetPropertyData(object, &address, 0, std::ptr::null(), &mut size, data) };
+    if status != 0 {
+        return None;
+    }
+    let mut buffer = [0u8; 256];
+    // SAFETY: `buffer` is as long as we say
+    let len = unsafe { libc::proc_name(pid, buffer.as_mut_ptr().cast(), buffer.len() as u32) };
+    let name = String::from_utf8_lossy(&buffer[..len.max(0) as usize]).into_owned();
+    let bundle_id = cf::string_property(object, PROCESS_BUNDLE_ID).unwrap_or_default();
+    Some(Process { object, pid, name, bundle_id })
+}
+
+/// A tap on some processes, and the device it's captured from. Both go when
+/// it's dropped.
+pub struct Tap {
+    /// What osxaudiosrc captures from.
+    pub device: AudioObjectId,
+    /// The process objects tapped.
+    pub processes: Vec<AudioObjectId>,
+    tap: AudioObjectId,
+}
+
+/// Tap `processes` (process objects), mixed to stereo.
+pub fn create(processes: &[AudioObjectId]) -> Result<Tap> {
+    let api = api()?;
+    let description = describe(processes)?;
+    let mut tap: AudioObjectId = 0;
+    // SAFETY: the description is a CATapDescription; `tap` is written on success
+    let status = unsafe { (api.create)(description.0, &mut tap) };
+    ensure!(status == 0, "CoreAudio couldn't make the tap ({status})");
+    let device = match aggregate(tap) {
+        Ok(device) => device,
+        Err(e) => {
+            // SAFETY: the tap is ours, and used by nothing yet
+            unsafe { (api.destroy)(tap) };
+            return Err(e);
+        }
+    };
+    Ok(Tap { device, processes: processes.to_vec(), tap })
+}
+
+impl Drop for Tap {
+    fn drop(&mut self) {
+        // SAFETY: both are ours; the device goes first, as it holds the tap
+        let status = unsafe { macos::AudioHardwareDestroyAggregateDevice(self.device) };
+        if status != 0 {
+            warn!(target: "sender", "couldn't remove the tap device ({status})");
+        }
+        if let Ok(api) = api() {
+            // SAFETY: as above
+            unsafe { (api.destroy)(self.tap) };
+        }
+    }
+}
+
+fn api() -> Result<&'static TapApi> {
+    static API: OnceLock<Option<TapApi>> = OnceLock::new();
+    let api = API.get_or_init(|| {
+        let create = symbol(c"AudioHardwareCreateProcessTap")?;
+        let destroy = symbol(c"AudioHardwareDestroyProcessTap")?;
+        // SAFETY: these are CoreAudio's, of the types Apple declares
+        Some(unsafe { TapApi { create: std::mem::transmute(create), destroy: std::mem::transmute(destroy) } })
+    });
+    api.as_ref().context("--capture-app needs macOS 14.4 or later (Core Audio process taps)")
+}
+
+fn symbol(name: &CStr) -> Option<*mut c_void> {
+    // SAFETY: `name` is NUL-terminated; RTLD_DEFAULT searches what's loaded
+    let f = unsafe { libc::dlsym(libc::RTLD_DEFAULT, name.as_ptr()) };
+    (!f.is_null()).then_some(f)
+}
+
+/// `[[CATapDescription alloc] initStereoMixdownOfProcesses:…]`, private to us.
+fn describe(processes: &[AudioObjectId]) -> Result<Cf> {
+    type New = unsafe extern "C" fn(*const c_void, *const c_void) -> *const c_void;
+    type WithObject = unsafe extern "C" fn(*const c_void, *const c_void, *const c_void) -> *const c_void;
+    type WithBool = unsafe extern "C" fn(*const c_void, *const c_void, i8);
+    // SAFETY: objc_msgSend is called as each message's signature has it
+    let (new, with_object, with_bool): (New, WithObject, WithBool) = unsafe {
+        let send = objc_msgSend as *const ();
+        (std::mem::transmute(send), std::mem::transmute(send), std::mem::transmute(send))
+    };
+    let sel = |name: &CStr| {
+        // SAFETY: `name` is NUL-terminated
+        unsafe { sel_registerName(name.as_ptr()) }
+    };
+    // SAFETY: as above
+    let class = unsafe { objc_getClass(c"CATapDescription".as_ptr()) };
+    if class.is_null() {
+        bail!("no CATapDescription (--capture-app needs macOS 14.4 or later)");
+    }
+    // NSArray<NSNumber>, toll-free bridged
+    let objects = Cf::array(&processes.iter().map(|&p| Cf::number(p as i32)).collect::<Vec<_>>());
+    let name = Cf::string(TAP_NAME)?;
+    // SAFETY: alloc, then init… (which takes over the allocation); the
+    // setters take a BOOL and an NSString
+    let description = unsafe {
+        let allocated = new(class, sel(c"alloc"));
+        let description = Cf(with_object(allocated, sel(c"initStereoMixdownOfProcesses:"), objects.0));
+        ensure!(!description.0.is_null(), "CATapDescription wouldn't take the processes");
+        with_bool(description.0, sel(c"setPrivate:"), 1);
+        with_object(description.0, sel(c"setName:"), name.0);
+        description
+    };
+    Ok(description)
+}
+
+/// A private aggregate device with the tap as its input, clocked by the
+/// default output.
+fn aggregate(tap: AudioObjectId) -> Result<AudioObjectId> {
+    static COUNT: AtomicU32 = AtomicU32::new(0);
+    let tap_uid = cf::string_property(tap, TAP_UID).context("the tap has no UID")?;
+    let output = macos::default_device(macos::DEFAULT_OUTPUT).context("no default output device")?;
+    let output_uid = cf::string_property(output, macos::DEVICE_UID).context("the default output has no UID")?;
+    let uid = format!("org.audiobridge.tap.{}.{}", std::process::id(), COUNT.fetch_add(1, Ordering::Relaxed));
+    let description = Cf::dictionary(&[
+        ("uid", Cf::string(&uid)?),
+        ("name", Cf::string(TAP_NAME)?),
+        ("master", Cf::string(&output_uid)?),
+        ("private", Cf::number(1)),
+        ("stacked", Cf::number(0)),
+        ("tapautostart", Cf::number(1)),
+        ("subdevices", Cf::array(&[Cf::dictionary(&[("uid", Cf::string(&output_uid)?)])?])),
+        ("taps", Cf::array(&[Cf::dictionary(&[("uid", Cf::string(&tap_uid)?), ("drift", Cf::number(1))])?])),
+    ])?;
+    let mut device: AudioObjectId = 0;
+    // SAFETY: the description is a CFDictionary; `device` is written on success
+    let status = unsafe { macos::AudioHardwareCreateAggregateDevice(description.0, &mut device) };
+    ensure!(status == 0, "CoreAudio couldn't make the tap device ({status})");
+    Ok(device)
+}
@@ -0,0 +1,199 @@
+// `--send-to` entries that name a peer instead of an address: an mDNS
+// instance name ("studio-mac") or a `.local` hostname ("studio-mac.local").
+// They are resolved once discovery is running and re-checked in the
+// background, so a peer that picks up a new DHCP lease keeps receiving.
+use anyhow::{Result, bail};
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use crate::mdns::{HostResolver, Peer, Peers};
+
+/// How long startup waits for named peers to show up.
+const STARTUP_WAIT: Duration = Duration::from_secs(5);
+/// How often names are re-resolved afterwards.
+const RECHECK_INTERVAL: Duration = Duration::from_secs(10);
+/// Per-query budget for `.local` lookups on the wire.
+const QUERY_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Whether `entry` goes through discovery (IP literals and DNS names don't).
+pub fn is_peer_name(entry: &str) -> bool {
+    if entry.parse::<IpAddr>().is_ok() {
+        return false;
+    }
+    let host = entry.trim_end_matches('.');
+    !host.contains('.') || host.to_ascii_lowercase().ends_with(".local")
+}
+
+/// The `--send-to` list plus what it takes to resolve its named entries.
+#[derive(Clone)]
+pub struct Targets {
+    entries: Vec<String>,
+    peers: Peers,
+    resolver: Option<HostResolver>,
+}
+
+impl Targets {
+    pub fn new(entries: Vec<String>, peers: Peers, resolver: Option<HostResolver>) -> Self {
+        Self { entries, peers, resolver }
+    }
+
+    pub fn any_named(&self) -> bool {
+        self.entries.iter().any(|e| is_peer_name(e))
+    }
+
+    /// Resolve the list, giving discovery up to `STARTUP_WAIT` to find named
+    /// peers. Names still unknown are passed through unchanged so the system
+    /// resolver (nss-mdns, Bonjour, /etc/hosts) gets a go at them.
+    pub async fn resolve_initial(&self) -> Vec<String> {
+        let deadline = Instant::now() + STARTUP_WAIT;
+        let resolved = loop {
+            let resolved = self.resolve_blocking().await;
+            if resolved.iter().all(Option::is_some) || Instant::now() >= deadline {
+                break resolved;
+            }
+            tokio::time::sleep(Duration::from_millis(250)).await;
+        };
+        self.entries
+            .iter()
+            .zip(resolved)
+            .map(|(entry, ip)| match ip {
+                Some(ip) => {
+                    if is_peer_name(entry) {
+                        info!(target: "send-to", "{entry} → {ip}");
+                    }
+                    ip
+                }
+                None => {
+                    warn!(target: "send-to", "peer '{entry}' not discovered; leaving it to the system resolver");
+                    entry.clone()
+                }
+            })
+            .collect()
+    }
+
+    /// Re-resolve every `RECHECK_INTERVAL` and call `on_change` with the new
+    /// list whenever a named peer's address moves. A peer that drops out of
+    /// discovery keeps its last known address. Abort the handle to stop.
+    pub fn watch<F>(self, mut current: Vec<String>, mut on_change: F) -> tokio::task::JoinHandle<()>
+    where
+        F: FnMut(&[String]) + Send + 'static,
+    {
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(RECHECK_INTERVAL).await;
+                let resolved = self.resolve_blocking().await;
+                let next: Vec<String> = current
+                    .iter()
+                    .zip(resolved)
+                    .map(|(prev, ip)| ip.unwrap_or_else(|| prev.clone()))
+                    .collect();
+                if next != current {
+                    for ((entry, old), new) in self.entries.iter().zip(&current).zip(&next) {
+                        if old != new {
+                            info!(target: "send-to", "{entry} moved {old} → {new}; reconnecting");
+                        }
+                    }
+                    on_change(&next);
+                    current = next;
+                }
+            }
+        })
+    }
+
+    /// One pass over the list; `None` marks a named entry nobody answered for.
+    async fn resolve_blocking(&self) -> Vec<Option<String>> {
+        let this = self.clone();
+        tokio::task::spawn_blocking(move || {
+            this.entries
+                .iter()
+                .map(|e| {
+                    if is_peer_name(e) {
+                        this.lookup(e).map(|ip| ip.to_string())
+                    } else {
+                        Some(e.clone())
+                    }
+                })
+                .collect()
+        })
+        .await
+        .unwrap_or_else(|_| vec![None; self.entries.len()])
+    }
+
+    /// The discovered peers behind the entries: named ones, and addresses a
+    /// peer has announced.
+    pub fn discovered(&self) -> Vec<Peer> {
+        let all = self.peers.snapshot();
+        self.entries
+            .iter()
+            .filter_map(|entry| {
+                let host = entry.trim_end_matches('.');
+                match host.parse::<IpAddr>() {
+                    Ok(ip) => all.iter().find(|p| p.addrs.contains(&ip)).cloned(),
+                    Err(_) => self
+                        .peers
+                        .get(host)
+                        .or_else(|| self.peers.by_hostname(host))
+                        .or_else(|| self.peers.by_hostname(&format!("{host}.local"))),
+                }
+            })
+            .collect()
+    }
+
+    fn lookup(&self, name: &str) -> Option<IpAddr> {
+        let host = name.trim_end_matches('.');
+        if host.to_ascii_lowercase().ends_with(".local") {
+            self.peers
+                .by_hostname(host)
+                .and_then(|p| p.addr())
+                .or_else(|| self.resolver.as_ref()?.resolve(host, QUERY_TIMEOUT))
+        } else {
+            self.peers
+                .get(host)
+                .or_else(|| self.peers.by_hostname(&format!("{host}.local")))
+                .and_then(|p| p.addr())
+        }
+    }
+}
+
+/// `--auto-send`: the single discovered peer (matching `hint`, if given).
+/// Gives discovery `STARTUP_WAIT` to see everyone, then keeps waiting if the
+/// LAN is still empty; several candidates is an error listing them.
+pub async fn pick_auto(peers: &Peers, hint: Option<&str>) -> Result<Peer> {
+    let hint = hint.map(str::to_ascii_lowercase);
+    let matches = |p: &Peer| {
+        hint.as_deref().is_none_or(|h| {
+            p.name.to_ascii_lowercase().contains(h) || p.hostname.to_ascii_lowercase().contains(h)
+        })
+    };
+    tokio::time::sleep(STARTUP_WAIT).await;
+    let mut waiting_logged = false;
+    loop {
+        let mut candidates: Vec<Peer> = peers.snapshot().into_iter().filter(|p| matches(p)).collect();
+        match candidates.len() {
+            0 => {
+                if !waiting_logged {
+                    info!(target: "send-to", "--auto-send: waiting for a peer to appear on mDNS…");
+                    waiting_logged = true;
+                }
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+            1 => {
+                let peer = candidates.remove(0);
+                info!(target: "send-to", "--auto-send picked {} ({:?}:{})", peer.name, peer.addrs, peer.port);
+                return Ok(peer);
+            }
+            _ => {
+                let list = candidates
+                    .iter()
+                    .map(|p| format!("  {} ({}) {:?}:{}", p.name, p.hostname, p.addrs, p.port))
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                bail!(
+                    "--auto-send found {} peers; pick one with --auto-send-hint <name> or use --send-to:\n{list}",
+                    candidates.len()
+                );
+            }
+        }
+    }
+}
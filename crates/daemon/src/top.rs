@@ -0,0 +1,191 @@
+// `ab-daemon top`: a live view of the running daemon, redrawn in place on
+// the terminal's alternate screen: what's sent and received, a VU meter per
+// channel (RMS bar, peak, and the peak held over the last couple of seconds),
+// bitrate, loss, jitterbuffer and queue fill, and the peers on the LAN. It
+// polls the control socket like `status`; Ctrl-C leaves.
+use anyhow::Result;
+use serde_json::{Value, json};
+use std::io::Write;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::control;
+
+/// Left end of a meter; anything quieter shows as nothing.
+const FLOOR_DB: f64 = -60.0;
+const METER_WIDTH: usize = 40;
+/// How long a peak stays marked on the meter.
+const PEAK_HOLD: Duration = Duration::from_secs(2);
+
+/// Alternate screen, cursor hidden; and back.
+const ENTER: &str = "\x1b[?1049h\x1b[?25l";
+const LEAVE: &str = "\x1b[?25h\x1b[?1049l";
+
+pub async fn run(path: &Path, refresh: Duration) -> Result<()> {
+    // Complain on the normal screen if nothing is running
+    control::request(path, json!({ "cmd": "status" }))?;
+    let mut out = std::io::stdout();
+    write!(out, "{ENTER}")?;
+    let result = async {
+        let mut tick = tokio::time::interval(refresh);
+        loop {
+            tokio::select! {
+                _ = tick.tick() => {}
+                _ = tokio::signal::ctrl_c() => return Ok(()),
+            }
+            let screen = draw(path, refresh)?;
+            // Overwrite in place: home, each line cleared to its end, the rest cleared
+            write!(out, "\x1b[H{screen}\x1b[J")?;
+            out.flush()?;
+        }
+    }
+    .await;
+    write!(out, "{LEAVE}")?;
+    out.flush()?;
+    result
+}
+
+fn line(screen: &mut String, text: impl AsRef<str>) {
+    screen.push_str(text.as_ref());
+    screen.push_str("\x1b[K\n");
+}
+
+fn draw(path: &Path, refresh: Duration) -> Result<String> {
+    let status = control::request(path, json!({ "cmd": "status" }))?;
+    let levels = control::request(path, json!({ "cmd": "levels", "seconds": PEAK_HOLD.as_secs_f64() }))?;
+    let peers = control::request(path, json!({ "cmd": "list-peers" }))?;
+    let mut s = String::new();
+    line(
+        &mut s,
+        format!(
+            "{} (ab-daemon {}) · every {} ms · Ctrl-C quits",
+            status["name"].as_str().unwrap_or("?"),
+            status["version"].as_str().unwrap_or("?"),
+            refresh.as_millis()
+        ),
+    );
+    line(&mut s, "");
+
+    let tx = &status["sending"];
+    if tx.is_null() {
+        line(&mut s, "SENDING    off");
+    } else {
+        let bitrate = tx["bitrate"].as_i64().map(|b| format!("  {} kbit/s", b / 1000)).unwrap_or_default();
+        let mut flags = String::new();
+        if tx["silent"] == true {
+            flags.push_str("  [silent]");
+        }
+        if let Some(talking) = tx["talking"].as_bool() {
+            flags.push_str(if talking { "  [talking]" } else { "  [muted]" });
+        }
+        if !tx["recovery"].is_null() {
+            flags.push_str("  [failed]");
+        }
+        line(
+            &mut s,
+            format!(
+                "SENDING    → {} :{} {}{bitrate}{}{flags}",
+                control::strings(&tx["hosts"]).join(", "),
+                tx["port"],
+                tx["transport"].as_str().unwrap_or("?"),
+                queued(&tx["queued_ms"]),
+            ),
+        );
+        meters(&mut s, &tx["level"], &levels["sending"]);
+        for r in tx["receivers"].as_array().into_iter().flatten() {
+            line(&mut s, format!("  receiver {}  {}", r["ssrc"].as_str().unwrap_or("?"), control::quality(r)));
+        }
+    }
+    line(&mut s, "");
+
+    let rx = &status["receiving"];
+    if rx.is_null() {
+        line(&mut s, "RECEIVING  off");
+    } else {
+        let mut flags = String::new();
+        if rx["idle"] == true {
+            flags.push_str("  [idle]");
+        }
+        if !rx["recovery"].is_null() {
+            flags.push_str("  [failed]");
+        }
+        line(
+            &mut s,
+            format!(
+                "RECEIVING  :{} {}  volume {:.0}%{}{}{}{flags}",
+                rx["port"],
+                rx["transport"].as_str().unwrap_or("?"),
+                rx["volume"].as_f64().unwrap_or(1.0) * 100.0,
+                rx["jitterbuffer_ms"].as_u64().map(|ms| format!("  jitterbuffer {ms} ms")).unwrap_or_default(),
+                rx["latency_ms"].as_f64().map(|ms| format!("  latency {ms:.0} ms")).unwrap_or_default(),
+                queued(&rx["queued_ms"]),
+            ),
+        );
+        meters(&mut s, &rx["level"], &levels["receiving"]);
+        for r in rx["senders"].as_array().into_iter().flatten() {
+            let who = match (r["name"].as_str(), r["addr"].as_str()) {
+                (Some(name), Some(addr)) => format!("{name} ({addr})"),
+                (None, Some(addr)) => addr.to_string(),
+                _ => format!("ssrc {}", r["ssrc"].as_str().unwrap_or("?")),
+            };
+            let state = r["state"].as_str().unwrap_or("?");
+            line(&mut s, format!("  from {who}  {state}  {}", control::quality(r)));
+        }
+    }
+    line(&mut s, "");
+
+    let peers = peers["peers"].as_array().map(Vec::as_slice).unwrap_or_default();
+    line(&mut s, format!("PEERS      {}", peers.len()));
+    for p in peers {
+        line(
+            &mut s,
+            format!(
+                "  {:<24} {:<24} {:<6} {}",
+                p["name"].as_str().unwrap_or("?"),
+                p["addr"].as_str().unwrap_or("-"),
+                p["port"],
+                p["last_seen_s"].as_u64().map(|s| format!("{s}s ago")).unwrap_or_default()
+            ),
+        );
+    }
+    Ok(s)
+}
+
+fn queued(ms: &Value) -> String {
+    ms.as_f64().map(|ms| format!("  queued {ms:.0} ms")).unwrap_or_default()
+}
+
+/// A meter per channel of `level`, holding the loudest peak in `history`.
+fn meters(s: &mut String, level: &Value, history: &Value) {
+    // Silence comes through as null (-inf dB)
+    let at = |l: &Value, field: &str, ch: usize| l[field][ch].as_f64().unwrap_or(f64::NEG_INFINITY);
+    let channels = level["rms_db"].as_array().map_or(0, Vec::len);
+    if channels == 0 {
+        line(s, "  no level yet");
+    }
+    for ch in 0..channels {
+        let (rms, peak) = (at(level, "rms_db", ch), at(level, "peak_db", ch));
+        let hold = history.as_array().into_iter().flatten().map(|l| at(l, "peak_db", ch)).fold(peak, f64::max);
+        line(s, format!("  {:>2} {} {:>6} dBFS  peak {:>6}", ch + 1, meter(rms, peak, hold), db(rms), db(hold)));
+    }
+}
+
+fn db(v: f64) -> String {
+    if v.is_finite() { format!("{v:.1}") } else { "-inf".into() }
+}
+
+/// RMS as a solid bar, peak as a lighter one past it, the held peak as a tick.
+fn meter(rms: f64, peak: f64, hold: f64) -> String {
+    let cells = |db: f64| {
+        if db.is_finite() { ((db - FLOOR_DB) / -FLOOR_DB).clamp(0.0, 1.0) * METER_WIDTH as f64 } else { 0.0 }
+    };
+    let (rms, peak, hold) = (cells(rms).round() as usize, cells(peak).round() as usize, cells(hold).round() as usize);
+    (0..METER_WIDTH)
+        .map(|i| match i {
+            i if i < rms => '█',
+            i if i < peak => '▒',
+            i if i + 1 == hold => '│',
+            _ => '·',
+        })
+        .collect()
+}
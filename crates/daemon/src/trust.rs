@@ -0,0 +1,161 @@
+// Trust store: the peers this node has paired with or met, kept as
+// `peers.toml` in the config directory and keyed by peer name.
+//
+//   [peers.studio-mac]
+//   key = "<hex Ed25519 public key>"
+//   added = 1760000000
+//   trust = "paired"          # or "seen": pinned on first contact (TOFU)
+//
+// With --tofu, every identity that proves itself on the handshake port is
+// pinned the first time; without it only `ab-daemon pair` adds peers, so
+// strangers can't fill the store or take a name first. Either way a known name
+// turning up with another key is refused loudly. Handshakes run a thread each,
+// so changes go through `lock()`, and the file is replaced atomically.
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard};
+use tracing::{info, warn};
+
+use crate::identity::{config_dir, fingerprint, hex, unhex};
+
+/// How a peer got into the store.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Trust {
+    /// PIN-confirmed with `ab-daemon pair`.
+    #[default]
+    Paired,
+    /// Pinned on first contact.
+    Seen,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrustedPeer {
+    /// Hex Ed25519 public key.
+    pub key: String,
+    /// Unix time it was added.
+    pub added: u64,
+    #[serde(default)]
+    pub trust: Trust,
+}
+
+impl TrustedPeer {
+    pub fn fingerprint(&self) -> String {
+        unhex(&self.key).map(|k| fingerprint(&k)).unwrap_or_else(|| "?".into())
+    }
+}
+
+/// What `record` made of an identity.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Seen {
+    /// Already stored (under this name, which may differ from the claimed one).
+    Known { name: String, trust: Trust },
+    /// First contact; now pinned as `Trust::Seen` (--tofu).
+    New,
+    /// First contact, not pinned (no --tofu).
+    Unknown,
+    /// The name is pinned to a different key. Nothing was changed.
+    Changed,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct TrustStore {
+    #[serde(default)]
+    pub peers: BTreeMap<String, TrustedPeer>,
+}
+
+impl TrustStore {
+    pub fn path() -> Result<PathBuf> {
+        Ok(config_dir()?.join("peers.toml"))
+    }
+
+    /// The store on disk; empty if it doesn't exist yet.
+    pub fn load() -> Result<Self> {
+        let path = Self::path()?;
+        match std::fs::read_to_string(&path) {
+            Ok(text) => toml::from_str(&text).with_context(|| format!("parse {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).with_context(|| format!("read {}", path.display())),
+        }
+    }
+
+    /// Write the store out; readers see the old file or the new one, never
+    /// half of it. Hold `lock()` from `load` to here.
+    pub fn save(&self) -> Result<()> {
+        let path = Self::path()?;
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        let text = toml::to_string_pretty(self).context("serialize trust store")?;
+        let tmp = path.with_extension(format!("toml.{}.tmp", std::process::id()));
+        std::fs::write(&tmp, text).with_context(|| format!("write {}", tmp.display()))?;
+        std::fs::rename(&tmp, &path).with_context(|| format!("replace {}", path.display()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.peers.is_empty()
+    }
+
+    /// Whether anyone was paired with `ab-daemon pair` (not just seen).
+    pub fn has_paired(&self) -> bool {
+        self.peers.values().any(|p| p.trust == Trust::Paired)
+    }
+
+    /// Trust `key` under `name` as paired, replacing whatever that name had before.
+    pub fn insert(&mut self, name: &str, key: &[u8]) {
+        self.put(name, key, Trust::Paired);
+    }
+
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.peers.remove(name).is_some()
+    }
+
+    fn put(&mut self, name: &str, key: &[u8], trust: Trust) {
+        let added = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        self.peers.insert(name.to_string(), TrustedPeer { key: hex(key), added, trust });
+    }
+
+    /// Name of the trusted peer holding `key`, if any.
+    pub fn name_of(&self, key: &[u8]) -> Option<&str> {
+        let key = hex(key);
+        self.peers.iter().find(|(_, p)| p.key == key).map(|(n, _)| n.as_str())
+    }
+}
+
+/// Held while the store is loaded, changed and saved, so concurrent
+/// handshakes don't undo each other's changes.
+pub fn lock() -> MutexGuard<'static, ()> {
+    static LOCK: Mutex<()> = Mutex::new(());
+    LOCK.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Report what we knew about `key` for `name`, pinning it on first contact
+/// with `tofu`. Call only once the peer has proven it holds the key.
+pub fn record(name: &str, key: &[u8], tofu: bool) -> Result<Seen> {
+    let _lock = lock();
+    let mut store = TrustStore::load()?;
+    let hex_key = hex(key);
+    if let Some((known, p)) = store.peers.iter().find(|(_, p)| p.key == hex_key) {
+        return Ok(Seen::Known { name: known.clone(), trust: p.trust });
+    }
+    if let Some(pinned) = store.peers.get(name) {
+        let old = pinned.fingerprint();
+        let new = fingerprint(key);
+        warn!(target: "trust", "================================================================");
+        warn!(target: "trust", "IDENTITY OF '{name}' HAS CHANGED: pinned {old}, now {new}");
+        warn!(target: "trust", "Someone may be impersonating it. If it was reinstalled, run");
+        warn!(target: "trust", "`ab-daemon trust remove {name}` and pair again. Refusing it until then.");
+        warn!(target: "trust", "================================================================");
+        return Ok(Seen::Changed);
+    }
+    if !tofu {
+        return Ok(Seen::Unknown);
+    }
+    store.put(name, key, Trust::Seen);
+    store.save()?;
+    info!(target: "trust", "pinned {name} ({}) on first contact", fingerprint(key));
+    Ok(Seen::New)
+}
@@ -0,0 +1,77 @@
+// Linux (--virtual-sink NAME): a null sink for apps to play into, captured
+// through its monitor, the way BlackHole is used on a Mac. It's made with
+// `pactl load-module module-null-sink` (PulseAudio, or PipeWire with
+// pipewire-pulse) when the daemon starts and unloaded when it stops; a sink
+// of that name that's already there (from linux_setup.sh, say) is used and
+// left alone. With --virtual-sink-default it's also the default output while
+// the daemon runs, so everything plays into it; the previous default comes
+// back on exit, unless the default was changed again meanwhile.
+use anyhow::{Context, Result, bail};
+use std::process::Command;
+use tracing::{info, warn};
+
+pub struct VirtualSink {
+    name: String,
+    /// The module we loaded, to unload.
+    module: Option<u32>,
+    /// The default sink before we took over.
+    previous_default: Option<String>,
+}
+
+/// Make (or find) sink `name`, and make it the default output if asked to.
+pub fn create(name: &str, make_default: bool) -> Result<VirtualSink> {
+    let exists = pactl(&["list", "short", "sinks"])?.lines().any(|l| l.split('\t').nth(1) == Some(name));
+    let module = if exists {
+        info!(target: "vsink", "using the existing sink '{name}'");
+        None
+    } else {
+        let sink_name = format!("sink_name={name}");
+        let description = format!("sink_properties=device.description={name}");
+        let out = pactl(&["load-module", "module-null-sink", &sink_name, &description])?;
+        let module = out.trim().parse().with_context(|| format!("pactl load-module said {out:?}"))?;
+        info!(target: "vsink", "created sink '{name}' (module {module}); play apps into it to send them");
+        Some(module)
+    };
+    let mut sink = VirtualSink { name: name.to_string(), module, previous_default: None };
+    if make_default {
+        let previous = pactl(&["get-default-sink"])?.trim().to_string();
+        if previous != name {
+            pactl(&["set-default-sink", name])?;
+            info!(target: "vsink", "'{name}' is the default output (was '{previous}')");
+            sink.previous_default = Some(previous);
+        }
+    }
+    Ok(sink)
+}
+
+impl Drop for VirtualSink {
+    fn drop(&mut self) {
+        if let Some(previous) = &self.previous_default {
+            let current = pactl(&["get-default-sink"]).map(|s| s.trim().to_string());
+            if current.as_deref().is_ok_and(|c| c == self.name) {
+                match pactl(&["set-default-sink", previous]) {
+                    Ok(_) => info!(target: "vsink", "'{previous}' is the default output again"),
+                    Err(e) => warn!(target: "vsink", "{e:#}"),
+                }
+            }
+        }
+        if let Some(module) = self.module {
+            match pactl(&["unload-module", &module.to_string()]) {
+                Ok(_) => info!(target: "vsink", "removed sink '{}'", self.name),
+                Err(e) => warn!(target: "vsink", "{e:#}"),
+            }
+        }
+    }
+}
+
+/// Run pactl: what it printed.
+pub fn pactl(args: &[&str]) -> Result<String> {
+    let out = Command::new("pactl")
+        .args(args)
+        .output()
+        .context("run pactl (install pulseaudio-utils, or pipewire-pulse)")?;
+    if !out.status.success() {
+        bail!("pactl {} failed: {}", args.join(" "), String::from_utf8_lossy(&out.stderr).trim());
+    }
+    Ok(String::from_utf8_lossy(&out.stdout).into_owned())
+}